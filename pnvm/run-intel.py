@@ -9,12 +9,12 @@ import numpy as np
 
 def print_header_ycsb(out_fd):
     # out_fd.write("thread_num,obj_num,set_size,zipf,pc_num,success,abort,total_time,new_order\n")
-    out_fd.write("thread_num,zipf,rw_ratio,txn_num_ops,ops_per_iter,mode,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size\n")
+    out_fd.write("thread_num,zipf,rw_ratio,txn_num_ops,ops_per_iter,mode,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size,persist_domain,persistency,ro_tput,rw_tput\n")
     out_fd.flush()
 
 def print_header(out_fd):
     # out_fd.write("thread_num,obj_num,set_size,zipf,pc_num,success,abort,total_time,new_order\n")
-    out_fd.write("thread_num,wh_num,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size\n")
+    out_fd.write("thread_num,wh_num,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size,persist_domain,persistency,ro_tput,rw_tput\n")
     out_fd.flush()
 
 def run(bench_config, out_fd):
@@ -10,7 +10,7 @@ import numpy as np
 
 def print_header(out_fd):
     # out_fd.write("thread_num,obj_num,set_size,zipf,pc_num,success,abort,total_time,new_order\n")
-    out_fd.write("thread_num,wh_num,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size\n")
+    out_fd.write("thread_num,wh_num,success,abort,pc_success,pc_abort,mmap_cnt,total_time,log_size,flush_size,persist_domain,persistency,ro_tput,rw_tput\n")
     out_fd.flush()
 
 def run(bench_config, out_fd):
@@ -46,6 +46,9 @@ def run(bench_config, out_fd):
                 'PNVM_WARMUP_TIME' : str(bench_config['warmup_time']),
                 'PNVM_DURATION' : str(bench_config['duration']),
                 'PNVM_PARTITION' : str(bench_config['partition']),
+                'PNVM_PMEM_LAYOUT' : bench_config.get('pmem_layout', 'packed'),
+                'PNVM_COPY_STRATEGY' : bench_config.get('copy_strategy', 'pmdk'),
+                'PNVM_PERSISTENCY' : bench_config.get('persistency', 'epoch'),
                 }
         sys_env = dict(os.environ)
         env = {**sys_env, **exp_env}
@@ -109,6 +112,29 @@ def do_vol_rel(bench_config, runs):
                 run(bench_config, out_fd)
 
 
+def do_vol_mvcc(bench_config):
+    # OrderStatus and StockLevel read a snapshot, compare ro_tput with do_vol_rel
+    runs = {
+            "proto" : ['TPCC_OCC', 'TPCC_NVM'],
+            "proto_names": ['occ', 'ppnvm'],
+            "cont" : [[1, 1, 1, 1, 1,1], [1, 4, 8, 16, 32,48]],
+            "cont_names": ['high', 'low'],
+    }
+    compile_vol = 'cargo clean && cargo +nightly build --release --features "unstable mvcc"'
+    os.system(compile_vol)
+
+    for (i, proto) in enumerate(runs["proto"]):
+        protocol_name = runs["proto_names"][i]
+        bench_config["name"] = proto
+        bench_config["partition"] = 0
+        for (j,cont) in enumerate(runs["cont"]):
+            bench_config["wh_num"] = cont
+            cont_name = runs["cont_names"][j]
+            path  = "$PNVM_ROOT/pnvm/benchmark/{}-vol-mvcc-{}-output.csv".format(cont_name, protocol_name)
+            with open(os.path.expandvars(path), "w+") as out_fd:
+                print_header(out_fd)
+                run(bench_config, out_fd)
+
 def do_pmem_rel(bench_config):
     runs = {
             "proto" : ['TPCC_OCC', 'TPCC_NVM', 'NO_2PL', 'NO_NVM'],
@@ -201,6 +227,96 @@ def do_pmem_wdrain(bench_config):
                 print_header(out_fd)
                 run(bench_config, out_fd)
 
+def do_pmem_ppipe(bench_config):
+    # Pieces persisted nodrain, dependents wait on the rank they read at
+    runs = {
+            "proto" : ['TPCC_NVM',  'NO_NVM'],
+            "proto_names": ['ppnvm',  'no-ppnvm'],
+            "cont" : [[1, 1, 1, 1, 1,1], [1, 4, 8, 16, 32,48]],
+            "cont_names": ['high', 'low'],
+    }
+    compile_pmem = 'cargo clean && PMEM_FILE_DIR=~/ParNVM/data PLOG_FILE_PATH=~/ParNVM/data/log cargo +nightly build --release --features "unstable pmem plog ppipe"'
+    os.system(compile_pmem)
+
+    for (i, proto) in enumerate(runs["proto"]):
+        protocol_name = runs["proto_names"][i]
+        bench_config["name"] = proto
+        for (j,cont) in enumerate(runs["cont"]):
+            bench_config["wh_num"] = cont
+            cont_name = runs["cont_names"][j]
+            path  = "$PNVM_ROOT/pnvm/benchmark/{}-pmem-pp-{}-output.csv".format(cont_name, protocol_name)
+            with open(os.path.expandvars(path), "w+") as out_fd:
+                print_header(out_fd)
+                run(bench_config, out_fd)
+
+def do_pmem_layout(bench_config):
+    # Flushes of TPCC_NVM per pmem row layout, see pnvm_lib/src/storage/layout.rs
+    # The flush_size column is the MB/s of cache lines flushed
+    runs = {
+            "layout" : ['packed', 'cache_line', 'xpline', 'align_hot', 'xpline_align_hot'],
+            "cont" : [[1, 1, 1, 1, 1,1], [1, 4, 8, 16, 32,48]],
+            "cont_names": ['high', 'low'],
+    }
+    compile_pmem = 'cargo clean && PMEM_FILE_DIR=~/ParNVM/data PLOG_FILE_PATH=~/ParNVM/data/log cargo +nightly build --release --features "unstable pmem plog"'
+    os.system(compile_pmem)
+
+    bench_config["name"] = 'TPCC_NVM'
+    bench_config["partition"] = 0
+    for layout in runs["layout"]:
+        bench_config["pmem_layout"] = layout
+        for (j,cont) in enumerate(runs["cont"]):
+            bench_config["wh_num"] = cont
+            cont_name = runs["cont_names"][j]
+            path  = "$PNVM_ROOT/pnvm/benchmark/{}-pmem-layout-{}-ppnvm-output.csv".format(cont_name, layout)
+            with open(os.path.expandvars(path), "w+") as out_fd:
+                print_header(out_fd)
+                run(bench_config, out_fd)
+    bench_config["pmem_layout"] = 'packed'
+
+def do_pmem_copy(bench_config):
+    # TPCC_NVM per copy strategy, see pnvm_sys/src/copy.rs
+    runs = {
+            "strategy" : ['pmdk', 'mov-clwb', 'mov-clflushopt', 'movnt-clwb', 'movnt-clflush'],
+            "cont" : [[1, 1, 1, 1, 1,1], [1, 4, 8, 16, 32,48]],
+            "cont_names": ['high', 'low'],
+    }
+    compile_pmem = 'cargo clean && PMEM_FILE_DIR=~/ParNVM/data PLOG_FILE_PATH=~/ParNVM/data/log cargo +nightly build --release --features "unstable pmem plog"'
+    os.system(compile_pmem)
+
+    bench_config["name"] = 'TPCC_NVM'
+    bench_config["partition"] = 0
+    for strategy in runs["strategy"]:
+        bench_config["copy_strategy"] = strategy
+        for (j,cont) in enumerate(runs["cont"]):
+            bench_config["wh_num"] = cont
+            cont_name = runs["cont_names"][j]
+            path  = "$PNVM_ROOT/pnvm/benchmark/{}-pmem-copy-{}-ppnvm-output.csv".format(cont_name, strategy)
+            with open(os.path.expandvars(path), "w+") as out_fd:
+                print_header(out_fd)
+                run(bench_config, out_fd)
+    bench_config["copy_strategy"] = 'pmdk'
+
+def do_pmem_persistency(bench_config, runs):
+    # Every protocol per persistency model, see pnvm_lib/src/persistency.rs
+    models = ['strict', 'epoch', 'strand']
+    compile_pmem = 'cargo clean && PMEM_FILE_DIR=~/ParNVM/data PLOG_FILE_PATH=~/ParNVM/data/log cargo +nightly build --release --features "unstable pmem plog"'
+    os.system(compile_pmem)
+
+    bench_config["partition"] = 0
+    for model in models:
+        bench_config["persistency"] = model
+        for (i, proto) in enumerate(runs["proto"]):
+            protocol_name = runs["proto_names"][i]
+            bench_config["name"] = proto
+            for (j,cont) in enumerate(runs["cont"]):
+                bench_config["wh_num"] = cont
+                cont_name = runs["cont_names"][j]
+                path  = "$PNVM_ROOT/pnvm/benchmark/{}-pmem-{}-{}-output.csv".format(cont_name, model, protocol_name)
+                with open(os.path.expandvars(path), "w+") as out_fd:
+                    print_header(out_fd)
+                    run(bench_config, out_fd)
+    bench_config["persistency"] = 'epoch'
+
 if __name__ == '__main__':
     bench_config = {
             "thread_num" :[1, 4, 8,16, 32, 48],
@@ -223,10 +339,15 @@ if __name__ == '__main__':
     # Without Piece Drain
     # do_pmem_rel(bench_config)
     do_pmem_wdrain(bench_config)
+    do_pmem_ppipe(bench_config)
     do_pmem_dir(bench_config, runs)
+    do_pmem_layout(bench_config)
+    do_pmem_copy(bench_config)
+    do_pmem_persistency(bench_config, runs)
     #do_pmem_no_partition(bench_config, runs)
 
     do_vol_rel(bench_config,runs)
+    do_vol_mvcc(bench_config)
     # do_vol_no_partition(bench_config, runs)
 
     # # With MemCpy
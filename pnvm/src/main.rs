@@ -6,6 +6,9 @@
 
 extern crate pnvm_lib;
 
+#[macro_use]
+extern crate pnvm_derive;
+
 #[cfg(any(feature = "pmem", feature = "disk"))]
 extern crate pnvm_sys;
 
@@ -55,6 +58,10 @@ use pnvm_lib::{
     parnvm::nvm_txn_occ::TransactionParOCC, parnvm::nvm_txn_raw::TransactionParOCCRaw, tcore::*,
     txn::*,
 };
+use pnvm_lib::flush::CACHE_LINE_SIZE;
+
+#[cfg(feature = "history")]
+use pnvm_lib::history::{checker, recorder::HistoryRecorder};
 
 //#[cfg(feature = "pmem")]
 //#[global_allocator]
@@ -70,6 +77,14 @@ fn main() {
     let conf = util::read_env();
     warn!("{:?}", conf);
 
+    #[cfg(feature = "pmem")]
+    {
+        pnvm_lib::flush::detect_persist_domain(conf.persist_domain);
+        pnvm_lib::flush::set_copy_strategy(conf.copy_strategy);
+        PmemFac::set_capacity(conf.pmem_fac_mb << 20);
+    }
+    pnvm_lib::persistency::set_persistency_model(conf.persistency);
+
     // #[cfg(feature = "pmem")]
     // println!("PMEM on");
 
@@ -90,6 +105,8 @@ fn main() {
 }
 
 fn run_micro_2pl(conf: Config) {
+    #[cfg(feature = "pmem")]
+    PmemFac::init();
     //Prepare object pools
     let values: Vec<Arc<TBox<u32>>> = (0..conf.obj_num as u32).map(|x| TBox::new(x)).collect();
 
@@ -255,7 +272,7 @@ fn run_micro_2pl(conf: Config) {
 //
 
 fn run_nvm_occ_micro(conf: Config) {
-    #[cfg(all(feature = "pmem", any(feature = "wdrain", feature = "dir")))]
+    #[cfg(feature = "pmem")]
     PmemFac::init();
     let workload = util::TestHelper::prepare_workload_nvm_occ(&conf);
     let work = workload.work_;
@@ -353,6 +370,8 @@ fn run_nvm_occ_micro(conf: Config) {
 
 fn run_occ_micro(conf: Config) {
     let mtx = Arc::new(Mutex::new(0));
+    #[cfg(feature = "pmem")]
+    PmemFac::init();
     let dataset = util::TestHelper::prepare_workload_occ(&conf).get_dataset();
     let keys = dataset.keys;
     let maps = dataset.maps;
@@ -464,6 +483,9 @@ fn run_occ_micro(conf: Config) {
 fn run_pc_tpcc(conf: Config, kind: WorkloadType, piece_kind: PieceType) {
     let mut rng = SmallRng::from_rng(&mut thread_rng()).unwrap();
     //FIXME: rename the function, parepare workload
+    #[cfg(feature = "pmem")]
+    PmemFac::init();
+
     let tables = tpcc::workload_common::prepare_workload(&conf, &mut rng);
 
     let atomic_cnt = Arc::new(AtomicUsize::new(1));
@@ -491,7 +513,7 @@ fn run_pc_tpcc(conf: Config, kind: WorkloadType, piece_kind: PieceType) {
             .spawn(move || {
                 TidFac::set_thd_mask(i as u32);
                 OidFac::set_obj_mask(i as u64);
-                #[cfg(all(feature = "pmem", feature = "wdrain"))]
+                #[cfg(feature = "pmem")]
                 PmemFac::init();
 
                 tpcc::workload_common::num_warehouse_set(wh_num);
@@ -722,6 +744,9 @@ fn run_pc_tpcc(conf: Config, kind: WorkloadType, piece_kind: PieceType) {
                     info!("[THREAD {:} - TXN {:?}] COMMITS", i + 1, tid);
                 }
 
+                #[cfg(feature = "history")]
+                HistoryRecorder::flush_thd();
+
                 BenchmarkCounter::copy()
             })
             .unwrap();
@@ -868,6 +893,9 @@ fn run_ycsb(conf: Config, txn_type: TxnType) {
 //Run the OCC contention management TPCC workload
 fn run_tpcc(conf: Config, txn_type: TxnType) {
     let mut rng = SmallRng::from_rng(&mut thread_rng()).unwrap();
+    #[cfg(feature = "pmem")]
+    PmemFac::init();
+
     let tables = tpcc::workload_common::prepare_workload(&conf, &mut rng);
 
     let atomic_cnt = Arc::new(AtomicUsize::new(1));
@@ -932,7 +960,7 @@ fn run_tpcc(conf: Config, txn_type: TxnType) {
         let handle = builder
             .spawn(move || {
                 /* Thread local initialization */
-                #[cfg(all(feature = "pmem", feature = "wdrain"))]
+                #[cfg(feature = "pmem")]
                 PmemFac::init();
 
                 TidFac::set_thd_mask(i as u32);
@@ -968,7 +996,12 @@ fn run_tpcc(conf: Config, txn_type: TxnType) {
                     match txn_type {
                         TxnType::OCC => {
                             let tid = tid.clone();
-                            let tx = &mut occ_txn::TransactionOCC::new(tid);
+                            /* OrderStatus and StockLevel */
+                            let tx = &mut if j < 4 || (j >= 8 && j < 12) {
+                                occ_txn::TransactionOCC::new_snapshot(tid)
+                            } else {
+                                occ_txn::TransactionOCC::new(tid)
+                            };
                             while {
                                 info!(
                                     "\n------------------TXN[{:?} Starts-----------------\n",
@@ -1048,6 +1081,9 @@ fn run_tpcc(conf: Config, txn_type: TxnType) {
                     info!("[THREAD {:} - TXN {:?}] COMMITS", i + 1, tid);
                 }
 
+                #[cfg(feature = "history")]
+                HistoryRecorder::flush_thd();
+
                 BenchmarkCounter::copy()
             })
             .unwrap();
@@ -1142,6 +1178,7 @@ fn run_single(conf: Config) {
 fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config) {
     let mut total_abort = 0;
     let mut total_success = 0;
+    let mut total_ro_success = 0;
     let mut total_pc_abort = 0;
     let mut total_pc_success = 0;
     let mut total_time = time::Duration::new(0, 0);
@@ -1157,12 +1194,13 @@ fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config)
         match handle.join() {
             Ok(per_thd) => {
                 total_success += per_thd.success_cnt;
+                total_ro_success += per_thd.success_ro_cnt;
                 total_abort += per_thd.abort_cnt;
                 total_pc_abort += per_thd.abort_piece_cnt;
                 total_pc_success += per_thd.success_piece_cnt;
                 total_new_order += per_thd.new_order_cnt;
                 total_mmap_cnt += per_thd.mmap_cnt;
-                total_flush += per_thd.pmem_flush_size;
+                total_flush += per_thd.pmem_flush_lines as u64;
                 total_log += per_thd.pmem_log_size;
                 total_time = std::cmp::max(
                     total_time,
@@ -1176,10 +1214,14 @@ fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config)
         }
     }
 
+    /* Committed txns per second, read-only and read-write */
+    let ro_tput = total_ro_success / total_time.as_secs() as u32;
+    let rw_tput = (total_success - total_ro_success) / total_time.as_secs() as u32;
+
     match conf.test_name.as_ref() {
         "TPCC_OCC" | "TPCC_NVM" | "NO_NVM" | "TPCC_PC_RAW" | "NO_PC_RAW" | "NO_2PL" => {
             println!(
-                "{}, {}, {},{},{}, {},{},{:?},{},{}",
+                "{}, {}, {},{},{}, {},{},{:?},{},{},{},{},{},{}",
                 conf.thread_num,
                 conf.wh_num,
                 total_success,
@@ -1189,12 +1231,16 @@ fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config)
                 total_mmap_cnt,
                 total_time.as_secs() as u32 * 1000 + total_time.subsec_millis(),
                 total_log / 1024 / 1024 / total_time.as_secs() as u32,
-                total_flush / 1024 / 1024 / total_time.as_secs() as u32
+                total_flush * CACHE_LINE_SIZE as u64 / 1024 / 1024 / total_time.as_secs(),
+                persist_domain_name(),
+                conf.persistency.name(),
+                ro_tput,
+                rw_tput
             );
         }
         "YCSB_OCC" => {
             println!(
-                "{},{},{},{},{},{},{},{},{},{},{},{:?},{},{}",
+                "{},{},{},{},{},{},{},{},{},{},{},{:?},{},{},{},{},{},{}",
                 conf.thread_num,
                 conf.zipf_coeff,
                 conf.ycsb_rw_ratio,
@@ -1208,12 +1254,19 @@ fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config)
                 total_mmap_cnt,
                 total_time.as_secs() as u32 * 1000 + total_time.subsec_millis(),
                 total_log / 1024 / 1024 / total_time.as_secs() as u32,
-                total_flush / 1024 / 1024 / total_time.as_secs() as u32
+                total_flush * CACHE_LINE_SIZE as u64 / 1024 / 1024 / total_time.as_secs(),
+                persist_domain_name(),
+                conf.persistency.name(),
+                ro_tput,
+                rw_tput
             );
         }
         _ => panic!("Not supported anymore"),
     }
 
+    #[cfg(feature = "history")]
+    report_history();
+
     for i in (1..total_timestamps.len()).rev() {
         if total_timestamps[i] > total_timestamps[i - 1] {
             total_timestamps[i] -= total_timestamps[i - 1];
@@ -1236,6 +1289,32 @@ fn report_stat(handles: Vec<thread::JoinHandle<BenchmarkCounter>>, conf: Config)
     //     )
 }
 
+//Reported with the stats, see pnvm_sys::PersistDomain
+#[cfg(feature = "pmem")]
+fn persist_domain_name() -> &'static str {
+    pnvm_lib::flush::persist_domain().name()
+}
+
+#[cfg(not(feature = "pmem"))]
+fn persist_domain_name() -> &'static str {
+    "volatile"
+}
+
+//Check the histories flushed by all the benchmark threads
+#[cfg(feature = "history")]
+fn report_history() {
+    let records = HistoryRecorder::take_all();
+    match checker::check_serializable(&records) {
+        Ok(_) => println!("history: {} txns, serializable", records.len()),
+        Err(cycles) => {
+            println!("history: {} txns, {} cycles", records.len(), cycles.len());
+            for cycle in cycles.iter() {
+                println!("{}", cycle);
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 enum TxnType {
     OCC,
@@ -5,21 +5,25 @@
 //- Warehouse
 //- NewOrder
 //- ....
+//
+//#[derive(TableEntry)] generates the XXXXRef of each
+//entry and its TRef impl, see pnvm_derive. Fields marked
+//#[field] can be logged on their own, #[varlen] ones are
+//VarLen columns stored out of line.
 
 use numeric::Numeric;
 use std::{
     fmt::{self, Debug},
     hash::Hash,
-    mem::size_of_val,
     sync::Arc,
 };
-use table::{Key, Table};
+use table::{Key, Table, VarLen};
 use workload_common::{num_district_get, num_warehouse_get};
 
 use pnvm_lib::{tcore::*, txn::TxnInfo};
 
 #[inline]
-fn copy_from_string(dest: &mut [u8], src: String) {
+pub fn copy_from_string(dest: &mut [u8], src: String) {
     dest[..src.len()].copy_from_slice(src.as_bytes());
 }
 
@@ -34,7 +38,8 @@ pub const W_TAX: usize = 7;
 pub const W_YTD: usize = 8;
 
 //90 Bytes
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(key = "i32", table = "warehouse")]
 #[repr(C)]
 pub struct Warehouse {
     pub w_id: i32,
@@ -45,6 +50,7 @@ pub struct Warehouse {
     pub w_state: [u8; 2],
     pub w_zip: [u8; 9],
     pub w_tax: Numeric, // Numeric(4, 4)
+    #[field]
     pub w_ytd: Numeric, // Numeric(12, 2)
 }
 
@@ -57,15 +63,6 @@ impl Key<i32> for Warehouse {
     fn bucket_key(&self) -> usize {
         self.w_id as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[W_YTD] = (&self.w_ytd as *const _ as *const u8).wrapping_offset_from(base);
-        fields[W_YTD + 1] = fields[W_YTD] + size_of_val(&self.w_ytd) as isize;
-
-        fields
-    }
 }
 
 impl Warehouse {
@@ -110,7 +107,8 @@ impl Warehouse {
 pub const D_YTD: usize = 9;
 pub const D_NEXT_O_ID: usize = 10;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(key = "(i32, i32)", table = "district")]
 #[repr(C)]
 pub struct District {
     pub d_id: i32,
@@ -122,7 +120,9 @@ pub struct District {
     pub d_state: [u8; 2],
     pub d_zip: [u8; 9],
     pub d_tax: Numeric, // Numeric(4, 4)
+    #[field]
     pub d_ytd: Numeric, // Numeric(12,2)
+    #[field]
     pub d_next_o_id: i32,
 }
 
@@ -137,17 +137,6 @@ impl Key<(i32, i32)> for District {
         let dis_num = num_district_get();
         (self.d_w_id * dis_num + self.d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[D_YTD] = (&self.d_ytd as *const _ as *const u8).wrapping_offset_from(base);
-        fields[D_NEXT_O_ID] =
-            (&self.d_next_o_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[D_NEXT_O_ID + 1] = fields[D_NEXT_O_ID] + size_of_val(&self.d_next_o_id) as isize;
-
-        fields
-    }
 }
 
 impl District {
@@ -216,8 +205,16 @@ pub const C_PAYMENT_CNT: usize = 18;
 pub const C_DELIVERY_CNT: usize = 19;
 pub const C_DATA: usize = 20;
 
+//Max length of c_data
+pub const C_DATA_LEN: usize = 500;
+
 //700Bytes
-#[derive(Clone)]
+#[derive(Clone, TableEntry)]
+#[entry(
+    key = "(i32, i32, i32)",
+    table = "customer",
+    push_index = "update_sec_index"
+)]
 #[repr(C)]
 pub struct Customer {
     pub c_id: i32,
@@ -234,13 +231,19 @@ pub struct Customer {
     pub c_phone: [u8; 16],
     pub c_since: i32, // Timestamp
     pub c_credit: [u8; 2],
-    pub c_credit_lim: Numeric,   // Numeric(12,2)
-    pub c_discount: Numeric,     // Numeric(4, 4)
-    pub c_balance: Numeric,      // Numeric(12,2)
-    pub c_ytd_payment: Numeric,  // Numeric(12,2)
-    pub c_payment_cnt: Numeric,  // Numeric(4,0)
+    pub c_credit_lim: Numeric, // Numeric(12,2)
+    pub c_discount: Numeric,   // Numeric(4, 4)
+    #[field]
+    pub c_balance: Numeric, // Numeric(12,2)
+    #[field]
+    pub c_ytd_payment: Numeric, // Numeric(12,2)
+    #[field]
+    pub c_payment_cnt: Numeric, // Numeric(4,0)
+    #[field]
     pub c_delivery_cnt: Numeric, // Numeric(4,0)
-    pub c_data: [u8; 500],
+    #[field]
+    #[varlen]
+    pub c_data: VarLen,
 }
 
 impl Key<(i32, i32, i32)> for Customer {
@@ -254,23 +257,6 @@ impl Key<(i32, i32, i32)> for Customer {
         let dis_num = num_district_get();
         (self.c_w_id * dis_num + self.c_d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[C_BALANCE] = (&self.c_balance as *const _ as *const u8).wrapping_offset_from(base);
-        fields[C_YTD_PAYMENT] =
-            (&self.c_ytd_payment as *const _ as *const u8).wrapping_offset_from(base);
-        fields[C_PAYMENT_CNT] =
-            (&self.c_payment_cnt as *const _ as *const u8).wrapping_offset_from(base);
-        fields[C_DELIVERY_CNT] =
-            (&self.c_delivery_cnt as *const _ as *const u8).wrapping_offset_from(base);
-        fields[C_DATA] = (&self.c_data as *const _ as *const u8).wrapping_offset_from(base);
-
-        fields[C_DATA + 1] = fields[C_DATA] + size_of_val(&self.c_data) as isize;
-
-        fields
-    }
 }
 
 impl Debug for Customer {
@@ -313,7 +299,6 @@ impl Customer {
         let mut c_zip: [u8; 9] = Default::default();
         let mut c_phone: [u8; 16] = Default::default();
         let mut c_credit: [u8; 2] = Default::default();
-        let mut c_data: [u8; 500] = [0; 500];
 
         copy_from_string(&mut c_first, c_first_str);
         copy_from_string(&mut c_middle, c_middle_str);
@@ -323,7 +308,6 @@ impl Customer {
         copy_from_string(&mut c_city, c_city_str);
         copy_from_string(&mut c_state, c_state_str);
         copy_from_string(&mut c_zip, c_zip_str);
-        copy_from_string(&mut c_data, c_data_str);
         copy_from_string(&mut c_credit, c_credit_str);
         copy_from_string(&mut c_phone, c_phone_str);
 
@@ -348,16 +332,33 @@ impl Customer {
             c_ytd_payment,  // Numeric(12,2)
             c_payment_cnt,  // Numeric(4,0)
             c_delivery_cnt, // Numeric(4,0)
-            c_data,
+            c_data: VarLen::from_str(&c_data_str),
         }
     }
+
+    //Prepends to c_data, which keeps its first C_DATA_LEN bytes
+    pub fn prepend_data(&mut self, new_data: &str) {
+        let mut data = Vec::with_capacity(new_data.len() + self.c_data.len());
+        data.extend_from_slice(new_data.as_bytes());
+        data.extend_from_slice(self.c_data.as_bytes());
+        data.truncate(C_DATA_LEN);
+        self.c_data = VarLen::new(&data);
+    }
 }
 
 pub const NO_O_ID: usize = 0;
 pub const NO_D_ID: usize = 1;
 pub const NO_W_ID: usize = 2;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(
+    key = "(i32, i32, i32)",
+    table = "neworder",
+    push,
+    push_index = "update_wd_index",
+    delete,
+    delete_index = "delete_index"
+)]
 #[repr(C)]
 pub struct NewOrder {
     pub no_o_id: i32,
@@ -376,10 +377,6 @@ impl Key<(i32, i32, i32)> for NewOrder {
         let dis_num = num_district_get();
         (self.no_w_id * dis_num + self.no_d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        [-1; 32]
-    }
 }
 
 pub const O_ID: usize = 0;
@@ -392,7 +389,13 @@ pub const O_OL_CNT: usize = 6;
 pub const O_ALL_LOCAL: usize = 7;
 
 //48 B
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(
+    key = "(i32, i32, i32)",
+    table = "order",
+    push,
+    push_index = "update_cus_index"
+)]
 #[repr(C)]
 pub struct Order {
     pub o_id: i32,
@@ -400,8 +403,10 @@ pub struct Order {
     pub o_w_id: i32,
     pub o_c_id: i32,
     pub o_entry_d: i32, // Timestamp
+    #[field]
     pub o_carrier_id: i32,
-    pub o_ol_cnt: Numeric,    // Numeric(2,0)
+    #[field]
+    pub o_ol_cnt: Numeric, // Numeric(2,0)
     pub o_all_local: Numeric, // Numeric(1, 0)
 }
 
@@ -415,16 +420,6 @@ impl Key<(i32, i32, i32)> for Order {
         let dis_num = num_district_get();
         (self.o_w_id * dis_num + self.o_d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[O_CARRIER_ID] =
-            (&self.o_carrier_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[O_OL_CNT] = (&self.o_ol_cnt as *const _ as *const u8).wrapping_offset_from(base);
-
-        fields
-    }
 }
 
 //40 Bytes
@@ -453,7 +448,13 @@ impl Order {
 }
 
 //70Bytes
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(
+    key = "(i32, i32, i32, i32)",
+    table = "orderline",
+    push,
+    push_index = "update_order_index"
+)]
 #[repr(C)]
 pub struct OrderLine {
     pub ol_o_id: i32,
@@ -462,9 +463,11 @@ pub struct OrderLine {
     pub ol_number: i32,
     pub ol_i_id: i32,
     pub ol_supply_w_id: i32,
+    #[field]
     pub ol_delivery_d: i32,
+    #[field]
     pub ol_quantity: Numeric, // Numeric(2,0)
-    pub ol_amount: Numeric,   // Numeric(6, 2)
+    pub ol_amount: Numeric, // Numeric(6, 2)
     pub ol_dist_info: [u8; 24],
 }
 
@@ -479,17 +482,6 @@ impl Key<(i32, i32, i32, i32)> for OrderLine {
         let dis_num = num_district_get();
         (self.ol_w_id * dis_num + self.ol_d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[OL_DELIVERY_D] =
-            (&self.ol_delivery_d as *const _ as *const u8).wrapping_offset_from(base);
-        fields[OL_QUANTITY] =
-            (&self.ol_quantity as *const _ as *const u8).wrapping_offset_from(base);
-
-        fields
-    }
 }
 
 pub const OL_O_ID: usize = 0;
@@ -540,7 +532,8 @@ pub const I_NAME: usize = 2;
 pub const I_PRICE: usize = 3;
 pub const I_DATA: usize = 4;
 
-#[derive(Clone)]
+#[derive(Clone, TableEntry)]
+#[entry(key = "i32", table = "item", read_only)]
 #[repr(C)]
 pub struct Item {
     pub i_id: i32,
@@ -589,10 +582,6 @@ impl Key<i32> for Item {
     fn bucket_key(&self) -> usize {
         (self.i_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        [-1; 32]
-    }
 }
 
 pub const S_I_ID: usize = 0;
@@ -613,12 +602,17 @@ pub const S_ORDER_CNT: usize = 14;
 pub const S_REMOTE_CNT: usize = 15;
 pub const S_DATA: usize = 16;
 
-#[derive(Clone)]
+#[derive(Clone, TableEntry)]
+#[entry(key = "(i32, i32)", table = "stock")]
 #[repr(C)]
 pub struct Stock {
+    #[field]
     pub s_i_id: i32,
+    #[field]
     pub s_w_id: i32,
+    #[field]
     pub s_quantity: Numeric, // Numeric(4,0)
+    #[field]
     pub s_dist_01: [u8; 24],
     pub s_dist_02: [u8; 24],
     pub s_dist_03: [u8; 24],
@@ -629,9 +623,12 @@ pub struct Stock {
     pub s_dist_08: [u8; 24],
     pub s_dist_09: [u8; 24],
     pub s_dist_10: [u8; 24],
-    pub s_ytd: Numeric,        // Numeric(8,0)
-    pub s_order_cnt: Numeric,  // Numeric(4, 0)
+    pub s_ytd: Numeric, // Numeric(8,0)
+    #[field]
+    pub s_order_cnt: Numeric, // Numeric(4, 0)
+    #[field]
     pub s_remote_cnt: Numeric, // Numeric(4,0)
+    #[field]
     pub s_data: [u8; 50],
 }
 
@@ -651,22 +648,6 @@ impl Key<(i32, i32)> for Stock {
     fn bucket_key(&self) -> usize {
         self.s_w_id as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[S_I_ID] = (&self.s_i_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_W_ID] = (&self.s_w_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_QUANTITY] = (&self.s_quantity as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_DIST_01] = (&self.s_dist_01 as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_ORDER_CNT] =
-            (&self.s_order_cnt as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_REMOTE_CNT] =
-            (&self.s_remote_cnt as *const _ as *const u8).wrapping_offset_from(base);
-        fields[S_DATA] = (&self.s_data as *const _ as *const u8).wrapping_offset_from(base);
-
-        fields
-    }
 }
 
 impl Stock {
@@ -734,16 +715,25 @@ impl Stock {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, TableEntry)]
+#[entry(key = "(i32, i32)", table = "history", push)]
 #[repr(C)]
 pub struct History {
+    #[field]
     pub h_c_id: i32,
+    #[field]
     pub h_c_d_id: i32,
+    #[field]
     pub h_c_w_id: i32,
+    #[field]
     pub h_d_id: i32,
+    #[field]
     pub h_w_id: i32,
-    pub h_date: i32,       //timestamp
+    #[field]
+    pub h_date: i32, //timestamp
+    #[field]
     pub h_amount: Numeric, //Numeric(6,2)
+    #[field]
     pub h_data: [u8; 24],
 }
 
@@ -767,23 +757,6 @@ impl Key<(i32, i32)> for History {
         let dis_num = num_district_get();
         (self.h_w_id * dis_num + self.h_d_id) as usize
     }
-
-    fn field_offset(&self) -> [isize; 32] {
-        let mut fields: [isize; 32] = [-1; 32];
-        let base: *const u8 = self as *const _ as *const u8;
-        fields[H_C_ID] = (&self.h_c_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_C_D_ID] = (&self.h_c_d_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_C_W_ID] = (&self.h_c_w_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_D_ID] = (&self.h_d_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_W_ID] = (&self.h_w_id as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_DATE] = (&self.h_date as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_AMOUNT] = (&self.h_amount as *const _ as *const u8).wrapping_offset_from(base);
-        fields[H_DATA] = (&self.h_data as *const _ as *const u8).wrapping_offset_from(base);
-
-        fields[H_DATA + 1] = fields[H_DATA] + size_of_val(&self.h_data) as isize;
-
-        fields
-    }
 }
 
 impl History {
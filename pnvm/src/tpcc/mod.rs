@@ -1,5 +1,4 @@
 pub mod entry;
-pub mod entry_ref;
 pub mod numeric;
 pub mod table;
 pub mod tpcc_tables;
@@ -41,17 +41,23 @@ pub type DistrictTable = Table<District, (i32, i32)>;
 
 #[derive(Debug)]
 pub struct CustomerTable {
-    pub name_index_: SecIndex<(String, i32, i32), (i32, i32, i32, [u8; 16])>,
+    //c_last, c_w_id, c_d_id
+    pub name_index_: SecIndex<([u8; 16], i32, i32), (i32, i32, i32, [u8; 16])>,
     table_: Table<Customer, (i32, i32, i32)>,
 }
 
 impl CustomerTable {
-    pub fn new_with_buckets(num: usize, bkt_size: usize, name: &str) -> CustomerTable {
+    pub fn new_with_layout(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        layout: PmemLayout,
+    ) -> CustomerTable {
         let num_whs = num_warehouse_get();
         let num_dis = num_district_get();
         let total_wd = num_whs * num_dis;
         CustomerTable {
-            table_: Table::new_with_buckets(num, bkt_size, name),
+            table_: Table::new_with_layout(num, bkt_size, name, layout),
             name_index_: SecIndex::new_with_buckets(
                 total_wd as usize,
                 Box::new(move |key| {
@@ -66,41 +72,24 @@ impl CustomerTable {
         /* Indexes Updates */
         let (w_id, d_id, c_id) = entry.primary_key();
         let index_val = (w_id, d_id, c_id, entry.c_first);
-        let index_key = (
-            String::from(
-                str::from_utf8(&entry.c_last)
-                    .unwrap()
-                    .trim_right_matches(char::from(0)),
-            ),
-            entry.c_w_id,
-            entry.c_d_id,
-        );
-        self.name_index_.insert_index(index_key.clone(), index_val);
+        let index_key = (entry.c_last, entry.c_w_id, entry.c_d_id);
+        let mut index = self.name_index_.lock_bucket(&index_key);
+        index.insert(index_key, index_val, Tid::default());
 
         //println!("PUSHING CUSTOMER {}, {}, {}", entry.c_id, entry.c_w_id, entry.c_d_id);
         self.table_.push_raw(entry);
-        self.name_index_.unlock_bucket(&index_key);
     }
 
+    //Installed by the push of the row, see CustomerRef
     //FIXME: deleting an entry needs to be fixed
-    pub fn update_sec_index(&self, arc: &Arc<Row<Customer, (i32, i32, i32)>>) {
+    pub fn update_sec_index(&self, arc: &Arc<Row<Customer, (i32, i32, i32)>>, tid: Tid) {
         let c = arc.get_data();
-
-        let idx_key = (
-            String::from(
-                str::from_utf8(&c.c_last)
-                    .unwrap()
-                    .trim_right_matches(char::from(0)),
-            ),
-            c.c_w_id,
-            c.c_d_id,
-        );
+        let idx_key = (c.c_last, c.c_w_id, c.c_d_id);
 
         let (w_id, d_id, c_id) = c.primary_key();
         let idx_val = (w_id, d_id, c_id, c.c_first);
 
-        self.name_index_.insert_index(idx_key.clone(), idx_val);
-        self.name_index_.unlock_bucket(&idx_key);
+        self.name_index_.insert_index(idx_key, idx_val, tid);
     }
 
     pub fn retrieve(&self, index: &(i32, i32, i32)) -> Option<Arc<Row<Customer, (i32, i32, i32)>>> {
@@ -109,6 +98,12 @@ impl CustomerTable {
         self.table_.retrieve(index, bucket_idx as usize)
     }
 
+    pub fn retrieve_bucket(&self, bucket: &(i32, i32)) -> &Bucket<Customer, (i32, i32, i32)> {
+        let dis_num = num_district_get();
+        let bucket_idx = bucket.0 * dis_num + bucket.1;
+        self.get_bucket(bucket_idx as usize)
+    }
+
     pub fn get_bucket(&self, bkt_idx: usize) -> &Bucket<Customer, (i32, i32, i32)> {
         self.table_.get_bucket(bkt_idx)
     }
@@ -118,11 +113,13 @@ impl CustomerTable {
         index: &(String, i32, i32),
     ) -> Option<Arc<Row<Customer, (i32, i32, i32)>>> {
         let dis_num = num_district_get();
-        match self.name_index_.find_one_bucket_mut(index) {
-            None => {
-                self.name_index_.unlock_bucket(index);
-                None
-            }
+        let mut c_last: [u8; 16] = Default::default();
+        copy_from_string(&mut c_last, index.0.clone());
+        let index = &(c_last, index.1, index.2);
+
+        let name_index = self.name_index_.lock_bucket(index);
+        let ret = match name_index.get(index) {
+            None => None,
             Some(tuples) => {
                 assert_eq!(tuples.len() > 0, true);
                 let (front, back) = tuples.as_slices();
@@ -131,13 +128,11 @@ impl CustomerTable {
 
                 let i = tuples.len() / 2;
                 let (w_id, d_id, c_id, _) = tuples[i];
-                let ret = self
-                    .table_
-                    .retrieve(&(w_id, d_id, c_id), (dis_num * w_id + d_id) as usize);
-                self.name_index_.unlock_bucket(index);
-                ret
+                self.table_
+                    .retrieve(&(w_id, d_id, c_id), (dis_num * w_id + d_id) as usize)
             }
-        }
+        };
+        ret
     }
 }
 
@@ -152,12 +147,17 @@ pub struct NewOrderTable {
 }
 
 impl NewOrderTable {
-    pub fn new_with_buckets(num: usize, bkt_size: usize, name: &str) -> NewOrderTable {
+    pub fn new_with_layout(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        layout: PmemLayout,
+    ) -> NewOrderTable {
         let num_whs = num_warehouse_get();
         let num_dis = num_district_get();
         let total_wd = num_whs * num_dis;
         NewOrderTable {
-            table_: Table::new_with_buckets(num, bkt_size, name),
+            table_: Table::new_with_layout(num, bkt_size, name, layout),
             wd_index_: SecIndex::new_with_buckets(
                 total_wd as usize,
                 Box::new(move |key| ((key.0 * num_dis + key.1) % total_wd) as usize),
@@ -167,25 +167,30 @@ impl NewOrderTable {
 
     pub fn push_pc(&self, tx: &mut TransactionParOCC, entry: NewOrder, tables: &Arc<Tables>)
     where
-        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef,
+        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc(tx, entry, tables);
     }
     pub fn push_pc_raw(&self, tx: &mut TransactionParOCCRaw, entry: NewOrder, tables: &Arc<Tables>)
     where
-        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef,
+        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc_raw(tx, entry, tables);
     }
 
     pub fn push(&self, tx: &mut TransactionOCC, entry: NewOrder, tables: &Arc<Tables>)
     where
-        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef,
+        Arc<Row<NewOrder, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push(tx, entry, tables);
     }
 
-    pub fn push_lock(&self, tx: &mut Transaction2PL, entry: NewOrder, tables: &Arc<Tables>) {
+    pub fn push_lock(
+        &self,
+        tx: &mut Transaction2PL,
+        entry: NewOrder,
+        tables: &Arc<Tables>,
+    ) -> bool {
         self.table_.push_lock(tx, entry, tables)
     }
 
@@ -193,19 +198,18 @@ impl NewOrderTable {
         let p_key = entry.primary_key();
         let idx_key = (entry.no_w_id, entry.no_d_id);
 
-        self.wd_index_.insert_index(idx_key.clone(), p_key);
+        let mut index = self.wd_index_.lock_bucket(&idx_key);
+        index.insert(idx_key, p_key, Tid::default());
 
         self.table_.push_raw(entry);
-        self.wd_index_.unlock_bucket(&idx_key);
     }
 
-    pub fn update_wd_index(&self, arc: &Arc<Row<NewOrder, (i32, i32, i32)>>) {
+    //Installed by the push of the row, see NewOrderRef
+    pub fn update_wd_index(&self, arc: &Arc<Row<NewOrder, (i32, i32, i32)>>, tid: Tid) {
         let no = arc.get_data();
         let idx_key = (no.no_w_id, no.no_d_id);
 
-        self.wd_index_
-            .insert_index(idx_key.clone(), no.primary_key());
-        self.wd_index_.unlock_bucket(&idx_key);
+        self.wd_index_.insert_index(idx_key, no.primary_key(), tid);
     }
 
     pub fn retrieve(&self, index: &(i32, i32, i32)) -> Option<Arc<Row<NewOrder, (i32, i32, i32)>>> {
@@ -229,26 +233,21 @@ impl NewOrderTable {
         index: &(i32, i32),
     ) -> Option<Arc<Row<NewOrder, (i32, i32, i32)>>> {
         let dis_num = num_district_get();
-        match self.wd_index_.find_one_bucket(index) {
-            None => {
-                self.wd_index_.unlock_bucket(index);
-                None
-            }
+        let wd_index = self.wd_index_.lock_bucket(index);
+        let ret = match wd_index.get(index) {
+            None => None,
 
             Some(vecs) => {
                 //assert_eq!(vecs.len()> 0, true);
                 if vecs.len() == 0 {
-                    self.wd_index_.unlock_bucket(index);
                     return None;
                 }
                 let min_no = vecs[0];
-                let ret = self
-                    .table_
-                    .retrieve(&min_no, (min_no.0 * dis_num + min_no.1) as usize);
-                self.wd_index_.unlock_bucket(index);
-                ret
+                self.table_
+                    .retrieve(&min_no, (min_no.0 * dis_num + min_no.1) as usize)
             }
-        }
+        };
+        ret
     }
 
     pub fn delete(
@@ -292,29 +291,26 @@ impl NewOrderTable {
             .delete_pc(tx, index, tables, bucket_idx as usize)
     }
 
-    //Holding on bucket lock
-    pub fn delete_index(&self, arc: &Arc<Row<NewOrder, (i32, i32, i32)>>) -> bool {
+    //Installed by the delete of the row, holding on bucket lock
+    pub fn delete_index(&self, arc: &Arc<Row<NewOrder, (i32, i32, i32)>>, tid: Tid) -> bool {
         let no = arc.get_data();
         let index = no.primary_key();
         let (w_id, d_id, o_id) = index;
         //update index
-        match self.wd_index_.find_one_bucket_mut(&(w_id, d_id)) {
+        let mut wd_index = self.wd_index_.lock_bucket(&(w_id, d_id));
+        if !wd_index.contains_key(&(w_id, d_id)) {
+            panic!("NewOrderTable::delete_index : missing index");
+        }
+
+        match wd_index.remove(&(w_id, d_id), |x| x.2 == o_id, tid) {
             None => {
-                panic!("NewOrderTable::delete_index : missing index");
+                warn!("delete_index:: no id {:?}", (w_id, d_id, o_id));
+                false
+            }
+            Some(removed) => {
+                assert_eq!(removed.2 == o_id, true);
+                true
             }
-            Some(v) => match v.iter().position(|&x| x.2 == o_id) {
-                None => {
-                    warn!("delete_index:: no id {:?}", (w_id, d_id, o_id));
-                    self.wd_index_.unlock_bucket(&(w_id, d_id));
-                    false
-                }
-                Some(idx) => {
-                    let removed = v.remove(idx).unwrap();
-                    assert_eq!(removed.2 == o_id, true);
-                    self.wd_index_.unlock_bucket(&(w_id, d_id));
-                    true
-                }
-            },
         }
     }
 }
@@ -333,13 +329,18 @@ unsafe impl Sync for OrderLineTable {}
 unsafe impl Send for OrderLineTable {}
 
 impl OrderLineTable {
-    pub fn new_with_buckets(num: usize, bkt_size: usize, name: &str) -> OrderLineTable {
+    pub fn new_with_layout(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        layout: PmemLayout,
+    ) -> OrderLineTable {
         let num_whs = num_warehouse_get();
         let num_dis = num_district_get();
         let total_wd = num_whs * num_dis;
 
         OrderLineTable {
-            table_: Table::new_with_buckets(num, bkt_size, name),
+            table_: Table::new_with_layout(num, bkt_size, name, layout),
             order_index_: SecIndex::new_with_buckets(
                 total_wd as usize,
                 Box::new(move |key| {
@@ -352,46 +353,49 @@ impl OrderLineTable {
 
     pub fn push_pc_raw(&self, tx: &mut TransactionParOCCRaw, entry: OrderLine, tables: &Arc<Tables>)
     where
-        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef,
+        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc_raw(tx, entry, tables);
     }
 
     pub fn push_pc(&self, tx: &mut TransactionParOCC, entry: OrderLine, tables: &Arc<Tables>)
     where
-        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef,
+        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc(tx, entry, tables);
     }
 
-    pub fn push_lock(&self, tx: &mut Transaction2PL, entry: OrderLine, tables: &Arc<Tables>) {
+    pub fn push_lock(
+        &self,
+        tx: &mut Transaction2PL,
+        entry: OrderLine,
+        tables: &Arc<Tables>,
+    ) -> bool {
         self.table_.push_lock(tx, entry, tables)
     }
 
     pub fn push(&self, tx: &mut TransactionOCC, entry: OrderLine, tables: &Arc<Tables>)
     where
-        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef,
+        Arc<Row<OrderLine, (i32, i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push(tx, entry, tables);
     }
 
     pub fn push_raw(&self, ol: OrderLine) {
         let idx_key = (ol.ol_w_id, ol.ol_d_id, ol.ol_o_id);
-        self.order_index_
-            .insert_index(idx_key.clone(), ol.primary_key());
+        let mut index = self.order_index_.lock_bucket(&idx_key);
+        index.insert(idx_key, ol.primary_key(), Tid::default());
 
         self.table_.push_raw(ol);
-        self.order_index_.unlock_bucket(&idx_key);
     }
 
-    pub fn update_order_index(&self, arc: &Arc<Row<OrderLine, (i32, i32, i32, i32)>>) {
+    //Installed by the push of the row, see OrderLineRef
+    pub fn update_order_index(&self, arc: &Arc<Row<OrderLine, (i32, i32, i32, i32)>>, tid: Tid) {
         let ol = arc.get_data();
         let idx_key = (ol.ol_w_id, ol.ol_d_id, ol.ol_o_id);
         // warn!("[-][ORDERLINE-INDEX] Updating Orderline index: {}, {}, {} => {:?}",
         //       ol.ol_w_id, ol.ol_d_id, ol.ol_o_id, ol.primary_key());
-        self.order_index_
-            .insert_index(idx_key.clone(), ol.primary_key());
-        self.order_index_.unlock_bucket(&idx_key);
+        self.order_index_.insert_index(idx_key, ol.primary_key(), tid);
     }
 
     pub fn retrieve(
@@ -418,21 +422,15 @@ impl OrderLineTable {
         &self,
         key: &(i32, i32, i32),
     ) -> Vec<Arc<Row<OrderLine, (i32, i32, i32, i32)>>> {
-        match self.order_index_.find_one_bucket(key) {
-            None => {
-                self.order_index_.unlock_bucket(key);
-                Vec::new()
-            }
-            Some(ids) => {
-                let ret = ids
-                    .iter()
-                    .filter_map(|id| self.retrieve(id))
-                    .collect::<Vec<_>>();
-
-                self.order_index_.unlock_bucket(key);
-                ret
-            }
-        }
+        let order_index = self.order_index_.lock_bucket(key);
+        let ret = match order_index.get(key) {
+            None => Vec::new(),
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| self.retrieve(id))
+                .collect::<Vec<_>>(),
+        };
+        ret
     }
 
     pub fn find_range(
@@ -445,13 +443,12 @@ impl OrderLineTable {
         let mut ids = Vec::new();
         for o_id in o_id_low..=o_id_high {
             let key = (w_id, d_id, o_id);
-            match self.order_index_.find_one_bucket(&key) {
+            match self.order_index_.lock_bucket(&key).get(&key) {
                 None => {}
                 Some(v) => {
                     ids.append(&mut v.clone().into());
                 }
             }
-            self.order_index_.unlock_bucket(&key);
         }
 
         let arcs = ids
@@ -491,12 +488,17 @@ impl OrderTable {
     //     }
     // }
 
-    pub fn new_with_buckets(num: usize, bkt_size: usize, name: &str) -> OrderTable {
+    pub fn new_with_layout(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        layout: PmemLayout,
+    ) -> OrderTable {
         let num_whs = num_warehouse_get();
         let num_dis = num_district_get();
         let total_wd = (num_whs * num_dis) as usize;
         OrderTable {
-            table_: Table::new_with_buckets(num, bkt_size, name),
+            table_: Table::new_with_layout(num, bkt_size, name, layout),
             cus_index_: SecIndex::new_with_buckets(
                 total_wd,
                 Box::new(move |key| {
@@ -508,25 +510,25 @@ impl OrderTable {
     }
     pub fn push_pc_raw(&self, tx: &mut TransactionParOCCRaw, entry: Order, tables: &Arc<Tables>)
     where
-        Arc<Row<Order, (i32, i32, i32)>>: TableRef,
+        Arc<Row<Order, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc_raw(tx, entry, tables)
     }
 
     pub fn push_pc(&self, tx: &mut TransactionParOCC, entry: Order, tables: &Arc<Tables>)
     where
-        Arc<Row<Order, (i32, i32, i32)>>: TableRef,
+        Arc<Row<Order, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push_pc(tx, entry, tables)
     }
 
-    pub fn push_lock(&self, tx: &mut Transaction2PL, entry: Order, tables: &Arc<Tables>) {
+    pub fn push_lock(&self, tx: &mut Transaction2PL, entry: Order, tables: &Arc<Tables>) -> bool {
         self.table_.push_lock(tx, entry, tables)
     }
 
     pub fn push(&self, tx: &mut TransactionOCC, entry: Order, tables: &Arc<Tables>)
     where
-        Arc<Row<Order, (i32, i32, i32)>>: TableRef,
+        Arc<Row<Order, (i32, i32, i32)>>: TableRef<Tables>,
     {
         self.table_.push(tx, entry, tables)
     }
@@ -552,19 +554,18 @@ impl OrderTable {
         let idx_val = entry.primary_key();
         let idx_key = (entry.o_w_id, entry.o_d_id, entry.o_c_id);
 
-        self.cus_index_.insert_index(idx_key.clone(), idx_val);
+        let mut index = self.cus_index_.lock_bucket(&idx_key);
+        index.insert(idx_key, idx_val, Tid::default());
 
         self.table_.push_raw(entry);
-        self.cus_index_.unlock_bucket(&idx_key);
     }
 
-    pub fn update_cus_index(&self, arc: &Arc<Row<Order, (i32, i32, i32)>>) {
+    //Installed by the push of the row, see OrderRef
+    pub fn update_cus_index(&self, arc: &Arc<Row<Order, (i32, i32, i32)>>, tid: Tid) {
         let o = arc.get_data();
         let idx_key = (o.o_w_id, o.o_d_id, o.o_c_id);
 
-        self.cus_index_
-            .insert_index(idx_key.clone(), o.primary_key());
-        self.cus_index_.unlock_bucket(&idx_key);
+        self.cus_index_.insert_index(idx_key, o.primary_key(), tid);
     }
 
     //TODO: update index?
@@ -573,11 +574,9 @@ impl OrderTable {
         key: &(i32, i32, i32),
     ) -> Option<Arc<Row<Order, (i32, i32, i32)>>> {
         //let wh_num = num_warehouse_get();
-        match self.cus_index_.find_one_bucket(key) {
-            None => {
-                self.cus_index_.unlock_bucket(key);
-                None
-            }
+        let cus_index = self.cus_index_.lock_bucket(key);
+        let ret = match cus_index.get(key) {
+            None => None,
             Some(ids) => {
                 let max_pos = ids
                     .iter()
@@ -588,9 +587,33 @@ impl OrderTable {
                 if ret.is_none() {
                     warn!("retrieve_by_cid: none {:?}", max_pos);
                 }
-                self.cus_index_.unlock_bucket(key);
                 ret
             }
-        }
+        };
+        ret
+    }
+
+    //The last order of the customer that visible() accepts,
+    //e.g. the last one a snapshot sees
+    pub fn retrieve_by_cid_where<F>(
+        &self,
+        key: &(i32, i32, i32),
+        mut visible: F,
+    ) -> Option<Arc<Row<Order, (i32, i32, i32)>>>
+    where
+        F: FnMut(&Row<Order, (i32, i32, i32)>) -> bool,
+    {
+        let cus_index = self.cus_index_.lock_bucket(key);
+        let ret = match cus_index.get(key) {
+            None => None,
+            Some(ids) => {
+                let mut ids = ids.iter().cloned().collect::<Vec<_>>();
+                ids.sort_unstable_by(|a, b| b.2.cmp(&a.2));
+                ids.iter()
+                    .filter_map(|id| self.retrieve(id))
+                    .find(|row| visible(row))
+            }
+        };
+        ret
     }
 }
@@ -115,7 +115,7 @@ fn new_order(
         tid, o_id, w_id, d_id, o_id, c_id, ol_cnt
     );
 
-    tables.order.push_lock(
+    if !tables.order.push_lock(
         tx,
         Order {
             o_id: o_id,
@@ -128,10 +128,12 @@ fn new_order(
             o_all_local: Numeric::new(all_local, 1, 0),
         },
         tables,
-    );
+    ) {
+        return Err(());
+    }
 
     info!("[{:?}][TXN-NEWORDER] push_lock NEWORDER  {:?}", tid, o_id);
-    tables.neworder.push_lock(
+    if !tables.neworder.push_lock(
         tx,
         NewOrder {
             no_o_id: o_id,
@@ -139,7 +141,9 @@ fn new_order(
             no_w_id: w_id,
         },
         tables,
-    );
+    ) {
+        return Err(());
+    }
 
     for i in 0..ol_cnt as usize {
         //let i_price = tables.item.retrieve(item_ids[i]).unwrap().read(tx).i_price;
@@ -192,7 +196,7 @@ fn new_order(
 
         //println!("{}", s_dist);
         info!("[{:?}][TXN-NEWORDER] push_lockING ORDERLINE  (w_id:{:?}, d_id:{}, o_id: {}, ol_cnt: {})", tid, w_id, d_id, o_id, i+1);
-        tables.orderline.push_lock(
+        if !tables.orderline.push_lock(
             tx,
             OrderLine {
                 ol_o_id: o_id,
@@ -207,7 +211,9 @@ fn new_order(
                 ol_dist_info: s_dist,
             },
             tables,
-        );
+        ) {
+            return Err(());
+        }
     }
 
     Ok(())
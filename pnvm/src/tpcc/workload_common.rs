@@ -1,4 +1,4 @@
-use super::{entry::*, entry_ref::*, numeric::*, table::*, tpcc_tables::*};
+use super::{entry::*, numeric::*, table::*, tpcc_tables::*};
 
 use util::Config;
 
@@ -50,42 +50,56 @@ pub fn prepare_workload(conf: &Config, rng: &mut SmallRng) -> TablesRef {
     let total_wd: usize = (num_wh * num_dis) as usize;
 
     let scale_ratio = conf.thread_num / num_wh as usize;
+    let layout = conf.pmem_layout;
     let mut tables = if conf.partition != 0 {
         let par = conf.partition;
         Tables {
-            warehouse: Table::new_with_buckets(par, conf.wh_num as usize, "warehouse"),
-            district: Table::new_with_buckets(par, num_dis as usize, "district"),
-            customer: CustomerTable::new_with_buckets(par, 4096, "customer"),
-            neworder: NewOrderTable::new_with_buckets(par, 4096 * 16 * scale_ratio, "neworder"),
-            order: OrderTable::new_with_buckets(par, 32768 * 2 * scale_ratio, "order"),
-            orderline: OrderLineTable::new_with_buckets(par, 8096 * 64 * scale_ratio, "orderline"),
-            item: Table::new_with_buckets(512, 256, "item"),
-            history: Table::new_with_buckets(par, 51200 * scale_ratio, "history"),
-            stock: Table::new_with_buckets(par, 65536 * 2, "stock"),
+            warehouse: Table::new_with_layout(par, conf.wh_num as usize, "warehouse", layout),
+            district: Table::new_with_layout(par, num_dis as usize, "district", layout),
+            customer: CustomerTable::new_with_layout(par, 4096, "customer", layout),
+            neworder: NewOrderTable::new_with_layout(
+                par,
+                4096 * 16 * scale_ratio,
+                "neworder",
+                layout,
+            ),
+            order: OrderTable::new_with_layout(par, 32768 * 2 * scale_ratio, "order", layout),
+            orderline: OrderLineTable::new_with_layout(
+                par,
+                8096 * 64 * scale_ratio,
+                "orderline",
+                layout,
+            ),
+            item: Table::new_with_layout(512, 256, "item", layout),
+            history: Table::new_with_layout(par, 51200 * scale_ratio, "history", layout),
+            stock: Table::new_with_layout(par, 65536 * 2, "stock", layout),
         }
     } else {
         Tables {
-            warehouse: Table::new_with_buckets(
+            warehouse: Table::new_with_layout(
                 total_wd as usize,
                 conf.wh_num as usize,
                 "warehouse",
+                layout,
             ),
-            district: Table::new_with_buckets(total_wd, num_dis as usize, "district"),
-            customer: CustomerTable::new_with_buckets(total_wd, 4096, "customer"),
-            neworder: NewOrderTable::new_with_buckets(
+            district: Table::new_with_layout(total_wd, num_dis as usize, "district", layout),
+            customer: CustomerTable::new_with_layout(total_wd, 4096, "customer", layout),
+            neworder: NewOrderTable::new_with_layout(
                 total_wd,
                 4096 * 16 * scale_ratio,
                 "neworder",
+                layout,
             ),
-            order: OrderTable::new_with_buckets(total_wd, 32768 * 2 * scale_ratio, "order"),
-            orderline: OrderLineTable::new_with_buckets(
+            order: OrderTable::new_with_layout(total_wd, 32768 * 2 * scale_ratio, "order", layout),
+            orderline: OrderLineTable::new_with_layout(
                 total_wd,
                 8096 * 64 * scale_ratio,
                 "orderline",
+                layout,
             ),
-            item: Table::new_with_buckets(512, 256, "item"),
-            history: Table::new_with_buckets(total_wd, 51200 * scale_ratio, "history"),
-            stock: Table::new_with_buckets(total_wd, 65536 * 2, "stock"),
+            item: Table::new_with_layout(512, 256, "item", layout),
+            history: Table::new_with_layout(total_wd, 51200 * scale_ratio, "history", layout),
+            stock: Table::new_with_layout(total_wd, 65536 * 2, "stock", layout),
         }
     };
 
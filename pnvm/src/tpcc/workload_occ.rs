@@ -367,6 +367,7 @@ fn payment(
             assert!(c_last.is_some());
             info!("[{:?}][TXN-PAYMENT] Getting by Name {:?}", tid, c_last);
             let c_last = c_last.unwrap();
+            tables.customer.retrieve_bucket(&(c_w_id, c_d_id)).scan(tx);
             match tables
                 .customer
                 .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -400,10 +401,7 @@ fn payment(
                 w_id,
                 h_amount.as_string()
             );
-            let len = new_data_str.len();
-            let new_data = new_data_str.as_bytes();
-            c.c_data.rotate_right(len);
-            c.c_data[0..len].copy_from_slice(new_data);
+            c.prepend_data(&new_data_str);
 
             c_fields.push(C_DATA);
         }
@@ -472,6 +470,7 @@ fn orderstatus(
             assert!(c_last.is_some());
             info!("[{:?}][ORDER-STATUS] Getting by Name {:?}", tid, c_last);
             let c_last = c_last.unwrap();
+            tables.customer.retrieve_bucket(&(c_w_id, c_d_id)).scan(tx);
             match tables
                 .customer
                 .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -491,7 +490,11 @@ fn orderstatus(
     //     .retrieve_by_cid(&(c_w_id, c_d_id, c_id))
     //     .expect(format!("order tempty {:?}", (c_w_id,c_d_id, c_id)).as_str())
     //     .into_table_ref(None, None);
-    let o_row = match tables.order.retrieve_by_cid(&(c_w_id, c_d_id, c_id)) {
+    tables.order.retrieve_bucket(&(c_w_id, c_d_id)).scan(tx);
+    let o_row = match tables
+        .order
+        .retrieve_by_cid_where(&(c_w_id, c_d_id, c_id), |o| tx.sees(o))
+    {
         None => {
             tx.should_abort();
             warn!("retrieve_by_cid:: corrupted");
@@ -505,6 +508,7 @@ fn orderstatus(
         "[{:?}][ORDER-STATUS] GET ORDER FROM CUSTOMER [w_d: {}-{}, o_id: {}, c_id: {}]",
         tid, c_w_id, c_d_id, o_id, c_id
     );
+    tables.orderline.retrieve_bucket(&(c_w_id, c_d_id)).scan(tx);
     let ol_arcs = tables.orderline.find_by_oid(&(c_w_id, c_d_id, o_id));
 
     for ol_arc in ol_arcs {
@@ -520,6 +524,7 @@ pub fn delivery(tx: &mut TransactionOCC, tables: &Arc<Tables>, w_id: i32, o_carr
 
     for d_id in 1..=num_dis {
         //TODO:
+        tables.neworder.retrieve_bucket(&(w_id, d_id)).scan(tx);
         let no_arc = tables.neworder.retrieve_min_oid(&(w_id, d_id));
         if no_arc.is_some() {
             let no_row = no_arc.unwrap().into_table_ref(None, None);
@@ -553,6 +558,7 @@ pub fn delivery(tx: &mut TransactionOCC, tables: &Arc<Tables>, w_id: i32, o_carr
 
             //tx.write_field(o_row, &vals, val_num);
 
+            tables.orderline.retrieve_bucket(&(w_id, d_id)).scan(tx);
             let ol_arcs = tables.orderline.find_by_oid(&(w_id, d_id, o_id));
             let now = gen_now();
             let mut ol_amount_sum = Numeric::new(0, 6, 2);
@@ -611,12 +617,17 @@ pub fn stocklevel(
     );
 
     //TODO
+    tables.orderline.retrieve_bucket(&(w_id, d_id)).scan(tx);
     let ol_arcs = tables
         .orderline
         .find_range(w_id, d_id, d_next_o_id - 20, d_next_o_id);
 
     let mut ol_i_ids = vec![];
     for ol_arc in ol_arcs {
+        /* Pushed after the snapshot */
+        if !tx.sees(&ol_arc) {
+            continue;
+        }
         let ol_row = ol_arc.into_table_ref(None, None);
         let ol = tx.read::<OrderLine>(ol_row);
         ol_i_ids.push(ol.ol_i_id);
@@ -644,3 +655,82 @@ pub fn stocklevel(
         }
     }
 }
+
+#[cfg(all(test, feature = "porder"))]
+mod tests {
+    use super::*;
+
+    use pnvm_lib::{flush::CopyStrategy, persistency::PersistencyModel, storage::PmemLayout};
+    use rand::SeedableRng;
+
+    fn small_config() -> Config {
+        Config {
+            thread_num: 1,
+            obj_num: 0,
+            set_size: 0,
+            round_num: 0,
+            zipf_coeff: 0.0,
+            test_name: String::from("TPCC_OCC"),
+            cfl_pc_num: 0,
+            cfl_txn_num: 0,
+            pc_num: 0,
+            duration: 0,
+            wh_num: 1,
+            d_num: 1,
+            no_warmup: true,
+            warmup_time: 0,
+            partition: 0,
+            pmem_layout: PmemLayout::from_name("packed").unwrap(),
+            persist_domain: None,
+            copy_strategy: CopyStrategy::from_name("pmdk").unwrap(),
+            persistency: PersistencyModel::from_name("epoch").unwrap(),
+            pmem_fac_mb: 0,
+            ycsb_sampler: String::from("None"),
+            ycsb_num_rows: 0,
+            ycsb_rw_ratio: 0.5,
+            ycsb_mode: String::new(),
+            ycsb_ops_per_iter: 0,
+            ycsb_txn_num_ops: 0,
+        }
+    }
+
+    //The c_data of a BC customer is a new payload: flushed with
+    //the row, but never logged
+    #[test]
+    fn test_payment_bc_porder() {
+        PmemFac::init();
+        let conf = small_config();
+        let mut rng = SmallRng::seed_from_u64(7);
+        let tables = prepare_workload(&conf, &mut rng);
+
+        let c_id = (1..=NUM_INIT_CUSTOMER)
+            .find(|c_id| {
+                let row = tables.customer.retrieve(&(1, 1, *c_id)).unwrap();
+                &row.get_data().c_credit == b"BC"
+            })
+            .expect("no BC customer");
+
+        for _ in 0..2 {
+            let tx = &mut TransactionOCC::new(TidFac::get_thd_next());
+            payment(
+                tx,
+                &tables,
+                1,
+                1,
+                1,
+                1,
+                None,
+                Some(c_id),
+                Numeric::new(1000, 6, 2),
+                gen_now(),
+                &mut rng,
+            );
+            assert!(tx.try_commit());
+        }
+
+        let row = tables.customer.retrieve(&(1, 1, c_id)).unwrap();
+        let c_data = row.get_data().c_data.as_str().unwrap().to_string();
+        let payment = format!("|{},1,1,1,1,10|", c_id);
+        assert!(c_data.starts_with(&payment.repeat(2)), "{}", c_data);
+    }
+}
@@ -341,6 +341,10 @@ pub fn do_pc_stocklevel(
     while {
         let tid = tx.id().clone();
 
+        tables
+            .orderline
+            .retrieve_bucket(&(w_id, d_id))
+            .scan_pc_raw(tx);
         let ol_arcs = tables
             .orderline
             .find_range(w_id, d_id, d_next_o_id - 20, d_next_o_id);
@@ -410,6 +414,10 @@ pub fn do_pc_delivery(
     let mut no_o_id_arr = Vec::with_capacity(num_dis as usize);
     while {
         for d_id in 1..=num_dis {
+            tables
+                .neworder
+                .retrieve_bucket(&(w_id, d_id))
+                .scan_pc_raw(tx);
             let no_arc = tables.neworder.retrieve_min_oid(&(w_id, d_id));
             if no_arc.is_some() {
                 let no_row = no_arc.unwrap().into_table_ref(None, None);
@@ -460,6 +468,10 @@ pub fn do_pc_delivery(
                     //tx.write(o_row, o);
                     tx.write_field(o_row, o, vec![O_CARRIER_ID]);
 
+                    tables
+                        .orderline
+                        .retrieve_bucket(&(w_id, d_id))
+                        .scan_pc_raw(tx);
                     let ol_arcs = tables.orderline.find_by_oid(&(w_id, d_id, o_id));
                     let now = gen_now();
                     let mut ol_amount_sum = Numeric::new(0, 6, 2);
@@ -562,6 +574,10 @@ pub fn do_pc_orderstatus(
                 assert!(c_last.is_some());
                 info!("[{:?}][ORDER-STATUS] Getting by Name {:?}", tid, c_last);
                 let c_last = c_last.unwrap();
+                tables
+                    .customer
+                    .retrieve_bucket(&(c_w_id, c_d_id))
+                    .scan_pc_raw(tx);
                 match tables
                     .customer
                     .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -576,6 +592,10 @@ pub fn do_pc_orderstatus(
         };
 
         let c_id = tx.read::<Customer>(c_row).c_id;
+        tables
+            .order
+            .retrieve_bucket(&(c_w_id, c_d_id))
+            .scan_pc_raw(tx);
         let o_row = match tables.order.retrieve_by_cid(&(c_w_id, c_d_id, c_id)) {
             None => {
                 warn!("retrieve_by_cid:: corrupted");
@@ -590,6 +610,10 @@ pub fn do_pc_orderstatus(
             tid, c_w_id, c_d_id, o_id, c_id
         );
 
+        tables
+            .orderline
+            .retrieve_bucket(&(c_w_id, c_d_id))
+            .scan_pc_raw(tx);
         let ol_arcs = tables.orderline.find_by_oid(&(c_w_id, c_d_id, o_id));
 
         for ol_arc in ol_arcs {
@@ -757,6 +781,10 @@ pub fn do_pc_payment(
                 assert!(c_last.is_some());
                 info!("[{:?}][TXN-PAYMENT] Getting by Name {:?}", tid, c_last);
                 let c_last = c_last.unwrap();
+                tables
+                    .customer
+                    .retrieve_bucket(&(c_w_id, c_d_id))
+                    .scan_pc_raw(tx);
                 match tables
                     .customer
                     .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -790,10 +818,7 @@ pub fn do_pc_payment(
                     w_id,
                     h_amount.as_string()
                 );
-                let len = new_data_str.len();
-                let new_data = new_data_str.as_bytes();
-                c.c_data.rotate_right(len);
-                c.c_data[0..len].copy_from_slice(new_data);
+                c.prepend_data(&new_data_str);
                 c_fields.push(C_DATA);
             }
             _ => {}
@@ -830,12 +830,17 @@ pub fn pc_stocklevel_base(
         let d_next_o_id = *tx.get_output::<i32>(0);
         let tid = tx.id().clone();
 
+        tables.orderline.retrieve_bucket(&(w_id, d_id)).scan_pc(tx);
         let ol_arcs = tables
             .orderline
             .find_range(w_id, d_id, d_next_o_id - 20, d_next_o_id);
 
         let mut ol_i_ids = vec![];
         for ol_arc in ol_arcs {
+            /* Pushed after the snapshot, or by a pending txn */
+            if !tx.sees(&ol_arc) {
+                continue;
+            }
             let ol_row = ol_arc.into_table_ref(None, None);
             let ol = tx.read::<OrderLine>(ol_row);
             ol_i_ids.push(ol.ol_i_id);
@@ -882,7 +887,7 @@ pub fn pc_stocklevel_base(
 
     let pieces = vec![p2, p1];
 
-    TransactionParBaseOCC::new(pieces, String::from("stocklevel"))
+    TransactionParBaseOCC::new_snapshot(pieces, String::from("stocklevel"))
 }
 
 /*   ********************************
@@ -911,6 +916,7 @@ pub fn pc_delivery_base(
         let tid = tx.id().clone();
         let mut no_o_id_arr = Vec::with_capacity(num_dis as usize);
         for d_id in 1..=num_dis {
+            tables.neworder.retrieve_bucket(&(w_id, d_id)).scan_pc(tx);
             let no_arc = tables.neworder.retrieve_min_oid(&(w_id, d_id));
             if no_arc.is_some() {
                 let no_row = no_arc.unwrap().into_table_ref(None, None);
@@ -964,6 +970,7 @@ pub fn pc_delivery_base(
                     //tx.write(o_row, o);
                     tx.write_field(o_row, o, vec![O_CARRIER_ID]);
 
+                    tables.orderline.retrieve_bucket(&(w_id, d_id)).scan_pc(tx);
                     let ol_arcs = tables.orderline.find_by_oid(&(w_id, d_id, o_id));
                     let now = gen_now();
                     let mut ol_amount_sum = Numeric::new(0, 6, 2);
@@ -1082,6 +1089,10 @@ pub fn pc_orderstatus_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
                 assert!(c_last.is_some());
                 info!("[{:?}][ORDER-STATUS] Getting by Name {:?}", tid, c_last);
                 let c_last = c_last.unwrap();
+                tables
+                    .customer
+                    .retrieve_bucket(&(c_w_id, c_d_id))
+                    .scan_pc(tx);
                 match tables
                     .customer
                     .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -1097,7 +1108,11 @@ pub fn pc_orderstatus_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
         };
 
         let c_id = tx.read::<Customer>(c_row).c_id;
-        let o_row = match tables.order.retrieve_by_cid(&(c_w_id, c_d_id, c_id)) {
+        tables.order.retrieve_bucket(&(c_w_id, c_d_id)).scan_pc(tx);
+        let o_row = match tables
+            .order
+            .retrieve_by_cid_where(&(c_w_id, c_d_id, c_id), |o| tx.sees(o))
+        {
             None => {
                 tx.should_abort();
                 warn!("retrieve_by_cid:: corrupted");
@@ -1112,6 +1127,10 @@ pub fn pc_orderstatus_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
             tid, c_w_id, c_d_id, o_id, c_id
         );
 
+        tables
+            .orderline
+            .retrieve_bucket(&(c_w_id, c_d_id))
+            .scan_pc(tx);
         let ol_arcs = tables.orderline.find_by_oid(&(c_w_id, c_d_id, o_id));
 
         for ol_arc in ol_arcs {
@@ -1130,7 +1149,7 @@ pub fn pc_orderstatus_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
 
     let pieces = vec![p1];
 
-    TransactionParBaseOCC::new(pieces, String::from("orderstatus"))
+    TransactionParBaseOCC::new_snapshot(pieces, String::from("orderstatus"))
 }
 
 /*   ********************************
@@ -1293,6 +1312,10 @@ pub fn pc_payment_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
                 assert!(c_last.is_some());
                 info!("[{:?}][TXN-PAYMENT] Getting by Name {:?}", tid, c_last);
                 let c_last = c_last.unwrap();
+                tables
+                    .customer
+                    .retrieve_bucket(&(c_w_id, c_d_id))
+                    .scan_pc(tx);
                 match tables
                     .customer
                     .find_by_name_id(&(c_last.clone(), c_w_id, c_d_id))
@@ -1327,10 +1350,7 @@ pub fn pc_payment_base(_tables: &Arc<Tables>) -> TransactionParBaseOCC {
                     w_id,
                     h_amount.as_string()
                 );
-                let len = new_data_str.len();
-                let new_data = new_data_str.as_bytes();
-                c.c_data.rotate_right(len);
-                c.c_data[0..len].copy_from_slice(new_data);
+                c.prepend_data(&new_data_str);
                 c_fields.push(C_DATA);
             }
             _ => {}
@@ -26,8 +26,11 @@ use ycsb::generator::{YCSBConfig, YCSBMode, YCSBSampler};
 
 use pnvm_lib::{
     datatype::tbox::*,
+    flush::{CopyStrategy, PersistDomain},
     occ::{map::*, occ_txn::*},
     parnvm::{map::*, nvm_txn_2pl::*, nvm_txn_occ::*, piece::*},
+    persistency::PersistencyModel,
+    storage::PmemLayout,
     tcore::*,
     txn::*,
 };
@@ -535,6 +538,13 @@ pub struct Config {
     pub no_warmup: bool,
     pub warmup_time: u64,
     pub partition: usize,
+    pub pmem_layout: PmemLayout,
+    //None to detect it
+    pub persist_domain: Option<PersistDomain>,
+    pub copy_strategy: CopyStrategy,
+    pub persistency: PersistencyModel,
+    //First PmemFac extent of a thread, in MiB, 0 for the default
+    pub pmem_fac_mb: usize,
     //pub no_conflict: bool,
     pub ycsb_sampler: String,
     pub ycsb_num_rows: usize,
@@ -570,6 +580,31 @@ pub fn read_env() -> Config {
         no_warmup: settings.get_bool("NO_WARMUP").unwrap(),
         partition: settings.get_int("PARTITION").unwrap_or(0) as usize,
         warmup_time: settings.get_int("WARMUP_TIME").unwrap_or(10) as u64,
+        pmem_layout: PmemLayout::from_name(
+            &settings
+                .get_str("PMEM_LAYOUT")
+                .unwrap_or(String::from("packed")),
+        )
+        .expect("Unknown pmem layout"),
+        persist_domain: parse_persist_domain(
+            &settings
+                .get_str("PERSIST_DOMAIN")
+                .unwrap_or(String::from("auto")),
+        ),
+        copy_strategy: CopyStrategy::from_name(
+            &settings
+                .get_str("COPY_STRATEGY")
+                .unwrap_or(String::from("pmdk")),
+        )
+        .expect("Unknown copy strategy"),
+        persistency: PersistencyModel::parse(
+            &settings
+                .get_str("PERSISTENCY")
+                .unwrap_or(String::from("epoch")),
+            is_pipelined(&settings.get_str("TEST_NAME").unwrap()),
+        )
+        .unwrap_or_else(|err| panic!("{}", err)),
+        pmem_fac_mb: settings.get_int("PMEM_FAC_MB").unwrap_or(0) as usize,
 
         //YCSB Config
         ycsb_num_rows: settings.get_int("YCSB_NUM_ROWS").unwrap_or(0) as usize,
@@ -583,6 +618,23 @@ pub fn read_env() -> Config {
     }
 }
 
+//Whether the test runs TransactionParOCC(Raw)
+pub fn is_pipelined(test_name: &str) -> bool {
+    match test_name {
+        "PNVM_OCC" | "TPCC_NVM" | "NO_NVM" | "TPCC_PC_RAW" | "NO_PC_RAW" => true,
+        _ => false,
+    }
+}
+
+pub fn parse_persist_domain(name: &str) -> Option<PersistDomain> {
+    match name {
+        "auto" => None,
+        "adr" => Some(PersistDomain::Adr),
+        "eadr" => Some(PersistDomain::Eadr),
+        _ => panic!("Unknown persistence domain"),
+    }
+}
+
 pub fn parse_ycsb_config(config: &Config) -> YCSBConfig {
     let sampler = match config.ycsb_sampler.as_ref() {
         "Uniform" => YCSBSampler::Uniform(config.ycsb_num_rows),
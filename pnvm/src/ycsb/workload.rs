@@ -27,7 +27,7 @@ use core::alloc::Layout;
 use pnvm_sys::Layout;
 
 //FIXME: reusing code from TPCC
-use tpcc::table::{Key, Row};
+use pnvm_lib::storage::{FieldOffset, Key, Row};
 
 const YCSB_FIELD_LEN: usize = 100;
 
@@ -45,7 +45,9 @@ impl Key<isize> for YCSBEntry {
     fn bucket_key(&self) -> usize {
         panic!("bucket_key not implemented for YCSBEntry");
     }
+}
 
+impl FieldOffset for YCSBEntry {
     fn field_offset(&self) -> [isize; 32] {
         [-1; 32]
     }
@@ -165,6 +167,11 @@ impl TRef for YCSBRef {
         self.inner_.get_data()
     }
 
+    #[cfg(feature = "mvcc")]
+    fn read_at(&self, ts: usize) -> Option<Arc<Any>> {
+        Some(self.inner_.get_data_at(ts) as Arc<Any>)
+    }
+
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
     fn write(&mut self, ptr: *mut u8) {
         self.pd_ptr = ptr as *mut YCSBEntry;
@@ -40,6 +40,11 @@ def run():
     command = ["../target/release/pnvm_bench"]
     sys_env = dict(os.environ)
 
+    # The copy modes are native (pnvm_sys/src/copy.rs), the
+    # PMEM_NO_* variables only change what the pmdk mode does
+    print("PMDK")
+    run_exp(sys_env, "pmdk", command, out_fd)
+
     # # Test simple clflush
     # config_env = {
     #     "PMEM_NO_CLWB" : str(1),
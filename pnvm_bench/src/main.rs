@@ -182,7 +182,7 @@ fn do_memcpy(bench: &Arc<Bench>, thd_idx: usize) {
         let (src, dest) = get_copy_addr(bench, i, thd_idx);
 
         match bench.copy_mode {
-            BenchCopyMode::PMDKNoDrain(_) =>  memcpy_nodrain(dest,src, chunk_size),
+            BenchCopyMode::Copy(strategy) => strategy.copy_nodrain(dest, src, chunk_size),
             BenchCopyMode::Simple => unsafe {
                 src.copy_to(dest, chunk_size)
             },
@@ -200,7 +200,7 @@ fn do_flush_freq(bench: &Arc<Bench>,  thd_idx: usize, data: &[u8])
         let (src, dest) = get_copy_addr(bench,i, thd_idx);
 
         match bench.copy_mode {
-            BenchCopyMode::PMDKNoDrain(_) => {
+            BenchCopyMode::Copy(strategy) => {
                 unsafe {ptr::copy(data.as_ptr(), src, chunk_size)};
                 records.push((src, dest));
                 cnt += 1;
@@ -208,7 +208,7 @@ fn do_flush_freq(bench: &Arc<Bench>,  thd_idx: usize, data: &[u8])
                 if cnt == bench.flush_freq {
                     for j in i-cnt+1..=i {
                         let (src, dest) = records[i];
-                        memcpy_nodrain(dest, src, chunk_size);
+                        strategy.copy_nodrain(dest, src, chunk_size);
                         if bench.rd_after_w {
                             for i in 0..chunk_size {
                                 unsafe {
@@ -279,18 +279,14 @@ fn prep_bench(config: &Config) -> Bench {
         }).collect();
 
     
+    /* movnt-clwb, mov-clflushopt... or pmdk, see pnvm_sys::copy */
     let copy_mode = match config.mode.as_ref() {
-        "movnt-clflush" 
-            | "movnt-empty" 
-            | "movnt-clwb"
-            | "movnt-clflushopt"
-            | "mov-empty" 
-            | "mov-clwb"
-            | "mov-clflushopt"
-            | "mov-clflush"
-            => BenchCopyMode::PMDKNoDrain(config.mode.clone()),
         "simple" =>BenchCopyMode::Simple,
-        _ => panic!("Unknown copy mode"),
+        mode => match CopyStrategy::from_name(mode) {
+            Some(strategy) if strategy.is_supported() => BenchCopyMode::Copy(strategy),
+            Some(_) => panic!("Copy mode not supported by this CPU"),
+            None => panic!("Unknown copy mode"),
+        },
     };
 
    let bench =  Bench {
@@ -354,7 +350,7 @@ unsafe impl Send for Bench{}
 #[derive(Clone, Debug)]
 enum BenchCopyMode {
     Simple,
-    PMDKNoDrain(String)
+    Copy(CopyStrategy)
 }
 
 
@@ -0,0 +1,757 @@
+//************************************************
+//Derive for the TPCC table entries
+//
+//#[derive(TableEntry)] on an entry X generates:
+//- XRef              the TRef of a row of X
+//- TableRef          for Arc<Row<X, Key>>
+//- BucketPushRef     with `push`
+//- BucketDeleteRef   with `delete`
+//- FieldOffset       from the #[field] annotations, with the
+//                    hot_range of a #[repr(C)] entry
+//
+//Entry attributes, #[entry(...)]:
+//- key = "(i32, i32)"      primary key type of X
+//- table = "district"      field of Tables holding X, also the TRef name
+//- push                    rows can be pushed into a bucket
+//- push_index = "f"        table.f(&row, tid) is called after a push
+//- delete                  rows can be deleted from a bucket
+//- delete_index = "f"      the row is only removed from the bucket
+//                          if table.f(&row, tid) returns true
+//- read_only               X is never written (e.g. Item)
+//- tables = "path"         context type of the pushes and deletes,
+//                          ::tpcc::tpcc_tables::Tables by default
+//
+//A #[field] can be logged on its own, see Row::get_field_ptr.
+//Its index is the declaration order, as the X_YYY consts of
+//tpcc/entry.rs.
+//
+//A #[varlen] field is a VarLen, whose out of line payload is
+//logged and persisted with the row, see storage::varlen.
+//
+//The storage types are taken from pnvm_lib::storage, the
+//crate must depend on pnvm_lib.
+//************************************************
+
+#![recursion_limit = "256"]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::{
+    parse::{Error, Result},
+    spanned::Spanned,
+    Data, DeriveInput, Field, Fields, Ident, Lit, Meta, NestedMeta, Type,
+};
+
+//Size of the offset table of Row
+const MAX_FIELDS: usize = 32;
+
+#[proc_macro_derive(TableEntry, attributes(entry, field, varlen))]
+pub fn derive_table_entry(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = syn::parse(input).expect("TableEntry: not a struct definition");
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct EntryAttrs {
+    key_: Type,
+    table_: Ident,
+    tables_: Type,
+    push_: bool,
+    push_index_: Option<Ident>,
+    delete_: bool,
+    delete_index_: Option<Ident>,
+    read_only_: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let attrs = parse_attrs(input)?;
+    let fields = named_fields(input)?;
+
+    let field_offset = expand_field_offset(input, &fields)?;
+    let entry_ref = expand_ref(input, &attrs);
+
+    Ok(quote! {
+        #field_offset
+        #entry_ref
+    })
+}
+
+fn parse_attrs(input: &DeriveInput) -> Result<EntryAttrs> {
+    let mut key = None;
+    let mut table = None;
+    let mut tables = None;
+    let mut push = false;
+    let mut push_index = None;
+    let mut delete = false;
+    let mut delete_index = None;
+    let mut read_only = false;
+
+    for attr in &input.attrs {
+        let list = match attr.interpret_meta() {
+            Some(Meta::List(ref list)) if list.ident == "entry" => list.clone(),
+            Some(ref meta) if meta.name() == "entry" => {
+                return Err(Error::new(attr.span(), "expected #[entry(...)]"))
+            }
+            _ => continue,
+        };
+
+        for nested in list.nested.iter() {
+            match *nested {
+                NestedMeta::Meta(Meta::Word(ref word)) if word == "push" => push = true,
+                NestedMeta::Meta(Meta::Word(ref word)) if word == "delete" => delete = true,
+                NestedMeta::Meta(Meta::Word(ref word)) if word == "read_only" => read_only = true,
+                NestedMeta::Meta(Meta::NameValue(ref nv)) => {
+                    let val = match nv.lit {
+                        Lit::Str(ref val) => val,
+                        _ => return Err(Error::new(nv.lit.span(), "expected a string")),
+                    };
+
+                    if nv.ident == "key" {
+                        key = Some(val.parse::<Type>()?);
+                    } else if nv.ident == "table" {
+                        table = Some(val.parse::<Ident>()?);
+                    } else if nv.ident == "tables" {
+                        tables = Some(val.parse::<Type>()?);
+                    } else if nv.ident == "push_index" {
+                        push_index = Some(val.parse::<Ident>()?);
+                    } else if nv.ident == "delete_index" {
+                        delete_index = Some(val.parse::<Ident>()?);
+                    } else {
+                        return Err(Error::new(nv.ident.span(), "unknown entry attribute"));
+                    }
+                }
+                _ => return Err(Error::new(nested.span(), "unknown entry attribute")),
+            }
+        }
+    }
+
+    let key = key.ok_or_else(|| Error::new(input.ident.span(), "missing #[entry(key = ...)]"))?;
+    let table =
+        table.ok_or_else(|| Error::new(input.ident.span(), "missing #[entry(table = ...)]"))?;
+
+    let tables = match tables {
+        Some(tables) => tables,
+        None => syn::parse_str::<Type>("::tpcc::tpcc_tables::Tables")?,
+    };
+
+    if read_only && (push || delete) {
+        return Err(Error::new(
+            input.ident.span(),
+            "a read_only entry cannot be pushed or deleted",
+        ));
+    }
+
+    Ok(EntryAttrs {
+        key_: key,
+        table_: table,
+        tables_: tables,
+        push_: push,
+        push_index_: push_index,
+        delete_: delete,
+        delete_index_: delete_index,
+        read_only_: read_only,
+    })
+}
+
+fn named_fields(input: &DeriveInput) -> Result<Vec<Field>> {
+    match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => Ok(fields.named.iter().cloned().collect()),
+            _ => Err(Error::new(
+                input.ident.span(),
+                "TableEntry needs a struct with named fields",
+            )),
+        },
+        _ => Err(Error::new(input.ident.span(), "TableEntry needs a struct")),
+    }
+}
+
+fn has_word_attr(field: &Field, name: &str) -> bool {
+    field.attrs.iter().any(|attr| match attr.interpret_meta() {
+        Some(Meta::Word(ref word)) => word == name,
+        _ => false,
+    })
+}
+
+fn is_tracked(field: &Field) -> bool {
+    has_word_attr(field, "field")
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter().any(|attr| match attr.interpret_meta() {
+        Some(Meta::List(ref list)) if list.ident == "repr" => {
+            list.nested.iter().any(|n| match *n {
+                NestedMeta::Meta(Meta::Word(ref word)) => word == "C",
+                _ => false,
+            })
+        }
+        _ => false,
+    })
+}
+
+//FieldOffset::hot_range, from the #[repr(C)] layout rules: each
+//field is at the end of the previous one, rounded up to its own
+//alignment. It spans from the first #[field] to the end of the last.
+fn expand_hot_range(input: &DeriveInput, fields: &[Field]) -> TokenStream2 {
+    if !is_repr_c(input) {
+        return quote! {};
+    }
+
+    let steps: Vec<TokenStream2> = fields
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            let track = if is_tracked(field) {
+                quote! {
+                    hot = Some((hot.map_or(off, |(start, _)| start), off + ::std::mem::size_of::<#ty>()));
+                }
+            } else {
+                quote! {}
+            };
+
+            quote! {
+                let align = ::std::mem::align_of::<#ty>();
+                off = (off + align - 1) / align * align;
+                #track
+                off += ::std::mem::size_of::<#ty>();
+            }
+        })
+        .collect();
+
+    quote! {
+        fn hot_range() -> Option<(usize, usize)> {
+            let mut off: usize = 0;
+            let mut hot: Option<(usize, usize)> = None;
+            #(#steps)*
+
+            let _ = off;
+            hot
+        }
+    }
+}
+
+//FieldOffset::var_fields, the index of a #[varlen] is only
+//given if it is also a #[field]
+fn expand_var_fields(fields: &[Field]) -> TokenStream2 {
+    let vars: Vec<TokenStream2> = fields
+        .iter()
+        .enumerate()
+        .filter(|&(_, field)| has_word_attr(field, "varlen"))
+        .map(|(i, field)| {
+            let ident = field.ident.as_ref().expect("named field");
+            let idx = if is_tracked(field) {
+                quote! { Some(#i) }
+            } else {
+                quote! { None }
+            };
+            quote! { (#idx, self.#ident) }
+        })
+        .collect();
+
+    if vars.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        fn var_fields(&self) -> Vec<(Option<usize>, ::pnvm_lib::storage::VarLen)> {
+            vec![#(#vars),*]
+        }
+    }
+}
+
+//fields[i] is the byte offset of the ith field. The end of a tracked
+//field is stored at i+1 when the next one is not tracked, so that
+//get_field_size(i) = fields[i+1] - fields[i] always holds.
+fn expand_field_offset(input: &DeriveInput, fields: &[Field]) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let tracked: Vec<bool> = fields.iter().map(is_tracked).collect();
+    let var_fields = expand_var_fields(fields);
+    let hot_range = expand_hot_range(input, fields);
+
+    if !tracked.iter().any(|t| *t) {
+        return Ok(quote! {
+            impl ::pnvm_lib::storage::FieldOffset for #name {
+                fn field_offset(&self) -> [isize; 32] {
+                    [-1; 32]
+                }
+
+                #var_fields
+            }
+        });
+    }
+
+    let mut offsets = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if !tracked[i] {
+            continue;
+        }
+
+        if i + 1 >= MAX_FIELDS {
+            return Err(Error::new(
+                field.span(),
+                format!("only the first {} fields can be tracked", MAX_FIELDS - 1),
+            ));
+        }
+
+        let ident = field.ident.as_ref().expect("named field");
+        offsets.push(quote! {
+            fields[#i] = (&self.#ident as *const _ as *const u8).wrapping_offset_from(base);
+        });
+
+        if !tracked.get(i + 1).cloned().unwrap_or(false) {
+            let end = i + 1;
+            offsets.push(quote! {
+                fields[#end] = fields[#i] + ::std::mem::size_of_val(&self.#ident) as isize;
+            });
+        }
+    }
+
+    Ok(quote! {
+        impl ::pnvm_lib::storage::FieldOffset for #name {
+            fn field_offset(&self) -> [isize; 32] {
+                let mut fields: [isize; 32] = [-1; 32];
+                let base: *const u8 = self as *const _ as *const u8;
+                #(#offsets)*
+
+                fields
+            }
+
+            #hot_range
+
+            #var_fields
+        }
+    })
+}
+
+fn expand_ref(input: &DeriveInput, attrs: &EntryAttrs) -> TokenStream2 {
+    let tables = &attrs.tables_;
+    let name = &input.ident;
+    let ref_name = Ident::new(&format!("{}Ref", name), Span::call_site());
+    let key = &attrs.key_;
+    let table = &attrs.table_;
+
+    let trait_impl = if attrs.read_only_ {
+        expand_read_only_impl(input, attrs)
+    } else {
+        expand_read_write_impl(input, attrs)
+    };
+
+    let push_impl = if attrs.push_ {
+        quote! {
+            impl ::pnvm_lib::storage::BucketPushRef<#tables> for ::std::sync::Arc<::pnvm_lib::storage::Row<#name, #key>> {
+                fn into_push_table_ref(
+                    self,
+                    bucket_idx: usize,
+                    table_ref: ::std::sync::Arc<#tables>,
+                ) -> Box<::pnvm_lib::tcore::TRef> {
+                    Box::new(#ref_name::new(
+                        self,
+                        Some(bucket_idx),
+                        Some(table_ref),
+                        ::pnvm_lib::tcore::Operation::Push,
+                        None,
+                    ))
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let delete_impl = if attrs.delete_ {
+        quote! {
+            impl ::pnvm_lib::storage::BucketDeleteRef<#tables> for ::std::sync::Arc<::pnvm_lib::storage::Row<#name, #key>> {
+                fn into_delete_table_ref(
+                    self,
+                    bucket_idx: usize,
+                    table_ref: ::std::sync::Arc<#tables>,
+                    deleter: ::std::sync::Arc<::pnvm_lib::txn::TxnInfo>,
+                ) -> Box<::pnvm_lib::tcore::TRef> {
+                    Box::new(#ref_name::new(
+                        self,
+                        Some(bucket_idx),
+                        Some(table_ref),
+                        ::pnvm_lib::tcore::Operation::Delete,
+                        Some(deleter),
+                    ))
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #[derive(Clone, Debug)]
+        pub struct #ref_name {
+            inner_: ::std::sync::Arc<::pnvm_lib::storage::Row<#name, #key>>,
+            bucket_idx_: Option<usize>,
+            table_ref_: Option<::std::sync::Arc<#tables>>,
+            data_: Option<Box<#name>>,
+            ops_: ::pnvm_lib::tcore::Operation,
+            //The txn of a Delete
+            deleter_: Option<::std::sync::Arc<::pnvm_lib::txn::TxnInfo>>,
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            pd_ptr: *mut #name,
+        }
+
+        impl #ref_name {
+            fn new(
+                inner: ::std::sync::Arc<::pnvm_lib::storage::Row<#name, #key>>,
+                bucket_idx: Option<usize>,
+                table_ref: Option<::std::sync::Arc<#tables>>,
+                ops: ::pnvm_lib::tcore::Operation,
+                deleter: Option<::std::sync::Arc<::pnvm_lib::txn::TxnInfo>>,
+            ) -> #ref_name {
+                #ref_name {
+                    inner_: inner,
+                    bucket_idx_: bucket_idx,
+                    table_ref_: table_ref,
+                    data_: None,
+                    ops_: ops,
+                    deleter_: deleter,
+
+                    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+                    pd_ptr: ::std::ptr::null_mut(),
+                }
+            }
+
+            //Only Push and Delete carry the tables
+            fn table(&self) -> &#tables {
+                self.table_ref_.as_ref().expect("no table ref")
+            }
+
+            fn bucket(&self) -> &::pnvm_lib::storage::Bucket<#name, #key> {
+                self.table()
+                    .#table
+                    .get_bucket(self.bucket_idx_.expect("no bucket idx"))
+            }
+        }
+
+        impl ::pnvm_lib::storage::TableRef<#tables> for ::std::sync::Arc<::pnvm_lib::storage::Row<#name, #key>> {
+            fn into_table_ref(
+                self,
+                bucket_idx: Option<usize>,
+                table_ref: Option<::std::sync::Arc<#tables>>,
+            ) -> Box<::pnvm_lib::tcore::TRef> {
+                Box::new(#ref_name::new(
+                    self,
+                    bucket_idx,
+                    table_ref,
+                    ::pnvm_lib::tcore::Operation::RWrite,
+                    None,
+                ))
+            }
+        }
+
+        #push_impl
+        #delete_impl
+        #trait_impl
+    }
+}
+
+//Methods which only forward to the row
+fn expand_row_methods(attrs: &EntryAttrs) -> TokenStream2 {
+    let table_name = attrs.table_.to_string();
+
+    quote! {
+        fn get_ptr(&self) -> *mut u8 {
+            self.inner_.get_ptr()
+        }
+
+        #[cfg(not(any(feature = "pmem", feature = "disk")))]
+        fn get_layout(&self) -> ::core::alloc::Layout {
+            self.inner_.get_layout()
+        }
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        fn get_layout(&self) -> ::pnvm_sys::Layout {
+            self.inner_.get_layout()
+        }
+
+        fn get_field_ptr(&self, field_idx: usize) -> *mut u8 {
+            self.inner_.get_field_ptr(field_idx)
+        }
+
+        fn get_field_size(&self, field_idx: usize) -> usize {
+            self.inner_.get_field_size(field_idx)
+        }
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        fn get_pmem_addr(&self) -> *mut u8 {
+            self.inner_.get_pmem_addr() as *mut u8
+        }
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        fn get_pmem_field_addr(&self, field_idx: usize) -> *mut u8 {
+            self.inner_.get_pmem_field_addr(field_idx) as *mut u8
+        }
+
+        fn box_clone(&self) -> Box<::pnvm_lib::tcore::TRef> {
+            Box::new(self.clone())
+        }
+
+        fn get_id(&self) -> &::pnvm_lib::tcore::ObjectId {
+            self.inner_.get_id()
+        }
+
+        fn get_tvers(&self) -> &::std::sync::Arc<::pnvm_lib::tcore::TVersion> {
+            &self.inner_.vers_
+        }
+
+        fn get_version(&self) -> u32 {
+            self.inner_.get_version()
+        }
+
+        fn read(&self) -> &::std::any::Any {
+            self.inner_.get_data()
+        }
+
+        #[cfg(feature = "mvcc")]
+        fn read_at(&self, ts: usize) -> Option<::std::sync::Arc<::std::any::Any>> {
+            Some(self.inner_.get_data_at(ts) as ::std::sync::Arc<::std::any::Any>)
+        }
+
+        fn get_access_info(&self) -> ::std::sync::Arc<::pnvm_lib::txn::TxnInfo> {
+            self.inner_.get_access_info()
+        }
+
+        fn get_name(&self) -> String {
+            String::from(#table_name)
+        }
+
+        fn get_var_fields(&self) -> Vec<(Option<usize>, ::pnvm_lib::storage::VarLen)> {
+            self.inner_.get_var_fields()
+        }
+    }
+}
+
+fn expand_read_write_impl(input: &DeriveInput, attrs: &EntryAttrs) -> TokenStream2 {
+    let name = &input.ident;
+    let ref_name = Ident::new(&format!("{}Ref", name), Span::call_site());
+    let table = &attrs.table_;
+    let row_methods = expand_row_methods(attrs);
+    let write_err = format!("{}::write value should be Box<{}>", ref_name, name);
+    let write_through_err = format!("runtime value should be {}", name);
+
+    let push_index = match attrs.push_index_ {
+        Some(ref index) => quote! {
+            self.table().#table.#index(&row, id);
+        },
+        None => quote! {},
+    };
+
+    let delete_row = match attrs.delete_index_ {
+        Some(ref index) => quote! {
+            //FIXME: hack so double delete allowed
+            if self.table().#table.#index(&row, id) {
+                bucket.delete(row, deleter);
+            }
+        },
+        None => quote! {
+            bucket.delete(row, deleter);
+        },
+    };
+
+    quote! {
+        impl ::pnvm_lib::tcore::TRef for #ref_name {
+            #row_methods
+
+            fn install(&self, id: ::pnvm_lib::txn::Tid) {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => {
+                        #[cfg(all(feature = "pmem", feature = "wdrain"))]
+                        {
+                            if !self.pd_ptr.is_null() {
+                                self.inner_.install_ptr(self.pd_ptr, id);
+                            } else {
+                                panic!("pd_ptr should not be null at write");
+                            }
+                        }
+
+                        #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+                        self.inner_.install_val(self.data_.as_ref().expect("no data"), id);
+                    }
+                    ::pnvm_lib::tcore::Operation::Push => {
+                        //Scanners of the bucket fail their check
+                        let bucket = self.bucket();
+                        let row = self.inner_.clone();
+                        bucket.set_version(id.into());
+                        bucket.push(row.clone());
+                        #push_index
+                    }
+                    ::pnvm_lib::tcore::Operation::Delete => {
+                        let bucket = self.bucket();
+                        let row = self.inner_.clone();
+                        let deleter = self.deleter_.clone().expect("no deleter");
+                        bucket.set_version(id.into());
+                        #delete_row
+                    }
+                }
+            }
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            fn write(&mut self, ptr: *mut u8) {
+                self.pd_ptr = ptr as *mut #name;
+            }
+
+            #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+            fn write(&mut self, val: Box<::std::any::Any>) {
+                match val.downcast::<#name>() {
+                    Ok(val) => self.data_ = Some(val),
+                    Err(_) => panic!(#write_err),
+                }
+            }
+
+            fn lock(&self, tid: ::pnvm_lib::txn::Tid) -> bool {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => self.inner_.lock(tid),
+                    ::pnvm_lib::tcore::Operation::Push => self.bucket().lock(tid),
+                    //Lock both the deleted entry and the bucket, a
+                    //deleted entry fails and releases the bucket
+                    ::pnvm_lib::tcore::Operation::Delete => {
+                        if !self.bucket().lock(tid) {
+                            return false;
+                        }
+                        if !self.inner_.lock(tid) {
+                            self.bucket().unlock();
+                            return false;
+                        }
+                        true
+                    }
+                }
+            }
+
+            fn unlock(&self) {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => self.inner_.unlock(),
+                    ::pnvm_lib::tcore::Operation::Push => self.bucket().unlock(),
+                    ::pnvm_lib::tcore::Operation::Delete => {
+                        self.inner_.unlock();
+                        self.bucket().unlock();
+                    }
+                }
+            }
+
+            //Pushes and deletes are checked with the bucket lock
+            fn check(&self, vers: u32, tid: u32) -> bool {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => self.inner_.check(vers, tid),
+                    _ => true,
+                }
+            }
+
+            fn set_access_info(&mut self, txn_info: ::std::sync::Arc<::pnvm_lib::txn::TxnInfo>) {
+                self.inner_.set_access_info(txn_info);
+            }
+
+            /* For 2 Phase Locking */
+            fn write_through(&self, val: Box<::std::any::Any>, tid: ::pnvm_lib::txn::Tid) {
+                match val.downcast::<#name>() {
+                    Ok(val) => self.inner_.install_val(&val, tid),
+                    Err(_) => panic!(#write_through_err),
+                }
+            }
+
+            fn read_lock(&self, tid: u32) -> bool {
+                self.inner_.vers_.read_lock(tid)
+            }
+
+            fn read_unlock(&self, tid: u32) {
+                self.inner_.vers_.read_unlock(tid)
+            }
+
+            fn write_lock(&self, tid: u32) -> bool {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => self.inner_.vers_.write_lock(tid),
+                    ::pnvm_lib::tcore::Operation::Push => self.bucket().vers_.write_lock(tid),
+                    ::pnvm_lib::tcore::Operation::Delete => {
+                        self.bucket().vers_.write_lock(tid) && self.inner_.vers_.write_lock(tid)
+                    }
+                }
+            }
+
+            fn write_unlock(&self, tid: u32) {
+                match self.ops_ {
+                    ::pnvm_lib::tcore::Operation::RWrite => self.inner_.vers_.write_unlock(tid),
+                    ::pnvm_lib::tcore::Operation::Push => self.bucket().vers_.write_unlock(tid),
+                    ::pnvm_lib::tcore::Operation::Delete => {
+                        self.inner_.vers_.write_unlock(tid);
+                        self.bucket().vers_.write_unlock(tid);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//Read-only entries are loaded once and never locked nor validated
+fn expand_read_only_impl(input: &DeriveInput, attrs: &EntryAttrs) -> TokenStream2 {
+    let ref_name = Ident::new(&format!("{}Ref", input.ident), Span::call_site());
+    let row_methods = expand_row_methods(attrs);
+    let read_only_err = format!("{} is read only", input.ident);
+
+    quote! {
+        impl ::pnvm_lib::tcore::TRef for #ref_name {
+            #row_methods
+
+            fn install(&self, id: ::pnvm_lib::txn::Tid) {
+                panic!(#read_only_err);
+            }
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            fn write(&mut self, ptr: *mut u8) {
+                panic!(#read_only_err);
+            }
+
+            #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+            fn write(&mut self, val: Box<::std::any::Any>) {
+                panic!(#read_only_err);
+            }
+
+            fn lock(&self, tid: ::pnvm_lib::txn::Tid) -> bool {
+                panic!(#read_only_err);
+            }
+
+            fn unlock(&self) {
+                panic!(#read_only_err);
+            }
+
+            fn check(&self, vers: u32, tid: u32) -> bool {
+                true
+            }
+
+            fn set_access_info(&mut self, txn_info: ::std::sync::Arc<::pnvm_lib::txn::TxnInfo>) {}
+
+            /* For 2 Phase Locking */
+            fn write_through(&self, val: Box<::std::any::Any>, tid: ::pnvm_lib::txn::Tid) {
+                panic!(#read_only_err);
+            }
+
+            fn read_lock(&self, tid: u32) -> bool {
+                true
+            }
+
+            fn read_unlock(&self, tid: u32) {}
+
+            /* Never granted, so never released */
+            fn write_lock(&self, tid: u32) -> bool {
+                false
+            }
+
+            fn write_unlock(&self, tid: u32) {
+                panic!(#read_only_err);
+            }
+        }
+    }
+}
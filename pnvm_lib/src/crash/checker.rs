@@ -0,0 +1,231 @@
+//************************************************
+//Durable linearizability checker
+//
+//The recovered state must equal the replay, in the serial
+//order of the history, of the recovered transactions. They
+//must include:
+//- every transaction acknowledged as persisted
+//- the WW/WR dependencies of every recovered transaction
+//
+//Recovered but unacknowledged transactions were in flight
+//at the crash, and may or may not be part of the prefix.
+//************************************************
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use history::{
+    checker::{self as history_checker, Cycle},
+    recorder::TxnRecord,
+};
+use tcore::ObjectId;
+
+//What the workload did, independently of the pmem content
+pub struct Execution {
+    pub records_: Vec<TxnRecord>,
+    pub initial_: HashMap<ObjectId, Vec<u8>>,
+    pub writes_:  HashMap<u32, Vec<(ObjectId, Vec<u8>)>>, /* Values installed by each txn */
+}
+
+#[derive(Debug)]
+pub enum Violation {
+    Lost(u32),                            /* Acknowledged but not recovered */
+    MissingDep(u32, u32),                 /* (txn, dependency) recovered without the dependency */
+    Diverged(ObjectId, Vec<u8>, Vec<u8>), /* (object, expected, recovered) */
+    NotSerializable(Vec<Cycle>),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Lost(tid) => write!(f, "[LOST] {} acknowledged but not recovered", tid),
+            Violation::MissingDep(tid, dep) => write!(
+                f,
+                "[MISSING DEP] {} recovered without its dependency {}",
+                tid, dep
+            ),
+            Violation::Diverged(oid, expect, found) => write!(
+                f,
+                "[DIVERGED] {:?}: EXPECT: {:?}, BUT: {:?}",
+                oid, expect, found
+            ),
+            Violation::NotSerializable(cycles) => {
+                write!(f, "[NOT SERIALIZABLE] {} cycles", cycles.len())
+            }
+        }
+    }
+}
+
+//State after replaying the prefix in serial order
+pub fn replay(
+    exec: &Execution,
+    order: &[u32],
+    prefix: &HashSet<u32>,
+) -> HashMap<ObjectId, Vec<u8>> {
+    let mut state = exec.initial_.clone();
+    for tid in order.iter().filter(|tid| prefix.contains(tid)) {
+        if let Some(writes) = exec.writes_.get(tid) {
+            for (oid, val) in writes.iter() {
+                state.insert(*oid, val.clone());
+            }
+        }
+    }
+    state
+}
+
+pub fn check_durable(
+    exec: &Execution,
+    acked: &[u32],
+    recovered: &[u32],
+    contents: &HashMap<ObjectId, Vec<u8>>,
+) -> Result<(), Vec<Violation>> {
+    let order = match history_checker::serial_order(&exec.records_) {
+        Ok(order) => order,
+        Err(cycles) => return Err(vec![Violation::NotSerializable(cycles)]),
+    };
+
+    let mut violations = vec![];
+    let prefix: HashSet<u32> = recovered.iter().cloned().collect();
+
+    for tid in acked.iter() {
+        if !prefix.contains(tid) {
+            violations.push(Violation::Lost(*tid));
+        }
+    }
+
+    let deps = history_checker::write_deps(&exec.records_);
+    for tid in recovered.iter() {
+        for dep in deps.get(tid).map(|d| d.as_slice()).unwrap_or(&[]) {
+            if !prefix.contains(dep) {
+                violations.push(Violation::MissingDep(*tid, *dep));
+            }
+        }
+    }
+
+    let expect = replay(exec, &order, &prefix);
+    for (oid, val) in expect.iter() {
+        match contents.get(oid) {
+            Some(found) if found == val => {}
+            Some(found) => violations.push(Violation::Diverged(*oid, val.clone(), found.clone())),
+            None => violations.push(Violation::Diverged(*oid, val.clone(), vec![])),
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        for violation in violations.iter() {
+            warn!("check_durable::{}", violation);
+        }
+        Err(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcore::OidFac;
+
+    fn record(
+        tid: u32,
+        seq: usize,
+        reads: Vec<(ObjectId, u32)>,
+        writes: Vec<(ObjectId, u32)>,
+    ) -> TxnRecord {
+        TxnRecord {
+            tid_:    tid,
+            seq_:    seq,
+            reads_:  reads,
+            writes_: writes,
+        }
+    }
+
+    /* T1 writes x, T2 reads x and writes y */
+    fn execution(x: ObjectId, y: ObjectId) -> Execution {
+        let mut initial = HashMap::new();
+        initial.insert(x, vec![0]);
+        initial.insert(y, vec![0]);
+
+        let mut writes = HashMap::new();
+        writes.insert(1, vec![(x, vec![1])]);
+        writes.insert(2, vec![(y, vec![2])]);
+
+        Execution {
+            records_: vec![
+                record(1, 1, vec![], vec![(x, 0)]),
+                record(2, 2, vec![(x, 1)], vec![(y, 0)]),
+            ],
+            initial_: initial,
+            writes_:  writes,
+        }
+    }
+
+    fn contents(x: ObjectId, y: ObjectId, vx: u8, vy: u8) -> HashMap<ObjectId, Vec<u8>> {
+        let mut contents = HashMap::new();
+        contents.insert(x, vec![vx]);
+        contents.insert(y, vec![vy]);
+        contents
+    }
+
+    #[test]
+    fn test_prefix_recovered() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+        let exec = execution(x, y);
+
+        let state = contents(x, y, 0, 0);
+        assert_eq!(check_durable(&exec, &[], &[], &state).is_ok(), true);
+
+        /* T2 in flight */
+        let state = contents(x, y, 1, 0);
+        assert_eq!(check_durable(&exec, &[1], &[1], &state).is_ok(), true);
+
+        let state = contents(x, y, 1, 2);
+        assert_eq!(check_durable(&exec, &[1], &[1, 2], &state).is_ok(), true);
+        assert_eq!(check_durable(&exec, &[1, 2], &[1, 2], &state).is_ok(), true);
+    }
+
+    #[test]
+    fn test_lost_ack_detected() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+        let exec = execution(x, y);
+
+        let violations = check_durable(&exec, &[1, 2], &[1], &contents(x, y, 1, 0)).unwrap_err();
+        assert_eq!(violations.len(), 1);
+        match violations[0] {
+            Violation::Lost(2) => {}
+            ref v => panic!("unexpected violation {}", v),
+        }
+    }
+
+    #[test]
+    fn test_missing_dep_detected() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+        let exec = execution(x, y);
+
+        let violations = check_durable(&exec, &[2], &[2], &contents(x, y, 0, 2)).unwrap_err();
+        match violations[0] {
+            Violation::MissingDep(2, 1) => {}
+            ref v => panic!("unexpected violation {}", v),
+        }
+    }
+
+    #[test]
+    fn test_torn_state_detected() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+        let exec = execution(x, y);
+
+        /* T2 not recovered but its write leaked */
+        let violations = check_durable(&exec, &[1], &[1], &contents(x, y, 1, 2)).unwrap_err();
+        assert_eq!(violations.len(), 1);
+        match violations[0] {
+            Violation::Diverged(oid, _, _) => assert_eq!(oid, y),
+            ref v => panic!("unexpected violation {}", v),
+        }
+    }
+}
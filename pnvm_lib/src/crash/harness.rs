@@ -0,0 +1,536 @@
+//************************************************
+//Crash simulation harness
+//
+//Runs a small workload over cells kept in emulated pmem.
+//The device snapshots itself at random crash points; every
+//snapshot is recovered and checked against the history.
+//
+//Each transaction reads a cell and writes another one with
+//a value derived from its tid and the value read, so that
+//reads create dependencies between transactions.
+//
+//The run sets its persistency model: under strand, the
+//recovered transactions need not include the readers of
+//what they overwrote.
+//
+//PPNVMAsync commits the pipelined txns with commit_async,
+//and acknowledges them from their durability callbacks.
+//************************************************
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    mem,
+    sync::mpsc,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use core::alloc::Layout;
+
+use super::{
+    checker::{self, Execution, Violation},
+    recovery,
+};
+use history::recorder::HistoryRecorder;
+use occ::occ_txn::TransactionOCC;
+use parnvm::{
+    nvm_txn_occ::{TransactionParBaseOCC, TransactionParOCC},
+    piece::{Pid, PieceOCC},
+};
+use persistency::{self, PersistencyModel};
+use pnvm_sys::{self, emul};
+use tcore::{BoxRef, ObjectId, OidFac, TRef, TValue, TVersion};
+use txn::{Tid, TidFac, Transaction, TxState, TxnInfo};
+
+const CELL_SIZE: usize = 64; /* One cell per cache line */
+
+lazy_static! {
+    /* The device and the history are global, one run at a time */
+    pub static ref RUN_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Protocol {
+    OCC,
+    PPNVM,
+    PPNVMAsync,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CrashConfig {
+    pub protocol_:    Protocol,
+    pub persistency_: PersistencyModel,
+    pub thread_num_:  usize,
+    pub txn_num_:     usize, /* Per thread */
+    pub cell_num_:    usize,
+    pub crash_rate_:  usize, /* Crash at one in crash_rate_ pmem events */
+}
+
+#[derive(Debug)]
+pub struct CrashCell {
+    tvalue_: TValue<u64>,
+    vers_:   Arc<TVersion>,
+    id_:     ObjectId,
+    pmem_:   *mut u8,
+}
+
+unsafe impl Sync for CrashCell {}
+unsafe impl Send for CrashCell {}
+
+impl CrashCell {
+    pub fn new(val: u64, pmem: *mut u8) -> Arc<CrashCell> {
+        let cell = CrashCell {
+            tvalue_: TValue::new(val),
+            vers_:   Arc::new(TVersion::default()),
+            id_:     OidFac::get_obj_next(),
+            pmem_:   pmem,
+        };
+        pnvm_sys::memcpy_persist(
+            pmem,
+            cell.tvalue_.get_ptr() as *mut u8,
+            mem::size_of::<u64>(),
+        );
+        Arc::new(cell)
+    }
+
+    pub fn install(&self, val: &u64, tid: Tid) {
+        self.tvalue_.store(*val);
+        self.vers_.set_version(tid.into());
+    }
+}
+
+impl BoxRef<u64> for Arc<CrashCell> {
+    fn into_box_ref(self) -> Box<dyn TRef> {
+        Box::new(CellRef {
+            inner_: self,
+            data_:  None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CellRef {
+    inner_: Arc<CrashCell>,
+    data_:  Option<Box<u64>>,
+}
+
+impl TRef for CellRef {
+    fn install(&self, id: Tid) {
+        match self.data_ {
+            Some(ref val) => self.inner_.install(val, id),
+            None => panic!("only write should get installed"),
+        }
+    }
+
+    fn get_pmem_addr(&self) -> *mut u8 {
+        self.inner_.pmem_
+    }
+
+    fn get_ptr(&self) -> *mut u8 {
+        self.inner_.tvalue_.get_ptr() as *mut u8
+    }
+
+    fn get_layout(&self) -> Layout {
+        Layout::new::<u64>()
+    }
+
+    fn box_clone(&self) -> Box<dyn TRef> {
+        Box::new(CellRef {
+            inner_: self.inner_.clone(),
+            data_:  self.data_.clone(),
+        })
+    }
+
+    fn get_id(&self) -> &ObjectId {
+        &self.inner_.id_
+    }
+
+    fn get_tvers(&self) -> &Arc<TVersion> {
+        &self.inner_.vers_
+    }
+
+    fn get_version(&self) -> u32 {
+        self.inner_.vers_.get_version()
+    }
+
+    //Unused
+    fn get_field_ptr(&self, _i: usize) -> *mut u8 {
+        panic!("crash cell has no fields")
+    }
+    fn get_field_size(&self, _i: usize) -> usize {
+        panic!("crash cell has no fields")
+    }
+    fn get_pmem_field_addr(&self, _i: usize) -> *mut u8 {
+        panic!("crash cell has no fields")
+    }
+
+    fn read(&self) -> &Any {
+        self.inner_.tvalue_.load()
+    }
+
+    fn write(&mut self, val: Box<Any>) {
+        match val.downcast::<u64>() {
+            Ok(val) => self.data_ = Some(val),
+            Err(_) => panic!("runtime value should be u64"),
+        }
+    }
+
+    fn write_through(&self, val: Box<Any>, tid: Tid) {
+        match val.downcast::<u64>() {
+            Ok(val) => self.inner_.install(&val, tid),
+            Err(_) => panic!("runtime value should be u64 at write_through"),
+        }
+    }
+
+    fn lock(&self, tid: Tid) -> bool {
+        self.inner_.vers_.lock(tid)
+    }
+
+    fn unlock(&self) {
+        self.inner_.vers_.unlock()
+    }
+
+    fn check(&self, vers: u32, tid: u32) -> bool {
+        self.inner_.vers_.check_version(vers, tid)
+    }
+
+    fn set_access_info(&mut self, txn_info: Arc<TxnInfo>) {
+        self.inner_.vers_.set_access_info(txn_info);
+    }
+
+    fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.inner_.vers_.get_access_info()
+    }
+
+    fn get_name(&self) -> String {
+        String::from("crash-cell")
+    }
+
+    /* For 2 Phase Locking */
+    fn read_lock(&self, tid: u32) -> bool {
+        self.inner_.vers_.read_lock(tid)
+    }
+
+    fn read_unlock(&self, tid: u32) {
+        self.inner_.vers_.read_unlock(tid)
+    }
+
+    fn write_lock(&self, tid: u32) -> bool {
+        self.inner_.vers_.write_lock(tid)
+    }
+
+    fn write_unlock(&self, tid: u32) {
+        self.inner_.vers_.write_unlock(tid)
+    }
+}
+
+type Writes = Vec<(u32, Vec<(ObjectId, Vec<u8>)>)>;
+
+fn as_bytes(val: &u64) -> Vec<u8> {
+    let ptr = val as *const u64 as *const u8;
+    unsafe { ::std::slice::from_raw_parts(ptr, mem::size_of::<u64>()) }.to_vec()
+}
+
+fn next_value(tid: Tid, read: u64) -> u64 {
+    let tid: u32 = tid.into();
+    ((tid as u64) << 32) | (read & 0xffff_ffff)
+}
+
+/* xorshift, the workload only needs to be spread out */
+fn next_rand(seed: &mut u64) -> usize {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed as usize
+}
+
+/* Distinct cells: (write, read) for each piece. Piece i only
+ * uses the i-th part of the cells, as the pieces of a rank
+ * access their own tables: across ranks, conflicts could make
+ * the pieces wait on each other */
+fn pick_cells(seed: &mut u64, cell_num: usize, pc_num: usize) -> Vec<(usize, usize)> {
+    let part = cell_num / pc_num;
+    (0..pc_num)
+        .map(|pc| {
+            let w = next_rand(seed) % part;
+            let mut r = next_rand(seed) % part;
+            while r == w {
+                r = next_rand(seed) % part;
+            }
+            (pc * part + w, pc * part + r)
+        })
+        .collect()
+}
+
+fn run_occ(cells: &[Arc<CrashCell>], conf: &CrashConfig, seed: &mut u64) -> Writes {
+    let mut writes = vec![];
+    for _ in 0..conf.txn_num_ {
+        let (w, r) = pick_cells(seed, conf.cell_num_, 1)[0];
+        loop {
+            let tid = TidFac::get_thd_next();
+            let mut tx = TransactionOCC::new(tid);
+            let read = *tx.read::<u64>(cells[r].clone().into_box_ref());
+            let val = next_value(tid, read);
+            tx.write(cells[w].clone().into_box_ref(), val);
+
+            if tx.try_commit() {
+                emul::ack(tid.into());
+                writes.push((tid.into(), vec![(cells[w].id_, as_bytes(&val))]));
+                break;
+            }
+        }
+    }
+    writes
+}
+
+fn make_pc_base(cells: Arc<Vec<Arc<CrashCell>>>) -> TransactionParBaseOCC {
+    let mut pieces = vec![];
+    for pc_idx in 0..2 {
+        let cells = cells.clone();
+        let cb = move |tx: &mut TransactionParOCC| {
+            let (w, r) = tx.get_input::<Vec<(usize, usize)>>()[pc_idx];
+            let read = *tx.read::<u64>(cells[r].clone().into_box_ref());
+            let val = next_value(*tx.id(), read);
+            tx.write(cells[w].clone().into_box_ref(), val);
+            tx.add_output(Box::new((cells[w].id_, val)), pc_idx);
+        };
+
+        pieces.push(PieceOCC::new(
+            Pid::new(pc_idx as u32),
+            String::from("crash"),
+            Arc::new(Box::new(cb)),
+            "crash-rw",
+            pc_idx + 1,
+        ));
+    }
+
+    /* Pieces are popped from the back */
+    pieces.reverse();
+    TransactionParBaseOCC::new(pieces, String::from("crash"))
+}
+
+fn run_ppnvm(
+    base: &TransactionParBaseOCC,
+    conf: &CrashConfig,
+    seed: &mut u64,
+    async_commit: bool,
+) -> Writes {
+    let mut writes = vec![];
+    let (acked, acks) = mpsc::channel();
+    let mut pending = 0;
+
+    for _ in 0..conf.txn_num_ {
+        let tid = TidFac::get_thd_next();
+        let inputs = pick_cells(seed, conf.cell_num_, 2);
+        let mut tx = TransactionParOCC::new_from_base(base, tid, Box::new(inputs));
+
+        let committed = if async_commit {
+            match tx.execute_txn_async() {
+                Some(handle) => {
+                    let acked = acked.clone();
+                    handle.on_durable(move || {
+                        emul::ack(tid.into());
+                        acked.send(()).unwrap();
+                    });
+                    pending += 1;
+                    true
+                }
+                None => false,
+            }
+        } else {
+            tx.execute_txn();
+            let persisted = *tx.status() == TxState::PERSIST;
+            if persisted {
+                emul::ack(tid.into());
+            }
+            persisted
+        };
+
+        if committed {
+            let txn_writes = (0..2)
+                .map(|idx| {
+                    let (oid, val) = *tx.get_output::<(ObjectId, u64)>(idx);
+                    (oid, as_bytes(&val))
+                })
+                .collect();
+            writes.push((tid.into(), txn_writes));
+        }
+    }
+
+    /* Acknowledged within this run */
+    for _ in 0..pending {
+        acks.recv().unwrap();
+    }
+    writes
+}
+
+//Run the workload, then recover and check every crash image.
+//Returns the number of images checked.
+pub fn run(conf: &CrashConfig) -> Result<usize, Vec<Violation>> {
+    let _guard = RUN_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    persistency::set_persistency_model(conf.persistency_);
+
+    /* Initial load */
+    let pmem_len = conf.cell_num_ * CELL_SIZE;
+    let pmem = pnvm_sys::mmap_file(String::from("crash"), pmem_len);
+    let cells: Arc<Vec<Arc<CrashCell>>> = Arc::new(
+        (0..conf.cell_num_)
+            .map(|i| CrashCell::new(i as u64, unsafe { pmem.add(i * CELL_SIZE) }))
+            .collect(),
+    );
+    let initial: HashMap<ObjectId, Vec<u8>> = cells
+        .iter()
+        .map(|c| (c.id_, as_bytes(c.tvalue_.load())))
+        .collect();
+
+    HistoryRecorder::take_all();
+    emul::checkpoint();
+    emul::arm(conf.crash_rate_);
+
+    let base = make_pc_base(cells.clone());
+    let mut handles = vec![];
+    for i in 0..conf.thread_num_ {
+        let cells = cells.clone();
+        let base = base.clone();
+        let protocol = conf.protocol_;
+        let conf = *conf;
+
+        let handle = thread::Builder::new()
+            .name(format!("crash-{}", i))
+            .spawn(move || {
+                TidFac::set_thd_mask(i as u32 + 1);
+                let mut seed = 0x9e37_79b9_7f4a_7c15 ^ (i as u64 + 1);
+
+                let writes = match protocol {
+                    Protocol::OCC => run_occ(&cells, &conf, &mut seed),
+                    Protocol::PPNVM => run_ppnvm(&base, &conf, &mut seed, false),
+                    Protocol::PPNVMAsync => run_ppnvm(&base, &conf, &mut seed, true),
+                };
+
+                HistoryRecorder::flush_thd();
+                writes
+            })
+            .unwrap();
+        handles.push(handle);
+    }
+
+    let mut writes = HashMap::new();
+    for handle in handles {
+        writes.extend(handle.join().unwrap());
+    }
+
+    emul::arm(0);
+    let mut images = emul::take_images();
+    images.push(emul::crash());
+
+    let exec = Execution {
+        records_: HistoryRecorder::take_all(),
+        initial_: initial,
+        writes_:  writes,
+    };
+
+    let mut violations = vec![];
+    for image in images.iter_mut() {
+        let recovered = recovery::recover(image);
+        let contents: HashMap<ObjectId, Vec<u8>> = cells
+            .iter()
+            .map(|c| (c.id_, image.read(c.pmem_, mem::size_of::<u64>()).to_vec()))
+            .collect();
+
+        if let Err(mut v) = checker::check_durable(&exec, image.acks(), &recovered, &contents) {
+            warn!("run::crash at event {} not recovered", image.event());
+            violations.append(&mut v);
+        }
+    }
+
+    pnvm_sys::unmap(pmem, pmem_len);
+
+    if violations.is_empty() {
+        Ok(images.len())
+    } else {
+        Err(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occ_crash_recovery() {
+        let conf = CrashConfig {
+            protocol_:    Protocol::OCC,
+            persistency_: PersistencyModel::Epoch,
+            thread_num_:  4,
+            txn_num_:     100,
+            cell_num_:    8,
+            crash_rate_:  16,
+        };
+
+        let checked = run(&conf).unwrap();
+        assert_eq!(checked > 1, true);
+    }
+
+    #[test]
+    fn test_ppnvm_crash_recovery() {
+        let conf = CrashConfig {
+            protocol_:    Protocol::PPNVM,
+            persistency_: PersistencyModel::Epoch,
+            thread_num_:  4,
+            txn_num_:     100,
+            cell_num_:    8,
+            crash_rate_:  16,
+        };
+
+        let checked = run(&conf).unwrap();
+        assert_eq!(checked > 1, true);
+    }
+
+    #[test]
+    fn test_crash_recovery_strict() {
+        for protocol in &[Protocol::OCC, Protocol::PPNVM] {
+            let conf = CrashConfig {
+                protocol_:    *protocol,
+                persistency_: PersistencyModel::Strict,
+                thread_num_:  4,
+                txn_num_:     50,
+                cell_num_:    8,
+                crash_rate_:  16,
+            };
+
+            let checked = run(&conf).unwrap();
+            assert_eq!(checked > 1, true);
+        }
+    }
+
+    #[test]
+    fn test_ppnvm_crash_recovery_strand() {
+        let conf = CrashConfig {
+            protocol_:    Protocol::PPNVM,
+            persistency_: PersistencyModel::Strand,
+            thread_num_:  4,
+            txn_num_:     100,
+            cell_num_:    8,
+            crash_rate_:  16,
+        };
+
+        let checked = run(&conf).unwrap();
+        assert_eq!(checked > 1, true);
+    }
+
+    #[test]
+    fn test_ppnvm_crash_recovery_async() {
+        let conf = CrashConfig {
+            protocol_:    Protocol::PPNVMAsync,
+            persistency_: PersistencyModel::Epoch,
+            thread_num_:  4,
+            txn_num_:     100,
+            cell_num_:    8,
+            crash_rate_:  16,
+        };
+
+        let checked = run(&conf).unwrap();
+        assert_eq!(checked > 1, true);
+    }
+}
@@ -0,0 +1,24 @@
+//************************************************
+//Crash simulation
+//
+//Checks durable linearizability on the emulated pmem of
+//pnvm_sys: after a crash, the recovered state must be the
+//state after a prefix of the history that contains every
+//transaction acknowledged as persisted.
+//
+//Only built with the "crash" feature, which runs the pmem
+//data path on the emulated device and records the history.
+//
+//Modules:
+//- recovery    undo the transactions without commit record,
+//              and tell the live rows of the tables
+//- checker     compare a recovered state with the history
+//- harness     workload, crash points and the tests
+//************************************************
+
+#[cfg(any(feature = "dir", feature = "wdrain", feature = "disk"))]
+compile_error!("crash simulation only covers the in place pmem data path");
+
+pub mod checker;
+pub mod harness;
+pub mod recovery;
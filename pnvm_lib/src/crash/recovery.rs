@@ -0,0 +1,77 @@
+//************************************************
+//Log based recovery
+//
+//Data entries of the log carry the image of an object,
+//or of one of its fields, before the write was installed
+//(see TTag::make_log), the object's pmem address and the
+//offset of the field. A transaction is durable once its
+//commit record is in the log.
+//
+//Recovery keeps the durable transactions, and undoes the
+//others by copying their before images back to pmem,
+//latest first.
+//
+//Slot records (plog::persist_slots) tell which pmem rows
+//of the tables were pushed or deleted. slot_states replays
+//those of the durable transactions, in log order: the
+//device has a single log, and a slot is only reused once
+//its deleter is durable.
+//************************************************
+
+use std::collections::{HashMap, HashSet};
+
+use plog;
+use pnvm_sys::emul::PmemImage;
+
+//Recover the image in place. Returns the durable transactions,
+//in the order of their commit records.
+pub fn recover(image: &mut PmemImage) -> Vec<u32> {
+    let entries = plog::decode_log(image.log());
+
+    let durable: Vec<u32> = entries
+        .iter()
+        .filter(|e| e.is_txn())
+        .map(|e| e.txn_id_)
+        .collect();
+    let durable_set: HashSet<u32> = durable.iter().cloned().collect();
+
+    for entry in entries.iter().rev() {
+        if entry.is_txn() || entry.is_slot() || durable_set.contains(&entry.txn_id_) {
+            continue;
+        }
+
+        if entry.pmem_addr_ == 0 {
+            warn!(
+                "recover::[{}] log entry without pmem address skipped",
+                entry.txn_id_
+            );
+            continue;
+        }
+
+        image.write(
+            (entry.pmem_addr_ + entry.offset_) as *const u8,
+            &entry.data_,
+        );
+    }
+
+    durable
+}
+
+//Whether the row in each slot logged is live, by pmem
+//address: false for a row deleted by a durable txn
+pub fn slot_states(image: &PmemImage) -> HashMap<usize, bool> {
+    let entries = plog::decode_log(image.log());
+    let durable: HashSet<u32> = entries
+        .iter()
+        .filter(|e| e.is_txn())
+        .map(|e| e.txn_id_)
+        .collect();
+
+    let mut states = HashMap::new();
+    for entry in entries.iter() {
+        if entry.is_slot() && durable.contains(&entry.txn_id_) {
+            states.insert(entry.pmem_addr_, entry.is_live());
+        }
+    }
+    states
+}
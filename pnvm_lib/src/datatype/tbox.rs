@@ -1,11 +1,12 @@
 use txn::{Tid, TxnInfo};
 
-#[cfg(all(feature = "pmem", feature = "wdrain"))]
-use txn::PmemFac;
+#[cfg(feature = "pmem")]
+use {pnvm_sys, txn::PmemFac};
 
 //use std::cell::RefCell;
 use std::{
     any::Any,
+    fmt::Debug,
     mem,
     ptr,
     //rc::Rc,
@@ -23,7 +24,7 @@ use flame;
 
 use crossbeam::sync::ArcCell;
 use tcore;
-use tcore::{BoxRef, ObjectId, TRef, TValue, TVersion};
+use tcore::{BoxRef, ObjectId, OidFac, TRef, TValue, TVersion};
 
 #[derive(Debug)]
 pub struct TBox<T>
@@ -33,6 +34,9 @@ where
     tvalue_: TValue<T>,
     vers_:   Arc<TVersion>,
     id_:     ObjectId,
+
+    #[cfg(feature = "pmem")]
+    pmem_:   *mut u8, /* Persistent home of the value */
 }
 
 //impl<T> _TObject<T> for TBox<T>
@@ -55,6 +59,8 @@ where
     #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
     pub fn install(&self, val: &T, tid: Tid) {
         self.tvalue_.store(T::clone(val));
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        self.copy_to_pmem();
         self.vers_.set_version(tid.into());
     }
 
@@ -62,9 +68,20 @@ where
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
     pub fn install(&self, ptr: *mut T, tid: Tid) {
         self.tvalue_.store(ptr);
+        #[cfg(feature = "dir")]
+        self.copy_to_pmem();
         self.vers_.set_version(tid.into());
     }
 
+    //Direct writes go to the pmem home at install, the
+    //persist path then only flushes it
+    #[cfg(all(feature = "pmem", feature = "dir"))]
+    fn copy_to_pmem(&self) {
+        unsafe {
+            ptr::copy_nonoverlapping(self.get_ptr(), self.pmem_, mem::size_of::<T>());
+        }
+    }
+
     #[inline(always)]
     pub fn unlock(&self) {
         self.vers_.unlock();
@@ -76,6 +93,11 @@ where
         self.tvalue_.load()
     }
 
+    #[cfg(feature = "mvcc")]
+    pub fn get_data_at(&self, ts: usize) -> Arc<T> {
+        self.tvalue_.load_at(ts)
+    }
+
     #[cfg_attr(feature = "profile", flame)]
     #[inline(always)]
     pub fn get_id(&self) -> &ObjectId {
@@ -92,6 +114,12 @@ where
         self.tvalue_.get_ptr() as *mut u8
     }
 
+    #[cfg(feature = "pmem")]
+    #[inline(always)]
+    pub fn get_pmem_addr(&self) -> *mut u8 {
+        self.pmem_
+    }
+
     pub fn get_layout(&self) -> Layout {
         Layout::new::<T>()
     }
@@ -128,27 +156,27 @@ where
     T: Clone,
 {
     pub fn new(val: T) -> Arc<TBox<T>> {
-        let id;
-        unsafe {
-            id = tcore::next_id();
-        }
-        Arc::new(TBox {
-            tvalue_: TValue::new(val),
-            id_:     id,
-            vers_:   Arc::new(TVersion::default()),
-        })
+        Arc::new(TBox::new_default(val))
     }
 
+    //With "pmem", the value gets a home from the PmemFac of the thread
     pub fn new_default(val: T) -> TBox<T> {
-        let id;
-        unsafe {
-            id = tcore::next_id();
-        }
+        let tvalue = TValue::new(val);
+
+        #[cfg(feature = "pmem")]
+        let pmem = {
+            let size = mem::size_of::<T>();
+            let pmem = PmemFac::alloc(size);
+            pnvm_sys::memcpy_persist(pmem, tvalue.get_ptr() as *mut u8, size);
+            pmem
+        };
 
         TBox {
-            tvalue_: TValue::new(val),
-            id_:     id,
+            tvalue_: tvalue,
+            id_:     OidFac::get_obj_next(),
             vers_:   Arc::new(TVersion::default()),
+            #[cfg(feature = "pmem")]
+            pmem_:   pmem,
         }
     }
 }
@@ -157,38 +185,35 @@ unsafe impl<T: Clone> Sync for TBox<T> {}
 unsafe impl<T: Clone> Send for TBox<T> {}
 
 /* Concrete Types Instances */
-impl BoxRef<u32> for Arc<TBox<u32>> {
+impl<T> BoxRef<T> for Arc<TBox<T>>
+where
+    T: 'static + Clone + Debug,
+{
     fn into_box_ref(self) -> Box<dyn TRef> {
-        Box::new(TInt {
-            inner_: self,
-            data_: None,
-            #[cfg(all(feature = "pmem", feature = "wdrain"))]
-            pd_ptr: ptr::null_mut(),
-        })
+        Box::new(TBoxRef::new(self))
     }
 }
 
-//impl BoxRef<u32> for (u32, Arc<TBox<u32>>) {
-//    fn into_box_ref(self) -> Box<dyn TRef> {
-//        let (val, tbox) = self;
-//        Box::new(TInt {
-//            inner_ : tbox,
-//            data_ : Some(Box::new(val)),
-//            pd_ptr: ptr::null_mut(),
-//        })
-//    }
-//}
-
-//TRef implementation for the TBox<u32>
+//TRef implementation for the TBox<T>
+//A box has a single field, index 0, covering the whole value
 #[derive(Debug)]
-pub struct TInt {
-    inner_: Arc<TBox<u32>>,
-    data_:  Option<Box<u32>>,
+pub struct TBoxRef<T>
+where
+    T: Clone,
+{
+    inner_: Arc<TBox<T>>,
+    data_:  Option<Box<T>>,
 
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
-    pd_ptr: *mut u32,
+    pd_ptr: *mut T,
 }
-impl TRef for TInt {
+
+pub type TInt = TBoxRef<u32>;
+
+impl<T> TRef for TBoxRef<T>
+where
+    T: 'static + Clone + Debug,
+{
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
     fn install(&self, id: Tid) {
         match self.pd_ptr.is_null() {
@@ -200,7 +225,7 @@ impl TRef for TInt {
     #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
     fn install(&self, id: Tid) {
         match self.data_ {
-            Some(ref as_u32) => self.inner_.install(as_u32, id),
+            Some(ref val) => self.inner_.install(val, id),
             None => {
                 panic!("only write should get installed");
             }
@@ -209,7 +234,15 @@ impl TRef for TInt {
 
     #[cfg(any(feature = "pmem", feature = "disk"))]
     fn get_pmem_addr(&self) -> *mut u8 {
-        self.inner_.get_ptr()
+        #[cfg(feature = "pmem")]
+        {
+            self.inner_.get_pmem_addr()
+        }
+
+        #[cfg(not(feature = "pmem"))]
+        {
+            self.inner_.get_ptr()
+        }
     }
 
     fn get_ptr(&self) -> *mut u8 {
@@ -221,7 +254,7 @@ impl TRef for TInt {
     }
 
     fn box_clone(&self) -> Box<dyn TRef> {
-        Box::new(TInt {
+        Box::new(TBoxRef {
             inner_: self.inner_.clone(),
             data_: self.data_.clone(),
             #[cfg(all(feature = "pmem", feature = "wdrain"))]
@@ -241,26 +274,37 @@ impl TRef for TInt {
         self.inner_.get_version()
     }
 
-    //Unused
-    fn get_field_ptr(&self, _i: usize) -> *mut u8 {
-        panic!("tbox not implemented")
+    fn get_field_ptr(&self, i: usize) -> *mut u8 {
+        assert_eq!(i, 0, "tbox has a single field");
+        self.inner_.get_ptr()
     }
-    fn get_field_size(&self, _i: usize) -> usize {
-        panic!("tbox not implemented")
+
+    fn get_field_size(&self, i: usize) -> usize {
+        assert_eq!(i, 0, "tbox has a single field");
+        mem::size_of::<T>()
     }
 
     #[cfg(any(feature = "pmem", feature = "disk"))]
-    fn get_pmem_field_addr(&self, _i: usize) -> *mut u8 {
-        panic!("tbox not implemented")
+    fn get_pmem_field_addr(&self, i: usize) -> *mut u8 {
+        assert_eq!(i, 0, "tbox has a single field");
+        self.get_pmem_addr()
     }
 
     fn read(&self) -> &Any {
-        self.inner_.get_data()
+        match self.data_ {
+            Some(ref val) => val.as_ref(),
+            None => self.inner_.get_data(),
+        }
+    }
+
+    #[cfg(feature = "mvcc")]
+    fn read_at(&self, ts: usize) -> Option<Arc<Any>> {
+        Some(self.inner_.get_data_at(ts) as Arc<Any>)
     }
 
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
     fn write(&mut self, val: *mut u8) {
-        self.pd_ptr = val as *mut u32;
+        self.pd_ptr = val as *mut T;
     }
 
     #[cfg(all(feature = "pmem", feature = "wdrain"))]
@@ -270,17 +314,17 @@ impl TRef for TInt {
 
     #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
     fn write(&mut self, val: Box<Any>) {
-        match val.downcast::<u32>() {
+        match val.downcast::<T>() {
             Ok(val) => self.data_ = Some(val),
-            Err(_) => panic!("runtime value should be u32"),
+            Err(_) => panic!("runtime value should be the boxed type"),
         }
     }
 
     #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
     fn write_through(&self, val: Box<Any>, tid: Tid) {
-        match val.downcast::<u32>() {
+        match val.downcast::<T>() {
             Ok(val) => self.inner_.install(&val, tid),
-            Err(_) => panic!("runtime value should be u32 at write_throught"),
+            Err(_) => panic!("runtime value should be the boxed type at write_throught"),
         }
     }
 
@@ -305,7 +349,7 @@ impl TRef for TInt {
     }
 
     fn get_name(&self) -> String {
-        String::from("int")
+        String::from("tbox")
     }
 
     /* For 2 Phase Locking */
@@ -326,14 +370,17 @@ impl TRef for TInt {
     }
 }
 
-impl TInt {
-    pub fn new(inner: Arc<TBox<u32>>) -> Self {
-        TInt {
+impl<T> TBoxRef<T>
+where
+    T: Clone,
+{
+    pub fn new(inner: Arc<TBox<T>>) -> Self {
+        TBoxRef {
             inner_: inner,
             data_:  None,
 
             #[cfg(all(feature = "pmem", feature = "wdrain"))]
-            pd_ptr:                                                   ptr::null_mut(),
+            pd_ptr: ptr::null_mut(),
         }
     }
 }
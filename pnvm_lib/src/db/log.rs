@@ -0,0 +1,288 @@
+//************************************************
+//Log of a database
+//
+//The committed writes of a database, appended to the file
+//it is opened on. A txn appends its writes and syncs the
+//file at commit, with its write set still locked (see
+//TransactionOCC::on_commit): two txns writing a key append
+//in the order they wrote it. Database::open reads the log
+//back, create_table loads the rows of its table from it.
+//
+//A record holds the writes of one txn, or one loaded row:
+//    RecordHeader | writes
+//sum_ is the FNV-1a hash of the writes. A write is
+//    WriteHeader | table name | key | value
+//without a value for a delete. A record torn by a crash
+//fails its sum, the log is cut before it on open.
+//
+//Keys and values are Copy, logged as their bytes. The log
+//is not compacted, open replays it whole.
+//
+//Types:
+//- DbLog
+//- LogWrite            a write read back from the log
+//************************************************
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::Path,
+    ptr, slice,
+    sync::Mutex,
+};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct RecordHeader {
+    len_: u64, /* Bytes of the writes */
+    sum_: u64,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct WriteHeader {
+    name_len_: u32,
+    key_len_:  u32,
+    val_len_:  u32,
+    delete_:   u32,
+}
+
+pub struct DbLog {
+    file_: Mutex<File>,
+}
+
+//Raw key and value of a write, None for a delete
+#[derive(Clone, Debug)]
+pub struct LogWrite {
+    key_: Vec<u8>,
+    val_: Option<Vec<u8>>,
+}
+
+impl LogWrite {
+    //None if the key was logged with another type
+    pub fn key<K: Copy>(&self) -> Option<K> {
+        from_bytes(&self.key_)
+    }
+
+    //Some(None) for a delete
+    pub fn value<V: Copy>(&self) -> Option<Option<V>> {
+        match self.val_ {
+            None => Some(None),
+            Some(ref val) => from_bytes(val).map(Some),
+        }
+    }
+}
+
+impl DbLog {
+    //The writes logged at path, per table, in log order
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<(DbLog, HashMap<String, Vec<LogWrite>>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+
+        let mut writes = HashMap::new();
+        let end = decode_log(&buf, &mut writes);
+        if end < buf.len() {
+            warn!(
+                "DbLog::open: {} bytes of a torn record cut",
+                buf.len() - end
+            );
+            file.set_len(end as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        Ok((
+            DbLog {
+                file_: Mutex::new(file),
+            },
+            writes,
+        ))
+    }
+
+    //Durable once it returns
+    pub fn append(&self, writes: &[u8]) -> io::Result<()> {
+        let header = RecordHeader {
+            len_: writes.len() as u64,
+            sum_: checksum(writes),
+        };
+
+        let mut record = Vec::with_capacity(size_of::<RecordHeader>() + writes.len());
+        record.extend_from_slice(as_bytes(&header));
+        record.extend_from_slice(writes);
+
+        let mut file = self.file_.lock().unwrap();
+        file.write_all(&record)?;
+        file.sync_data()
+    }
+}
+
+//Adds a write to the writes of a record
+pub fn encode_write<K: Copy, V: Copy>(buf: &mut Vec<u8>, table: &str, key: &K, val: Option<&V>) {
+    let header = WriteHeader {
+        name_len_: table.len() as u32,
+        key_len_:  size_of::<K>() as u32,
+        val_len_:  val.map_or(0, |_| size_of::<V>() as u32),
+        delete_:   val.is_none() as u32,
+    };
+
+    buf.extend_from_slice(as_bytes(&header));
+    buf.extend_from_slice(table.as_bytes());
+    buf.extend_from_slice(as_bytes(key));
+    if let Some(val) = val {
+        buf.extend_from_slice(as_bytes(val));
+    }
+}
+
+//Returns the end of the last whole record
+fn decode_log(buf: &[u8], writes: &mut HashMap<String, Vec<LogWrite>>) -> usize {
+    let header_size = size_of::<RecordHeader>();
+    let mut off = 0;
+
+    while off + header_size <= buf.len() {
+        let header: RecordHeader = from_bytes(&buf[off..off + header_size]).unwrap();
+        let start = off + header_size;
+        if header.len_ > (buf.len() - start) as u64 {
+            break;
+        }
+
+        let end = start + header.len_ as usize;
+        if checksum(&buf[start..end]) != header.sum_ || !decode_writes(&buf[start..end], writes) {
+            break;
+        }
+        off = end;
+    }
+
+    off
+}
+
+//false if the writes do not parse, they are then left out
+fn decode_writes(buf: &[u8], writes: &mut HashMap<String, Vec<LogWrite>>) -> bool {
+    let header_size = size_of::<WriteHeader>();
+    let mut decoded = vec![];
+    let mut off = 0;
+
+    while off < buf.len() {
+        if off + header_size > buf.len() {
+            return false;
+        }
+        let header: WriteHeader = from_bytes(&buf[off..off + header_size]).unwrap();
+        off += header_size;
+
+        let lens = [header.name_len_, header.key_len_, header.val_len_];
+        if lens.iter().map(|len| *len as usize).sum::<usize>() > buf.len() - off {
+            return false;
+        }
+
+        let name = match String::from_utf8(buf[off..off + header.name_len_ as usize].to_vec()) {
+            Ok(name) => name,
+            Err(_) => return false,
+        };
+        off += header.name_len_ as usize;
+
+        let key = buf[off..off + header.key_len_ as usize].to_vec();
+        off += header.key_len_ as usize;
+
+        let val = if header.delete_ != 0 {
+            None
+        } else {
+            Some(buf[off..off + header.val_len_ as usize].to_vec())
+        };
+        off += header.val_len_ as usize;
+
+        decoded.push((
+            name,
+            LogWrite {
+                key_: key,
+                val_: val,
+            },
+        ));
+    }
+
+    for (name, write) in decoded {
+        writes.entry(name).or_insert_with(Vec::new).push(write);
+    }
+    true
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    buf.iter().fold(FNV_OFFSET, |sum, byte| {
+        (sum ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn as_bytes<T: Copy>(val: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) }
+}
+
+fn from_bytes<T: Copy>(buf: &[u8]) -> Option<T> {
+    if buf.len() != size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf, process};
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pnvm_dblog_{}_{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_torn_record_cut() {
+        let path = log_path("torn");
+        {
+            let (log, writes) = DbLog::open(&path).unwrap();
+            assert_eq!(writes.is_empty(), true);
+
+            let mut buf = vec![];
+            encode_write(&mut buf, "kv", &1u64, Some(&10u64));
+            encode_write::<u64, u64>(&mut buf, "kv", &2u64, None);
+            log.append(&buf).unwrap();
+
+            /* A crash in the middle of the next append */
+            let mut buf = vec![];
+            encode_write(&mut buf, "kv", &3u64, Some(&30u64));
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(as_bytes(&RecordHeader {
+                len_: buf.len() as u64,
+                sum_: checksum(&buf),
+            })).unwrap();
+            file.write_all(&buf[..buf.len() / 2]).unwrap();
+        }
+
+        let (log, writes) = DbLog::open(&path).unwrap();
+        let kv = &writes["kv"];
+        assert_eq!(kv.len(), 2);
+        assert_eq!(kv[0].key::<u64>(), Some(1));
+        assert_eq!(kv[0].value::<u64>(), Some(Some(10)));
+        assert_eq!(kv[1].value::<u64>(), Some(None));
+        assert_eq!(kv[1].key::<u32>(), None);
+
+        /* Appended after the cut */
+        let mut buf = vec![];
+        encode_write(&mut buf, "kv", &4u64, Some(&40u64));
+        log.append(&buf).unwrap();
+        drop(log);
+
+        let (_, writes) = DbLog::open(&path).unwrap();
+        assert_eq!(writes["kv"].len(), 3);
+        assert_eq!(writes["kv"][2].key::<u64>(), Some(4));
+        let _ = fs::remove_file(&path);
+    }
+}
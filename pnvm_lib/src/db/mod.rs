@@ -0,0 +1,552 @@
+//************************************************
+//Embedded key-value database
+//
+//    let db = Database::open("/path/to/accounts.db")?;
+//    let accounts = db.create_table::<u64, u64>("accounts")?;
+//    db.transaction(|tx| {
+//        let v = tx.get(&accounts, &1)?.unwrap_or(0);
+//        tx.put(&accounts, 1, v + 1)?;
+//        tx.delete(&accounts, &2)
+//    })?;
+//
+//Tables are storage::Table of Record<K, V>, hash
+//partitioned on the key. A transaction runs as a
+//TransactionOCC and is retried until it commits,
+//unless the closure returns an error.
+//
+//The database is the log at the path it is opened on, see
+//log.rs. transaction returns once the writes of the txn
+//are in the log. Reopening reads the log back, and
+//create_table loads the rows of its table: a table is
+//created again on each open, with the same key and value
+//types. A txn without writes only validates its reads, and
+//persists nothing.
+//
+//Keys and values are Copy, and logged as their bytes: they
+//must not point to memory, e.g. hold a &'static str.
+//
+//With the pmem feature, the rows are also backed by files
+//under PMEM_FILE_DIR and the undo logs are appended to
+//PLOG_FILE_PATH, as for any TransactionOCC. They are not
+//read back on open.
+//
+//Types:
+//- Database            catalog of the tables
+//- DbTable<K, V>       handle of a table
+//- Tx                  transaction handle, see tx.rs
+//- DbError
+//************************************************
+
+pub mod log;
+pub mod record;
+pub mod tx;
+
+pub use self::record::{Record, RecordRef, RecordTable};
+pub use self::tx::Tx;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+};
+
+use occ::occ_txn::TransactionOCC;
+use tcore::OidFac;
+use txn::{AbortReason, TidFac, Transaction};
+
+#[cfg(all(feature = "pmem", feature = "wdrain"))]
+use txn::PmemFac;
+
+use self::log::{DbLog, LogWrite};
+use self::record::key_bucket;
+
+const DEFAULT_BUCKET_NUM: usize = 64;
+const DEFAULT_BUCKET_SIZE: usize = 1024;
+
+/* Tid masks are 8 bits wide, 0 is left to the threads not using a database */
+const MAX_DB_THREADS: usize = 255;
+
+/* Next thread mask never handed out to a database thread */
+static NEXT_THREAD_MASK: AtomicUsize = AtomicUsize::new(1);
+
+lazy_static! {
+    /* Masks of the exited database threads, with their next tid and oid */
+    static ref FREE_THREAD_MASKS: Mutex<Vec<(usize, u32, u64)>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    static THREAD_INIT: Cell<bool> = Cell::new(false);
+    static THREAD_MASK: RefCell<Option<ThreadMask>> = RefCell::new(None);
+}
+
+//Mask of a database thread, given back when the thread exits. The
+//next thread taking it goes on with its tids and oids, so that they
+//stay unique.
+struct ThreadMask(usize);
+
+impl Drop for ThreadMask {
+    fn drop(&mut self) {
+        /* With the id factories already gone, the mask is not reused */
+        if let (Some(tid), Some(oid)) = (TidFac::try_thd_next_id(), OidFac::try_obj_next_id()) {
+            FREE_THREAD_MASKS.lock().unwrap().push((self.0, tid, oid));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbError {
+    TableExists(String),
+    NoTable(String),
+    TypeMismatch(String), /* Table declared with other key/value types */
+    Aborted,              /* Returned by a closure to give up the txn */
+    Io(String),           /* Opening the log */
+}
+
+pub struct DbTable<K, V>
+where
+    K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+    V: 'static + Copy + Debug + Send + Sync,
+{
+    id_:    usize,
+    table_: Arc<RecordTable<K, V>>,
+    log_:   Arc<DbLog>,
+}
+
+impl<K, V> Clone for DbTable<K, V>
+where
+    K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+    V: 'static + Copy + Debug + Send + Sync,
+{
+    fn clone(&self) -> Self {
+        DbTable {
+            id_:    self.id_,
+            table_: self.table_.clone(),
+            log_:   self.log_.clone(),
+        }
+    }
+}
+
+impl<K, V> DbTable<K, V>
+where
+    K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+    V: 'static + Copy + Debug + Send + Sync,
+{
+    pub fn name(&self) -> &str {
+        self.table_.name()
+    }
+
+    //Loads a row outside of any transaction, logged as well
+    pub fn load(&self, key: K, val: V) {
+        let mut writes = vec![];
+        log::encode_write(&mut writes, self.name(), &key, Some(&val));
+        self.log_
+            .append(&writes)
+            .expect("DbTable::load: log append failed");
+
+        self.table_.push_raw(Record::new(key, val));
+    }
+
+    //Latest committed value, not part of any transaction
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.table_
+            .retrieve(key, key_bucket(key))
+            .map(|row| row.get_data().value().clone())
+    }
+}
+
+pub struct Database {
+    tables_:      RwLock<HashMap<String, (usize, Box<Any + Send + Sync>)>>,
+    log_:         Arc<DbLog>,
+    recovered_:   Mutex<HashMap<String, Vec<LogWrite>>>, /* Of the tables not created yet */
+    bucket_num_:  usize,
+    bucket_size_: usize,
+}
+
+impl Database {
+    //Creates the log at path, or reads it back
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database, DbError> {
+        Database::open_with_buckets(path, DEFAULT_BUCKET_NUM, DEFAULT_BUCKET_SIZE)
+    }
+
+    //bucket_size is the initial capacity of a bucket
+    pub fn open_with_buckets<P: AsRef<Path>>(
+        path: P,
+        bucket_num: usize,
+        bucket_size: usize,
+    ) -> Result<Database, DbError> {
+        Database::init_thread();
+
+        let (log, recovered) = DbLog::open(path).map_err(|e| DbError::Io(e.to_string()))?;
+        Ok(Database {
+            tables_:      RwLock::new(HashMap::new()),
+            log_:         Arc::new(log),
+            recovered_:   Mutex::new(recovered),
+            bucket_num_:  bucket_num,
+            bucket_size_: bucket_size,
+        })
+    }
+
+    pub fn create_table<K, V>(&self, name: &str) -> Result<DbTable<K, V>, DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        Database::init_thread();
+
+        let mut tables = self.tables_.write().unwrap();
+        if tables.contains_key(name) {
+            return Err(DbError::TableExists(String::from(name)));
+        }
+
+        let rows = self.recovered_rows::<K, V>(name)?;
+        let table = DbTable {
+            id_:    tables.len(),
+            table_: Arc::new(RecordTable::new_with_buckets(
+                self.bucket_num_,
+                self.bucket_size_,
+                name,
+            )),
+            log_:   self.log_.clone(),
+        };
+        for (key, val) in rows {
+            table.table_.push_raw(Record::new(key, val));
+        }
+
+        self.recovered_.lock().unwrap().remove(name);
+        tables.insert(String::from(name), (table.id_, Box::new(table.clone())));
+        Ok(table)
+    }
+
+    //The rows of a table left by the log, its writes replayed
+    //in log order
+    fn recovered_rows<K, V>(&self, name: &str) -> Result<HashMap<K, V>, DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        let mut rows = HashMap::new();
+        if let Some(writes) = self.recovered_.lock().unwrap().get(name) {
+            for write in writes.iter() {
+                match (write.key::<K>(), write.value::<V>()) {
+                    (Some(key), Some(Some(val))) => {
+                        rows.insert(key, val);
+                    }
+                    (Some(key), Some(None)) => {
+                        rows.remove(&key);
+                    }
+                    _ => return Err(DbError::TypeMismatch(String::from(name))),
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    pub fn table<K, V>(&self, name: &str) -> Result<DbTable<K, V>, DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        let tables = self.tables_.read().unwrap();
+        match tables.get(name) {
+            None => Err(DbError::NoTable(String::from(name))),
+            Some(&(_, ref table)) => match table.downcast_ref::<DbTable<K, V>>() {
+                Some(table) => Ok(table.clone()),
+                None => Err(DbError::TypeMismatch(String::from(name))),
+            },
+        }
+    }
+
+    //Runs f until its txn commits. An error from f aborts
+    //the txn and is returned as is.
+    pub fn transaction<F, R>(&self, mut f: F) -> Result<R, DbError>
+    where
+        F: FnMut(&mut Tx) -> Result<R, DbError>,
+    {
+        Database::init_thread();
+
+        let tid = TidFac::get_thd_next();
+        let txn = &mut TransactionOCC::new(tid);
+        loop {
+            let ret = {
+                let mut tx = Tx::new(txn);
+                f(&mut tx).map(|ret| (ret, tx.apply()))
+            };
+
+            match ret {
+                Ok((ret, writes)) => {
+                    /* Logged before the write set unlocks */
+                    if !writes.is_empty() {
+                        let log = self.log_.clone();
+                        txn.on_commit(move || {
+                            log.append(&writes)
+                                .expect("Database::transaction: log append failed")
+                        });
+                    }
+
+                    if txn.try_commit() {
+                        return Ok(ret);
+                    }
+                }
+                Err(err) => {
+                    txn.abort(AbortReason::User);
+                    return Err(err);
+                }
+            }
+
+            thread::yield_now();
+        }
+    }
+
+    //Tids and object ids are only unique with a thread mask
+    fn init_thread() {
+        THREAD_INIT.with(|init| {
+            if init.get() {
+                return;
+            }
+
+            let free = FREE_THREAD_MASKS.lock().unwrap().pop();
+            let mask = match free {
+                Some((mask, next_tid, next_oid)) => {
+                    TidFac::set_thd_next_id(next_tid);
+                    OidFac::set_obj_next_id(next_oid);
+                    mask
+                }
+                None => {
+                    let mask = NEXT_THREAD_MASK.fetch_add(1, Ordering::SeqCst);
+                    assert!(mask <= MAX_DB_THREADS, "too many live database threads");
+                    mask
+                }
+            };
+            TidFac::set_thd_mask(mask as u32);
+            OidFac::set_obj_mask(mask as u64);
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            PmemFac::init();
+
+            /* Set after the id factories are used, so that it drops first */
+            THREAD_MASK.with(|m| *m.borrow_mut() = Some(ThreadMask(mask)));
+            init.set(true);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf, process, thread};
+    use tcore::BenchmarkCounter;
+
+    fn db_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pnvm_db_{}_{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let db = Database::open_with_buckets(db_path("put_get_delete"), 4, 16).unwrap();
+        let t = db.create_table::<u64, [u8; 4]>("kv").unwrap();
+
+        db.transaction(|tx| {
+            tx.put(&t, 1, *b"one ")?;
+            tx.put(&t, 2, *b"two ")?;
+            assert_eq!(tx.get(&t, &1)?, Some(*b"one "));
+            Ok(())
+        }).unwrap();
+        assert_eq!(t.get(&2), Some(*b"two "));
+
+        let present = db.transaction(|tx| {
+            tx.put(&t, 1, *b"uno ")?;
+            tx.delete(&t, &2)
+        }).unwrap();
+        assert_eq!(present, true);
+        assert_eq!(t.get(&1), Some(*b"uno "));
+        assert_eq!(t.get(&2), None);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = db_path("reopen");
+        {
+            let db = Database::open_with_buckets(&path, 4, 16).unwrap();
+            let t = db.create_table::<u64, u64>("kv").unwrap();
+            let other = db.create_table::<u32, (u32, u32)>("other").unwrap();
+            t.load(1, 10);
+
+            db.transaction(|tx| {
+                tx.put(&t, 2, 20)?;
+                tx.put(&t, 3, 30)?;
+                tx.put(&other, 7, (7, 70))
+            }).unwrap();
+            db.transaction(|tx| {
+                tx.put(&t, 1, 11)?;
+                tx.delete(&t, &3)
+            }).unwrap();
+
+            /* Neither aborted nor read-only txns reach the log */
+            let _: Result<(), DbError> = db.transaction(|tx| {
+                tx.put(&t, 4, 40)?;
+                Err(DbError::Aborted)
+            });
+            db.transaction(|tx| tx.get(&t, &1)).unwrap();
+        }
+
+        let db = Database::open_with_buckets(&path, 4, 16).unwrap();
+        assert_eq!(
+            db.create_table::<u64, u32>("kv").err(),
+            Some(DbError::TypeMismatch(String::from("kv")))
+        );
+
+        let t = db.create_table::<u64, u64>("kv").unwrap();
+        assert_eq!(t.get(&1), Some(11));
+        assert_eq!(t.get(&2), Some(20));
+        assert_eq!(t.get(&3), None);
+        assert_eq!(t.get(&4), None);
+
+        /* Goes on from the rows loaded back */
+        db.transaction(|tx| {
+            let v = tx.get(&t, &2)?.unwrap_or(0);
+            tx.put(&t, 2, v + 1)
+        }).unwrap();
+        drop(t);
+        drop(db);
+
+        let db = Database::open_with_buckets(&path, 4, 16).unwrap();
+        let t = db.create_table::<u64, u64>("kv").unwrap();
+        assert_eq!(t.get(&2), Some(21));
+        let other = db.create_table::<u32, (u32, u32)>("other").unwrap();
+        assert_eq!(other.get(&7), Some((7, 70)));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_aborted_txn_has_no_effect() {
+        let db = Database::open_with_buckets(db_path("aborted_txn_has_no_effect"), 4, 16).unwrap();
+        let t = db.create_table::<u64, u64>("kv").unwrap();
+        t.load(1, 10);
+
+        let ret: Result<(), DbError> = db.transaction(|tx| {
+            tx.put(&t, 1, 11)?;
+            tx.put(&t, 2, 20)?;
+            Err(DbError::Aborted)
+        });
+        assert_eq!(ret, Err(DbError::Aborted));
+        assert_eq!(t.get(&1), Some(10));
+        assert_eq!(t.get(&2), None);
+    }
+
+    #[test]
+    fn test_read_only_fast_path() {
+        let db = Database::open_with_buckets(db_path("read_only_fast_path"), 4, 16).unwrap();
+        let t = db.create_table::<u64, u64>("kv").unwrap();
+        t.load(1, 10);
+
+        let before = BenchmarkCounter::copy().success_ro_cnt;
+        assert_eq!(db.transaction(|tx| tx.get(&t, &1)).unwrap(), Some(10));
+        assert_eq!(db.transaction(|tx| tx.get(&t, &2)).unwrap(), None);
+        assert_eq!(BenchmarkCounter::copy().success_ro_cnt, before + 2);
+
+        db.transaction(|tx| tx.put(&t, 1, 11)).unwrap();
+        assert_eq!(BenchmarkCounter::copy().success_ro_cnt, before + 2);
+        assert_eq!(t.get(&1), Some(11));
+    }
+
+    #[test]
+    fn test_catalog() {
+        let db = Database::open(db_path("catalog")).unwrap();
+        db.create_table::<u64, u64>("kv").unwrap();
+
+        assert_eq!(
+            db.create_table::<u64, u64>("kv").err(),
+            Some(DbError::TableExists(String::from("kv")))
+        );
+        assert_eq!(db.table::<u64, u64>("kv").is_ok(), true);
+        assert_eq!(
+            db.table::<u64, u32>("kv").err(),
+            Some(DbError::TypeMismatch(String::from("kv")))
+        );
+        assert_eq!(
+            db.table::<u64, u64>("none").err(),
+            Some(DbError::NoTable(String::from("none")))
+        );
+    }
+
+    #[test]
+    fn test_concurrent_increments() {
+        let db =
+            Arc::new(Database::open_with_buckets(db_path("concurrent_increments"), 4, 16).unwrap());
+        let t = db.create_table::<u64, u64>("counter").unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let t = t.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        db.transaction(|tx| {
+                            let v = tx.get(&t, &0)?.unwrap_or(0);
+                            tx.put(&t, 0, v + 1)
+                        }).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(t.get(&0), Some(400));
+    }
+
+    #[test]
+    fn test_absent_read_validated() {
+        let db = Database::open_with_buckets(db_path("absent_read_validated"), 4, 16).unwrap();
+        let t = db.create_table::<u64, u64>("kv").unwrap();
+
+        /* Each reads the key the other inserts */
+        let run = |read: u64, put: u64| {
+            let mut txn = TransactionOCC::new(TidFac::get_thd_next());
+            {
+                let mut tx = Tx::new(&mut txn);
+                assert_eq!(tx.get(&t, &read).unwrap(), None);
+                tx.put(&t, put, put * 10).unwrap();
+                tx.apply();
+            }
+            txn
+        };
+        let mut t1 = run(1, 2);
+        let mut t2 = run(2, 1);
+
+        assert_eq!(t1.try_commit(), true);
+        assert_eq!(t2.try_commit(), false);
+        assert_eq!(t.get(&1), None);
+        assert_eq!(t.get(&2), Some(20));
+    }
+
+    #[test]
+    fn test_thread_masks_reused() {
+        let db =
+            Arc::new(Database::open_with_buckets(db_path("thread_masks_reused"), 4, 16).unwrap());
+        let t = db.create_table::<u64, u64>("counter").unwrap();
+
+        /* More short lived threads than masks */
+        for _ in 0..MAX_DB_THREADS + 45 {
+            let db = db.clone();
+            let t = t.clone();
+            thread::spawn(move || {
+                db.transaction(|tx| {
+                    let v = tx.get(&t, &0)?.unwrap_or(0);
+                    tx.put(&t, 0, v + 1)
+                }).unwrap();
+            }).join()
+                .unwrap();
+        }
+        assert_eq!(t.get(&0), Some(MAX_DB_THREADS as u64 + 45));
+    }
+}
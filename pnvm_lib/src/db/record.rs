@@ -0,0 +1,430 @@
+//************************************************
+//Rows of a database table
+//
+//Types:
+//- Record<K, V>        the entry stored in a Table
+//- RecordRef<K, V>     the TRef of a Row of Record<K, V>
+//
+//RecordRef is what pnvm_derive generates for a TPCC
+//entry, with the table itself as the push/delete context.
+//Unlike the TPCC refs, a push or a delete fails to lock
+//if another transaction got there first, so a key is
+//never inserted twice.
+//************************************************
+
+use std::{
+    any::Any,
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    ptr,
+    sync::Arc,
+};
+
+use pnvm_sys::Layout;
+
+use storage::{BucketDeleteRef, BucketPushRef, FieldOffset, Key, Row, Table, TableRef};
+use tcore::{ObjectId, Operation, TRef, TVersion};
+use txn::{Tid, TxnInfo};
+
+pub type RecordTable<K, V> = Table<Record<K, V>, K>;
+
+//Bucket of a key, also the partition of its record
+pub fn key_bucket<K: Hash>(key: &K) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize
+}
+
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct Record<K, V> {
+    key_:   K,
+    value_: V,
+}
+
+impl<K, V> Record<K, V> {
+    pub fn new(key: K, value: V) -> Record<K, V> {
+        Record {
+            key_:   key,
+            value_: value,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key_
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value_
+    }
+}
+
+//Records are always logged as a whole
+impl<K, V> FieldOffset for Record<K, V> {
+    fn field_offset(&self) -> [isize; 32] {
+        [-1; 32]
+    }
+}
+
+impl<K, V> Key<K> for Record<K, V>
+where
+    K: Hash + Clone,
+{
+    fn primary_key(&self) -> K {
+        self.key_.clone()
+    }
+
+    fn bucket_key(&self) -> usize {
+        key_bucket(&self.key_)
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordRef<K, V>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    inner_:      Arc<Row<Record<K, V>, K>>,
+    bucket_idx_: Option<usize>,
+    table_ref_:  Option<Arc<RecordTable<K, V>>>,
+    data_:       Option<Box<Record<K, V>>>,
+    ops_:        Operation,
+    //The txn of a Delete
+    deleter_:    Option<Arc<TxnInfo>>,
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    pd_ptr: *mut Record<K, V>,
+}
+
+impl<K, V> Clone for RecordRef<K, V>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn clone(&self) -> Self {
+        RecordRef {
+            inner_:      self.inner_.clone(),
+            bucket_idx_: self.bucket_idx_,
+            table_ref_:  self.table_ref_.clone(),
+            data_:       self.data_.clone(),
+            ops_:        self.ops_,
+            deleter_:    self.deleter_.clone(),
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            pd_ptr: self.pd_ptr,
+        }
+    }
+}
+
+impl<K, V> RecordRef<K, V>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn new(
+        inner: Arc<Row<Record<K, V>, K>>,
+        bucket_idx: Option<usize>,
+        table_ref: Option<Arc<RecordTable<K, V>>>,
+        ops: Operation,
+        deleter: Option<Arc<TxnInfo>>,
+    ) -> RecordRef<K, V> {
+        RecordRef {
+            inner_:      inner,
+            bucket_idx_: bucket_idx,
+            table_ref_:  table_ref,
+            data_:       None,
+            ops_:        ops,
+            deleter_:    deleter,
+
+            #[cfg(all(feature = "pmem", feature = "wdrain"))]
+            pd_ptr: ptr::null_mut(),
+        }
+    }
+
+    fn bucket(&self) -> &::storage::Bucket<Record<K, V>, K> {
+        self.table_ref_
+            .as_ref()
+            .expect("no table ref")
+            .get_bucket(self.bucket_idx_.expect("no bucket idx"))
+    }
+
+    //The row a key maps to in the bucket, if any
+    fn indexed(&self) -> Option<Arc<Row<Record<K, V>, K>>> {
+        self.bucket().retrieve(&self.inner_.get_data().key_)
+    }
+}
+
+impl<K, V> TableRef<RecordTable<K, V>> for Arc<Row<Record<K, V>, K>>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn into_table_ref(
+        self,
+        bucket_idx: Option<usize>,
+        table_ref: Option<Arc<RecordTable<K, V>>>,
+    ) -> Box<dyn TRef> {
+        Box::new(RecordRef::new(
+            self,
+            bucket_idx,
+            table_ref,
+            Operation::RWrite,
+            None,
+        ))
+    }
+}
+
+impl<K, V> BucketPushRef<RecordTable<K, V>> for Arc<Row<Record<K, V>, K>>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn into_push_table_ref(
+        self,
+        bucket_idx: usize,
+        table_ref: Arc<RecordTable<K, V>>,
+    ) -> Box<dyn TRef> {
+        Box::new(RecordRef::new(
+            self,
+            Some(bucket_idx),
+            Some(table_ref),
+            Operation::Push,
+            None,
+        ))
+    }
+}
+
+impl<K, V> BucketDeleteRef<RecordTable<K, V>> for Arc<Row<Record<K, V>, K>>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn into_delete_table_ref(
+        self,
+        bucket_idx: usize,
+        table_ref: Arc<RecordTable<K, V>>,
+        deleter: Arc<TxnInfo>,
+    ) -> Box<dyn TRef> {
+        Box::new(RecordRef::new(
+            self,
+            Some(bucket_idx),
+            Some(table_ref),
+            Operation::Delete,
+            Some(deleter),
+        ))
+    }
+}
+
+impl<K, V> TRef for RecordRef<K, V>
+where
+    K: 'static + Hash + Eq + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    fn get_ptr(&self) -> *mut u8 {
+        self.inner_.get_ptr()
+    }
+
+    fn get_layout(&self) -> Layout {
+        self.inner_.get_layout()
+    }
+
+    fn get_field_ptr(&self, field_idx: usize) -> *mut u8 {
+        self.inner_.get_field_ptr(field_idx)
+    }
+
+    fn get_field_size(&self, field_idx: usize) -> usize {
+        self.inner_.get_field_size(field_idx)
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_addr(&self) -> *mut u8 {
+        self.inner_.get_pmem_addr() as *mut u8
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_field_addr(&self, field_idx: usize) -> *mut u8 {
+        self.inner_.get_pmem_field_addr(field_idx) as *mut u8
+    }
+
+    fn box_clone(&self) -> Box<dyn TRef> {
+        Box::new(self.clone())
+    }
+
+    fn get_id(&self) -> &ObjectId {
+        self.inner_.get_id()
+    }
+
+    fn get_tvers(&self) -> &Arc<TVersion> {
+        &self.inner_.vers_
+    }
+
+    fn get_version(&self) -> u32 {
+        self.inner_.get_version()
+    }
+
+    fn read(&self) -> &Any {
+        self.inner_.get_data()
+    }
+
+    #[cfg(feature = "mvcc")]
+    fn read_at(&self, ts: usize) -> Option<Arc<Any>> {
+        Some(self.inner_.get_data_at(ts) as Arc<Any>)
+    }
+
+    fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.inner_.get_access_info()
+    }
+
+    fn set_access_info(&mut self, txn_info: Arc<TxnInfo>) {
+        self.inner_.set_access_info(txn_info);
+    }
+
+    fn get_name(&self) -> String {
+        match self.table_ref_ {
+            Some(ref table) => String::from(table.name()),
+            None => String::from("record"),
+        }
+    }
+
+    fn install(&self, id: Tid) {
+        match self.ops_ {
+            Operation::RWrite => {
+                #[cfg(all(feature = "pmem", feature = "wdrain"))]
+                {
+                    if !self.pd_ptr.is_null() {
+                        self.inner_.install_ptr(self.pd_ptr, id);
+                    } else {
+                        panic!("pd_ptr should not be null at write");
+                    }
+                }
+
+                #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+                self.inner_
+                    .install_val(self.data_.as_ref().expect("no data"), id);
+            }
+            Operation::Push => {
+                let bucket = self.bucket();
+                bucket.set_version(id.into());
+                bucket.push(self.inner_.clone());
+            }
+            Operation::Delete => {
+                let bucket = self.bucket();
+                bucket.set_version(id.into());
+                bucket.delete(
+                    self.inner_.clone(),
+                    self.deleter_.clone().expect("no deleter"),
+                );
+
+                //Readers of the deleted row fail their check
+                self.inner_.vers_.set_version(id.into());
+            }
+        }
+    }
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    fn write(&mut self, ptr: *mut u8) {
+        self.pd_ptr = ptr as *mut Record<K, V>;
+    }
+
+    #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+    fn write(&mut self, val: Box<Any>) {
+        match val.downcast::<Record<K, V>>() {
+            Ok(val) => self.data_ = Some(val),
+            Err(_) => panic!("RecordRef::write value should be Box<Record>"),
+        }
+    }
+
+    //A push only holds if the key is still free, and a delete
+    //if the row is still indexed. The bucket lock is released
+    //on failure, the txn only unlocks what locked fine.
+    fn lock(&self, tid: Tid) -> bool {
+        match self.ops_ {
+            Operation::RWrite => self.inner_.lock(tid),
+            Operation::Push => {
+                if !self.bucket().lock(tid) {
+                    return false;
+                }
+                if self.indexed().is_some() {
+                    self.bucket().unlock();
+                    return false;
+                }
+                true
+            }
+            Operation::Delete => {
+                if !self.bucket().lock(tid) {
+                    return false;
+                }
+                if !self.inner_.lock(tid) {
+                    self.bucket().unlock();
+                    return false;
+                }
+                match self.indexed() {
+                    Some(ref row) if row.get_id() == self.inner_.get_id() => true,
+                    _ => {
+                        self.inner_.unlock();
+                        self.bucket().unlock();
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    fn unlock(&self) {
+        match self.ops_ {
+            Operation::RWrite => self.inner_.unlock(),
+            Operation::Push => self.bucket().unlock(),
+            Operation::Delete => {
+                self.inner_.unlock();
+                self.bucket().unlock();
+            }
+        }
+    }
+
+    //Pushes and deletes are checked with the bucket lock
+    fn check(&self, vers: u32, tid: u32) -> bool {
+        match self.ops_ {
+            Operation::RWrite => self.inner_.check(vers, tid),
+            _ => true,
+        }
+    }
+
+    /* For 2 Phase Locking */
+    fn write_through(&self, val: Box<Any>, tid: Tid) {
+        match val.downcast::<Record<K, V>>() {
+            Ok(val) => self.inner_.install_val(&val, tid),
+            Err(_) => panic!("runtime value should be Record"),
+        }
+    }
+
+    fn read_lock(&self, tid: u32) -> bool {
+        self.inner_.vers_.read_lock(tid)
+    }
+
+    fn read_unlock(&self, tid: u32) {
+        self.inner_.vers_.read_unlock(tid)
+    }
+
+    fn write_lock(&self, tid: u32) -> bool {
+        match self.ops_ {
+            Operation::RWrite => self.inner_.vers_.write_lock(tid),
+            Operation::Push => self.bucket().vers_.write_lock(tid),
+            Operation::Delete => {
+                self.bucket().vers_.write_lock(tid) && self.inner_.vers_.write_lock(tid)
+            }
+        }
+    }
+
+    fn write_unlock(&self, tid: u32) {
+        match self.ops_ {
+            Operation::RWrite => self.inner_.vers_.write_unlock(tid),
+            Operation::Push => self.bucket().vers_.write_unlock(tid),
+            Operation::Delete => {
+                self.inner_.vers_.write_unlock(tid);
+                self.bucket().vers_.write_unlock(tid);
+            }
+        }
+    }
+}
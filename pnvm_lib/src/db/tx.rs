@@ -0,0 +1,185 @@
+//************************************************
+//Transaction handle of a database
+//
+//Writes are buffered per table until the closure of
+//Database::transaction returns, so that a txn reads its
+//own writes and a key written twice is applied once.
+//They are then turned into the tags of the underlying
+//TransactionOCC:
+//- put of a present key        write
+//- put of an absent key        push
+//- delete of a present key     delete
+//
+//Reads of present keys are validated at commit. A key
+//read as absent scans its bucket (Bucket::scan), so that
+//a push into the bucket before the commit aborts the txn.
+//A pushed key must still be absent when its bucket is
+//locked. The writes applied are encoded for the log of
+//the database, see log.rs.
+//************************************************
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    sync::Arc,
+};
+
+use occ::occ_txn::TransactionOCC;
+use storage::{Row, TableRef};
+use txn::Transaction;
+
+use super::log;
+use super::record::{key_bucket, Record};
+use super::{DbError, DbTable};
+
+//Buffered writes and absent reads of one table
+trait PendingWrites {
+    fn as_any(&mut self) -> &mut Any;
+    fn apply(&mut self, txn: &mut TransactionOCC, log: &mut Vec<u8>);
+}
+
+struct TableWrites<K, V>
+where
+    K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+    V: 'static + Copy + Debug + Send + Sync,
+{
+    table_:  DbTable<K, V>,
+    writes_: HashMap<K, Option<V>>, /* None for a delete */
+    absent_: HashSet<K>,
+}
+
+impl<K, V> PendingWrites for TableWrites<K, V>
+where
+    K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+    V: 'static + Copy + Debug + Send + Sync,
+{
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+
+    fn apply(&mut self, txn: &mut TransactionOCC, log: &mut Vec<u8>) {
+        let table = &self.table_.table_;
+        for key in self.absent_.drain() {
+            if table.retrieve(&key, key_bucket(&key)).is_some() {
+                txn.should_abort();
+            }
+        }
+
+        for (key, val) in self.writes_.drain() {
+            let bucket_idx = key_bucket(&key);
+            match (table.retrieve(&key, bucket_idx), val) {
+                (Some(row), Some(val)) => {
+                    //Read as well, so a concurrent delete aborts the write
+                    let tref = row.into_table_ref(None, None);
+                    txn.read::<Record<K, V>>(tref.box_clone());
+                    txn.write(tref, Record::new(key, val));
+                }
+                (None, Some(val)) => table.push(txn, Record::new(key, val), table),
+                (Some(_), None) => {
+                    table.delete(txn, &key, table, bucket_idx);
+                }
+                (None, None) => continue,
+            }
+            log::encode_write(log, table.name(), &key, val.as_ref());
+        }
+    }
+}
+
+pub struct Tx<'a> {
+    txn_:    &'a mut TransactionOCC,
+    writes_: HashMap<usize, Box<PendingWrites>>,
+}
+
+impl<'a> Tx<'a> {
+    pub(super) fn new(txn: &'a mut TransactionOCC) -> Tx<'a> {
+        Tx {
+            txn_:    txn,
+            writes_: HashMap::new(),
+        }
+    }
+
+    pub fn get<K, V>(&mut self, table: &DbTable<K, V>, key: &K) -> Result<Option<V>, DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        if let Some(val) = self.table_writes(table).writes_.get(key) {
+            return Ok(val.clone());
+        }
+
+        let val = match self.read_row(table, key) {
+            Some(val) => Some(val),
+            None => {
+                //Scan first, then look again: a push before the scan
+                //is found, one after it fails the validation
+                table.table_.get_bucket(key_bucket(key)).scan(self.txn_);
+                self.read_row(table, key)
+            }
+        };
+        if val.is_none() {
+            self.table_writes(table).absent_.insert(key.clone());
+        }
+        Ok(val)
+    }
+
+    pub fn put<K, V>(&mut self, table: &DbTable<K, V>, key: K, val: V) -> Result<(), DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        self.table_writes(table).writes_.insert(key, Some(val));
+        Ok(())
+    }
+
+    //Returns if the key was present
+    pub fn delete<K, V>(&mut self, table: &DbTable<K, V>, key: &K) -> Result<bool, DbError>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        let present = self.get(table, key)?.is_some();
+        self.table_writes(table).writes_.insert(key.clone(), None);
+        Ok(present)
+    }
+
+    //Adds the buffered writes to the txn, returns them
+    //encoded for the log
+    pub(super) fn apply(&mut self) -> Vec<u8> {
+        let mut log = vec![];
+        for (_, writes) in self.writes_.iter_mut() {
+            writes.apply(self.txn_, &mut log);
+        }
+        log
+    }
+
+    fn read_row<K, V>(&mut self, table: &DbTable<K, V>, key: &K) -> Option<V>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        let row: Arc<Row<Record<K, V>, K>> = table.table_.retrieve(key, key_bucket(key))?;
+        let tref = row.into_table_ref(None, None);
+        Some(self.txn_.read::<Record<K, V>>(tref).value().clone())
+    }
+
+    fn table_writes<K, V>(&mut self, table: &DbTable<K, V>) -> &mut TableWrites<K, V>
+    where
+        K: 'static + Hash + Eq + Copy + Debug + Send + Sync,
+        V: 'static + Copy + Debug + Send + Sync,
+    {
+        self.writes_
+            .entry(table.id_)
+            .or_insert_with(|| {
+                Box::new(TableWrites {
+                    table_:  table.clone(),
+                    writes_: HashMap::new(),
+                    absent_: HashSet::new(),
+                })
+            })
+            .as_any()
+            .downcast_mut::<TableWrites<K, V>>()
+            .expect("table id reused with other types")
+    }
+}
@@ -0,0 +1,332 @@
+//************************************************
+//Flush planner
+//
+//Persisting a write set field by field issues one
+//copy and one flush per field, even when the fields
+//share a cache line or the rows are adjacent in the
+//pmem extent of a bucket. A FlushPlan collects the
+//ranges of a transaction instead:
+//- copies (pmem dst, DRAM src, len), without "dir"
+//- ranges already written in pmem, with "dir"
+//- payloads freshly allocated (VarLen), unpublished
+//  until the row pointing to them is
+//
+//issue() merges the copies contiguous in both src and
+//dst, copies them, then flushes the ranges merged into
+//cache line aligned spans, one flush per span. The
+//caller still drains once, at persist_commit. A plan
+//fencing each ref (strict persistency, see persistency)
+//writes back and drains every add_ref instead.
+//
+//The copies and flushes go through the copy strategy
+//(pnvm_sys::CopyStrategy). A streaming one copies with
+//non-temporal stores, which leave no line to flush but
+//the partial ones at the ends of a copy.
+//
+//BenchmarkCounter::flush counts the lines flushed, or
+//streamed.
+//
+//With eADR (pnvm_sys::PersistDomain), the caches are
+//persistent: issue() only copies, and flushes nothing,
+//but on the emul device, which always models ADR.
+//
+//With "porder", issue() tags the ranges as data of the
+//txn of the plan (pnvm_sys::porder): its undo records
+//must be fenced by then. The payloads are tagged as
+//allocations, which need no undo record. With "dir",
+//the data is already in pmem, so this is checked after
+//the fact.
+//************************************************
+
+pub use pnvm_sys::{
+    copy_strategy, detect_persist_domain, persist_domain, set_copy_strategy, CopyStrategy,
+    PersistDomain, CACHE_LINE_SIZE,
+};
+
+use pnvm_sys;
+#[cfg(feature = "porder")]
+use pnvm_sys::porder::{self, Role};
+use std::{cmp, ptr};
+use tcore::{self, BenchmarkCounter, FieldArray, TRef};
+use txn::Tid;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CopyRange {
+    dst_: usize,
+    src_: usize,
+    len_: usize,
+}
+
+#[derive(Default)]
+pub struct FlushPlan {
+    copies_:     Vec<CopyRange>,
+    ranges_:     Vec<(usize, usize)>,
+    allocs_:     Vec<(usize, usize)>,
+    tid_:        u32,
+    fence_each_: bool,
+}
+
+impl FlushPlan {
+    pub fn new() -> FlushPlan {
+        FlushPlan::default()
+    }
+
+    //The data of txn id
+    pub fn for_txn(id: Tid) -> FlushPlan {
+        FlushPlan {
+            tid_: id.into(),
+            ..FlushPlan::default()
+        }
+    }
+
+    //Each add_ref is written back and fenced at once
+    pub fn fence_each(mut self, fence_each: bool) -> FlushPlan {
+        self.fence_each_ = fence_each;
+        self
+    }
+
+    //len bytes at src go to paddr in pmem
+    pub fn add_copy(&mut self, paddr: *mut u8, src: *mut u8, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.copies_.push(CopyRange {
+            dst_: paddr as usize,
+            src_: src as usize,
+            len_: len,
+        });
+    }
+
+    //len bytes already written at paddr in pmem
+    pub fn add_flush(&mut self, paddr: *mut u8, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.ranges_.push((paddr as usize, len));
+    }
+
+    //len bytes at paddr, allocated for this txn and not yet
+    //reachable from a committed row: flushed, never logged
+    pub fn add_alloc(&mut self, paddr: *mut u8, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.allocs_.push((paddr as usize, len));
+    }
+
+    //The fields written of tref, or all of it, and the
+    //payloads of its VarLen columns written
+    #[cfg(feature = "pmem")]
+    pub fn add_ref(&mut self, tref: &dyn TRef, fields: Option<&FieldArray>) {
+        match fields {
+            Some(fields) => {
+                for field in fields.iter() {
+                    let paddr = tref.get_pmem_field_addr(*field);
+                    let size = tref.get_field_size(*field);
+
+                    #[cfg(feature = "dir")]
+                    self.add_flush(paddr, size);
+
+                    #[cfg(not(feature = "dir"))]
+                    self.add_copy(paddr, tref.get_field_ptr(*field), size);
+                }
+            }
+            None => {
+                let paddr = tref.get_pmem_addr();
+                let size = tref.get_layout().size();
+
+                #[cfg(feature = "dir")]
+                self.add_flush(paddr, size);
+
+                #[cfg(not(feature = "dir"))]
+                self.add_copy(paddr, tref.get_ptr(), size);
+            }
+        }
+
+        for var in tcore::written_var_fields(tref, fields) {
+            self.add_alloc(var.as_ptr(), var.len());
+        }
+
+        if self.fence_each_ {
+            self.write_back();
+            pnvm_sys::drain();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.copies_.is_empty() && self.ranges_.is_empty() && self.allocs_.is_empty()
+    }
+
+    //Copies contiguous in both src and dst, or repeated,
+    //become a single one
+    fn merged_copies(&mut self) -> Vec<CopyRange> {
+        self.copies_.sort_by_key(|c| c.dst_);
+
+        let mut merged: Vec<CopyRange> = Vec::with_capacity(self.copies_.len());
+        for c in self.copies_.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                let last_end = last.dst_ + last.len_;
+                if c.dst_ <= last_end && c.dst_ - last.dst_ == c.src_.wrapping_sub(last.src_) {
+                    last.len_ = cmp::max(last_end, c.dst_ + c.len_) - last.dst_;
+                    continue;
+                }
+            }
+            merged.push(c);
+        }
+        merged
+    }
+
+    //Plain stores, the lines copied are left to flush
+    fn copy(&mut self) {
+        for c in self.merged_copies() {
+            unsafe { ptr::copy_nonoverlapping(c.src_ as *const u8, c.dst_ as *mut u8, c.len_) };
+            self.ranges_.push((c.dst_, c.len_));
+        }
+    }
+
+    //Non-temporal stores, flushing the partial lines.
+    //Returns the number of lines streamed.
+    fn stream(&mut self, strategy: CopyStrategy) -> usize {
+        let mut total = 0;
+        for c in self.merged_copies() {
+            strategy.copy_nodrain(c.dst_ as *mut u8, c.src_ as *mut u8, c.len_);
+            total += lines_of(c.dst_ as *const u8, c.len_);
+        }
+        total
+    }
+
+    //Cache line aligned spans covering the ranges, as
+    //(start, lines)
+    pub fn spans(&self) -> Vec<(usize, usize)> {
+        let mut lines: Vec<(usize, usize)> = self
+            .ranges_
+            .iter()
+            .map(|&(addr, len)| (line_of(addr), line_of(addr + len - 1) + 1))
+            .collect();
+        lines.sort();
+
+        let mut spans: Vec<(usize, usize)> = Vec::with_capacity(lines.len());
+        for (start, end) in lines {
+            if let Some(last) = spans.last_mut() {
+                if start <= last.1 {
+                    last.1 = cmp::max(last.1, end);
+                    continue;
+                }
+            }
+            spans.push((start, end));
+        }
+
+        spans
+            .into_iter()
+            .map(|(start, end)| (start * CACHE_LINE_SIZE, end - start))
+            .collect()
+    }
+
+    //Copies then flushes, without draining.
+    //Returns the number of lines flushed.
+    pub fn issue(mut self) -> usize {
+        self.write_back()
+    }
+
+    //issue(), leaving the plan empty
+    fn write_back(&mut self) -> usize {
+        #[cfg(feature = "porder")]
+        self.tag();
+        self.ranges_.extend(self.allocs_.drain(..));
+
+        if pnvm_sys::skips_flushes() {
+            self.copy();
+            self.ranges_.clear();
+            return 0;
+        }
+
+        let strategy = pnvm_sys::copy_strategy();
+        let mut total = 0;
+        if strategy.streams() {
+            total += self.stream(strategy);
+        } else {
+            self.copy();
+        }
+
+        for (start, lines) in self.spans() {
+            strategy.flush(start as *mut u8, lines * CACHE_LINE_SIZE);
+            total += lines;
+        }
+        self.ranges_.clear();
+
+        BenchmarkCounter::flush(total);
+        total
+    }
+
+    #[cfg(feature = "porder")]
+    fn tag(&self) {
+        for c in self.copies_.iter() {
+            porder::tag(Role::Data, self.tid_, c.dst_ as *const u8, c.len_);
+        }
+        for &(addr, len) in self.ranges_.iter() {
+            porder::tag(Role::Data, self.tid_, addr as *const u8, len);
+        }
+        for &(addr, len) in self.allocs_.iter() {
+            porder::tag(Role::Alloc, self.tid_, addr as *const u8, len);
+        }
+    }
+}
+
+fn line_of(addr: usize) -> usize {
+    addr / CACHE_LINE_SIZE
+}
+
+//Cache lines spanned by len bytes at addr
+pub fn lines_of(addr: *const u8, len: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let addr = addr as usize;
+    line_of(addr + len - 1) - line_of(addr) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_into_lines() {
+        let mut plan = FlushPlan::new();
+        let base = 64 * 1024;
+
+        /* Two fields of one line, a row over the next two, and a far one */
+        plan.add_flush((base + 8) as *mut u8, 8);
+        plan.add_flush((base + 40) as *mut u8, 4);
+        plan.add_flush((base + 64) as *mut u8, 100);
+        plan.add_flush((base + 640) as *mut u8, 1);
+
+        assert_eq!(plan.spans(), vec![(base, 3), (base + 640, 1)]);
+        assert_eq!(lines_of((base + 60) as *const u8, 8), 2);
+    }
+
+    #[test]
+    fn test_merge_copies() {
+        let src: Vec<u8> = (0..256).map(|i| i as u8).collect();
+        let mut dst = vec![0u8; 256];
+        let (s, d) = (src.as_ptr() as *mut u8, dst.as_mut_ptr());
+
+        let plan = || {
+            let mut plan = FlushPlan::new();
+            plan.add_copy(d.wrapping_add(16), s.wrapping_add(16), 16);
+            plan.add_copy(d, s, 16);
+            plan.add_copy(d, s, 8);
+            plan.add_copy(d.wrapping_add(128), s.wrapping_add(64), 8);
+            plan
+        };
+
+        let copies = plan().merged_copies();
+        assert_eq!(copies.len(), 2);
+        assert_eq!((copies[0].dst_, copies[0].len_), (d as usize, 32));
+        assert_eq!((copies[1].dst_, copies[1].len_), (d as usize + 128, 8));
+
+        plan().copy();
+        assert_eq!(&dst[..32], &src[..32]);
+        assert_eq!(&dst[32..128], &[0u8; 96][..]);
+        assert_eq!(&dst[128..136], &src[64..72]);
+    }
+}
@@ -0,0 +1,361 @@
+//************************************************
+//Offline serializability checker
+//
+//Builds the direct serialization graph of a recorded
+//history and reports its cycles.
+//
+//Edges (T1 -> T2 means T1 must be serialized before T2):
+//- WW: T2 overwrote the version installed by T1
+//- WR: T2 read the version installed by T1
+//- RW: T1 read a version that T2 overwrote
+//
+//Two transactions overwriting the same version is a lost
+//update, and is reported as a WW cycle between them.
+//Versions written by transactions missing from the history
+//(e.g. the initial load) do not add edges.
+//************************************************
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+};
+
+use super::recorder::TxnRecord;
+use tcore::ObjectId;
+
+const MAX_REPORTED_CYCLES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DepKind {
+    WW,
+    WR,
+    RW,
+}
+
+#[derive(Debug)]
+pub struct Cycle {
+    pub txns_:  Vec<TxnRecord>,
+    pub kinds_: Vec<DepKind>, /* kinds_[i] is the edge from txns_[i] to txns_[i + 1] */
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[CYCLE]")?;
+        for (txn, kind) in self.txns_.iter().zip(self.kinds_.iter()) {
+            write!(f, " {} -{:?}->", txn.tid_, kind)?;
+        }
+        writeln!(f, " {}", self.txns_[0].tid_)?;
+
+        for txn in self.txns_.iter() {
+            writeln!(
+                f,
+                "\t[TID: {}][SEQ: {}]\n\t\t[READS: {:?}]\n\t\t[WRITES: {:?}]",
+                txn.tid_, txn.seq_, txn.reads_, txn.writes_
+            )?;
+        }
+        Ok(())
+    }
+}
+
+type Graph = HashMap<u32, Vec<(u32, DepKind)>>;
+
+//Return Ok when the history is conflict serializable, or at most
+//MAX_REPORTED_CYCLES cycles of the serialization graph otherwise.
+pub fn check_serializable(records: &[TxnRecord]) -> Result<(), Vec<Cycle>> {
+    let graph = build_graph(records);
+
+    let txns: HashMap<u32, &TxnRecord> = records.iter().map(|r| (r.tid_, r)).collect();
+    let cycles: Vec<Cycle> = find_cycles(&graph, records)
+        .into_iter()
+        .map(|cycle| Cycle {
+            txns_:  cycle.iter().map(|(tid, _)| txns[tid].clone()).collect(),
+            kinds_: cycle.iter().map(|(_, kind)| *kind).collect(),
+        })
+        .collect();
+
+    if cycles.is_empty() {
+        Ok(())
+    } else {
+        for cycle in cycles.iter() {
+            warn!("check_serializable::{}", cycle);
+        }
+        Err(cycles)
+    }
+}
+
+//A serial order of the transactions consistent with the serialization
+//graph. Independent transactions keep their commit order.
+pub fn serial_order(records: &[TxnRecord]) -> Result<Vec<u32>, Vec<Cycle>> {
+    check_serializable(records)?;
+
+    let graph = build_graph(records);
+    let seqs: HashMap<u32, usize> = records.iter().map(|r| (r.tid_, r.seq_)).collect();
+    let mut in_degree: HashMap<u32, usize> = records.iter().map(|r| (r.tid_, 0)).collect();
+    for (next, _) in graph.values().flat_map(|edges| edges.iter()) {
+        *in_degree.get_mut(next).unwrap() += 1;
+    }
+
+    let mut ready: BinaryHeap<Reverse<(usize, u32)>> = in_degree
+        .iter()
+        .filter(|&(_, degree)| *degree == 0)
+        .map(|(tid, _)| Reverse((seqs[tid], *tid)))
+        .collect();
+
+    let mut order = Vec::with_capacity(records.len());
+    while let Some(Reverse((_, tid))) = ready.pop() {
+        order.push(tid);
+        for (next, _) in graph.get(&tid).map(|e| e.as_slice()).unwrap_or(&[]) {
+            let degree = in_degree.get_mut(next).unwrap();
+            *degree -= 1;
+            if *degree == 0 {
+                ready.push(Reverse((seqs[next], *next)));
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+//The transactions whose writes each transaction overwrote or read (WW, WR).
+//These have to be durable before the transaction itself is.
+pub fn write_deps(records: &[TxnRecord]) -> HashMap<u32, Vec<u32>> {
+    let mut deps: HashMap<u32, Vec<u32>> = HashMap::with_capacity(records.len());
+    for (from, edges) in build_graph(records).iter() {
+        for (to, kind) in edges.iter() {
+            if *kind == DepKind::RW {
+                continue;
+            }
+            let preds = deps.entry(*to).or_insert_with(Vec::new);
+            if !preds.contains(from) {
+                preds.push(*from);
+            }
+        }
+    }
+    deps
+}
+
+fn add_edge(graph: &mut Graph, from: u32, to: u32, kind: DepKind) {
+    if from != to {
+        graph.entry(from).or_insert_with(Vec::new).push((to, kind));
+    }
+}
+
+fn build_graph(records: &[TxnRecord]) -> Graph {
+    let nodes: HashSet<u32> = records.iter().map(|r| r.tid_).collect();
+    let mut graph: Graph = HashMap::with_capacity(records.len());
+
+    /* Who overwrote each version */
+    let mut next_writers: HashMap<(ObjectId, u32), Vec<u32>> = HashMap::new();
+    for r in records.iter() {
+        for (oid, prev) in r.writes_.iter() {
+            let writers = next_writers.entry((*oid, *prev)).or_insert_with(Vec::new);
+            if !writers.contains(&r.tid_) {
+                writers.push(r.tid_);
+            }
+        }
+    }
+
+    for r in records.iter() {
+        for (_, prev) in r.writes_.iter() {
+            if nodes.contains(prev) {
+                add_edge(&mut graph, *prev, r.tid_, DepKind::WW);
+            }
+        }
+
+        for (oid, vers) in r.reads_.iter() {
+            if nodes.contains(vers) {
+                add_edge(&mut graph, *vers, r.tid_, DepKind::WR);
+            }
+
+            if let Some(writers) = next_writers.get(&(*oid, *vers)) {
+                for w in writers.iter() {
+                    add_edge(&mut graph, r.tid_, *w, DepKind::RW);
+                }
+            }
+        }
+    }
+
+    /* Lost updates */
+    for writers in next_writers.values() {
+        for a in writers.iter() {
+            for b in writers.iter() {
+                add_edge(&mut graph, *a, *b, DepKind::WW);
+            }
+        }
+    }
+
+    graph
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Color {
+    White,
+    Gray,
+    Black,
+}
+
+//Iterative DFS, one cycle is reported per back edge found
+fn find_cycles(graph: &Graph, records: &[TxnRecord]) -> Vec<Vec<(u32, DepKind)>> {
+    let mut cycles = vec![];
+    let mut color: HashMap<u32, Color> = records.iter().map(|r| (r.tid_, Color::White)).collect();
+    let no_edges = vec![];
+
+    for root in records.iter().map(|r| r.tid_) {
+        if color[&root] != Color::White {
+            continue;
+        }
+
+        /* (node, next edge idx, kind of the edge into node) */
+        let mut stack: Vec<(u32, usize, DepKind)> = vec![(root, 0, DepKind::WW)];
+        let mut on_path: HashMap<u32, usize> = HashMap::new();
+        color.insert(root, Color::Gray);
+        on_path.insert(root, 0);
+
+        while !stack.is_empty() {
+            let top = stack.len() - 1;
+            let (node, idx, _) = stack[top];
+            let edges = graph.get(&node).unwrap_or(&no_edges);
+            if idx >= edges.len() {
+                color.insert(node, Color::Black);
+                on_path.remove(&node);
+                stack.pop();
+                continue;
+            }
+
+            let (next, kind) = edges[idx];
+            stack[top].1 += 1;
+
+            match color[&next] {
+                Color::White => {
+                    color.insert(next, Color::Gray);
+                    on_path.insert(next, stack.len());
+                    stack.push((next, 0, kind));
+                }
+                Color::Gray => {
+                    let start = on_path[&next];
+                    let cycle: Vec<(u32, DepKind)> = (start..stack.len())
+                        .map(|i| {
+                            let out_kind = if i + 1 < stack.len() {
+                                stack[i + 1].2
+                            } else {
+                                kind
+                            };
+                            (stack[i].0, out_kind)
+                        })
+                        .collect();
+                    cycles.push(cycle);
+
+                    if cycles.len() >= MAX_REPORTED_CYCLES {
+                        return cycles;
+                    }
+                }
+                Color::Black => {}
+            }
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcore::OidFac;
+
+    fn record(
+        tid: u32,
+        seq: usize,
+        reads: Vec<(ObjectId, u32)>,
+        writes: Vec<(ObjectId, u32)>,
+    ) -> TxnRecord {
+        TxnRecord {
+            tid_:    tid,
+            seq_:    seq,
+            reads_:  reads,
+            writes_: writes,
+        }
+    }
+
+    #[test]
+    fn test_serial_history() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+
+        let records = vec![
+            record(1, 1, vec![(y, 0)], vec![(x, 0)]),
+            record(2, 2, vec![(x, 1)], vec![(x, 1), (y, 0)]),
+            record(3, 3, vec![(x, 2), (y, 2)], vec![]),
+        ];
+
+        assert_eq!(check_serializable(&records).is_ok(), true);
+    }
+
+    #[test]
+    fn test_write_skew_detected() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+
+        let records = vec![
+            record(1, 1, vec![(x, 0), (y, 0)], vec![(x, 0)]),
+            record(2, 2, vec![(x, 0), (y, 0)], vec![(y, 0)]),
+        ];
+
+        let cycles = check_serializable(&records).unwrap_err();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].kinds_, vec![DepKind::RW, DepKind::RW]);
+
+        let mut tids: Vec<u32> = cycles[0].txns_.iter().map(|t| t.tid_).collect();
+        tids.sort();
+        assert_eq!(tids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_lost_update_detected() {
+        let x = OidFac::get_obj_next();
+
+        let records = vec![
+            record(1, 1, vec![], vec![(x, 0)]),
+            record(2, 2, vec![], vec![(x, 0)]),
+        ];
+
+        assert_eq!(check_serializable(&records).is_err(), true);
+    }
+
+    #[test]
+    fn test_serial_order_follows_deps() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+
+        /* T2 committed first, but read the x written by T1 */
+        let records = vec![
+            record(2, 1, vec![(x, 1)], vec![(y, 0)]),
+            record(1, 2, vec![], vec![(x, 0)]),
+            record(3, 3, vec![], vec![]),
+        ];
+        assert_eq!(serial_order(&records).unwrap(), vec![1, 2, 3]);
+        assert_eq!(write_deps(&records)[&2], vec![1]);
+        assert_eq!(write_deps(&records).contains_key(&1), false);
+    }
+
+    #[test]
+    fn test_dirty_read_cycle_detected() {
+        let x = OidFac::get_obj_next();
+        let y = OidFac::get_obj_next();
+
+        /* T2 reads T1's x, T1 reads y before T2 overwrites it */
+        let records = vec![
+            record(1, 1, vec![(y, 0)], vec![(x, 0)]),
+            record(2, 2, vec![(x, 1)], vec![(y, 0)]),
+            record(3, 3, vec![(x, 1), (y, 2)], vec![]),
+        ];
+        assert_eq!(check_serializable(&records).is_ok(), true);
+
+        let records = vec![
+            record(1, 1, vec![(y, 2)], vec![(x, 0)]),
+            record(2, 2, vec![(x, 1)], vec![(y, 0)]),
+        ];
+        let cycles = check_serializable(&records).unwrap_err();
+        assert_eq!(cycles[0].kinds_, vec![DepKind::WR, DepKind::WR]);
+    }
+}
@@ -0,0 +1,108 @@
+//************************************************
+//Execution history recorder
+//
+//Records the read versions, overwritten versions and
+//commit order of every committed transaction, so that
+//the checker can validate serializability offline.
+//
+//Recording is only done by the transactions when the
+//"history" feature is on.
+//
+//Types:
+//- TxnRecord           history of a single transaction
+//- HistoryRecorder     per-thread and global buffers
+//************************************************
+
+use std::{
+    cell::RefCell,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Mutex,
+};
+
+use tcore::{ObjectId, TTag};
+use txn::Tid;
+
+thread_local! {
+    pub static HISTORY: RefCell<Vec<TxnRecord>> = RefCell::new(Vec::with_capacity(1024));
+}
+
+lazy_static! {
+    static ref HISTORIES: Mutex<Vec<TxnRecord>> = Mutex::new(Vec::new());
+}
+
+/* Global commit order */
+static COMMIT_SEQ: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Clone, Debug)]
+pub struct TxnRecord {
+    pub tid_:    u32,
+    pub seq_:    usize,                /* Commit order, 0 before commit */
+    pub reads_:  Vec<(ObjectId, u32)>, /* (object, version read) */
+    pub writes_: Vec<(ObjectId, u32)>, /* (object, version overwritten) */
+}
+
+impl TxnRecord {
+    pub fn new(tid: Tid) -> TxnRecord {
+        TxnRecord {
+            tid_:    tid.into(),
+            seq_:    0,
+            reads_:  Vec::with_capacity(16),
+            writes_: Vec::with_capacity(16),
+        }
+    }
+
+    pub fn add_read(&mut self, oid: ObjectId, vers: u32) {
+        self.reads_.push((oid, vers));
+    }
+
+    pub fn add_write(&mut self, oid: ObjectId, prev: u32) {
+        self.writes_.push((oid, prev));
+    }
+
+    //Must be called with the write set locked and before the data is installed,
+    //so that the current versions are the ones being overwritten.
+    pub fn add_tags<'a, I>(&mut self, tags: I)
+    where
+        I: Iterator<Item = &'a TTag>,
+    {
+        for tag in tags {
+            if tag.has_read() {
+                self.add_read(tag.oid_, tag.vers_);
+            }
+            if tag.has_write() {
+                self.add_write(tag.oid_, tag.get_version());
+            }
+        }
+    }
+
+    //Take the commit order and append to the thread local history
+    pub fn commit(mut self) {
+        self.seq_ = COMMIT_SEQ.fetch_add(1, Ordering::SeqCst);
+        HISTORY.with(|h| h.borrow_mut().push(self));
+    }
+}
+
+pub struct HistoryRecorder;
+
+impl HistoryRecorder {
+    /* Thread Local methods */
+    //Move the thread local history into the global one.
+    //Benchmark threads call this before they exit.
+    pub fn flush_thd() {
+        let records = HISTORY.with(|h| mem::replace(&mut *h.borrow_mut(), Vec::new()));
+        HISTORIES.lock().unwrap().extend(records);
+    }
+
+    /* Thread Local methods */
+    pub fn thd_len() -> usize {
+        HISTORY.with(|h| h.borrow().len())
+    }
+
+    //Take all flushed histories, ordered by commit
+    pub fn take_all() -> Vec<TxnRecord> {
+        let mut records = mem::replace(&mut *HISTORIES.lock().unwrap(), Vec::new());
+        records.sort_by_key(|r| r.seq_);
+        records
+    }
+}
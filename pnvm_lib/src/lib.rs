@@ -35,13 +35,26 @@ extern crate strum;
 #[macro_use]
 extern crate strum_macros;
 
+#[cfg(loom)]
+extern crate loom;
+
 //#[cfg(any(feature = "pmem", feature ="disk"))]
 pub mod plog;
 
+#[cfg(feature = "crash")]
+pub mod crash;
 pub mod datatype;
+pub mod db;
+pub mod flush;
+pub mod history;
 pub mod lock;
+#[cfg(feature = "mvcc")]
+pub mod mvcc;
 pub mod occ;
 pub mod parnvm;
+pub mod persistency;
+pub mod storage;
+pub mod sync;
 pub mod tcore;
 pub mod txn;
 
@@ -50,282 +63,329 @@ mod tests {
     extern crate crossbeam;
     extern crate env_logger;
 
+    use super::datatype::tbox::{TBox, TInt};
     use super::occ::map::TMap;
     use super::occ::occ_txn::TransactionOCC;
-    //use super::tbox::TBox;
-    use super::tcore::TObject;
-    use super::txn;
-
-    // #[test]
-    // fn test_single_read() {
-    //     let _ = env_logger::init();
-    //     super::tcore::init();
-    //     let tb : TObject<u32> = TBox::new(1);
-    //     {
-    //         let tx = &mut TransactionOCC::new(Tid::new(1), true);
-    //         let val = tx.read(&tb);
-    //         tx.try_commit();
-    //     }
-    // }
-
-    // #[test]
-    // fn test_single_write() {
-    //     let _ = env_logger::init();
-    //     super::tcore::init();
-    //     let tb : TObject<u32> = TBox::new(1);
-    //     {
-    //         let tx = &mut TransactionOCC::new(Tid::new(1), true);
-    //         tx.write(&tb, 2);
-    //         assert_eq!(tx.try_commit(), true);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb), 2);
-    //     }
-    // }
-
-    // #[test]
-    // fn test_concurrent_read(){
-    //     super::tcore::init();
-    //     let tb1 : TObject<u32> = TBox::new(1);
-    //     let tb2 : TObject<u32> = TBox::new(2);
-
-    //     {
-    //         let tx1 = &mut TransactionOCC::new(Tid::new(1), true);
-    //         let tx2 = &mut TransactionOCC::new(Tid::new(2), true);
-
-    //         assert_eq!(tx1.read(&tb1), 1);
-    //         assert_eq!(tx2.read(&tb1), 1);
-
-    //         assert_eq!(tx1.read(&tb1), 1);
-    //         assert_eq!(tx2.read(&tb2), 2);
-    //
-    //         assert_eq!(tx1.try_commit(), true);
-    //         assert_eq!(tx2.try_commit(), true);
-    //     }
-
-    // }
-
-    // #[test]
-    // fn test_dirty_read_should_abort(){
-    //     super::tcore::init();
-    //     let tb1 : TObject<u32> = TBox::new(1);
-
-    //     {
-    //
-    //         let tx1 = &mut TransactionOCC::new(Tid::new(1), true);
-    //         let tx2 = &mut TransactionOCC::new(Tid::new(2), true);
-
-    //         assert_eq!(tx1.read(&tb1), 1);
-    //         tx2.write(&tb1, 2);
-    //
-    //         assert_eq!(tx2.try_commit(), true);
-    //         assert_eq!(tx1.try_commit(), false);
-    //
-    //     }
-    // }
-    //
-    // #[test]
-    // fn test_writes_in_order() {
-    //     super::tcore::init();
-
-    //     let tb1 : TObject<u32> = TBox::new(1);
-
-    //     {
-    //
-    //         let tx1 = &mut TransactionOCC::new(Tid::new(1), true);
-    //         let tx2 = &mut TransactionOCC::new(Tid::new(2), true);
-
-    //         tx1.write(&tb1, 10);
-    //         tx2.write(&tb1, 9999);
-    //
-    //         assert_eq!(tx2.try_commit(), true);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb1), 9999);
-    //         assert_eq!(tx1.try_commit(), true);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb1), 10);
-    //     }
-    //
-    // }
-
-    // #[test]
-    // fn test_read_own_write() {
-    //     super::tcore::init();
-    //     let tb1 : TObject<u32> = TBox::new(1);
-
-    //     {
-    //
-    //         let tx1 = &mut TransactionOCC::new(Tid::new(1), true);
-    //         assert_eq!(tx1.read(&tb1), 1);
-    //         tx1.write(&tb1, 10);
-    //         assert_eq!(tx1.read(&tb1), 10);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb1), 1);
-
-    //         assert_eq!(tx1.try_commit(), true);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb1), 10);
-    //     }
-    // }
-
-    // #[test]
-    // fn test_conflict_write_aborts() {
-    //
-    //     super::tcore::init();
-    //     let tb : TObject<u32> = TBox::new(1);
-    //     {
-    //         let tx = &mut TransactionOCC::new(Tid::new(1), true);
-    //         tx.write(&tb, 2);
-    //         assert_eq!(tx.read(&tb), 2);
-
-    //         TransactionOCC::notrans_lock(&tb, Tid::new(99));
-
-    //         assert_eq!(tx.try_commit(), false);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb), 1);
-    //     }
-    //
-    // }
-
-    // #[test]
-    // fn test_read_string() {
-    //
-    //     super::tcore::init();
-    //     let tb : TObject<String> = TBox::new(String::from("hillo"));
-
-    //     {
-
-    //         let tx = &mut TransactionOCC::new(Tid::new(1), true);
-    //         assert_eq!(tx.read(&tb), String::from("hillo"));
-
-    //         tx.write(&tb, String::from("world"));
-    //         assert_eq!(tx.read(&tb), String::from("world"));
-
-    //         assert_eq!(TransactionOCC::notrans_read(&tb), String::from("hillo"));
-    //         assert_eq!(tx.try_commit(), true);
-    //         assert_eq!(TransactionOCC::notrans_read(&tb), String::from("world"));
-    //     }
-
-    // }
-
-    // #[test]
-    // fn test_read_hashmap() {
-
-    //     super::tcore::init();
-
-    // }
-    //
-    // use super::parnvm::piece::{Pid, Piece};
-    // use std::{
-    //     rc::Rc,
-    //     cell::RefCell,
-    // };
-
-    // #[test]
-    // fn test_piece_run(){
-    //     let x = Rc::new(RefCell::new(1));
-    //     let mut piece = Piece::new(Pid::new(1), Tid::new(1), Box::new(|| {
-    //         let mut x = x.borrow_mut();
-    //         *x += 1;
-    //         *x
-    //     }));
-    //
-    //     assert_eq!(*(x.borrow()), 1);
-    //     piece.run();
-    //     assert_eq!(*(x.borrow()), 2);
-    // }
-
-    use super::parnvm::{dep::*, map::*, nvm_txn::*, piece::*};
-    use std::{
-        fs::File,
-        sync::{Arc, Barrier, RwLock},
-        thread,
+    use super::parnvm::{
+        nvm_txn_occ::{TransactionParBaseOCC, TransactionParOCC},
+        piece::{Pid, PieceOCC},
     };
-
-    // use super::table::*;
-    // use super::entry::*;
-    // #[test]
-    // fn test_table_new() {
-    //     let table = Table::<Warehouse, i32>::new();
-    //     let warehouse1 = Warehouse {
-    //         w_id: 1,
-    //         w_name: "Singapore".to_string(),
-    //     };
-    //     table.push(warehouse1);
-    //     let wh2 = table.retrieve(&1).expect("non empty").get_ref();
-    //     assert_eq!(wh2.w_name, "Singapore".to_string());
-
-    //     let mut wh2 = table.retrieve(&1).expect("non empty").get_mut();
-    //     (*wh2).w_name = "China".to_string();
-    //
-    //     let wh3 = table.retrieve(&1).expect("non empty").get_ref();
-    //     assert_eq!(wh3.w_name, "China".to_string());
-    // }
-
-    //#[test]
-    //fn test_single_piece_run() {
-    //
-    //    let data_map = Arc::new(PMap::new());
-    //    let barrier = Arc::new(Barrier::new(1));
-    //
-    //    let piece = Piece::new(
-    //        Pid::new(1),
-    //        "TXN_1".to_string(),
-    //        Arc::new(Box::new(move|tx : &mut TransactionPar| {
-    //            let val = PValue::new(1 as u32, tx);
-    //            data_map.insert(1,val);
-
-    //            let g = data_map.get(&1).unwrap();
-    //            let g1 = data_map.get(&1).unwrap();
-    //            println!("Read {}", (*g.read(tx).as_ref().unwrap()));
-
-    //            let id :u32 = tx.id().into();
-    //            let mut write_g = g1.write(tx);
-    //            let mut i = 0;
-    //            *write_g.as_mut().unwrap() = id * 100;
-
-    //            println!("Read {}", (*g.read(tx).as_ref().unwrap()));
-    //            1
-    //        })),
-    //        "insert-read",
-    //        1);
-    //
-    //    let txn_base = TransactionParBase::new(vec![piece], "TXN_1".to_string());
-
-    //    let tx = TransactionPar::new_from_base(&txn_base, Tid::new(1));
-    //
-    //
-    //    crossbeam::scope(|scope| {
-    //        //Prepare TXN1
-    //        let txn_base = txn_base.clone();
-    //        let c = barrier.clone();
-
-    //        let handler = scope.spawn(move|| {
-    //            c.wait();
-    //            let tx = TransactionPar::new_from_base(&txn_base, Tid::new(2));
-    //            TransactionPar::register(tx);
-    //            TransactionPar::execute();
-    //        });
-    //
-    //        barrier.wait();
-    //        TransactionPar::register(tx);
-    //        TransactionPar::execute();
-
-    //        handler.join();
-    //    });
-
-    //    // Dump the report to disk
-    //}
-
-    use test::Bencher;
-
-    //#[bench]
-    //fn bench_map_get(b: &mut Bencher) {
-    //    let map : Arc<TMap<u32, u32>> = Arc::new(TMap::new());
-    //    for key in 0..10000 {
-    //        map.insert(key as u32,TBox::new(key as u32));
-    //    }
-
-    //    let mut tx = TransactionOCC::new(Tid::new(1));
-    //
-    //    b.iter(|| {
-    //        let g = map.get(&1).unwrap();
-    //        let tag = tx.retrieve_tag(g.get_id(), g.clone());
-    //    });
-
-    //}
-
+    use super::tcore::{BoxRef, TRef};
+    use super::txn::{Tid, TidFac, Transaction, TxState};
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_single_read() {
+        let _ = env_logger::init();
+        let tb = TBox::new(1 as u32);
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            assert_eq!(*tx.read::<u32>(tb.clone().into_box_ref()), 1);
+            assert_eq!(tx.try_commit(), true);
+        }
+    }
+
+    #[test]
+    fn test_single_write() {
+        let tb = TBox::new(1 as u32);
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            tx.write(tb.clone().into_box_ref(), 2 as u32);
+            assert_eq!(tx.try_commit(), true);
+            assert_eq!(tb.raw_read(), 2);
+            assert_eq!(tb.get_version(), 1);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_read() {
+        let tb1 = TBox::new(1 as u32);
+        let tb2 = TBox::new(2 as u32);
+
+        {
+            let tx1 = &mut TransactionOCC::new(Tid::new(1));
+            let tx2 = &mut TransactionOCC::new(Tid::new(2));
+
+            assert_eq!(*tx1.read::<u32>(tb1.clone().into_box_ref()), 1);
+            assert_eq!(*tx2.read::<u32>(tb1.clone().into_box_ref()), 1);
+
+            assert_eq!(*tx1.read::<u32>(tb1.clone().into_box_ref()), 1);
+            assert_eq!(*tx2.read::<u32>(tb2.clone().into_box_ref()), 2);
+
+            assert_eq!(tx1.try_commit(), true);
+            assert_eq!(tx2.try_commit(), true);
+        }
+    }
+
+    #[test]
+    fn test_dirty_read_should_abort() {
+        let tb1 = TBox::new(1 as u32);
+
+        {
+            let tx1 = &mut TransactionOCC::new(Tid::new(1));
+            let tx2 = &mut TransactionOCC::new(Tid::new(2));
+
+            assert_eq!(*tx1.read::<u32>(tb1.clone().into_box_ref()), 1);
+            tx2.write(tb1.clone().into_box_ref(), 2 as u32);
+
+            assert_eq!(tx2.try_commit(), true);
+            assert_eq!(tx1.try_commit(), false);
+        }
+    }
+
+    #[test]
+    fn test_writes_in_order() {
+        let tb1 = TBox::new(1 as u32);
+
+        {
+            let tx1 = &mut TransactionOCC::new(Tid::new(1));
+            let tx2 = &mut TransactionOCC::new(Tid::new(2));
+
+            tx1.write(tb1.clone().into_box_ref(), 10 as u32);
+            tx2.write(tb1.clone().into_box_ref(), 9999 as u32);
+
+            /* Blind writes do not conflict, the last to commit wins */
+            assert_eq!(tx2.try_commit(), true);
+            assert_eq!(tb1.raw_read(), 9999);
+            assert_eq!(tx1.try_commit(), true);
+            assert_eq!(tb1.raw_read(), 10);
+        }
+    }
+
+    #[test]
+    fn test_read_own_write() {
+        let tb1 = TBox::new(1 as u32);
+
+        {
+            let tx1 = &mut TransactionOCC::new(Tid::new(1));
+            assert_eq!(*tx1.read::<u32>(tb1.clone().into_box_ref()), 1);
+            tx1.write(tb1.clone().into_box_ref(), 10 as u32);
+            assert_eq!(*tx1.read::<u32>(tb1.clone().into_box_ref()), 10);
+            assert_eq!(tb1.raw_read(), 1);
+
+            assert_eq!(tx1.try_commit(), true);
+            assert_eq!(tb1.raw_read(), 10);
+        }
+    }
+
+    #[test]
+    fn test_lost_update_should_abort() {
+        let tb1 = TBox::new(1 as u32);
+
+        {
+            let tx1 = &mut TransactionOCC::new(Tid::new(1));
+            let tx2 = &mut TransactionOCC::new(Tid::new(2));
+
+            /* Both increment from the same snapshot */
+            let val = *tx1.read::<u32>(tb1.clone().into_box_ref());
+            tx1.write(tb1.clone().into_box_ref(), val + 1);
+            let val = *tx2.read::<u32>(tb1.clone().into_box_ref());
+            tx2.write(tb1.clone().into_box_ref(), val + 1);
+
+            assert_eq!(tx2.try_commit(), true);
+            assert_eq!(tx1.try_commit(), false);
+            assert_eq!(tb1.raw_read(), 2);
+        }
+    }
+
+    #[test]
+    fn test_conflict_write_aborts() {
+        let tb = TBox::new(1 as u32);
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            tx.write(tb.clone().into_box_ref(), 2 as u32);
+            assert_eq!(*tx.read::<u32>(tb.clone().into_box_ref()), 2);
+
+            assert_eq!(tb.lock(Tid::new(99)), true);
+
+            assert_eq!(tx.try_commit(), false);
+            assert_eq!(tb.raw_read(), 1);
+            tb.unlock();
+        }
+    }
+
+    #[test]
+    fn test_read_string() {
+        let tb = TBox::new(String::from("hillo"));
+
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            assert_eq!(
+                *tx.read::<String>(tb.clone().into_box_ref()),
+                String::from("hillo")
+            );
+
+            tx.write(tb.clone().into_box_ref(), String::from("world"));
+            assert_eq!(
+                *tx.read::<String>(tb.clone().into_box_ref()),
+                String::from("world")
+            );
+
+            assert_eq!(tb.raw_read(), String::from("hillo"));
+            assert_eq!(tx.try_commit(), true);
+            assert_eq!(tb.raw_read(), String::from("world"));
+        }
+    }
+
+    #[test]
+    fn test_read_hashmap() {
+        let map: TMap<u32, u32> = TMap::new();
+        for key in 0..10 {
+            map.insert(key, TBox::new(key * 10));
+        }
+        assert_eq!(map.get_ref(&10).is_none(), true);
+
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            let val = *tx.read::<u32>(map.get_ref(&3).unwrap());
+            assert_eq!(val, 30);
+            tx.write(map.get_ref(&4).unwrap(), val + 1);
+            assert_eq!(tx.try_commit(), true);
+        }
+
+        assert_eq!(map.get(&4).unwrap().get().raw_read(), 31);
+    }
+
+    #[test]
+    fn test_write_field() {
+        let tb = TBox::new(1 as u32);
+        let tref = TInt::new(tb.clone());
+        assert_eq!(tref.get_field_size(0), 4);
+        assert_eq!(tref.get_field_ptr(0), tb.get_ptr());
+
+        {
+            let tx = &mut TransactionOCC::new(Tid::new(1));
+            tx.write_field(tb.clone().into_box_ref(), 7 as u32, vec![0]);
+            assert_eq!(*tx.read::<u32>(tb.clone().into_box_ref()), 7);
+            assert_eq!(tx.try_commit(), true);
+            assert_eq!(tb.raw_read(), 7);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_increments() {
+        const THREAD_NUM: u32 = 4;
+        const TXN_NUM: u32 = 100;
+        let tb = TBox::new(0 as u32);
+
+        let handles: Vec<_> = (0..THREAD_NUM)
+            .map(|i| {
+                let tb = tb.clone();
+                thread::spawn(move || {
+                    TidFac::set_thd_mask(i + 1);
+                    for _ in 0..TXN_NUM {
+                        loop {
+                            let tx = &mut TransactionOCC::new(TidFac::get_thd_next());
+                            let val = *tx.read::<u32>(tb.clone().into_box_ref());
+                            tx.write(tb.clone().into_box_ref(), val + 1);
+                            if tx.try_commit() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(tb.raw_read(), THREAD_NUM * TXN_NUM);
+    }
+
+    fn make_piece<F>(pid: u32, rank: usize, title: &str, cb: F) -> PieceOCC
+    where
+        F: Fn(&mut TransactionParOCC) + Send + Sync + 'static,
+    {
+        PieceOCC::new(
+            Pid::new(pid),
+            String::from("test"),
+            Arc::new(Box::new(cb)),
+            title,
+            rank,
+        )
+    }
+
+    #[test]
+    fn test_par_occ_pieces() {
+        let a = TBox::new(1 as u32);
+        let b = TBox::new(0 as u32);
+        let c = TBox::new(0 as u32);
+
+        /* Piece 1 copies a into b plus one, piece 2 doubles b into c */
+        let (a1, b1, b2, c2) = (a.clone(), b.clone(), b.clone(), c.clone());
+        let copy = make_piece(1, 1, "copy", move |tx| {
+            let val = *tx.read::<u32>(a1.clone().into_box_ref());
+            tx.write(b1.clone().into_box_ref(), val + 1);
+            tx.add_output(Box::new(val + 1), 0);
+        });
+        let double = make_piece(2, 2, "double", move |tx| {
+            let val = *tx.read::<u32>(b2.clone().into_box_ref());
+            tx.write(c2.clone().into_box_ref(), val * 2);
+            tx.add_output(Box::new(val * 2), 1);
+        });
+
+        /* Pieces are popped from the back */
+        let base = TransactionParBaseOCC::new(vec![double, copy], String::from("test"));
+        let mut tx = TransactionParOCC::new_from_base(&base, Tid::new(1), Box::new(()));
+        tx.execute_txn();
+
+        assert_eq!(*tx.status() == TxState::ABORTED, false);
+        assert_eq!(tx.txn_info().has_commit(), true);
+        assert_eq!(*tx.get_output::<u32>(0), 2);
+        assert_eq!(*tx.get_output::<u32>(1), 4);
+        assert_eq!((a.raw_read(), b.raw_read(), c.raw_read()), (1, 2, 4));
+    }
+
+    #[test]
+    fn test_par_occ_concurrent_pieces() {
+        const THREAD_NUM: u32 = 4;
+        const TXN_NUM: u32 = 50;
+        let a = TBox::new(0 as u32);
+        let b = TBox::new(0 as u32);
+        let c = TBox::new(0 as u32);
+
+        /* Piece 1 increments a, piece 2 increments b and copies the new a
+         * into c. Every object is accessed by a single rank, so the pieces
+         * of all txns run in the same order. */
+        let a1 = a.clone();
+        let incr = make_piece(1, 1, "incr", move |tx| {
+            let val = *tx.read::<u32>(a1.clone().into_box_ref());
+            tx.write(a1.clone().into_box_ref(), val + 1);
+            tx.add_output(Box::new(val + 1), 0);
+        });
+        let (b2, c2) = (b.clone(), c.clone());
+        let copy = make_piece(2, 2, "incr-copy", move |tx| {
+            let a_val = *tx.get_output::<u32>(0);
+            let val = *tx.read::<u32>(b2.clone().into_box_ref());
+            tx.write(b2.clone().into_box_ref(), val + 1);
+            tx.write(c2.clone().into_box_ref(), a_val);
+        });
+        let base = TransactionParBaseOCC::new(vec![copy, incr], String::from("test"));
+
+        let handles: Vec<_> = (0..THREAD_NUM)
+            .map(|i| {
+                let base = base.clone();
+                thread::spawn(move || {
+                    TidFac::set_thd_mask(i + 1);
+                    for _ in 0..TXN_NUM {
+                        let tid = TidFac::get_thd_next();
+                        let mut tx = TransactionParOCC::new_from_base(&base, tid, Box::new(()));
+                        tx.execute_txn();
+                        assert_eq!(*tx.status() == TxState::ABORTED, false);
+                    }
+                })
+            })
+            .collect();
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let total = THREAD_NUM * TXN_NUM;
+        assert_eq!(
+            (a.raw_read(), b.raw_read(), c.raw_read()),
+            (total, total, total)
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use std::{collections::HashMap, sync::Arc};
 
+use storage::varlen;
 use tcore::{self, BenchmarkCounter, FieldArray, ObjectId, TRef, TTag, TVersion};
 use txn::{self, AbortReason, Tid, Transaction, TxState, TxnInfo};
 
@@ -7,7 +8,16 @@ use txn::{self, AbortReason, Tid, Transaction, TxState, TxnInfo};
 use pnvm_sys;
 
 //#[cfg(any(feature = "pmem", feature = "disk"))]
-use plog::{self, PLog};
+use {
+    persistency::{self, PersistencyModel},
+    plog,
+};
+
+#[cfg(feature = "history")]
+use {history::recorder::TxnRecord, std::mem};
+
+#[cfg(feature = "mvcc")]
+use mvcc;
 
 pub struct Transaction2PL {
     tid_:      Tid,
@@ -16,7 +26,13 @@ pub struct Transaction2PL {
     txn_info_: Arc<TxnInfo>,
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     refs_: Vec<(Box<dyn TRef>, Option<FieldArray>)>,
+    //Pushes and deletes, installed at commit
+    installs_: Vec<Box<dyn TRef>>,
+    persistency_: PersistencyModel,
+    var_guard_: varlen::ReadGuard,
     //fields_ : HashMap<ObjectId, FieldArray>,
+    #[cfg(feature = "history")]
+    history_: TxnRecord,
 }
 
 impl Transaction2PL {
@@ -25,9 +41,14 @@ impl Transaction2PL {
             tid_:      id,
             state_:    TxState::EMBRYO,
             locks_:    HashMap::new(),
-            txn_info_: Arc::new(TxnInfo::default()),
+            txn_info_: Arc::new(TxnInfo::new(id)),
             //#[cfg(any(feature = "pmem", feature = "disk"))]
             refs_: Vec::new(),
+            installs_: Vec::new(),
+            persistency_: persistency::persistency_model(),
+            var_guard_: varlen::enter(),
+            #[cfg(feature = "history")]
+            history_: TxnRecord::new(id),
         }
     }
 
@@ -91,6 +112,9 @@ impl Transaction2PL {
     //Read the underlying value of the reference
     //Return none when failed locking
     pub fn read<'a, T: 'static + Clone>(&mut self, tref: &'a Box<dyn TRef>) -> &'a T {
+        #[cfg(feature = "history")]
+        self.history_.add_read(*tref.get_id(), tref.get_version());
+
         match tref.read().downcast_ref::<T>() {
             Some(data) => data,
             None => panic!("inconsistent type at read"),
@@ -100,6 +124,9 @@ impl Transaction2PL {
     //Write a value into the underlying reference
     //Return Result.Err if failed
     pub fn write<T: 'static + Clone>(&mut self, tref: &Box<dyn TRef>, val: T) {
+        #[cfg(feature = "history")]
+        self.history_.add_write(*tref.get_id(), tref.get_version());
+
         tref.write_through(Box::new(val), self.id().clone());
         //#[cfg(any(feature = "pmem", feature = "disk"))]
         self.refs_.push((tref.box_clone(), None));
@@ -111,6 +138,9 @@ impl Transaction2PL {
         val: T,
         fields: FieldArray,
     ) {
+        #[cfg(feature = "history")]
+        self.history_.add_write(*tref.get_id(), tref.get_version());
+
         //Make records for persist later
         tref.write_through(Box::new(val), self.id().clone());
         //Replace current fields
@@ -133,15 +163,52 @@ impl Transaction2PL {
         //#[cfg(any(feature = "pmem", feature = "disk"))]
         self.refs_.clear();
 
+        /* Never visible, so nothing to roll back */
+        self.installs_.clear();
+        varlen::retire(&self.txn_info_);
+
+        /* Nothing of it left to persist */
+        self.txn_info_.commit();
+        self.txn_info_.persist();
+
+        #[cfg(feature = "history")]
+        {
+            self.history_ = TxnRecord::new(self.tid_);
+        }
+
         self.unlock();
     }
 
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     pub fn add_ref(&mut self, tref: Box<dyn TRef>) {
+        #[cfg(feature = "history")]
+        self.history_.add_write(*tref.get_id(), tref.get_version());
+
         self.refs_.push((tref, None));
     }
 
+    //Defers tref.install() until commit, e.g. a push whose
+    //index entries must not outlive an abort
+    pub fn add_install(&mut self, tref: Box<dyn TRef>) {
+        self.installs_.push(tref);
+    }
+
     pub fn commit(&mut self) {
+        /* One timestamp for the pushes and deletes, all locked */
+        #[cfg(feature = "mvcc")]
+        mvcc::reserve();
+
+        let id = self.id();
+        for tref in self.installs_.drain(..) {
+            tref.install(id);
+        }
+
+        #[cfg(feature = "mvcc")]
+        mvcc::publish();
+
+        varlen::retire(&self.txn_info_);
+        plog::persist_slots(id.into());
+
         //Unlocks
         //#[cfg(any(feature = "pmem", feature = "disk"))]
         {
@@ -149,43 +216,27 @@ impl Transaction2PL {
             self.persist_data();
             self.persist_commit();
         }
+        self.txn_info_.commit();
+        self.txn_info_.persist();
 
         BenchmarkCounter::success();
+
+        /* Still holding all the locks */
+        #[cfg(feature = "history")]
+        mem::replace(&mut self.history_, TxnRecord::new(self.tid_)).commit();
+
         self.unlock();
     }
 
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     fn persist_data(&self) {
         #[cfg(feature = "pmem")]
-        for (tref, fields) in self.refs_.iter() {
-            match fields {
-                Some(fields) => {
-                    for field in fields.iter() {
-                        let pmemaddr = tref.get_pmem_field_addr(*field);
-                        let size = tref.get_field_size(*field);
-                        let vaddr = tref.get_field_ptr(*field);
-                        BenchmarkCounter::flush(size);
-
-                        #[cfg(feature = "dir")]
-                        pnvm_sys::flush(pmemaddr, size);
-
-                        #[cfg(not(feature = "dir"))]
-                        pnvm_sys::memcpy_nodrain(pmemaddr, vaddr, size);
-                    }
-                }
-                None => {
-                    BenchmarkCounter::flush(tref.get_layout().size());
-                    let paddr = tref.get_pmem_addr();
-                    let vaddr = tref.get_ptr();
-                    let size = tref.get_layout().size();
-
-                    #[cfg(feature = "dir")]
-                    pnvm_sys::flush(paddr, size);
-
-                    #[cfg(not(feature = "dir"))]
-                    pnvm_sys::memcpy_nodrain(paddr, vaddr, size);
-                }
+        {
+            let mut plan = self.persistency_.data_plan(self.id());
+            for (tref, fields) in self.refs_.iter() {
+                plan.add_ref(&**tref, fields.as_ref());
             }
+            plan.issue();
         }
 
         #[cfg(feature = "disk")]
@@ -203,15 +254,11 @@ impl Transaction2PL {
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     fn persist_log(&self) {
         let mut logs = vec![];
-        for (tref, _field) in self.refs_.iter() {
-            logs.push(PLog::new(
-                tref.get_ptr() as *mut u8,
-                tref.get_layout(),
-                self.id(),
-            ));
+        for (tref, fields) in self.refs_.iter() {
+            logs.extend(tcore::make_logs(&**tref, fields.as_ref(), self.id()));
         }
 
-        plog::persist_log(logs);
+        self.persistency_.persist_log(logs);
     }
 }
 
@@ -0,0 +1,506 @@
+//************************************************
+//Multi-version snapshot reads ("mvcc" feature)
+//
+//Versioned objects (Row, TValue) keep a short chain of
+//their versions. Each version carries the stamp of its
+//writer: PENDING until the writer commits, its commit
+//timestamp after. A snapshot reads, in every object, the
+//newest version stamped at or before its timestamp, with
+//no tag, validation nor dependency.
+//
+//Timestamps follow the serialization order:
+//- TransactionOCC reserves its timestamp with its write
+//  set locked, before validating, and stamps it before
+//  unlocking
+//- TransactionParOCC takes its timestamp at commit, once
+//  its dependencies committed
+//- Transaction2PL reserves its timestamp for the installs
+//  of its commit (pushes, deletes)
+//A snapshot is taken below every reserved timestamp not
+//stamped yet, so all it sees is installed. Writes out of
+//these commits (e.g. the 2PL writes through) are stamped
+//one by one, each with a timestamp reserved until its
+//version is in the chain.
+//
+//Versions are reclaimed by epoch. The epoch is the oldest
+//active snapshot, or the stable timestamp without any: a
+//chain drops the versions overwritten at the epoch when
+//it is next installed to.
+//
+//An inserted row starts its chain with the version of its
+//inserter (VersionChain::insert): a snapshot before the
+//insert, or while the inserter is pending, does not see
+//it (exists_at) and must skip it when it looks it up, see
+//the sees() of the txns. Deletes are not versioned: a
+//snapshot misses the rows deleted from the indexes.
+//
+//Types:
+//- Snapshot            a registered read timestamp
+//- SnapshotReader      snapshot reads of a transaction
+//- VersionChain        versions of an object
+//************************************************
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Arc, Mutex},
+};
+
+use tcore::TRef;
+
+/* Stamp of an uncommitted version */
+const PENDING: usize = usize::max_value();
+
+struct Clock {
+    next_ts_:  usize,
+    reserved_: BTreeSet<usize>,        /* Reserved, not stamped yet */
+    active_:   BTreeMap<usize, usize>, /* Snapshot timestamp -> count */
+}
+
+impl Clock {
+    //Every timestamp at or below is stamped, or abandoned
+    fn stable(&self) -> usize {
+        match self.reserved_.iter().next() {
+            Some(ts) => ts - 1,
+            None => self.next_ts_ - 1,
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        let ts = self.next_ts_;
+        self.next_ts_ += 1;
+        ts
+    }
+
+    fn update_epoch(&self) {
+        let epoch = match self.active_.keys().next() {
+            Some(ts) => *ts,
+            None => self.stable(),
+        };
+        EPOCH.store(epoch, Ordering::Release);
+    }
+}
+
+lazy_static! {
+    static ref CLOCK: Mutex<Clock> = Mutex::new(Clock {
+        next_ts_:  1,
+        reserved_: BTreeSet::new(),
+        active_:   BTreeMap::new(),
+    });
+}
+
+/* Versions overwritten at or before it are reclaimed */
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    //Stamp of the versions of the running commit, and its
+    //reserved timestamp (PENDING if taken at publish)
+    static COMMIT: RefCell<Option<(Arc<AtomicUsize>, usize)>> = RefCell::new(None);
+}
+
+//TransactionOCC, with its write set locked
+pub fn reserve() {
+    let ts = {
+        let mut clock = CLOCK.lock().unwrap();
+        let ts = clock.next();
+        clock.reserved_.insert(ts);
+        ts
+    };
+    COMMIT.with(|c| *c.borrow_mut() = Some((Arc::new(AtomicUsize::new(PENDING)), ts)));
+}
+
+//TransactionParOCC, at each piece: its timestamp is taken
+//at publish
+pub fn begin() {
+    COMMIT.with(|c| {
+        let mut c = c.borrow_mut();
+        if c.is_none() {
+            *c = Some((Arc::new(AtomicUsize::new(PENDING)), PENDING));
+        }
+    });
+}
+
+//Makes the versions of the running commit visible
+pub fn publish() {
+    if let Some((stamp, ts)) = COMMIT.with(|c| c.borrow_mut().take()) {
+        let mut clock = CLOCK.lock().unwrap();
+        let ts = if ts == PENDING {
+            clock.next()
+        } else {
+            clock.reserved_.remove(&ts);
+            ts
+        };
+        stamp.store(ts, Ordering::Release);
+        clock.update_epoch();
+    }
+}
+
+//The versions of the running commit stay pending, they
+//are never read
+pub fn abandon() {
+    if let Some((_, ts)) = COMMIT.with(|c| c.borrow_mut().take()) {
+        if ts != PENDING {
+            let mut clock = CLOCK.lock().unwrap();
+            clock.reserved_.remove(&ts);
+            clock.update_epoch();
+        }
+    }
+}
+
+//Every timestamp taken so far is at or below
+pub fn latest() -> usize {
+    CLOCK.lock().unwrap().next_ts_ - 1
+}
+
+//No snapshot, active or to come, reads below it
+pub fn epoch() -> usize {
+    EPOCH.load(Ordering::Acquire)
+}
+
+//The stamp of the running commit, or of the write alone:
+//its timestamp is then reserved until stamp_alone()
+fn install_stamp() -> (Arc<AtomicUsize>, Option<usize>) {
+    if let Some(stamp) = COMMIT.with(|c| c.borrow().as_ref().map(|c| c.0.clone())) {
+        return (stamp, None);
+    }
+
+    let mut clock = CLOCK.lock().unwrap();
+    let ts = clock.next();
+    clock.reserved_.insert(ts);
+    (Arc::new(AtomicUsize::new(PENDING)), Some(ts))
+}
+
+//Once the version of the write alone is in its chain
+fn stamp_alone(stamp: &AtomicUsize, ts: usize) {
+    let mut clock = CLOCK.lock().unwrap();
+    clock.reserved_.remove(&ts);
+    stamp.store(ts, Ordering::Release);
+    clock.update_epoch();
+}
+
+pub struct Snapshot {
+    ts_: usize,
+}
+
+impl Snapshot {
+    pub fn begin() -> Snapshot {
+        let mut clock = CLOCK.lock().unwrap();
+        let ts = clock.stable();
+        *clock.active_.entry(ts).or_insert(0) += 1;
+        clock.update_epoch();
+        Snapshot { ts_: ts }
+    }
+
+    pub fn ts(&self) -> usize {
+        self.ts_
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut clock = CLOCK.lock().unwrap();
+        let last = {
+            let cnt = clock
+                .active_
+                .get_mut(&self.ts_)
+                .expect("Snapshot::drop: not active");
+            *cnt -= 1;
+            *cnt == 0
+        };
+        if last {
+            clock.active_.remove(&self.ts_);
+        }
+        clock.update_epoch();
+    }
+}
+
+//The snapshot is taken at the first read, and held with
+//the versions read until clear()
+#[derive(Default)]
+pub struct SnapshotReader {
+    snap_: Option<Snapshot>,
+    vals_: Vec<Arc<Any>>,
+}
+
+impl SnapshotReader {
+    //Reads tref in the snapshot, false if it is not versioned
+    pub fn load(&mut self, tref: &dyn TRef) -> bool {
+        let ts = self.ts();
+        match tref.read_at(ts) {
+            Some(val) => {
+                self.vals_.push(val);
+                true
+            }
+            None => false,
+        }
+    }
+
+    //The snapshot timestamp, taken at the first call
+    pub fn ts(&mut self) -> usize {
+        self.snap_.get_or_insert_with(Snapshot::begin).ts()
+    }
+
+    //The version loaded last
+    pub fn last<T: 'static>(&self) -> &T {
+        self.vals_
+            .last()
+            .expect("SnapshotReader::last: nothing loaded")
+            .downcast_ref::<T>()
+            .expect("SnapshotReader::last: wrong type")
+    }
+
+    pub fn clear(&mut self) {
+        self.snap_ = None;
+        self.vals_.clear();
+    }
+}
+
+pub struct VersionChain<T> {
+    vers_: Mutex<Vec<(Arc<AtomicUsize>, Arc<T>)>>, /* Oldest first */
+}
+
+impl<T> Default for VersionChain<T> {
+    fn default() -> Self {
+        VersionChain {
+            vers_: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> fmt::Debug for VersionChain<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[VERSIONS: {}]", self.len())
+    }
+}
+
+impl<T> VersionChain<T> {
+    //Writes val over the object with write(), under the chain
+    //lock. The first write keeps the version it overwrites,
+    //read with cur(), as the initial one.
+    pub fn install<C, W>(&self, cur: C, val: T, write: W)
+    where
+        C: FnOnce() -> T,
+        W: FnOnce(),
+    {
+        let (stamp, alone) = install_stamp();
+        {
+            let mut vers = self.vers_.lock().unwrap();
+            if vers.is_empty() {
+                vers.push((Arc::new(AtomicUsize::new(0)), Arc::new(cur())));
+            }
+
+            write();
+            vers.push((stamp.clone(), Arc::new(val)));
+            Self::reclaim(&mut vers);
+        }
+
+        if let Some(ts) = alone {
+            stamp_alone(&stamp, ts);
+        }
+    }
+
+    //The object is new, val is its first version: stamped as
+    //its inserter's, before the object is reachable
+    pub fn insert(&self, val: T) {
+        let (stamp, alone) = install_stamp();
+        {
+            let mut vers = self.vers_.lock().unwrap();
+            debug_assert!(vers.is_empty(), "VersionChain::insert: not new");
+            vers.push((stamp.clone(), Arc::new(val)));
+        }
+
+        if let Some(ts) = alone {
+            stamp_alone(&stamp, ts);
+        }
+    }
+
+    //Whether a snapshot at ts sees the object: not if it was
+    //inserted after ts, or by a txn still pending
+    pub fn exists_at(&self, ts: usize) -> bool {
+        match self.vers_.lock().unwrap().first() {
+            Some((stamp, _)) => stamp.load(Ordering::Acquire) <= ts,
+            None => true,
+        }
+    }
+
+    //The newest version stamped at or before ts, the pending
+    //ones are stamped above any. None if the object is not
+    //there at ts (exists_at). The object is read with cur()
+    //if it was never written.
+    pub fn read_at<C>(&self, ts: usize, cur: C) -> Option<Arc<T>>
+    where
+        C: FnOnce() -> T,
+    {
+        let mut vers = self.vers_.lock().unwrap();
+        if vers.is_empty() {
+            vers.push((Arc::new(AtomicUsize::new(0)), Arc::new(cur())));
+        }
+
+        vers.iter()
+            .rev()
+            .find(|(stamp, _)| stamp.load(Ordering::Acquire) <= ts)
+            .map(|(_, val)| val.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.vers_.lock().unwrap().len()
+    }
+
+    //Keeps the newest version at the epoch, and the ones after
+    fn reclaim(vers: &mut Vec<(Arc<AtomicUsize>, Arc<T>)>) {
+        let epoch = EPOCH.load(Ordering::Acquire);
+        if let Some(idx) = vers
+            .iter()
+            .rposition(|(stamp, _)| stamp.load(Ordering::Acquire) <= epoch)
+        {
+            vers.drain(..idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datatype::tbox::TBox;
+    use occ::occ_txn::TransactionOCC;
+    use std::thread;
+    use tcore::BoxRef;
+    use txn::{Tid, Transaction};
+
+    //A snapshot of every write so far, once the commits of
+    //the other tests in between are stamped
+    fn settled() -> Snapshot {
+        let target = CLOCK.lock().unwrap().next_ts_ - 1;
+        while CLOCK.lock().unwrap().stable() < target {
+            thread::yield_now();
+        }
+        Snapshot::begin()
+    }
+
+    //A chain of u32 written in place in cell
+    fn write(chain: &VersionChain<u32>, cell: &Mutex<u32>, val: u32) {
+        chain.install(
+            || *cell.lock().unwrap(),
+            val,
+            || *cell.lock().unwrap() = val,
+        );
+    }
+
+    #[test]
+    fn test_snapshot_reads_committed_versions() {
+        let chain = VersionChain::default();
+        let cell = Mutex::new(1);
+
+        let before = Snapshot::begin();
+        write(&chain, &cell, 2);
+        let after = settled();
+
+        /* Pending until published */
+        begin();
+        write(&chain, &cell, 3);
+        let pending = settled();
+        publish();
+        let published = settled();
+
+        let read = |snap: &Snapshot| *chain.read_at(snap.ts(), || *cell.lock().unwrap()).unwrap();
+        assert_eq!(read(&before), 1);
+        assert_eq!(read(&after), 2);
+        assert_eq!(read(&pending), 2);
+        assert_eq!(read(&published), 3);
+    }
+
+    #[test]
+    fn test_snapshot_below_reserved() {
+        let chain = VersionChain::default();
+        let cell = Mutex::new(1);
+
+        reserve();
+        write(&chain, &cell, 2);
+        /* Reserved, not stamped: not stable */
+        let snap = Snapshot::begin();
+        publish();
+
+        assert_eq!(*chain.read_at(snap.ts(), || 0).unwrap(), 1);
+        assert_eq!(*chain.read_at(settled().ts(), || 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_abandoned_never_read() {
+        let chain = VersionChain::default();
+        let cell = Mutex::new(1);
+
+        reserve();
+        write(&chain, &cell, 2);
+        abandon();
+
+        assert_eq!(*chain.read_at(Snapshot::begin().ts(), || 0).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_insert_seen_once_published() {
+        let chain = VersionChain::default();
+        let before = Snapshot::begin();
+
+        /* Inserted by a pending txn */
+        begin();
+        chain.insert(1 as u32);
+        let pending = settled();
+        assert!(!chain.exists_at(pending.ts()));
+        assert!(chain.read_at(pending.ts(), || 0).is_none());
+        publish();
+
+        let published = settled();
+        assert!(!chain.exists_at(before.ts()));
+        assert!(chain.exists_at(published.ts()));
+        assert_eq!(*chain.read_at(published.ts(), || 0).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reclaim_by_epoch() {
+        let chain = VersionChain::default();
+        let cell = Mutex::new(0);
+
+        let snap = Snapshot::begin();
+        for val in 1..5 {
+            write(&chain, &cell, val);
+        }
+        /* The snapshot holds the initial version */
+        assert_eq!(*chain.read_at(snap.ts(), || 0).unwrap(), 0);
+        assert_eq!(chain.len(), 5);
+
+        /* Other tests may hold older snapshots meanwhile. A
+         * write alone keeps the version it overwrites, its own
+         * timestamp is reserved while it reclaims. */
+        drop(snap);
+        while chain.len() > 2 {
+            write(&chain, &cell, 5);
+        }
+        assert_eq!(*chain.read_at(settled().ts(), || 0).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_snapshot_txn_not_validated() {
+        let tb1 = TBox::new(1 as u32);
+        let tb2 = TBox::new(1 as u32);
+
+        let snap = &mut TransactionOCC::new_snapshot(Tid::new(1));
+        assert_eq!(*snap.read::<u32>(tb1.clone().into_box_ref()), 1);
+
+        /* Commits in between the reads of the snapshot */
+        let tx = &mut TransactionOCC::new(Tid::new(2));
+        tx.write(tb1.clone().into_box_ref(), 2 as u32);
+        tx.write(tb2.clone().into_box_ref(), 2 as u32);
+        assert!(tx.try_commit());
+
+        assert_eq!(*snap.read::<u32>(tb2.clone().into_box_ref()), 1);
+        assert!(snap.try_commit());
+
+        /* The next snapshot sees the writes */
+        drop(settled());
+        assert_eq!(*snap.read::<u32>(tb2.clone().into_box_ref()), 2);
+        assert!(snap.try_commit());
+    }
+}
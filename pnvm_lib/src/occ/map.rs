@@ -5,6 +5,7 @@ use std::collections::hash_map::RandomState;
 
 use datatype::tbox::TBox;
 use std::{fmt::Debug, hash::Hash, sync::Arc};
+use tcore::{BoxRef, TRef};
 
 pub struct TMap<K, V>
 where
@@ -37,6 +38,14 @@ where
         self.inner_.find(k)
     }
 
+    //Reference to the box of the key, to be accessed by a transaction
+    pub fn get_ref(&self, k: &K) -> Option<Box<dyn TRef>>
+    where
+        V: 'static,
+    {
+        self.inner_.find(k).map(|g| g.get().clone().into_box_ref())
+    }
+
     pub fn new_with_options(conc: u16) -> TMap<K, V> {
         let mut opt = Options::default();
         opt.concurrency = conc;
@@ -1,10 +1,20 @@
-use std::{collections::HashMap, rc::Rc, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, hash::Hash, rc::Rc, sync::Arc};
 
+use storage::{varlen, Key, Row};
 use txn::{self, AbortReason, Tid, Transaction, TxState, TxnInfo};
 
 //#[cfg(any(feature = "pmem", feature="disk"))]
 use tcore::{self, BenchmarkCounter, BoxRef, FieldArray, ObjectId, Operation, TRef, TTag};
-use {plog, pnvm_sys};
+use {
+    persistency::{self, PersistencyModel},
+    plog, pnvm_sys,
+};
+
+#[cfg(feature = "history")]
+use history::recorder::TxnRecord;
+
+#[cfg(feature = "mvcc")]
+use mvcc::{self, SnapshotReader};
 
 #[cfg(feature = "profile")]
 use flame;
@@ -21,8 +31,19 @@ pub struct TransactionOCC {
     locks_:        Vec<*const TTag>,
     txn_info_:     Arc<TxnInfo>,
     should_abort_: bool,
+    read_only_:    bool, /* Declared, checked in debug builds */
+    snapshot_:     bool, /* Reads a snapshot, with "mvcc" */
+    persistency_:  PersistencyModel,
+    on_commit_:    Vec<CommitHook>, /* Dropped on abort */
+    var_guard_:    varlen::ReadGuard,
+
+    #[cfg(feature = "mvcc")]
+    snap_:         SnapshotReader,
 }
 
+/* Run once, see on_commit */
+type CommitHook = Box<FnMut()>;
+
 impl Transaction for TransactionOCC {
     #[cfg_attr(feature = "profile", flame)]
     fn try_commit(&mut self) -> bool {
@@ -30,11 +51,23 @@ impl Transaction for TransactionOCC {
             return self.abort(AbortReason::IndexErr);
         }
 
+        //Read-only: validate the reads, nothing to lock or persist
+        if self.is_read_only() {
+            if !self.check() {
+                return self.abort(AbortReason::FailedLocking);
+            }
+            return self.commit_read_only();
+        }
+
         //Stage 1: lock
         if !self.lock() {
             return self.abort(AbortReason::FailedLocking);
         }
 
+        //Serialized here, the versions installed carry it
+        #[cfg(feature = "mvcc")]
+        mvcc::reserve();
+
         //Stage 2: Check
         if !self.check() {
             return self.abort(AbortReason::FailedLocking);
@@ -47,6 +80,13 @@ impl Transaction for TransactionOCC {
     }
 
     fn read<'b, T: 'static>(&'b mut self, tref: Box<dyn TRef>) -> &'b T {
+        #[cfg(feature = "mvcc")]
+        {
+            if self.snapshot_ && self.snap_.load(&*tref) {
+                return self.snap_.last();
+            }
+        }
+
         //Get the tx id
         let id = *tref.get_id();
 
@@ -115,15 +155,96 @@ impl TransactionOCC {
             txn_info_: Arc::new(TxnInfo::new(tid_)),
             should_abort_: false,
             records_: Vec::with_capacity(32),
+            read_only_: false,
+            snapshot_: false,
+            persistency_: persistency::persistency_model(),
+            on_commit_: Vec::new(),
+            var_guard_: varlen::enter(),
+
+            #[cfg(feature = "mvcc")]
+            snap_: SnapshotReader::default(),
         }
     }
 
+    //Commits without locks, undo records or commit marker,
+    //e.g. OrderStatus and StockLevel
+    pub fn new_read_only(tid: Tid) -> TransactionOCC {
+        TransactionOCC {
+            read_only_: true,
+            ..TransactionOCC::new(tid)
+        }
+    }
+
+    //Read-only, its reads of versioned objects are neither
+    //tagged nor validated (mvcc). The buckets it scans are
+    //not validated either: it skips the rows pushed after it
+    //with sees().
+    pub fn new_snapshot(tid: Tid) -> TransactionOCC {
+        TransactionOCC {
+            snapshot_: true,
+            ..TransactionOCC::new_read_only(tid)
+        }
+    }
+
+    //Only with "mvcc", new_snapshot is new_read_only otherwise
+    pub fn is_snapshot(&self) -> bool {
+        cfg!(feature = "mvcc") && self.snapshot_
+    }
+
+    //A snapshot does not see the rows pushed after it, see
+    //mvcc. Any other txn sees every row it looks up.
+    #[cfg_attr(not(feature = "mvcc"), allow(unused_variables))]
+    pub fn sees<Entry, Index>(&mut self, row: &Row<Entry, Index>) -> bool
+    where
+        Entry: 'static + Key<Index> + Clone + Debug,
+        Index: Eq + Hash + Clone,
+    {
+        #[cfg(feature = "mvcc")]
+        {
+            if self.snapshot_ {
+                return row.visible_at(self.snap_.ts());
+            }
+        }
+
+        true
+    }
+
+    //f runs if the txn commits with writes, once it is durable
+    //and before it unlocks: after the hooks of the txns it
+    //read from or overwrote, e.g. to log it (db::DbLog)
+    pub fn on_commit<F: FnOnce() + 'static>(&mut self, f: F) {
+        let mut f = Some(f);
+        self.on_commit_.push(Box::new(move || {
+            if let Some(f) = f.take() {
+                f()
+            }
+        }));
+    }
+
+    //Declared so, or without any write at commit
+    fn is_read_only(&self) -> bool {
+        if self.read_only_ {
+            debug_assert!(
+                self.deps_.values().all(|tag| !tag.has_write()),
+                "Tx[{:?}] declared read-only writes",
+                self.tid_
+            );
+            return true;
+        }
+        self.deps_.values().all(|tag| !tag.has_write())
+    }
+
     #[cfg_attr(feature = "profile", flame)]
     pub fn abort(&mut self, reason: AbortReason) -> bool {
         warn!("Tx[{:?}] is aborting - {}", self.tid_, reason.as_ref());
         //#[cfg(benchmark)]
         tcore::BenchmarkCounter::abort();
         self.state_ = TxState::ABORTED;
+
+        #[cfg(feature = "mvcc")]
+        mvcc::abandon();
+
+        varlen::discard();
         self.clean_up();
         false
     }
@@ -178,6 +299,9 @@ impl TransactionOCC {
         tcore::BenchmarkCounter::success();
         self.state_ = TxState::COMMITTED;
 
+        #[cfg(feature = "history")]
+        self.record_history();
+
         //Persist the write set logs
         //#[cfg(any(feature = "pmem", feature="disk"))]
         self.do_log();
@@ -192,6 +316,16 @@ impl TransactionOCC {
         //Persist commit the transaction
         //#[cfg(any(feature = "pmem", feature="disk"))]
         self.persist_commit();
+        self.txn_info_.commit();
+        self.txn_info_.persist();
+
+        //Durable, still locked
+        for mut hook in self.on_commit_.drain(..) {
+            hook();
+        }
+
+        #[cfg(feature = "mvcc")]
+        mvcc::publish();
 
         //Clean up local data structures.
         //txn::mark_commit(self.id());
@@ -199,6 +333,32 @@ impl TransactionOCC {
         true
     }
 
+    //The reads are validated, and every version read was
+    //persisted by its writer before it unlocked
+    #[cfg_attr(feature = "profile", flame)]
+    fn commit_read_only(&mut self) -> bool {
+        warn!("Tx[{:?}] is commiting read-only", self.tid_);
+        tcore::BenchmarkCounter::success();
+        tcore::BenchmarkCounter::read_only();
+        self.state_ = TxState::COMMITTED;
+
+        #[cfg(feature = "history")]
+        self.record_history();
+
+        self.txn_info_.commit();
+        self.txn_info_.persist();
+        self.clean_up();
+        true
+    }
+
+    //Write set is still locked here
+    #[cfg(feature = "history")]
+    fn record_history(&self) {
+        let mut record = TxnRecord::new(self.id());
+        record.add_tags(self.deps_.values());
+        record.commit();
+    }
+
     //#[cfg(any(feature = "pmem", feature="disk"))]
     #[cfg_attr(feature = "profile", flame)]
     fn persist_commit(&self) {
@@ -215,7 +375,7 @@ impl TransactionOCC {
         let id = self.id();
         for tag in self.deps_.values() {
             if tag.has_write() {
-                logs.push(tag.make_log(id));
+                logs.extend(tag.make_log(id));
 
                 #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
                 self.records_
@@ -223,48 +383,18 @@ impl TransactionOCC {
             }
         }
 
-        plog::persist_log(logs);
+        self.persistency_.persist_log(logs);
     }
 
     #[cfg(any(feature = "pmem", feature = "disk"))]
     #[cfg_attr(feature = "profile", flame)]
     fn persist_data(&mut self) {
+        #[cfg(feature = "pmem")]
+        let mut plan = self.persistency_.data_plan(self.id());
+
         for (record, fields) in self.records_.drain(..) {
             #[cfg(feature = "pmem")]
-            {
-                match fields {
-                    Some(ref fields) => {
-                        for field in fields.iter() {
-                            let paddr = record.get_pmem_field_addr(*field);
-                            let size = record.get_field_size(*field);
-                            BenchmarkCounter::flush(size);
-
-                            #[cfg(feature = "dir")]
-                            pnvm_sys::flush(paddr, size);
-
-                            #[cfg(not(feature = "dir"))]
-                            {
-                                let vaddr = record.get_field_ptr(*field);
-                                pnvm_sys::memcpy_nodrain(paddr, vaddr, size);
-                            }
-                        }
-                    }
-                    None => {
-                        let paddr = record.get_pmem_addr();
-                        let layout = record.get_layout();
-
-                        BenchmarkCounter::flush(layout.size());
-                        #[cfg(feature = "dir")]
-                        pnvm_sys::flush(paddr, layout.size());
-
-                        #[cfg(not(feature = "dir"))]
-                        {
-                            let vaddr = record.get_ptr();
-                            pnvm_sys::memcpy_nodrain(paddr, vaddr, layout.size());
-                        }
-                    }
-                }
-            }
+            plan.add_ref(&*record, fields.as_ref());
 
             #[cfg(feature = "disk")]
             {
@@ -276,6 +406,9 @@ impl TransactionOCC {
             }
         }
 
+        #[cfg(feature = "pmem")]
+        plan.issue();
+
         //for tag in self.deps_.values() {
         //    #[cfg(not(feature = "wdrain"))]
         //    tag.persist_data(self.id());
@@ -288,11 +421,18 @@ impl TransactionOCC {
         for tag in self.deps_.values_mut() {
             tag.commit_data(id);
         }
+        varlen::retire(&self.txn_info_);
+        plog::persist_slots(id.into());
     }
 
     #[cfg_attr(feature = "profile", flame)]
     fn clean_up(&mut self) {
         self.should_abort_ = false;
+        self.on_commit_.clear();
+
+        #[cfg(feature = "mvcc")]
+        self.snap_.clear();
+
         for (_, tag) in self.deps_.drain() {
             if tag.has_write() && tag.is_lock() {
                 tag.tobj_ref_.unlock();
@@ -313,6 +453,14 @@ impl Default for TransactionOCC {
             txn_info_:     Arc::new(TxnInfo::default()),
             should_abort_: false,
             records_:      Vec::with_capacity(INITIAL_RECORDS_VEC_CAP),
+            read_only_:    false,
+            snapshot_:     false,
+            persistency_:  persistency::persistency_model(),
+            on_commit_:    Vec::new(),
+            var_guard_:    varlen::enter(),
+
+            #[cfg(feature = "mvcc")]
+            snap_:         SnapshotReader::default(),
         }
     }
 }
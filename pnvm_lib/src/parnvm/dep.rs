@@ -0,0 +1,75 @@
+//************************************************
+//Dependency tracking shared by the pc transactions
+//
+//TransactionParOCC and TransactionParOCCRaw record the
+//txns they have to wait on before committing, and
+//register their piece reads so that later writers find
+//them (R->W).
+//
+//Funcs:
+//- add_dep_on          record a dependency on a txn
+//- register_reads      register as reader of the read set
+//- unregister_reads    drop the registrations of a piece,
+//                      or of an aborted txn
+//
+//A dependency, writer or reader, is waited on until it is
+//done (TxnInfo::has_done), see TVersion::get_readers.
+//************************************************
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use tcore::{ObjectId, Operation, TTag, TVersion};
+use txn::{Tid, TxnInfo};
+
+//ww_wr: txn_info wrote what we access, otherwise it
+//read what we overwrite
+pub fn add_dep_on(
+    deps: &mut HashMap<u32, Arc<TxnInfo>>,
+    ww_wr_deps: &mut HashSet<u32>,
+    me: u32,
+    txn_info: Arc<TxnInfo>,
+    ww_wr: bool,
+) {
+    if !txn_info.has_done() {
+        let id: u32 = txn_info.id().into();
+        /* Do not add myself into it */
+        if me == id {
+            return;
+        }
+        if ww_wr {
+            ww_wr_deps.insert(id);
+        }
+        if !deps.contains_key(&id) {
+            warn!("add_dep:: {:?} will wait on {:?}", me, id);
+            deps.insert(id, txn_info);
+        }
+    }
+}
+
+//Register as a reader before checking the read set so that a writer
+//locking concurrently either fails our check or sees us.
+pub fn register_reads(
+    tags: &HashMap<(ObjectId, Operation), TTag>,
+    txn_info: &Arc<TxnInfo>,
+    pc_reads: &mut Vec<Arc<TVersion>>,
+) {
+    for tag in tags.values() {
+        if !tag.has_read() {
+            continue;
+        }
+
+        let tvers = tag.tobj_ref_.get_tvers();
+        if tvers.add_reader(txn_info.clone()) {
+            pc_reads.push(tvers.clone());
+        }
+    }
+}
+
+pub fn unregister_reads(id: &Tid, pc_reads: &mut Vec<Arc<TVersion>>) {
+    for tvers in pc_reads.drain(..) {
+        tvers.remove_reader(id);
+    }
+}
@@ -1,6 +1,7 @@
-//pub mod dep;
+pub mod dep;
 pub mod map;
 pub mod nvm_txn_2pl;
 pub mod nvm_txn_occ;
 pub mod nvm_txn_raw;
+pub mod persister;
 pub mod piece;
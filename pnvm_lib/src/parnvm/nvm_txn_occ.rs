@@ -1,13 +1,20 @@
-use super::piece::*;
+use super::{dep, piece::*};
 
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use super::persister::{self, PersistJob};
+
+use storage::{varlen, Key, Row};
 use tcore::{self, *};
 use txn::{self, *};
 
 use std::{
     any::Any,
     cell::RefCell,
+    cmp,
     collections::{HashMap, HashSet},
     default::Default,
+    fmt,
+    hash::Hash,
     ptr::NonNull,
     rc::Rc,
     sync::Arc,
@@ -17,6 +24,7 @@ use std::{
 //#[cfg(any(feature= "pmem", feature = "disk"))]
 use {
     core::alloc::Layout,
+    persistency::{self, PersistencyModel},
     plog::{self, PLog},
 };
 
@@ -28,19 +36,36 @@ use log;
 #[cfg(feature = "profile")]
 use flame;
 
+#[cfg(feature = "history")]
+use {history::recorder::TxnRecord, std::mem};
+
+#[cfg(feature = "mvcc")]
+use mvcc::{self, SnapshotReader};
+
 const DEP_DEFAULT_SIZE: usize = 128;
 
 #[derive(Clone, Debug)]
 pub struct TransactionParBaseOCC {
-    all_ps_: Vec<PieceOCC>,
-    name_:   String,
+    all_ps_:   Vec<PieceOCC>,
+    name_:     String,
+    snapshot_: bool,
 }
 
 impl TransactionParBaseOCC {
     pub fn new(all_ps: Vec<PieceOCC>, name: String) -> TransactionParBaseOCC {
         TransactionParBaseOCC {
-            all_ps_: all_ps,
-            name_:   name,
+            all_ps_:   all_ps,
+            name_:     name,
+            snapshot_: false,
+        }
+    }
+
+    //Read-only pieces, reading a snapshot with "mvcc": no
+    //tag, validation nor dependency on the writers
+    pub fn new_snapshot(all_ps: Vec<PieceOCC>, name: String) -> TransactionParBaseOCC {
+        TransactionParBaseOCC {
+            snapshot_: true,
+            ..TransactionParBaseOCC::new(all_ps, name)
         }
     }
 }
@@ -50,6 +75,8 @@ pub struct TransactionParOCC {
     next_pc_idx_:  usize,
     total_pc_cnt_: usize,
     deps_:         HashMap<u32, Arc<TxnInfo>>,
+    ww_wr_:        HashSet<u32>, /* Deps whose writes were read or overwritten */
+    read_ranks_:   HashMap<u32, usize>, /* Rank of the other deps, when they read */
     id_:           Tid,
     name_:         String,
     status_:       TxState,
@@ -63,7 +90,18 @@ pub struct TransactionParOCC {
     do_piece_drain: bool,
 
     tags_:        HashMap<(ObjectId, Operation), TTag>,
+    pc_reads_:    Vec<Arc<TVersion>>, /* Reader registrations of the running piece */
+    reads_:       Vec<Arc<TVersion>>, /* Of the committed pieces */
+    persistency_: PersistencyModel,
+    var_guard_:   varlen::ReadGuard,
+
+    #[cfg(feature = "history")]
+    history_:     TxnRecord,
     early_abort_: bool,
+    snapshot_:    bool,
+
+    #[cfg(feature = "mvcc")]
+    snap_: SnapshotReader, /* Held until the txn ends */
 }
 
 impl TransactionParOCC {
@@ -95,6 +133,7 @@ impl TransactionParOCC {
         let mut txn_base = txn_base.clone();
         let pc_cnt = txn_base.all_ps_.len();
         let name = txn_base.name_;
+        let snapshot = txn_base.snapshot_;
         let mut pc = Vec::with_capacity(32);
         pc.append(&mut txn_base.all_ps_);
         TransactionParOCC {
@@ -107,6 +146,8 @@ impl TransactionParOCC {
             id_:           tid,
             status_:       TxState::EMBRYO,
             deps_:         HashMap::with_capacity(DEP_DEFAULT_SIZE),
+            ww_wr_:        HashSet::with_capacity(DEP_DEFAULT_SIZE),
+            read_ranks_:   HashMap::with_capacity(DEP_DEFAULT_SIZE),
             txn_info_:     Arc::new(TxnInfo::new(tid)),
 
             //#[cfg(any(feature= "pmem", feature = "disk"))]
@@ -114,10 +155,44 @@ impl TransactionParOCC {
 
             do_piece_drain: false,
             tags_:          HashMap::with_capacity(16),
+            pc_reads_:      Vec::with_capacity(16),
+            reads_:         Vec::new(),
+            persistency_:   persistency::persistency_model(),
+            var_guard_:     varlen::enter(),
+
+            #[cfg(feature = "history")]
+            history_:       TxnRecord::new(tid),
             early_abort_:   false, // User initiated abort for the whole Txn
+            snapshot_:      snapshot,
+
+            #[cfg(feature = "mvcc")]
+            snap_: SnapshotReader::default(),
         }
     }
 
+    //Only with "mvcc"
+    pub fn is_snapshot(&self) -> bool {
+        cfg!(feature = "mvcc") && self.snapshot_
+    }
+
+    //A snapshot does not see the rows pushed after it, nor
+    //by pending pieces, see mvcc
+    #[cfg_attr(not(feature = "mvcc"), allow(unused_variables))]
+    pub fn sees<Entry, Index>(&mut self, row: &Row<Entry, Index>) -> bool
+    where
+        Entry: 'static + Key<Index> + Clone + fmt::Debug,
+        Index: Eq + Hash + Clone,
+    {
+        #[cfg(feature = "mvcc")]
+        {
+            if self.snapshot_ {
+                return row.visible_at(self.snap_.ts());
+            }
+        }
+
+        true
+    }
+
     pub fn set_piece_drain_mode(&mut self, do_piece_drain: bool) {
         self.do_piece_drain = do_piece_drain;
     }
@@ -172,6 +247,13 @@ impl TransactionParOCC {
 
     /* Implement OCC interface */
     pub fn read<'a, T: 'static + Clone>(&'a mut self, tobj: Box<dyn TRef>) -> &'a T {
+        #[cfg(feature = "mvcc")]
+        {
+            if self.snapshot_ && self.snap_.load(&*tobj) {
+                return self.snap_.last();
+            }
+        }
+
         let tag = self.retrieve_tag(tobj.get_id(), tobj.box_clone(), Operation::RWrite);
         tag.add_version(tobj.get_version());
         tag.get_data()
@@ -205,19 +287,21 @@ impl TransactionParOCC {
             .or_insert(TTag::new(*id, tobj_ref))
     }
 
-    //FIXME: R->W dependency
+    //Wait on the last uncommitted writer of every accessed object (W->W, W->R),
+    //and on the uncommitted readers of every overwritten object (R->W)
     fn add_dep(&mut self) {
         let me: u32 = self.id().into();
         for (_, tag) in self.tags_.iter() {
             let txn_info = tag.tobj_ref_.get_access_info();
-            if !txn_info.has_commit() {
-                let id: u32 = txn_info.id().into();
-                if me != id {
-                    /* Do not add myself into it */
-                    if !self.deps_.contains_key(&id) && tag.has_write() {
-                        warn!("add_dep:: {:?} will wait on {:?}", me, id);
-                        self.deps_.insert(id, txn_info);
-                    }
+            dep::add_dep_on(&mut self.deps_, &mut self.ww_wr_, me, txn_info, true);
+
+            if tag.has_write() {
+                for reader in tag.tobj_ref_.get_tvers().get_readers() {
+                    /* It read in its running piece, or an earlier one */
+                    let rank = self.read_ranks_.entry(reader.id().into()).or_insert(0);
+                    *rank = cmp::max(*rank, reader.rank());
+
+                    dep::add_dep_on(&mut self.deps_, &mut self.ww_wr_, me, reader, false);
                 }
             }
         }
@@ -228,6 +312,8 @@ impl TransactionParOCC {
             return self.abort_piece(AbortReason::FailedLocking);
         }
 
+        dep::register_reads(&self.tags_, &self.txn_info_, &mut self.pc_reads_);
+
         if !self.check() {
             return self.abort_piece(AbortReason::FailedLocking);
         }
@@ -239,16 +325,39 @@ impl TransactionParOCC {
 
     fn abort_piece(&mut self, _: AbortReason) -> bool {
         tcore::BenchmarkCounter::abort_piece();
+        dep::unregister_reads(&self.id_, &mut self.pc_reads_);
+        varlen::discard();
         self.clean_up();
         false
     }
 
     fn commit_piece(&mut self) -> bool {
         tcore::BenchmarkCounter::success_piece();
+        self.reads_.extend(self.pc_reads_.drain(..));
+
+        #[cfg(all(feature = "pmem", feature = "ppipe"))]
+        let logged = self.tags_.values().any(|tag| tag.has_write());
 
         //#[cfg(all(any(feature = "pmem", feature = "disk"), feature = "plog"))]
         self.persist_logs();
 
+        //The append drained the data of the previous pieces,
+        //flushed since. Without writes, nothing was appended:
+        //the watermark waits for a later piece, or the commit.
+        #[cfg(all(feature = "pmem", feature = "ppipe"))]
+        {
+            if logged {
+                self.txn_info_.persist_below(self.cur_rank());
+            }
+        }
+
+        #[cfg(feature = "history")]
+        self.history_.add_tags(self.tags_.values());
+
+        //Versions stay pending until the txn commits
+        #[cfg(feature = "mvcc")]
+        mvcc::begin();
+
         //Install write sets into the underlying data
         self.install_data();
 
@@ -268,42 +377,12 @@ impl TransactionParOCC {
 
     #[cfg(any(feature = "pmem", feature = "disk"))]
     fn persist_data(&mut self) {
+        #[cfg(feature = "pmem")]
+        let mut plan = self.persistency_.data_plan(*self.id());
+
         for (record, fields) in self.records_.drain(..) {
             #[cfg(feature = "pmem")]
-            {
-                match fields {
-                    Some(ref fields) => {
-                        for field in fields.iter() {
-                            let paddr = record.get_pmem_field_addr(*field);
-                            let size = record.get_field_size(*field);
-                            BenchmarkCounter::flush(size);
-
-                            #[cfg(feature = "dir")]
-                            pnvm_sys::flush(paddr, size);
-
-                            #[cfg(not(feature = "dir"))]
-                            {
-                                let vaddr = record.get_field_ptr(*field);
-                                pnvm_sys::memcpy_nodrain(paddr, vaddr, size);
-                            }
-                        }
-                    }
-                    None => {
-                        let paddr = record.get_pmem_addr();
-                        let layout = record.get_layout();
-
-                        BenchmarkCounter::flush(layout.size());
-                        #[cfg(feature = "dir")]
-                        pnvm_sys::flush(paddr, layout.size());
-
-                        #[cfg(not(feature = "dir"))]
-                        {
-                            let vaddr = record.get_ptr();
-                            pnvm_sys::memcpy_nodrain(paddr, vaddr, layout.size());
-                        }
-                    }
-                }
-            }
+            plan.add_ref(&*record, fields.as_ref());
 
             #[cfg(feature = "disk")]
             {
@@ -315,6 +394,9 @@ impl TransactionParOCC {
             }
         }
 
+        #[cfg(feature = "pmem")]
+        plan.issue();
+
         //for tag in self.tags_.values() {
         //    tag.persist_data(*self.id());
         //}
@@ -324,10 +406,16 @@ impl TransactionParOCC {
         let id = *self.id();
         let txn_info = self.txn_info().clone();
         for tag in self.tags_.values_mut() {
+            if !tag.has_write() {
+                continue;
+            }
+
             tag.commit_data(id);
-            //FIXME: R->R also needs to be included
             tag.tobj_ref_.set_access_info(txn_info.clone());
+            tag.tobj_ref_.get_tvers().clear_readers();
         }
+        varlen::retire(&txn_info);
+        plog::persist_slots(id.into());
     }
 
     fn clean_up(&mut self) {
@@ -397,6 +485,24 @@ impl TransactionParOCC {
 
     #[cfg_attr(feature = "profile", flame)]
     pub fn execute_txn(&mut self) {
+        if self.execute_pieces() {
+            self.commit();
+        }
+    }
+
+    //Returns once the txn is visible, None if it aborted
+    #[cfg_attr(feature = "profile", flame)]
+    pub fn execute_txn_async(&mut self) -> Option<DurabilityHandle> {
+        if self.execute_pieces() {
+            Some(self.commit_async())
+        } else {
+            None
+        }
+    }
+
+    //Runs the pieces, then waits for the deps to commit.
+    //false if the txn aborted.
+    fn execute_pieces(&mut self) -> bool {
         self.status_ = TxState::ACTIVE;
 
         while let Some(piece) = self.get_next_piece() {
@@ -405,7 +511,7 @@ impl TransactionParOCC {
 
             if self.early_abort_ {
                 self.abort();
-                return;
+                return false;
             }
 
             //#[cfg(any(feature = "pmem", feature = "disk"))]
@@ -414,7 +520,7 @@ impl TransactionParOCC {
 
         //Commit
         self.wait_deps_commit();
-        self.commit();
+        true
     }
 
     //#[cfg(any(feature = "pmem", feature = "disk"))]
@@ -424,7 +530,7 @@ impl TransactionParOCC {
 
         for tag in self.tags_.values() {
             if tag.has_write() {
-                logs.push(tag.make_log(id));
+                logs.extend(tag.make_log(id));
 
                 #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
                 self.records_
@@ -438,7 +544,7 @@ impl TransactionParOCC {
         //            }
         //        }).collect();
 
-        plog::persist_log(logs);
+        self.persistency_.persist_log(logs);
     }
 
     //#[cfg(any(feature= "pmem", feature = "disk"))]
@@ -487,7 +593,8 @@ impl TransactionParOCC {
     #[cfg_attr(feature = "profile", flame)]
     fn persist_log(&self, records: &Vec<DataRecord>) {
         let id = self.id();
-        plog::persist_log(records.iter().map(|ref r| r.as_log(*id)).collect());
+        self.persistency_
+            .persist_log(records.iter().map(|ref r| r.as_log(*id)).collect());
     }
 
     //#[cfg(any(feature = "pmem", feature = "disk"))]
@@ -512,10 +619,18 @@ impl TransactionParOCC {
 
     #[cfg_attr(feature = "profile", flame)]
     pub fn commit(&mut self) {
+        //After the deps, before the dependents
+        #[cfg(feature = "mvcc")]
+        self.publish();
+
         self.txn_info_.commit();
+        self.reads_.clear();
         self.status_ = TxState::COMMITTED;
         tcore::BenchmarkCounter::success();
 
+        #[cfg(feature = "history")]
+        mem::replace(&mut self.history_, TxnRecord::new(self.id_)).commit();
+
         #[cfg(any(feature = "pmem", feature = "disk"))]
         {
             //Persist data here
@@ -531,8 +646,55 @@ impl TransactionParOCC {
         }
     }
 
+    //Returns once the txn is visible. Its data is fenced
+    //here, its commit record is left to the persister.
+    #[cfg_attr(feature = "profile", flame)]
+    pub fn commit_async(&mut self) -> DurabilityHandle {
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        {
+            #[cfg(not(any(feature = "wdrain", feature = "pdrain")))]
+            {
+                self.persist_data();
+            }
+            pnvm_sys::drain();
+
+            #[cfg(feature = "porder")]
+            pnvm_sys::porder::handoff(self.id().into());
+
+            /* Handed over before it is visible to its dependents */
+            let waits = self
+                .deps_
+                .iter()
+                .filter_map(|(id, dep)| self.dep_persist_below(id).map(|rank| (dep.clone(), rank)))
+                .collect();
+            persister::submit(PersistJob::new(self.txn_info_.clone(), waits));
+        }
+
+        #[cfg(feature = "mvcc")]
+        self.publish();
+
+        self.txn_info_.commit();
+        self.reads_.clear();
+        self.status_ = TxState::COMMITTED;
+        tcore::BenchmarkCounter::success();
+
+        #[cfg(feature = "history")]
+        mem::replace(&mut self.history_, TxnRecord::new(self.id_)).commit();
+
+        DurabilityHandle::new(self.txn_info_.clone())
+    }
+
     pub fn abort(&mut self) {
+        dep::unregister_reads(&self.id_, &mut self.reads_);
+        varlen::discard();
         self.clean_up();
+
+        #[cfg(feature = "mvcc")]
+        {
+            mvcc::abandon();
+            self.snap_.clear();
+        }
+
         self.txn_info_.commit();
 
         #[cfg(any(feature = "pmem", feature = "disk"))]
@@ -542,13 +704,23 @@ impl TransactionParOCC {
         tcore::BenchmarkCounter::abort();
     }
 
+    #[cfg(feature = "mvcc")]
+    fn publish(&mut self) {
+        mvcc::publish();
+        self.snap_.clear();
+    }
+
     #[cfg(any(feature = "pmem", feature = "disk"))]
     #[cfg_attr(feature = "profile", flame)]
     fn wait_deps_persist(&self) {
-        for (_, dep) in self.deps_.iter() {
+        for (id, dep) in self.deps_.iter() {
+            let below = match self.dep_persist_below(id) {
+                Some(below) => below,
+                None => continue,
+            };
             loop {
                 /* Busy wait here */
-                if !dep.has_persist() {
+                if !dep.has_persist_below(below) {
                     warn!(
                         "wait_deps_persist::{:?} waiting for {:?} to commit",
                         self.id(),
@@ -561,6 +733,22 @@ impl TransactionParOCC {
         }
     }
 
+    //The rank a dep must be durable below before our commit
+    //record, None if the persistency model does not wait
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn dep_persist_below(&self, id: &u32) -> Option<usize> {
+        let ww_wr = self.ww_wr_.contains(id);
+        if !self.persistency_.waits_for(ww_wr) {
+            return None;
+        }
+
+        /* A reader of what we overwrote: its pieces up to the read */
+        match self.read_ranks_.get(id) {
+            Some(rank) if !ww_wr => Some(rank + 1),
+            _ => Some(usize::max_value()),
+        }
+    }
+
     #[cfg_attr(feature = "profile", flame)]
     pub fn wait_deps_commit(&self) {
         for (_, dep) in self.deps_.iter() {
@@ -1,5 +1,6 @@
-use super::piece::*;
+use super::{dep, piece::*};
 
+use storage::varlen;
 use tcore::{self, *};
 use txn::{self, *};
 
@@ -17,6 +18,7 @@ use std::{
 //#[cfg(any(feature= "pmem", feature = "disk"))]
 use {
     core::alloc::Layout,
+    persistency::{self, PersistencyModel},
     plog::{self, PLog},
 };
 
@@ -28,10 +30,14 @@ use log;
 #[cfg(feature = "profile")]
 use flame;
 
+#[cfg(feature = "history")]
+use {history::recorder::TxnRecord, std::mem};
+
 const DEP_DEFAULT_SIZE: usize = 128;
 
 pub struct TransactionParOCCRaw {
     deps_:     HashMap<u32, Arc<TxnInfo>>,
+    ww_wr_:    HashSet<u32>, /* Deps whose writes were read or overwritten */
     id_:       Tid,
     status_:   TxState,
     txn_info_: Arc<TxnInfo>,
@@ -40,6 +46,13 @@ pub struct TransactionParOCCRaw {
     //#[cfg(any(feature= "pmem", feature = "disk"))]
     records_:         Vec<(Box<dyn TRef>, Option<FieldArray>)>,
     tags_:            HashMap<(ObjectId, Operation), TTag>,
+    pc_reads_:        Vec<Arc<TVersion>>, /* Reader registrations of the running piece */
+    reads_:           Vec<Arc<TVersion>>, /* Of the committed pieces */
+    persistency_:     PersistencyModel,
+    var_guard_:       varlen::ReadGuard,
+
+    #[cfg(feature = "history")]
+    history_:         TxnRecord,
     pub early_abort_: bool,
 }
 
@@ -47,13 +60,21 @@ impl TransactionParOCCRaw {
     pub fn new(id: Tid) -> TransactionParOCCRaw {
         TransactionParOCCRaw {
             deps_:     HashMap::with_capacity(DEP_DEFAULT_SIZE),
+            ww_wr_:    HashSet::with_capacity(DEP_DEFAULT_SIZE),
             id_:       id,
             status_:   TxState::EMBRYO,
             txn_info_: Arc::new(TxnInfo::new(id)),
 
             records_: Vec::new(),
 
-            tags_: HashMap::with_capacity(32),
+            tags_:     HashMap::with_capacity(32),
+            pc_reads_: Vec::with_capacity(32),
+            reads_:    Vec::new(),
+            persistency_: persistency::persistency_model(),
+            var_guard_: varlen::enter(),
+
+            #[cfg(feature = "history")]
+            history_:  TxnRecord::new(id),
 
             early_abort_: false,
         }
@@ -127,19 +148,17 @@ impl TransactionParOCCRaw {
             .or_insert(TTag::new(*id, tobj_ref))
     }
 
-    //FIXME: R->W dependency
+    //Wait on the last uncommitted writer of every accessed object (W->W, W->R),
+    //and on the uncommitted readers of every overwritten object (R->W)
     fn add_dep(&mut self) {
         let me: u32 = self.id().into();
         for (_, tag) in self.tags_.iter() {
             let txn_info = tag.tobj_ref_.get_access_info();
-            if !txn_info.has_commit() {
-                let id: u32 = txn_info.id().into();
-                if me != id {
-                    /* Do not add myself into it */
-                    if !self.deps_.contains_key(&id) && tag.has_write() {
-                        warn!("add_dep:: {:?} will wait on {:?}", me, id);
-                        self.deps_.insert(id, txn_info);
-                    }
+            dep::add_dep_on(&mut self.deps_, &mut self.ww_wr_, me, txn_info, true);
+
+            if tag.has_write() {
+                for reader in tag.tobj_ref_.get_tvers().get_readers() {
+                    dep::add_dep_on(&mut self.deps_, &mut self.ww_wr_, me, reader, false);
                 }
             }
         }
@@ -150,6 +169,8 @@ impl TransactionParOCCRaw {
             return self.abort_piece(AbortReason::FailedLocking);
         }
 
+        dep::register_reads(&self.tags_, &self.txn_info_, &mut self.pc_reads_);
+
         if !self.check() {
             return self.abort_piece(AbortReason::FailedLocking);
         }
@@ -161,16 +182,22 @@ impl TransactionParOCCRaw {
 
     fn abort_piece(&mut self, _: AbortReason) -> bool {
         tcore::BenchmarkCounter::abort_piece();
+        dep::unregister_reads(&self.id_, &mut self.pc_reads_);
+        varlen::discard();
         self.clean_up();
         false
     }
 
     fn commit_piece(&mut self) -> bool {
         tcore::BenchmarkCounter::success_piece();
+        self.reads_.extend(self.pc_reads_.drain(..));
 
         //#[cfg(all(any(feature = "pmem", feature = "disk"), feature = "plog"))]
         self.persist_logs();
 
+        #[cfg(feature = "history")]
+        self.history_.add_tags(self.tags_.values());
+
         //Install write sets into the underlying data
         self.install_data();
 
@@ -190,42 +217,12 @@ impl TransactionParOCCRaw {
 
     #[cfg(any(feature = "pmem", feature = "disk"))]
     fn persist_data(&mut self) {
+        #[cfg(feature = "pmem")]
+        let mut plan = self.persistency_.data_plan(*self.id());
+
         for (record, fields) in self.records_.drain(..) {
             #[cfg(feature = "pmem")]
-            {
-                match fields {
-                    Some(ref fields) => {
-                        for field in fields.iter() {
-                            let paddr = record.get_pmem_field_addr(*field);
-                            let size = record.get_field_size(*field);
-                            BenchmarkCounter::flush(size);
-
-                            #[cfg(feature = "dir")]
-                            pnvm_sys::flush(paddr, size);
-
-                            #[cfg(not(feature = "dir"))]
-                            {
-                                let vaddr = record.get_field_ptr(*field);
-                                pnvm_sys::memcpy_nodrain(paddr, vaddr, size);
-                            }
-                        }
-                    }
-                    None => {
-                        let paddr = record.get_pmem_addr();
-                        let layout = record.get_layout();
-
-                        BenchmarkCounter::flush(layout.size());
-                        #[cfg(feature = "dir")]
-                        pnvm_sys::flush(paddr, layout.size());
-
-                        #[cfg(not(feature = "dir"))]
-                        {
-                            let vaddr = record.get_ptr();
-                            pnvm_sys::memcpy_nodrain(paddr, vaddr, layout.size());
-                        }
-                    }
-                }
-            }
+            plan.add_ref(&*record, fields.as_ref());
 
             #[cfg(feature = "disk")]
             {
@@ -237,6 +234,9 @@ impl TransactionParOCCRaw {
             }
         }
 
+        #[cfg(feature = "pmem")]
+        plan.issue();
+
         //for tag in self.tags_.values() {
         //    tag.persist_data(*self.id());
         //}
@@ -246,10 +246,16 @@ impl TransactionParOCCRaw {
         let id = *self.id();
         let txn_info = self.txn_info().clone();
         for tag in self.tags_.values_mut() {
+            if !tag.has_write() {
+                continue;
+            }
+
             tag.commit_data(id);
-            //FIXME: R->R also needs to be included
             tag.tobj_ref_.set_access_info(txn_info.clone());
+            tag.tobj_ref_.get_tvers().clear_readers();
         }
+        varlen::retire(&txn_info);
+        plog::persist_slots(id.into());
     }
 
     fn clean_up(&mut self) {
@@ -328,7 +334,7 @@ impl TransactionParOCCRaw {
 
         for tag in self.tags_.values() {
             if tag.has_write() {
-                logs.push(tag.make_log(id));
+                logs.extend(tag.make_log(id));
 
                 #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
                 self.records_
@@ -342,7 +348,7 @@ impl TransactionParOCCRaw {
         //            }
         //        }).collect();
 
-        plog::persist_log(logs);
+        self.persistency_.persist_log(logs);
     }
 
     //#[cfg(any(feature= "pmem", feature = "disk"))]
@@ -378,7 +384,8 @@ impl TransactionParOCCRaw {
     #[cfg_attr(feature = "profile", flame)]
     fn persist_log(&self, records: &Vec<DataRecord>) {
         let id = self.id();
-        plog::persist_log(records.iter().map(|ref r| r.as_log(*id)).collect());
+        self.persistency_
+            .persist_log(records.iter().map(|ref r| r.as_log(*id)).collect());
     }
 
     //#[cfg(any(feature = "pmem", feature = "disk"))]
@@ -394,9 +401,13 @@ impl TransactionParOCCRaw {
     #[cfg_attr(feature = "profile", flame)]
     pub fn commit(&mut self) {
         self.txn_info_.commit();
+        self.reads_.clear();
         self.status_ = TxState::COMMITTED;
         tcore::BenchmarkCounter::success();
 
+        #[cfg(feature = "history")]
+        mem::replace(&mut self.history_, TxnRecord::new(self.id_)).commit();
+
         #[cfg(any(feature = "pmem", feature = "disk"))]
         {
             //Persist data here
@@ -413,6 +424,8 @@ impl TransactionParOCCRaw {
     }
 
     pub fn abort(&mut self) {
+        dep::unregister_reads(&self.id_, &mut self.reads_);
+        varlen::discard();
         self.clean_up();
         self.txn_info_.commit();
 
@@ -426,7 +439,10 @@ impl TransactionParOCCRaw {
     #[cfg(any(feature = "pmem", feature = "disk"))]
     #[cfg_attr(feature = "profile", flame)]
     fn wait_deps_persist(&self) {
-        for (_, dep) in self.deps_.iter() {
+        for (id, dep) in self.deps_.iter() {
+            if !self.persistency_.waits_for(self.ww_wr_.contains(id)) {
+                continue;
+            }
             loop {
                 /* Busy wait here */
                 if !dep.has_persist() {
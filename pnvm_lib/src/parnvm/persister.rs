@@ -0,0 +1,124 @@
+//************************************************
+//Persister of the asynchronous commits
+//
+//TransactionParOCC::commit_async fences the data of the
+//txn on its own thread, then hands the rest of its
+//persist path over to a background thread: wait for its
+//dependencies to persist, append its commit record and
+//set TxnInfo::persist(), which runs its callbacks.
+//
+//The txns are taken in the order they were handed over.
+//A txn is handed over before it is marked committed, and
+//its dependencies committed before it did, so it never
+//waits for a txn queued after it. It is persisted once it
+//is committed (TxnInfo::wait_commit): a durable txn is a
+//visible one, for its handle and its dependents alike. The commit record of a
+//txn goes in the log of the thread that committed it, with
+//the handle taken when its job is built: the records of
+//the consecutive txns of one thread, queued meanwhile, go
+//in one append, with one fence (plog::persist_txns_to).
+//
+//Types:
+//- PersistJob          what is left to persist of a txn
+//************************************************
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use plog;
+#[cfg(feature = "pmem")]
+use pnvm_sys::{self, LogHandle};
+use sync;
+use txn::TxnInfo;
+
+lazy_static! {
+    static ref PERSISTER: Mutex<Sender<PersistJob>> = Mutex::new(spawn());
+}
+
+pub struct PersistJob {
+    txn_info_: Arc<TxnInfo>,
+    waits_:    Vec<(Arc<TxnInfo>, usize)>, /* Deps, and the rank to be durable below */
+    #[cfg(feature = "pmem")]
+    log_:      LogHandle,
+}
+
+impl PersistJob {
+    //On the committing thread
+    pub fn new(txn_info: Arc<TxnInfo>, waits: Vec<(Arc<TxnInfo>, usize)>) -> PersistJob {
+        PersistJob {
+            txn_info_: txn_info,
+            waits_:    waits,
+            #[cfg(feature = "pmem")]
+            log_:      pnvm_sys::log_handle(),
+        }
+    }
+}
+
+pub fn submit(job: PersistJob) {
+    PERSISTER
+        .lock()
+        .unwrap()
+        .send(job)
+        .expect("persister::submit: persister exited");
+}
+
+fn spawn() -> Sender<PersistJob> {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name(String::from("persister"))
+        .spawn(move || run(receiver))
+        .unwrap();
+    sender
+}
+
+fn run(receiver: Receiver<PersistJob>) {
+    while let Ok(job) = receiver.recv() {
+        let mut batch = vec![job];
+        batch.extend(receiver.try_iter());
+        persist_batch(batch);
+    }
+}
+
+fn persist_batch(batch: Vec<PersistJob>) {
+    let ids: Vec<u32> = batch.iter().map(|job| job.txn_info_.id().into()).collect();
+
+    /* Still publishing, past its hand over */
+    for job in batch.iter() {
+        job.txn_info_.wait_commit();
+    }
+
+    for (idx, job) in batch.iter().enumerate() {
+        for (dep, below) in job.waits_.iter() {
+            /* Its commit record goes ahead in the same append */
+            if ids[..idx].contains(&dep.id().into()) {
+                continue;
+            }
+
+            while !dep.has_persist_below(*below) {
+                sync::yield_now();
+            }
+        }
+    }
+
+    /* Earlier records first, a dep may be in another log */
+    #[cfg(feature = "pmem")]
+    {
+        let mut start = 0;
+        for end in 1..=batch.len() {
+            if end == batch.len() || !batch[end].log_.same_log(&batch[start].log_) {
+                plog::persist_txns_to(&batch[start].log_, &ids[start..end]);
+                start = end;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "pmem"))]
+    plog::persist_txns(&ids);
+
+    for job in batch {
+        job.txn_info_.persist();
+    }
+}
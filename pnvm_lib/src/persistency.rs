@@ -0,0 +1,156 @@
+//************************************************
+//Persistency models
+//
+//Where the persist paths of the transactions fence, and
+//which persists of other transactions they are ordered
+//after:
+//- Strict: every undo record and every data record is
+//  fenced before the next one is written
+//- Epoch: the undo records of a piece (or of the whole
+//  txn) are one epoch, fenced by their append. The data
+//  of the txn (or of the piece, with "pdrain") is another,
+//  fenced once before the commit record. The commit record
+//  waits for every dependency to persist
+//- Strand: the epochs of epoch persistency, but each txn
+//  is a strand of its own, joined only with the strands
+//  of the txns it read from or overwrote (WW/WR). The
+//  commit record does not wait for the readers of what
+//  the txn overwrote: their persists are not ordered with
+//  its own, see crash::checker
+//
+//TransactionOCC and Transaction2PL keep their write locks
+//until their commit record is durable, and track no
+//dependency: under them, strand would place the fences of
+//epoch, so a config asking for it is rejected (parse).
+//The pipelined TransactionParOCC(Raw) wait for their
+//dependencies in wait_deps_persist.
+//
+//With "ppipe", the data of a TransactionParOCC piece is
+//fenced by the log append of the next piece with writes,
+//which raises the rank its pieces are durable below
+//(TxnInfo), or by the single drain of the commit. The
+//readers of what a txn overwrote are waited for up to the
+//rank they read at; the WW/WR deps still as a whole, for
+//their commit records to come first.
+//
+//The model is set per run, with set_persistency_model,
+//and read by each txn when it is created.
+//************************************************
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use flush::FlushPlan;
+use plog::{self, PLog};
+use txn::Tid;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PersistencyModel {
+    Strict = 0,
+    Epoch = 1,
+    Strand = 2,
+}
+
+static PERSISTENCY_MODEL: AtomicUsize = AtomicUsize::new(PersistencyModel::Epoch as usize);
+
+pub fn set_persistency_model(model: PersistencyModel) {
+    info!("persistency model: {}", model.name());
+    PERSISTENCY_MODEL.store(model as usize, Ordering::SeqCst);
+}
+
+#[inline(always)]
+pub fn persistency_model() -> PersistencyModel {
+    match PERSISTENCY_MODEL.load(Ordering::Relaxed) {
+        0 => PersistencyModel::Strict,
+        1 => PersistencyModel::Epoch,
+        _ => PersistencyModel::Strand,
+    }
+}
+
+impl PersistencyModel {
+    pub fn from_name(name: &str) -> Option<PersistencyModel> {
+        match name.to_lowercase().as_ref() {
+            "strict" => Some(PersistencyModel::Strict),
+            "epoch" => Some(PersistencyModel::Epoch),
+            "strand" => Some(PersistencyModel::Strand),
+            _ => None,
+        }
+    }
+
+    //The model of a config, for the pipelined txns or else
+    //TransactionOCC/Transaction2PL
+    pub fn parse(name: &str, pipelined: bool) -> Result<PersistencyModel, String> {
+        match PersistencyModel::from_name(name) {
+            Some(PersistencyModel::Strand) if !pipelined => Err(String::from(
+                "strand persistency needs a pipelined txn, it is epoch for OCC and 2PL",
+            )),
+            Some(model) => Ok(model),
+            None => Err(format!("Unknown persistency model {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PersistencyModel::Strict => "strict",
+            PersistencyModel::Epoch => "epoch",
+            PersistencyModel::Strand => "strand",
+        }
+    }
+
+    //Appends the undo records of a piece or txn: one
+    //append, or one per record under Strict
+    pub fn persist_log(&self, logs: Vec<PLog>) {
+        match *self {
+            PersistencyModel::Strict => {
+                for log in logs {
+                    plog::persist_log(vec![log]);
+                }
+            }
+            _ => plog::persist_log(logs),
+        }
+    }
+
+    //The data of txn id. Under Strict, each ref added is
+    //written back and fenced at once.
+    pub fn data_plan(&self, id: Tid) -> FlushPlan {
+        FlushPlan::for_txn(id).fence_each(*self == PersistencyModel::Strict)
+    }
+
+    //Whether the commit record waits for a dependency to
+    //persist: ww_wr if the txn read or overwrote its
+    //writes, otherwise it only read what the txn overwrote
+    pub fn waits_for(&self, ww_wr: bool) -> bool {
+        ww_wr || *self != PersistencyModel::Strand
+    }
+}
+
+impl Default for PersistencyModel {
+    fn default() -> PersistencyModel {
+        PersistencyModel::Epoch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in &["strict", "epoch", "strand"] {
+            assert_eq!(PersistencyModel::from_name(name).unwrap().name(), *name);
+        }
+        assert_eq!(
+            PersistencyModel::from_name("Strand"),
+            Some(PersistencyModel::Strand)
+        );
+        assert_eq!(PersistencyModel::from_name("buffered"), None);
+    }
+
+    #[test]
+    fn test_waits_for() {
+        for model in &[PersistencyModel::Strict, PersistencyModel::Epoch] {
+            assert!(model.waits_for(true) && model.waits_for(false));
+        }
+        assert!(PersistencyModel::Strand.waits_for(true));
+        assert!(!PersistencyModel::Strand.waits_for(false));
+    }
+}
@@ -1,7 +1,10 @@
 use super::tcore::BenchmarkCounter;
 use libc;
 use pnvm_sys;
+#[cfg(feature = "porder")]
+use pnvm_sys::porder::{self, Role};
 use std::{
+    cell::RefCell,
     mem::{self, size_of},
     ptr::{self, Unique},
 };
@@ -17,13 +20,17 @@ pub struct PLog {
     data:   PLogData,
 }
 
+//24 bytes: widest first, so that the pmem range of an
+//undo record costs no padding
 #[repr(C)]
 #[derive(Clone)]
 pub struct PLogHeader {
-    log_kind: u16,
-    len:      usize,
-    txn_id:   u32,
-    is_none:  bool,
+    pmem_addr: usize, /* Where the data lives in pmem, 0 if unknown */
+    len:       u32,
+    txn_id:    u32,
+    offset:    u32, /* Of the data in the object, for a field */
+    log_kind:  u16,
+    is_none:   bool,
 }
 
 #[repr(C)]
@@ -35,15 +42,27 @@ pub struct PLogData {
 
 const LOG_KIND_DATA: u16 = 0;
 const LOG_KIND_TXN: u16 = 1;
+const LOG_KIND_SLOT: u16 = 2;
+
+/* Data of a slot record: the slot is free, or holds a live row */
+static SLOT_STATES: [u32; 2] = [0, 1];
+
+thread_local! {
+    //Row slots pushed or deleted by the installs of the
+    //thread, not logged yet
+    static SLOTS: RefCell<Vec<(usize, bool)>> = RefCell::new(Vec::new());
+}
 
 impl PLog {
     pub fn new(ptr: *mut u8, layout: Layout, id: Tid) -> PLog {
         PLog {
             header: PLogHeader {
-                log_kind: LOG_KIND_DATA,
-                len:      layout.size(),
-                txn_id:   id.into(),
-                is_none:  false,
+                log_kind:  LOG_KIND_DATA,
+                len:       layout.size() as u32,
+                txn_id:    id.into(),
+                is_none:   false,
+                pmem_addr: 0,
+                offset:    0,
             },
             data:   PLogData {
                 addr: ptr,
@@ -52,13 +71,30 @@ impl PLog {
         }
     }
 
+    //A single field of an object, ptr is at offset in the object
+    pub fn new_field(ptr: *mut u8, offset: usize, size: usize, id: Tid) -> PLog {
+        PLog {
+            header: PLogHeader {
+                log_kind:  LOG_KIND_DATA,
+                len:       size as u32,
+                txn_id:    id.into(),
+                is_none:   false,
+                pmem_addr: 0,
+                offset:    offset as u32,
+            },
+            data:   PLogData { addr: ptr, size },
+        }
+    }
+
     pub fn new_none(layout: Layout, id: Tid) -> PLog {
         PLog {
             header: PLogHeader {
-                log_kind: LOG_KIND_DATA,
-                len:      layout.size(),
-                txn_id:   id.into(),
-                is_none:  true,
+                log_kind:  LOG_KIND_DATA,
+                len:       layout.size() as u32,
+                txn_id:    id.into(),
+                is_none:   true,
+                pmem_addr: 0,
+                offset:    0,
             },
             data:   PLogData {
                 addr: ptr::null_mut(),
@@ -67,6 +103,23 @@ impl PLog {
         }
     }
 
+    //Data entries carry the image of the object (or of a field) before
+    //the write is installed, recovery copies it back to pmem_addr
+    //(plus the offset of the field) to undo the write.
+    pub fn set_pmem_addr(&mut self, pmem_addr: *mut u8) {
+        self.header.pmem_addr = pmem_addr as usize;
+    }
+
+    //The pmem range the entry undoes
+    #[cfg(feature = "porder")]
+    fn tag(&self) {
+        if self.header.pmem_addr != 0 {
+            let paddr = self.header.pmem_addr + self.header.offset as usize;
+            let len = self.header.len as usize;
+            porder::tag(Role::Log, self.header.txn_id, paddr as *const u8, len);
+        }
+    }
+
     //pub fn new(obj : &TObject<T>, id : Tid) -> PLog<T> {
     //    let addr = (obj).get_addr();
     //
@@ -102,10 +155,10 @@ impl Into<libc::iovec> for PLogData {
     }
 }
 
-pub fn into_iovec(log: PLog) -> (libc::iovec, libc::iovec) {
-    let mut log = log;
+//The iovecs point into the log, which must outlive them
+pub fn into_iovec(log: &PLog) -> (libc::iovec, libc::iovec) {
     let iovp_header = libc::iovec {
-        iov_base: &mut log.header as *mut _ as *mut libc::c_void,
+        iov_base: &log.header as *const _ as *mut libc::c_void,
         iov_len:  size_of::<PLogHeader>(),
     };
 
@@ -121,7 +174,7 @@ pub fn into_iovec(log: PLog) -> (libc::iovec, libc::iovec) {
 pub fn persist_log(logs: Vec<PLog>) {
     let mut iovecs = Vec::with_capacity(logs.len());
 
-    for (iov_header, iov_data) in logs.into_iter().map(move |log| into_iovec(log)) {
+    for (iov_header, iov_data) in logs.iter().map(|log| into_iovec(log)) {
         iovecs.push(iov_header);
         iovecs.push(iov_data);
     }
@@ -129,6 +182,11 @@ pub fn persist_log(logs: Vec<PLog>) {
     iovecs.shrink_to_fit();
     debug_assert!(iovecs.capacity() == iovecs.len());
 
+    #[cfg(feature = "porder")]
+    for log in logs.iter() {
+        log.tag();
+    }
+
     #[cfg(feature = "pmem")]
     pnvm_sys::persist_log(&iovecs);
 
@@ -137,34 +195,165 @@ pub fn persist_log(logs: Vec<PLog>) {
 }
 
 pub fn persist_txn(id: u32) {
-    let mut iovecs: Vec<libc::iovec> = Vec::with_capacity(2);
-
-    let log = PLog {
-        header: PLogHeader {
-            log_kind: LOG_KIND_TXN,
-            len:      size_of::<u32>(),
-            txn_id:   id,
-            is_none:  false,
-        },
-
-        data: PLogData {
-            addr: unsafe { mem::transmute::<&u32, *mut u8>(&id) },
-            size: size_of::<u32>(),
-        },
-    };
+    persist_txns(&[id]);
+}
+
+//Commit records of several txns, in one append
+#[cfg_attr(not(any(feature = "pmem", feature = "disk")), allow(unused_variables))]
+pub fn persist_txns(ids: &[u32]) {
+    #[cfg(feature = "pmem")]
+    append_txns(ids, pnvm_sys::persist_log);
 
-    iovecs.push(log.header.into());
-    iovecs.push(log.data.into());
+    #[cfg(feature = "disk")]
+    append_txns(ids, pnvm_sys::disk_persist_log);
+}
+
+//Same, to the log of the thread the handle was taken on
+#[cfg(feature = "pmem")]
+pub fn persist_txns_to(log: &pnvm_sys::LogHandle, ids: &[u32]) {
+    append_txns(ids, |iovecs| pnvm_sys::persist_log_to(log, iovecs));
+}
+
+fn append_txns<F: FnOnce(&Vec<libc::iovec>)>(ids: &[u32], append: F) {
+    let logs: Vec<PLog> = ids
+        .iter()
+        .map(|id| PLog {
+            header: PLogHeader {
+                log_kind:  LOG_KIND_TXN,
+                len:       size_of::<u32>() as u32,
+                txn_id:    *id,
+                is_none:   false,
+                pmem_addr: 0,
+                offset:    0,
+            },
+
+            data: PLogData {
+                addr: id as *const u32 as *mut u8,
+                size: size_of::<u32>(),
+            },
+        })
+        .collect();
+
+    let mut iovecs: Vec<libc::iovec> = Vec::with_capacity(2 * logs.len());
+    for log in logs.iter() {
+        iovecs.push(libc::iovec {
+            iov_base: &log.header as *const _ as *mut libc::c_void,
+            iov_len:  size_of::<PLogHeader>(),
+        });
+        iovecs.push(log.data.clone().into());
+    }
 
-    iovecs.shrink_to_fit();
     debug_assert!(iovecs.capacity() == iovecs.len());
 
+    #[cfg(feature = "porder")]
+    for id in ids {
+        porder::tag(Role::Commit, *id, id as *const u32 as *const u8, size_of::<u32>());
+    }
+
+    append(&iovecs);
+    //pnvm_sys::walk(0, visit_log);
+}
+
+//The pmem row at addr was pushed (live) or deleted by an
+//install, see persist_slots
+pub fn slot_changed(addr: *mut u8, live: bool) {
+    SLOTS.with(|slots| slots.borrow_mut().push((addr as usize, live)));
+}
+
+//Slot records of the slots changed on this thread since the
+//last call, by txn id, in one append. Appended after the
+//install and before the commit record: once the txn is
+//durable, a deleted row no longer looks live in pmem.
+pub fn persist_slots(id: u32) {
+    let slots = SLOTS.with(|slots| mem::replace(&mut *slots.borrow_mut(), Vec::new()));
+    if slots.is_empty() {
+        return;
+    }
+
+    let logs: Vec<PLog> = slots
+        .iter()
+        .map(|&(addr, live)| PLog {
+            header: PLogHeader {
+                log_kind:  LOG_KIND_SLOT,
+                len:       size_of::<u32>() as u32,
+                txn_id:    id,
+                is_none:   false,
+                pmem_addr: addr,
+                offset:    0,
+            },
+
+            data: PLogData {
+                addr: &SLOT_STATES[live as usize] as *const u32 as *mut u8,
+                size: size_of::<u32>(),
+            },
+        })
+        .collect();
+
+    let mut iovecs = Vec::with_capacity(2 * logs.len());
+    for (iov_header, iov_data) in logs.iter().map(|log| into_iovec(log)) {
+        iovecs.push(iov_header);
+        iovecs.push(iov_data);
+    }
+
     #[cfg(feature = "pmem")]
     pnvm_sys::persist_log(&iovecs);
 
     #[cfg(feature = "disk")]
     pnvm_sys::disk_persist_log(&iovecs);
-    //pnvm_sys::walk(0, visit_log);
+}
+
+//An entry read back from a log
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub log_kind_:  u16,
+    pub txn_id_:    u32,
+    pub pmem_addr_: usize,
+    pub offset_:    usize,
+    pub data_:      Vec<u8>,
+}
+
+impl LogEntry {
+    pub fn is_txn(&self) -> bool {
+        self.log_kind_ == LOG_KIND_TXN
+    }
+
+    pub fn is_slot(&self) -> bool {
+        self.log_kind_ == LOG_KIND_SLOT
+    }
+
+    //For a slot record, whether the row at pmem_addr_ is live
+    pub fn is_live(&self) -> bool {
+        self.data_.iter().any(|b| *b != 0)
+    }
+}
+
+//Decode the raw content of a log, in append order.
+//A truncated entry at the tail is dropped.
+pub fn decode_log(buf: &[u8]) -> Vec<LogEntry> {
+    let mut entries = vec![];
+    let header_size = size_of::<PLogHeader>();
+    let mut off = 0;
+
+    while off + header_size <= buf.len() {
+        let header = unsafe { ptr::read_unaligned(buf[off..].as_ptr() as *const PLogHeader) };
+        off += header_size;
+
+        let len = if header.is_none { 0 } else { header.len as usize };
+        if off + len > buf.len() {
+            break;
+        }
+
+        entries.push(LogEntry {
+            log_kind_:  header.log_kind,
+            txn_id_:    header.txn_id,
+            pmem_addr_: header.pmem_addr,
+            offset_:    header.offset as usize,
+            data_:      buf[off..off + len].to_vec(),
+        });
+        off += len;
+    }
+
+    entries
 }
 
 //FOR u32 only
@@ -195,3 +384,32 @@ extern "C" fn visit_log(
     }
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_log_roundtrip() {
+        let mut obj: [u8; 16] = [0; 16];
+        obj[8..12].copy_from_slice(&[1, 2, 3, 4]);
+        let mut log = PLog::new_field(obj[8..].as_mut_ptr(), 8, 4, Tid::new(7));
+        log.set_pmem_addr(0x1000 as *mut u8);
+
+        let mut buf = vec![];
+        let (header, data) = into_iovec(&log);
+        for iov in [header, data].iter() {
+            buf.extend_from_slice(unsafe {
+                ::std::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len)
+            });
+        }
+
+        let entries = decode_log(&buf);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].txn_id_, 7);
+        assert_eq!(entries[0].pmem_addr_, 0x1000);
+        assert_eq!(entries[0].offset_, 8);
+        assert_eq!(entries[0].data_, vec![1, 2, 3, 4]);
+        assert_eq!(size_of::<PLogHeader>(), 24);
+    }
+}
@@ -0,0 +1,193 @@
+//************************************************
+//Placement of the pmem rows
+//
+//The rows of a bucket sit in slots of its pmem extents,
+//see Bucket::get_pmem_addr. Packed (the default), a slot
+//is size_of::<Entry>() and rows straddle cache lines:
+//flushing a field of one row may flush a line of its
+//neighbour.
+//
+//A PmemLayout can align the slots instead, to a cache
+//line or to an XPLine (256 B, the write unit of Optane
+//DIMMs), padding each slot to a multiple of it. No two
+//rows then share a line.
+//
+//With align_hot, a row is also offset in its slot so that
+//its hot fields, FieldOffset::hot_range, start a cache
+//line, if that covers them with fewer lines. The hot
+//fields are the #[field]s, i.e. the ones written on
+//their own. Only the row moves, its fields keep their
+//#[repr(C)] offsets: hot fields do not share lines unless
+//they are declared next to each other, and cold fields
+//between two hot ones stay in the hot lines.
+//
+//Splitting the hot and cold fields of a row into separate
+//slots is out of scope: a row is copied, logged and
+//recovered as a single Layout (Row, plog), and every
+//reader of a pmem row would have to gather it.
+//
+//SlotLayout is the layout resolved for an entry type.
+//************************************************
+
+use super::FieldOffset;
+use flush;
+use pnvm_sys::CACHE_LINE_SIZE;
+use std::{cmp, mem};
+
+pub const XPLINE_SIZE: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PmemLayout {
+    align_:     usize,
+    align_hot_: bool,
+}
+
+//Offset of a row in its slot, and the size of a slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlotLayout {
+    pub pad_:    usize,
+    pub stride_: usize,
+}
+
+impl PmemLayout {
+    //Rows back-to-back
+    pub fn packed() -> PmemLayout {
+        PmemLayout {
+            align_: 1,
+            align_hot_: false,
+        }
+    }
+
+    pub fn aligned(align: usize) -> PmemLayout {
+        assert!(align.is_power_of_two(), "PmemLayout: alignment {}", align);
+        PmemLayout {
+            align_: align,
+            align_hot_: false,
+        }
+    }
+
+    pub fn cache_line() -> PmemLayout {
+        PmemLayout::aligned(CACHE_LINE_SIZE)
+    }
+
+    pub fn xpline() -> PmemLayout {
+        PmemLayout::aligned(XPLINE_SIZE)
+    }
+
+    //Slots are at least cache line aligned
+    pub fn align_hot(self) -> PmemLayout {
+        PmemLayout {
+            align_: cmp::max(self.align_, CACHE_LINE_SIZE),
+            align_hot_: true,
+        }
+    }
+
+    //packed, cache_line, xpline, align_hot (cache_line
+    //with align_hot) or xpline_align_hot
+    pub fn from_name(name: &str) -> Option<PmemLayout> {
+        match name.to_lowercase().as_ref() {
+            "packed" => Some(PmemLayout::packed()),
+            "cache_line" => Some(PmemLayout::cache_line()),
+            "xpline" => Some(PmemLayout::xpline()),
+            "align_hot" => Some(PmemLayout::cache_line().align_hot()),
+            "xpline_align_hot" => Some(PmemLayout::xpline().align_hot()),
+            _ => None,
+        }
+    }
+
+    pub fn slot_of<Entry: FieldOffset>(&self) -> SlotLayout {
+        self.slot(
+            mem::size_of::<Entry>(),
+            mem::align_of::<Entry>(),
+            Entry::hot_range(),
+        )
+    }
+
+    fn slot(&self, size: usize, align: usize, hot: Option<(usize, usize)>) -> SlotLayout {
+        let size = cmp::max(size, 1);
+        let pad = match hot {
+            Some((start, end)) if self.align_hot_ && end > start => {
+                let line = CACHE_LINE_SIZE;
+                let pad = round_up((line - start % line) % line, align);
+                let lines = |at: usize| flush::lines_of(at as *const u8, end - start);
+                if lines(pad + start) < lines(start) {
+                    pad
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        };
+
+        SlotLayout {
+            pad_:    pad,
+            stride_: round_up(pad + size, cmp::max(self.align_, align)),
+        }
+    }
+}
+
+impl Default for PmemLayout {
+    fn default() -> PmemLayout {
+        PmemLayout::packed()
+    }
+}
+
+fn round_up(n: usize, align: usize) -> usize {
+    (n + align - 1) / align * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A 100 bytes entry with its hot fields at [56, 72)
+    const SIZE: usize = 100;
+    const HOT: Option<(usize, usize)> = Some((56, 72));
+
+    #[test]
+    fn test_slot_strides() {
+        let slot = |layout: PmemLayout, hot| {
+            let slot = layout.slot(SIZE, 4, hot);
+            (slot.pad_, slot.stride_)
+        };
+
+        assert_eq!(slot(PmemLayout::packed(), HOT), (0, 100));
+        assert_eq!(slot(PmemLayout::cache_line(), HOT), (0, 128));
+        assert_eq!(slot(PmemLayout::xpline(), HOT), (0, 256));
+
+        /* The hot fields move to the start of the second line */
+        let hot_aligned = PmemLayout::cache_line().align_hot();
+        assert_eq!(slot(hot_aligned, HOT), (8, 128));
+
+        /* Already within a line */
+        assert_eq!(slot(hot_aligned, Some((8, 24))), (0, 128));
+        assert_eq!(
+            PmemLayout::from_name("XPLINE_ALIGN_HOT"),
+            Some(PmemLayout::xpline().align_hot())
+        );
+    }
+
+    //Lines flushed when the hot fields of rows 0..N are
+    //written, one row per txn
+    #[test]
+    fn test_hot_lines_per_row() {
+        let (start, end) = HOT.unwrap();
+        let hot_lines = |layout: PmemLayout| -> usize {
+            let slot = layout.slot(SIZE, 4, HOT);
+            (0..64)
+                .map(|i| {
+                    let row = i * slot.stride_ + slot.pad_;
+                    flush::lines_of((row + start) as *const u8, end - start)
+                })
+                .sum()
+        };
+
+        let packed = hot_lines(PmemLayout::packed());
+        let aligned = hot_lines(PmemLayout::cache_line());
+        let hot_aligned = hot_lines(PmemLayout::cache_line().align_hot());
+
+        assert!(packed > hot_aligned);
+        assert_eq!(aligned, 2 * 64);
+        assert_eq!(hot_aligned, 64);
+    }
+}
@@ -0,0 +1,85 @@
+//************************************************
+//Transactional storage engine
+//
+//Types:
+//- Table<Entry, Index>     a table with many buckets
+//- Bucket<Entry, Index>    a single partition
+//- Row<Entry, Index>       a row with transactional implementation
+//- SecIndex                secondary index map for range queries,
+//                          locked through a SecIndexGuard
+//- OrderedIndex<K, V>      transactional ordered index, see ordered_index.rs
+//- SegVec<T>               append-only vector holding the rows of a bucket
+//- VarLen                  handle to an out of line column, see varlen.rs
+//- PmemLayout              placement of the pmem rows of a table, see layout.rs
+//
+//An entry type implements Key (and FieldOffset), and
+//the TRef traits below for the rows it is accessed
+//through. pnvm_derive generates the latter for TPCC.
+//
+//With the pmem or disk feature, the rows are backed by
+//files under PMEM_FILE_DIR, given at compile time.
+//The rows of a bucket are laid out in them as given by
+//the PmemLayout of its table.
+//************************************************
+
+pub mod layout;
+pub mod ordered_index;
+pub mod row;
+pub mod sec_index;
+pub mod segvec;
+pub mod table;
+pub mod varlen;
+
+pub use self::layout::{PmemLayout, SlotLayout};
+pub use self::ordered_index::OrderedIndex;
+pub use self::row::Row;
+pub use self::sec_index::{SecIndex, SecIndexGuard};
+pub use self::segvec::SegVec;
+pub use self::table::{Bucket, Partition, Table};
+pub use self::varlen::VarLen;
+
+use std::sync::Arc;
+use tcore::TRef;
+use txn::TxnInfo;
+
+//Byte offsets of the fields, -1 if not tracked.
+//The end of a tracked field i is stored at i+1.
+pub trait FieldOffset {
+    fn field_offset(&self) -> [isize; 32];
+
+    //The VarLen columns, with their field index if tracked
+    fn var_fields(&self) -> Vec<(Option<usize>, VarLen)> {
+        Vec::new()
+    }
+
+    //Byte range from the first tracked field to the end of
+    //the last, the same for every entry. See PmemLayout.
+    fn hot_range() -> Option<(usize, usize)>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+pub trait Key<T>: FieldOffset {
+    fn primary_key(&self) -> T;
+
+    //Default partition of the entry, see Table::new_with_buckets
+    fn bucket_key(&self) -> usize;
+}
+
+//T is what the TRef needs at install to reach the bucket
+//of a push or delete, e.g. all the tables of a workload.
+pub trait TableRef<T> {
+    fn into_table_ref(self, Option<usize>, Option<Arc<T>>) -> Box<dyn TRef>;
+}
+
+//The TxnInfo is the deleter's, see Bucket::delete
+pub trait BucketDeleteRef<T> {
+    fn into_delete_table_ref(self, usize, Arc<T>, Arc<TxnInfo>) -> Box<dyn TRef>;
+}
+
+pub trait BucketPushRef<T> {
+    fn into_push_table_ref(self, usize, Arc<T>) -> Box<dyn TRef>;
+}
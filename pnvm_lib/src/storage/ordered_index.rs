@@ -0,0 +1,1141 @@
+//************************************************
+//Ordered index
+//
+//A concurrent ordered map, for point lookups and range
+//scans. As a primary index, the value is the row itself
+//(Arc<Row<..>>). As a secondary index, a key is made
+//unique with the primary key, e.g. (c_last, c_id) -> ().
+//
+//The keys are range partitioned into nodes, each with
+//its own TVersion. A read records the version of every
+//node it looks into, so that a scan is validated as a
+//whole: an insert or delete in the range scanned fails
+//the check of the scanner, as does a split of its nodes.
+//
+//get*, range*, insert* and delete* run in a transaction:
+//- get, range, insert, delete      TransactionOCC
+//- *_pc                            TransactionParOCC
+//- *_pc_raw                        TransactionParOCCRaw
+//- *_lock                          Transaction2PL
+//
+//With OCC, the writes of a txn go to a single tag of the
+//index in program order. At commit, the nodes covering
+//the written keys are locked and the writes applied. A
+//node over capacity is split then, by its lock owner.
+//Nodes are never merged.
+//
+//The pipelined protocols track their dependencies on
+//the index as a whole, while the reads are still
+//validated per node.
+//
+//Under 2PL, the nodes are read or write locked for the
+//txn, and the writes applied in place.
+//
+//The index is volatile, it is rebuilt from the rows.
+//
+//TPCC does not use it yet: its tables keep the hash
+//indexes of Table and SecIndex.
+//************************************************
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    ops::Bound,
+    ptr,
+    sync::{Arc, RwLock},
+};
+
+use pnvm_sys::Layout;
+
+use lock::lock_txn::{LockType, Transaction2PL};
+use occ::occ_txn::TransactionOCC;
+use parnvm::nvm_txn_occ::TransactionParOCC;
+use parnvm::nvm_txn_raw::TransactionParOCCRaw;
+use tcore::{ObjectId, OidFac, Operation, TRef, TTag, TVersion};
+use txn::{Tid, Transaction, TxnInfo};
+
+const DEFAULT_NODE_CAP: usize = 128;
+
+type Nodes<K, V> = Arc<RwLock<Vec<Arc<IndexNode<K, V>>>>>;
+
+//Writes of a txn on the index, None for a delete
+type IndexWrites<K, V> = RefCell<Vec<(K, Option<V>)>>;
+
+struct IndexNode<K, V> {
+    id_:    ObjectId,
+    vers_:  Arc<TVersion>,
+    low_:   Option<K>, /* Inclusive, None for the first node */
+    inner_: RwLock<NodeInner<K, V>>,
+}
+
+struct NodeInner<K, V> {
+    entries_: BTreeMap<K, V>,
+    high_:    Option<K>, /* Exclusive, None for the last node */
+}
+
+impl<K: Ord, V> IndexNode<K, V> {
+    fn new(low: Option<K>, high: Option<K>, entries: BTreeMap<K, V>) -> IndexNode<K, V> {
+        IndexNode {
+            id_:    OidFac::get_obj_next(),
+            vers_:  Arc::new(TVersion::default()),
+            low_:   low,
+            inner_: RwLock::new(NodeInner {
+                entries_: entries,
+                high_:    high,
+            }),
+        }
+    }
+
+    //A node only shrinks from above, when split
+    fn covers(&self, key: &K) -> bool {
+        let above_low = match self.low_ {
+            None => true,
+            Some(ref low) => low <= key,
+        };
+        above_low
+            && match self.inner_.read().unwrap().high_ {
+                None => true,
+                Some(ref high) => key < high,
+            }
+    }
+
+    //If the node starts before the upper bound of a range
+    fn starts_before(&self, to: Bound<&K>) -> bool {
+        match (self.low_.as_ref(), to) {
+            (None, _) | (_, Bound::Unbounded) => true,
+            (Some(low), Bound::Included(to)) => low <= to,
+            (Some(low), Bound::Excluded(to)) => low < to,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.inner_.read().unwrap().entries_.len()
+    }
+}
+
+impl<K, V> Debug for IndexNode<K, V>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IndexNode {{ id: {:?}, low: {:?} }}",
+            self.id_, self.low_
+        )
+    }
+}
+
+//Position of the node covering key
+fn node_of<K: Ord, V>(nodes: &[Arc<IndexNode<K, V>>], key: &K) -> usize {
+    /* nodes[0] starts unbounded, find the last one starting at or below key */
+    let (mut lo, mut hi) = (0, nodes.len());
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        match nodes[mid].low_ {
+            Some(ref low) if low <= key => lo = mid,
+            _ => hi = mid,
+        }
+    }
+    lo
+}
+
+//Moves the upper half of nodes[pos] into a new node after it
+fn split<K: Ord + Clone, V>(
+    nodes: &mut Vec<Arc<IndexNode<K, V>>>,
+    pos: usize,
+) -> Arc<IndexNode<K, V>> {
+    let new = {
+        let mut inner = nodes[pos].inner_.write().unwrap();
+        let mid = inner
+            .entries_
+            .keys()
+            .nth(inner.entries_.len() / 2)
+            .expect("split of an empty node")
+            .clone();
+        let upper = inner.entries_.split_off(&mid);
+        let high = inner.high_.take();
+        inner.high_ = Some(mid.clone());
+        Arc::new(IndexNode::new(Some(mid), high, upper))
+    };
+    nodes.insert(pos + 1, new.clone());
+    new
+}
+
+fn in_range<K: Ord>(key: &K, from: Bound<&K>, to: Bound<&K>) -> bool {
+    (match from {
+        Bound::Unbounded => true,
+        Bound::Included(from) => from <= key,
+        Bound::Excluded(from) => from < key,
+    }) && match to {
+        Bound::Unbounded => true,
+        Bound::Included(to) => key <= to,
+        Bound::Excluded(to) => key < to,
+    }
+}
+
+fn is_empty_range<K: Ord>(from: Bound<&K>, to: Bound<&K>) -> bool {
+    match (from, to) {
+        (Bound::Included(from), Bound::Included(to)) => from > to,
+        (Bound::Included(from), Bound::Excluded(to))
+        | (Bound::Excluded(from), Bound::Included(to))
+        | (Bound::Excluded(from), Bound::Excluded(to)) => from >= to,
+        _ => false,
+    }
+}
+
+//The OCC transactions only differ in where their tags are kept
+trait TagTxn {
+    fn tag(&mut self, id: &ObjectId, tref: Box<dyn TRef>, ops: Operation) -> &mut TTag;
+}
+
+impl TagTxn for TransactionOCC {
+    fn tag(&mut self, id: &ObjectId, tref: Box<dyn TRef>, ops: Operation) -> &mut TTag {
+        self.retrieve_tag(id, tref, ops)
+    }
+}
+
+impl TagTxn for TransactionParOCC {
+    fn tag(&mut self, id: &ObjectId, tref: Box<dyn TRef>, ops: Operation) -> &mut TTag {
+        self.retrieve_tag(id, tref, ops)
+    }
+}
+
+impl TagTxn for TransactionParOCCRaw {
+    fn tag(&mut self, id: &ObjectId, tref: Box<dyn TRef>, ops: Operation) -> &mut TTag {
+        self.retrieve_tag(id, tref, ops)
+    }
+}
+
+pub struct OrderedIndex<K, V> {
+    id_:       ObjectId,
+    vers_:     Arc<TVersion>, /* Of the index as a whole */
+    nodes_:    Nodes<K, V>,   /* Sorted by low_ */
+    node_cap_: usize,
+    name_:     Arc<String>,
+}
+
+impl<K, V> Debug for OrderedIndex<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OrderedIndex {{ name: {} }}", self.name_)
+    }
+}
+
+impl<K, V> OrderedIndex<K, V>
+where
+    K: 'static + Ord + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    pub fn new(name: &str) -> OrderedIndex<K, V> {
+        OrderedIndex::with_node_capacity(DEFAULT_NODE_CAP, name)
+    }
+
+    //A node over node_cap entries is split in 2
+    pub fn with_node_capacity(node_cap: usize, name: &str) -> OrderedIndex<K, V> {
+        assert!(node_cap >= 2, "node capacity too small");
+
+        OrderedIndex {
+            id_:       OidFac::get_obj_next(),
+            vers_:     Arc::new(TVersion::default()),
+            nodes_:    Arc::new(RwLock::new(vec![Arc::new(IndexNode::new(
+                None,
+                None,
+                BTreeMap::new(),
+            ))])),
+            node_cap_: node_cap,
+            name_:     Arc::new(String::from(name)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name_
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes_
+            .read()
+            .unwrap()
+            .iter()
+            .map(|node| node.len())
+            .sum()
+    }
+
+    pub fn node_num(&self) -> usize {
+        self.nodes_.read().unwrap().len()
+    }
+
+    /* Outside of transactions, e.g. when loading */
+    pub fn insert_raw(&self, key: K, val: V) {
+        let mut nodes = self.nodes_.write().unwrap();
+        let pos = node_of(&nodes, &key);
+        let len = {
+            let mut inner = nodes[pos].inner_.write().unwrap();
+            inner.entries_.insert(key, val);
+            inner.entries_.len()
+        };
+        if len > self.node_cap_ {
+            split(&mut nodes, pos);
+        }
+    }
+
+    pub fn get_raw(&self, key: &K) -> Option<V> {
+        self.range_raw(Bound::Included(key), Bound::Included(key))
+            .pop()
+            .map(|(_, val)| val)
+    }
+
+    pub fn range_raw(&self, from: Bound<&K>, to: Bound<&K>) -> Vec<(K, V)> {
+        self.read_range(from, to).1
+    }
+
+    /* OCC */
+    pub fn get(&self, tx: &mut TransactionOCC, key: &K) -> Option<V> {
+        self.get_tag(tx, key)
+    }
+
+    pub fn range(&self, tx: &mut TransactionOCC, from: Bound<&K>, to: Bound<&K>) -> Vec<(K, V)> {
+        self.range_tag(tx, from, to)
+    }
+
+    //Inserts or replaces
+    pub fn insert(&self, tx: &mut TransactionOCC, key: K, val: V) {
+        self.write_tag(tx, key, Some(val))
+    }
+
+    //Returns if the key was present, which is validated
+    pub fn delete(&self, tx: &mut TransactionOCC, key: &K) -> bool {
+        self.delete_tag(tx, key)
+    }
+
+    /* Pipelined OCC */
+    pub fn get_pc(&self, tx: &mut TransactionParOCC, key: &K) -> Option<V> {
+        self.get_tag(tx, key)
+    }
+
+    pub fn range_pc(
+        &self,
+        tx: &mut TransactionParOCC,
+        from: Bound<&K>,
+        to: Bound<&K>,
+    ) -> Vec<(K, V)> {
+        self.range_tag(tx, from, to)
+    }
+
+    pub fn insert_pc(&self, tx: &mut TransactionParOCC, key: K, val: V) {
+        self.write_tag(tx, key, Some(val))
+    }
+
+    pub fn delete_pc(&self, tx: &mut TransactionParOCC, key: &K) -> bool {
+        self.delete_tag(tx, key)
+    }
+
+    pub fn get_pc_raw(&self, tx: &mut TransactionParOCCRaw, key: &K) -> Option<V> {
+        self.get_tag(tx, key)
+    }
+
+    pub fn range_pc_raw(
+        &self,
+        tx: &mut TransactionParOCCRaw,
+        from: Bound<&K>,
+        to: Bound<&K>,
+    ) -> Vec<(K, V)> {
+        self.range_tag(tx, from, to)
+    }
+
+    pub fn insert_pc_raw(&self, tx: &mut TransactionParOCCRaw, key: K, val: V) {
+        self.write_tag(tx, key, Some(val))
+    }
+
+    pub fn delete_pc_raw(&self, tx: &mut TransactionParOCCRaw, key: &K) -> bool {
+        self.delete_tag(tx, key)
+    }
+
+    /* 2PL, Err if a lock is not granted and the txn should abort */
+    pub fn get_lock(&self, tx: &mut Transaction2PL, key: &K) -> Result<Option<V>, ()> {
+        Ok(self
+            .range_lock(tx, Bound::Included(key), Bound::Included(key))?
+            .pop()
+            .map(|(_, val)| val))
+    }
+
+    //Read locks every node overlapping the range, so that
+    //no insert or delete can happen in it until the txn ends
+    pub fn range_lock(
+        &self,
+        tx: &mut Transaction2PL,
+        from: Bound<&K>,
+        to: Bound<&K>,
+    ) -> Result<Vec<(K, V)>, ()> {
+        if is_empty_range(from, to) {
+            return Ok(Vec::new());
+        }
+
+        let tid: u32 = tx.id().into();
+        loop {
+            let nodes = self.nodes_in(from, to);
+            for node in nodes.iter() {
+                if tx.has_lock(&(node.id_, LockType::Read))
+                    || tx.has_lock(&(node.id_, LockType::Write))
+                {
+                    continue;
+                }
+                if !node.vers_.read_lock(tid) {
+                    return Err(());
+                }
+                tx.add_locks((node.id_, LockType::Read), node.vers_.clone());
+            }
+
+            /* A node split before being locked misses the upper half */
+            let now = self.nodes_in(from, to);
+            if now.len() == nodes.len() {
+                return Ok(self.read_range(from, to).1);
+            }
+        }
+    }
+
+    pub fn insert_lock(&self, tx: &mut Transaction2PL, key: K, val: V) -> Result<(), ()> {
+        self.write_lock(tx, key, Some(val)).map(|_| ())
+    }
+
+    //Ok(present)
+    pub fn delete_lock(&self, tx: &mut Transaction2PL, key: &K) -> Result<bool, ()> {
+        self.write_lock(tx, key.clone(), None)
+            .map(|old| old.is_some())
+    }
+
+    fn write_lock(&self, tx: &mut Transaction2PL, key: K, val: Option<V>) -> Result<Option<V>, ()> {
+        let tid: u32 = tx.id().into();
+
+        /* Lock the node covering key */
+        loop {
+            let node = {
+                let nodes = self.nodes_.read().unwrap();
+                nodes[node_of(&nodes, &key)].clone()
+            };
+            if !tx.has_lock(&(node.id_, LockType::Write)) {
+                if !node.vers_.write_lock(tid) {
+                    return Err(());
+                }
+                tx.add_locks((node.id_, LockType::Write), node.vers_.clone());
+            }
+            if node.covers(&key) {
+                break;
+            }
+        }
+
+        /* Lock held */
+        let mut nodes = self.nodes_.write().unwrap();
+        let pos = node_of(&nodes, &key);
+        let node = nodes[pos].clone();
+        let old = {
+            let mut inner = node.inner_.write().unwrap();
+            match val {
+                Some(val) => inner.entries_.insert(key, val),
+                None => inner.entries_.remove(&key),
+            }
+        };
+        node.vers_.set_version(tid);
+
+        if node.len() > self.node_cap_ {
+            /* Unreachable but through nodes_, held */
+            let new = split(&mut nodes, pos);
+            if !new.vers_.write_lock(tid) {
+                panic!("write_lock of a new node failed");
+            }
+            new.vers_.set_version(tid);
+            tx.add_locks((new.id_, LockType::Write), new.vers_.clone());
+        }
+        Ok(old)
+    }
+
+    /* Shared by the OCC transactions */
+    fn get_tag<T: TagTxn>(&self, tx: &mut T, key: &K) -> Option<V> {
+        self.range_tag(tx, Bound::Included(key), Bound::Included(key))
+            .pop()
+            .map(|(_, val)| val)
+    }
+
+    fn range_tag<T: TagTxn>(&self, tx: &mut T, from: Bound<&K>, to: Bound<&K>) -> Vec<(K, V)> {
+        if is_empty_range(from, to) {
+            return Vec::new();
+        }
+
+        let (read, entries) = self.read_range(from, to);
+        for (node, vers) in read {
+            let tref = Box::new(IndexReadRef {
+                node_: node.clone(),
+                vers_: self.vers_.clone(),
+                name_: self.name_.clone(),
+            });
+            tx.tag(&node.id_, tref, Operation::RWrite).add_version(vers);
+        }
+
+        /* Reads its own writes */
+        let mut entries: BTreeMap<K, V> = entries.into_iter().collect();
+        let tag = tx.tag(&self.id_, self.write_ref(), Operation::Push);
+        for &(ref key, ref val) in tag.get_data::<IndexWrites<K, V>>().borrow().iter() {
+            if !in_range(key, from, to) {
+                continue;
+            }
+            match *val {
+                Some(ref val) => entries.insert(key.clone(), val.clone()),
+                None => entries.remove(key),
+            };
+        }
+        entries.into_iter().collect()
+    }
+
+    fn write_tag<T: TagTxn>(&self, tx: &mut T, key: K, val: Option<V>) {
+        let tag = tx.tag(&self.id_, self.write_ref(), Operation::Push);
+        tag.get_data::<IndexWrites<K, V>>()
+            .borrow_mut()
+            .push((key, val));
+        tag.set_write();
+    }
+
+    fn delete_tag<T: TagTxn>(&self, tx: &mut T, key: &K) -> bool {
+        let present = self.get_tag(tx, key).is_some();
+        self.write_tag(tx, key.clone(), None);
+        present
+    }
+
+    fn write_ref(&self) -> Box<dyn TRef> {
+        Box::new(IndexWriteRef {
+            id_:       self.id_,
+            vers_:     self.vers_.clone(),
+            nodes_:    self.nodes_.clone(),
+            node_cap_: self.node_cap_,
+            name_:     self.name_.clone(),
+            writes_:   RefCell::new(Vec::new()),
+            locked_:   RefCell::new(Vec::new()),
+        })
+    }
+
+    fn nodes_in(&self, from: Bound<&K>, to: Bound<&K>) -> Vec<Arc<IndexNode<K, V>>> {
+        let nodes = self.nodes_.read().unwrap();
+        let start = match from {
+            Bound::Unbounded => 0,
+            Bound::Included(key) | Bound::Excluded(key) => node_of(&nodes, key),
+        };
+        nodes[start..]
+            .iter()
+            .take_while(|node| node.starts_before(to))
+            .cloned()
+            .collect()
+    }
+
+    //The nodes overlapping the range with the version read, and the entries.
+    //Splits wait for the nodes lock, so the nodes cover the range.
+    fn read_range(
+        &self,
+        from: Bound<&K>,
+        to: Bound<&K>,
+    ) -> (Vec<(Arc<IndexNode<K, V>>, u32)>, Vec<(K, V)>) {
+        let mut read = Vec::new();
+        let mut entries = Vec::new();
+        if is_empty_range(from, to) {
+            return (read, entries);
+        }
+
+        let nodes = self.nodes_.read().unwrap();
+        let start = match from {
+            Bound::Unbounded => 0,
+            Bound::Included(key) | Bound::Excluded(key) => node_of(&nodes, key),
+        };
+        for node in nodes[start..].iter() {
+            if !node.starts_before(to) {
+                break;
+            }
+
+            /* Version first, a later write fails the check */
+            let vers = node.vers_.get_version();
+            let inner = node.inner_.read().unwrap();
+            entries.extend(
+                inner
+                    .entries_
+                    .range((from, to))
+                    .map(|(key, val)| (key.clone(), val.clone())),
+            );
+            read.push((node.clone(), vers));
+        }
+        (read, entries)
+    }
+}
+
+//A node read by a transaction
+#[derive(Debug)]
+struct IndexReadRef<K, V> {
+    node_: Arc<IndexNode<K, V>>,
+    vers_: Arc<TVersion>, /* Of the index, for dependencies */
+    name_: Arc<String>,
+}
+
+impl<K, V> Clone for IndexReadRef<K, V> {
+    fn clone(&self) -> Self {
+        IndexReadRef {
+            node_: self.node_.clone(),
+            vers_: self.vers_.clone(),
+            name_: self.name_.clone(),
+        }
+    }
+}
+
+impl<K, V> TRef for IndexReadRef<K, V>
+where
+    K: 'static + Ord + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    /* Nothing is logged for the index */
+    fn get_ptr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_layout(&self) -> Layout {
+        Layout::new::<()>()
+    }
+
+    fn get_field_ptr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_field_size(&self, _field_idx: usize) -> usize {
+        0
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_addr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_field_addr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn box_clone(&self) -> Box<dyn TRef> {
+        Box::new(self.clone())
+    }
+
+    fn get_id(&self) -> &ObjectId {
+        &self.node_.id_
+    }
+
+    fn get_tvers(&self) -> &Arc<TVersion> {
+        &self.vers_
+    }
+
+    fn get_version(&self) -> u32 {
+        self.node_.vers_.get_version()
+    }
+
+    fn read(&self) -> &Any {
+        &()
+    }
+
+    fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.vers_.get_access_info()
+    }
+
+    fn set_access_info(&mut self, txn_info: Arc<TxnInfo>) {
+        self.vers_.set_access_info(txn_info);
+    }
+
+    fn get_name(&self) -> String {
+        (*self.name_).clone()
+    }
+
+    fn install(&self, _id: Tid) {
+        panic!("IndexReadRef is never written");
+    }
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    fn write(&mut self, _ptr: *mut u8) {
+        panic!("IndexReadRef is never written");
+    }
+
+    #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+    fn write(&mut self, _val: Box<Any>) {
+        panic!("IndexReadRef is never written");
+    }
+
+    fn lock(&self, _tid: Tid) -> bool {
+        panic!("IndexReadRef is never written");
+    }
+
+    fn unlock(&self) {
+        panic!("IndexReadRef is never written");
+    }
+
+    fn check(&self, vers: u32, tid: u32) -> bool {
+        self.node_.vers_.check_version(vers, tid)
+    }
+
+    /* For 2 Phase Locking, see OrderedIndex::range_lock */
+    fn write_through(&self, _val: Box<Any>, _tid: Tid) {
+        panic!("IndexReadRef is never written");
+    }
+
+    fn read_lock(&self, tid: u32) -> bool {
+        self.node_.vers_.read_lock(tid)
+    }
+
+    fn read_unlock(&self, tid: u32) {
+        self.node_.vers_.read_unlock(tid)
+    }
+
+    fn write_lock(&self, _tid: u32) -> bool {
+        panic!("IndexReadRef is never written");
+    }
+
+    fn write_unlock(&self, _tid: u32) {
+        panic!("IndexReadRef is never written");
+    }
+}
+
+//The writes of a transaction on an index
+#[derive(Debug)]
+struct IndexWriteRef<K, V> {
+    id_:       ObjectId,
+    vers_:     Arc<TVersion>,
+    nodes_:    Nodes<K, V>,
+    node_cap_: usize,
+    name_:     Arc<String>,
+    writes_:   IndexWrites<K, V>,
+    locked_:   RefCell<Vec<Arc<IndexNode<K, V>>>>, /* Nodes locked at commit */
+}
+
+impl<K: Clone, V: Clone> Clone for IndexWriteRef<K, V> {
+    fn clone(&self) -> Self {
+        IndexWriteRef {
+            id_:       self.id_,
+            vers_:     self.vers_.clone(),
+            nodes_:    self.nodes_.clone(),
+            node_cap_: self.node_cap_,
+            name_:     self.name_.clone(),
+            writes_:   self.writes_.clone(),
+            locked_:   self.locked_.clone(),
+        }
+    }
+}
+
+impl<K, V> TRef for IndexWriteRef<K, V>
+where
+    K: 'static + Ord + Clone + Debug,
+    V: 'static + Clone + Debug,
+{
+    /* Nothing is logged for the index */
+    fn get_ptr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_layout(&self) -> Layout {
+        Layout::new::<()>()
+    }
+
+    fn get_field_ptr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_field_size(&self, _field_idx: usize) -> usize {
+        0
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_addr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_field_addr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn box_clone(&self) -> Box<dyn TRef> {
+        Box::new(self.clone())
+    }
+
+    fn get_id(&self) -> &ObjectId {
+        &self.id_
+    }
+
+    fn get_tvers(&self) -> &Arc<TVersion> {
+        &self.vers_
+    }
+
+    fn get_version(&self) -> u32 {
+        self.vers_.get_version()
+    }
+
+    //The writes, appended through TTag::get_data
+    fn read(&self) -> &Any {
+        &self.writes_
+    }
+
+    fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.vers_.get_access_info()
+    }
+
+    fn set_access_info(&mut self, txn_info: Arc<TxnInfo>) {
+        self.vers_.set_access_info(txn_info);
+    }
+
+    fn get_name(&self) -> String {
+        (*self.name_).clone()
+    }
+
+    //All the nodes covering the keys are locked by tid, the
+    //ones split from them included. So are the nodes split now.
+    fn install(&self, id: Tid) {
+        let tid: u32 = id.into();
+        let mut nodes = self.nodes_.write().unwrap();
+        for &(ref key, ref val) in self.writes_.borrow().iter() {
+            let pos = node_of(&nodes, key);
+            let node = nodes[pos].clone();
+            {
+                let mut inner = node.inner_.write().unwrap();
+                match *val {
+                    Some(ref val) => inner.entries_.insert(key.clone(), val.clone()),
+                    None => inner.entries_.remove(key),
+                };
+            }
+            node.vers_.set_version(tid);
+
+            if node.len() > self.node_cap_ {
+                let new = split(&mut nodes, pos);
+                if !new.vers_.lock(id) {
+                    panic!("lock of a new node failed");
+                }
+                new.vers_.set_version(tid);
+                self.locked_.borrow_mut().push(new);
+            }
+        }
+        self.vers_.set_version(tid);
+    }
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    fn write(&mut self, _ptr: *mut u8) {
+        panic!("IndexWriteRef is written through its writes");
+    }
+
+    #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+    fn write(&mut self, _val: Box<Any>) {
+        panic!("IndexWriteRef is written through its writes");
+    }
+
+    //Locks the node covering every key. A node split before
+    //being locked may not cover the key anymore, look again.
+    fn lock(&self, tid: Tid) -> bool {
+        for &(ref key, _) in self.writes_.borrow().iter() {
+            loop {
+                let node = {
+                    let nodes = self.nodes_.read().unwrap();
+                    nodes[node_of(&nodes, key)].clone()
+                };
+                let held = self
+                    .locked_
+                    .borrow()
+                    .iter()
+                    .any(|locked| Arc::ptr_eq(locked, &node));
+                if !held {
+                    if !node.vers_.lock(tid) {
+                        self.unlock();
+                        return false;
+                    }
+                    self.locked_.borrow_mut().push(node.clone());
+                }
+                if node.covers(key) {
+                    break;
+                }
+            }
+        }
+        true
+    }
+
+    fn unlock(&self) {
+        for node in self.locked_.borrow_mut().drain(..) {
+            node.vers_.unlock();
+        }
+    }
+
+    //The keys written are checked with the nodes lock
+    fn check(&self, _vers: u32, _tid: u32) -> bool {
+        true
+    }
+
+    /* For 2 Phase Locking, see OrderedIndex::insert_lock */
+    fn write_through(&self, _val: Box<Any>, _tid: Tid) {
+        panic!("IndexWriteRef is not used by 2PL");
+    }
+
+    fn read_lock(&self, _tid: u32) -> bool {
+        panic!("IndexWriteRef is not used by 2PL");
+    }
+
+    fn read_unlock(&self, _tid: u32) {
+        panic!("IndexWriteRef is not used by 2PL");
+    }
+
+    fn write_lock(&self, _tid: u32) -> bool {
+        panic!("IndexWriteRef is not used by 2PL");
+    }
+
+    fn write_unlock(&self, _tid: u32) {
+        panic!("IndexWriteRef is not used by 2PL");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parnvm::{
+        nvm_txn_occ::TransactionParBaseOCC,
+        piece::{Pid, PieceOCC},
+    };
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+    use txn::TidFac;
+
+    fn index(keys: &[u32]) -> OrderedIndex<u32, u32> {
+        let index = OrderedIndex::with_node_capacity(4, "ordered");
+        for &key in keys {
+            index.insert_raw(key, key * 10);
+        }
+        index
+    }
+
+    #[test]
+    fn test_split_keeps_order() {
+        let keys: Vec<u32> = (0..64).map(|i| (i * 37) % 64).collect();
+        let index = index(&keys);
+
+        assert_eq!(index.len(), 64);
+        assert_eq!(index.node_num() > 1, true);
+        let all: Vec<u32> = index
+            .range_raw(Bound::Unbounded, Bound::Unbounded)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(all, (0..64).collect::<Vec<u32>>());
+        assert_eq!(
+            index.range_raw(Bound::Excluded(&10), Bound::Included(&13)),
+            vec![(11, 110), (12, 120), (13, 130)]
+        );
+        assert_eq!(index.get_raw(&63), Some(630));
+    }
+
+    #[test]
+    fn test_occ_insert_delete() {
+        let index = index(&[1, 2, 3]);
+
+        let tx = &mut TransactionOCC::new(Tid::new(1));
+        index.insert(tx, 4, 40);
+        assert_eq!(index.delete(tx, &2), true);
+        assert_eq!(index.delete(tx, &9), false);
+        assert_eq!(
+            index.range(tx, Bound::Included(&2), Bound::Unbounded),
+            vec![(3, 30), (4, 40)]
+        );
+        assert_eq!(index.get_raw(&4), None);
+        assert_eq!(tx.try_commit(), true);
+
+        assert_eq!(
+            index.range_raw(Bound::Unbounded, Bound::Unbounded),
+            vec![(1, 10), (3, 30), (4, 40)]
+        );
+    }
+
+    #[test]
+    fn test_occ_write_order() {
+        let index = index(&[]);
+
+        let tx = &mut TransactionOCC::new(Tid::new(1));
+        index.insert(tx, 1, 10);
+        index.delete(tx, &1);
+        index.insert(tx, 2, 20);
+        index.insert(tx, 2, 21);
+        assert_eq!(tx.try_commit(), true);
+
+        assert_eq!(index.get_raw(&1), None);
+        assert_eq!(index.get_raw(&2), Some(21));
+    }
+
+    #[test]
+    fn test_occ_phantom_fails_scan() {
+        let index = index(&(0..32).filter(|k| k % 2 == 0).collect::<Vec<u32>>());
+
+        let scan = &mut TransactionOCC::new(Tid::new(1));
+        let read = index.range(scan, Bound::Included(&10), Bound::Excluded(&20));
+        assert_eq!(read.len(), 5);
+        index.insert(scan, 100, 1000);
+
+        /* Out of the range, the scan still holds */
+        let other = &mut TransactionOCC::new(Tid::new(2));
+        index.insert(other, 31, 310);
+        assert_eq!(other.try_commit(), true);
+
+        let phantom = &mut TransactionOCC::new(Tid::new(3));
+        index.insert(phantom, 15, 150);
+        assert_eq!(phantom.try_commit(), true);
+
+        assert_eq!(scan.try_commit(), false);
+        assert_eq!(index.get_raw(&100), None);
+    }
+
+    #[test]
+    fn test_pc_phantom_reruns_scan_piece() {
+        let index = Arc::new(index(&(0..32).filter(|k| k % 2 == 0).collect::<Vec<u32>>()));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let read_len = Arc::new(AtomicUsize::new(0));
+
+        let (idx, r, len) = (index.clone(), runs.clone(), read_len.clone());
+        let scan = PieceOCC::new(
+            Pid::new(1),
+            String::from("test"),
+            Arc::new(Box::new(move |tx: &mut TransactionParOCC| {
+                let read = idx.range_pc(tx, Bound::Included(&10), Bound::Excluded(&20));
+                len.store(read.len(), Ordering::SeqCst);
+                idx.insert_pc(tx, 100, 1000);
+
+                /* A phantom commits before the first run does */
+                if r.fetch_add(1, Ordering::SeqCst) == 0 {
+                    let phantom = &mut TransactionOCC::new(Tid::new(2));
+                    idx.insert(phantom, 15, 150);
+                    assert_eq!(phantom.try_commit(), true);
+                }
+            })),
+            "scan",
+            1,
+        );
+
+        let base = TransactionParBaseOCC::new(vec![scan], String::from("test"));
+        let mut tx = TransactionParOCC::new_from_base(&base, Tid::new(1), Box::new(()));
+        tx.execute_txn();
+
+        assert_eq!(tx.txn_info().has_commit(), true);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(read_len.load(Ordering::SeqCst), 6);
+        assert_eq!(index.get_raw(&100), Some(1000));
+    }
+
+    #[test]
+    fn test_pc_raw_phantom_fails_scan_piece() {
+        let index = index(&(0..32).filter(|k| k % 2 == 0).collect::<Vec<u32>>());
+
+        let scan = &mut TransactionParOCCRaw::new(Tid::new(1));
+        let read = index.range_pc_raw(scan, Bound::Included(&10), Bound::Excluded(&20));
+        assert_eq!(read.len(), 5);
+        assert_eq!(index.delete_pc_raw(scan, &12), true);
+
+        let phantom = &mut TransactionOCC::new(Tid::new(2));
+        index.insert(phantom, 15, 150);
+        assert_eq!(phantom.try_commit(), true);
+
+        /* The piece fails and leaves no write behind, its rerun sees the phantom */
+        assert_eq!(scan.try_commit_piece(), false);
+        assert_eq!(index.get_raw(&12), Some(120));
+
+        let read = index.range_pc_raw(scan, Bound::Included(&10), Bound::Excluded(&20));
+        assert_eq!(read.len(), 6);
+        assert_eq!(index.delete_pc_raw(scan, &12), true);
+        assert_eq!(scan.try_commit_piece(), true);
+        scan.commit();
+
+        assert_eq!(
+            index.range_raw(Bound::Included(&10), Bound::Excluded(&20)),
+            vec![(10, 100), (14, 140), (15, 150), (16, 160), (18, 180)]
+        );
+    }
+
+    #[test]
+    fn test_occ_split_fails_reader() {
+        let index = index(&[1, 2, 3]);
+
+        let reader = &mut TransactionOCC::new(Tid::new(1));
+        assert_eq!(index.get(reader, &9), None);
+        index.insert(reader, 0, 0);
+
+        /* Splits the only node, 9 now falls in the new one */
+        let writer = &mut TransactionOCC::new(Tid::new(2));
+        index.insert(writer, 4, 40);
+        index.insert(writer, 5, 50);
+        assert_eq!(writer.try_commit(), true);
+        assert_eq!(index.node_num(), 2);
+
+        let insert = &mut TransactionOCC::new(Tid::new(3));
+        index.insert(insert, 9, 90);
+        assert_eq!(insert.try_commit(), true);
+
+        assert_eq!(reader.try_commit(), false);
+    }
+
+    #[test]
+    fn test_2pl_scan_blocks_insert() {
+        let index = index(&[1, 2, 3, 10]);
+
+        let scan = &mut Transaction2PL::new(Tid::new(1));
+        assert_eq!(
+            index.range_lock(scan, Bound::Included(&1), Bound::Included(&5)),
+            Ok(vec![(1, 10), (2, 20), (3, 30)])
+        );
+
+        /* Younger, dies instead of waiting */
+        let insert = &mut Transaction2PL::new(Tid::new(2));
+        assert_eq!(index.insert_lock(insert, 4, 40), Err(()));
+        insert.abort();
+
+        assert_eq!(index.delete_lock(scan, &2), Ok(true));
+        assert_eq!(index.insert_lock(scan, 4, 40), Ok(()));
+        scan.commit();
+
+        let insert = &mut Transaction2PL::new(Tid::new(3));
+        assert_eq!(index.insert_lock(insert, 5, 50), Ok(()));
+        insert.commit();
+        assert_eq!(
+            index.range_raw(Bound::Unbounded, Bound::Unbounded),
+            vec![(1, 10), (3, 30), (4, 40), (5, 50), (10, 100)]
+        );
+    }
+
+    #[test]
+    fn test_occ_concurrent_inserts() {
+        let index = Arc::new(index(&[]));
+
+        let handles: Vec<_> = (1..5)
+            .map(|thd| {
+                let index = index.clone();
+                thread::spawn(move || {
+                    TidFac::set_thd_mask(thd);
+                    OidFac::set_obj_mask(thd as u64);
+                    for i in 0..50 {
+                        let tx = &mut TransactionOCC::new(TidFac::get_thd_next());
+                        loop {
+                            /* Keys of the threads interleave, the scan is shared */
+                            let count = index
+                                .range(tx, Bound::Unbounded, Bound::Unbounded)
+                                .iter()
+                                .filter(|&&(key, _)| key % 4 == thd - 1)
+                                .count() as u32;
+                            assert_eq!(count, i);
+                            index.insert(tx, i * 4 + thd - 1, i);
+                            if tx.try_commit() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(index.len(), 200);
+        for (key, val) in index.range_raw(Bound::Unbounded, Bound::Unbounded) {
+            assert_eq!(key / 4, val);
+        }
+    }
+}
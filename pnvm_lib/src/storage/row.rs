@@ -0,0 +1,462 @@
+//************************************************
+//Row of a table
+//
+//A row owns its entry (in DRAM or pmem) and the
+//TVersion used by every concurrency control.
+//
+//fields_offset_ caches Entry::field_offset() so that
+//single fields can be logged and persisted.
+//
+//A deleted row is a tombstone: it can no longer be
+//locked nor validated, so that its slot can be reused
+//while late readers still hold the Arc.
+//
+//With "mvcc", the installs keep the versions of the row
+//for the snapshot reads (get_data_at), see mvcc. A pushed
+//row is stamped by its inserter (stamp_insert).
+//
+//The payloads an install replaces are retired by the
+//txn installing, see varlen.
+//************************************************
+
+use alloc::alloc::Layout;
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use pnvm_sys;
+
+use std::{
+    fmt::{self, Debug},
+    hash::Hash,
+    mem, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    sync::Arc,
+};
+
+#[cfg(feature = "mvcc")]
+use mvcc::VersionChain;
+use tcore::{ObjectId, OidFac, TVersion};
+use txn::{Tid, TxnInfo};
+
+use super::{varlen, Key, VarLen};
+
+pub struct Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    //data_: UnsafeCell<Entry>,
+    data_: AtomicPtr<Entry>,
+    //The Box of data_ if owned, null when data_ is in pmem
+    heap_: *mut Entry,
+    tomb_: AtomicBool,
+    pub vers_: Arc<TVersion>,
+    id_: ObjectId,
+    index_: Index,
+
+    fields_offset_: [isize; 32],
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pmem_addr_: AtomicPtr<Entry>,
+
+    #[cfg(feature = "mvcc")]
+    versions_: VersionChain<Entry>,
+}
+
+impl<Entry, Index> Debug for Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        //   unsafe {write!(f, "[OID: {:?}][VERS: {:?}]\n\t[{:?}]",
+        //                  self.id_, self.vers_,self.data_.get().as_ref().unwrap())}
+        write!(
+            f,
+            "[OID: {:?}][VERS: {:?}]\n\t[{:?}]",
+            self.id_,
+            self.vers_,
+            self.data_.load(Ordering::SeqCst)
+        )
+    }
+}
+
+impl<Entry, Index> Drop for Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    fn drop(&mut self) {
+        if self.data_.load(Ordering::SeqCst).is_null() {
+            panic!("freeing null pointers")
+        }
+
+        /* Owned even once data_ moved, e.g. by copy_to_ptr */
+        if !self.heap_.is_null() {
+            // if TypeId::of::<Entry>() == TypeId::of::<Customer>() {
+            //     println!("{:?}", self.get_data());
+            // }
+            unsafe { drop(Box::from_raw(self.heap_)) }
+        }
+        /* Entries in pmem belong to their slot, which may be reused */
+
+        //println!("{:?}", self);
+        //mem::forget(self.vers_);
+    }
+}
+//impl<Entry, Index> Clone for Row<Entry, Index>
+//where Entry: 'static + Key<Index> + Clone,
+//      Index: Eq+Hash  + Clone
+//{
+//    fn clone(&self) -> Self {
+//        Row {
+//            data_ : unsafe {UnsafeCell::new(self.data_.get().as_ref().unwrap().clone())},
+//            vers_ : self.vers_.clone(),
+//            id_: self.id_,
+//            index_ : self.index_.clone()
+//        }
+//    }
+//}
+
+unsafe impl<Entry: Clone, Index> Sync for Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+}
+unsafe impl<Entry: Clone, Index> Send for Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+}
+
+impl<Entry, Index> Row<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    pub fn new(entry: Entry) -> Row<Entry, Index> {
+        let key = entry.primary_key();
+        let offsets = entry.field_offset();
+        let heap = Box::into_raw(Box::new(entry));
+        Row {
+            //data_: UnsafeCell::new(entry),
+            data_: AtomicPtr::new(heap),
+            heap_: heap,
+            tomb_: AtomicBool::new(false),
+            vers_: Arc::new(TVersion::default()), /* FIXME: this can carry txn info */
+            id_: OidFac::get_obj_next(),
+            index_: key,
+
+            fields_offset_: offsets,
+
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_addr_: AtomicPtr::default(),
+
+            #[cfg(feature = "mvcc")]
+            versions_: VersionChain::default(),
+        }
+    }
+
+    pub fn new_from_ptr(entry_ptr: *mut Entry) -> Row<Entry, Index> {
+        let data = AtomicPtr::new(entry_ptr);
+        unsafe {
+            let key = data
+                .load(Ordering::SeqCst)
+                .as_ref()
+                .expect("data ptr should be non null")
+                .primary_key();
+
+            let offsets = data
+                .load(Ordering::SeqCst)
+                .as_ref()
+                .expect("data ptr should be nonnull")
+                .field_offset();
+            Row {
+                data_: data,
+                heap_: ptr::null_mut(),
+                tomb_: AtomicBool::new(false),
+                vers_: Arc::new(TVersion::default()), /* FIXME: this can carry txn info */
+                id_: OidFac::get_obj_next(),
+                index_: key,
+                fields_offset_: offsets,
+                #[cfg(any(feature = "pmem", feature = "disk"))]
+                pmem_addr_: AtomicPtr::default(),
+                #[cfg(feature = "mvcc")]
+                versions_: VersionChain::default(),
+            }
+        }
+    }
+
+    pub fn new_from_pmem(
+        entry: Entry,
+        txn_info: Arc<TxnInfo>,
+        entry_ptr: *mut Entry,
+    ) -> Row<Entry, Index> {
+        let key = entry.primary_key();
+        let offsets = entry.field_offset();
+        unsafe { entry_ptr.write(entry) };
+
+        #[cfg(all(feature = "pmem", feature = "wdrain"))]
+        pnvm_sys::flush(entry_ptr as *mut u8, mem::size_of::<Entry>());
+
+        //FIXME: DO FLUSH HERE
+
+        // let bentry = Box::new(entry.clone());
+        // let ptr = Box::into_raw(bentry);
+        // unsafe { ptr.write(entry)};
+
+        //pnvm_sys::memcpy_nodrain(
+        //    entry_ptr as *mut u8,
+        //    &mut entry as *mut Entry as *mut u8,
+        //    mem::size_of::<Entry>());
+
+        let data = AtomicPtr::new(entry_ptr);
+
+        Row {
+            data_: data,
+            heap_: ptr::null_mut(),
+            tomb_: AtomicBool::new(false),
+            vers_: Arc::new(TVersion::new_with_info(txn_info)),
+            id_: OidFac::get_obj_next(),
+            index_: key,
+
+            fields_offset_: offsets,
+
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_addr_: AtomicPtr::default(),
+
+            #[cfg(feature = "mvcc")]
+            versions_: VersionChain::default(),
+        }
+    }
+
+    pub fn new_from_txn(entry: Entry, txn_info: Arc<TxnInfo>) -> Row<Entry, Index> {
+        let key = entry.primary_key();
+        let offsets = entry.field_offset();
+        let heap = Box::into_raw(Box::new(entry));
+        Row {
+            //data_ : UnsafeCell::new(entry),
+            data_: AtomicPtr::new(heap),
+            heap_: heap,
+            tomb_: AtomicBool::new(false),
+            vers_: Arc::new(TVersion::new_with_info(txn_info)),
+            id_: OidFac::get_obj_next(),
+            index_: key,
+
+            fields_offset_: offsets,
+
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_addr_: AtomicPtr::default(),
+
+            #[cfg(feature = "mvcc")]
+            versions_: VersionChain::default(),
+        }
+    }
+
+    ///Copy data to the pointer and make its data referenced the
+    ///newly copied ptr
+    pub fn copy_to_ptr(&self, p: *mut Entry) {
+        unsafe {
+            self.data_.load(Ordering::SeqCst).copy_to(p, 1);
+            self.data_.store(p, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pub fn set_pmem_addr(&self, addr: *mut Entry) {
+        self.pmem_addr_.store(addr, Ordering::SeqCst);
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pub fn get_pmem_addr(&self) -> *mut Entry {
+        #[cfg(feature = "dir")]
+        {
+            self.data_.load(Ordering::SeqCst)
+        }
+
+        #[cfg(not(feature = "dir"))]
+        {
+            self.pmem_addr_.load(Ordering::SeqCst)
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_data(&self) -> &Entry {
+        //unsafe { self.data_.get().as_ref().unwrap() }
+        unsafe {
+            self.data_
+                .load(Ordering::SeqCst)
+                .as_ref()
+                .expect("get_data(): data ptr should be nonnull")
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_ptr(&self) -> *mut u8 {
+        //unsafe {self.data_.get() as *mut u8}
+        self.data_.load(Ordering::SeqCst) as *mut u8
+    }
+
+    pub fn get_field_ptr(&self, field_idx: usize) -> *mut u8 {
+        let offset = self.fields_offset_[field_idx];
+        assert_eq!(offset >= 0, true);
+        self.get_ptr().wrapping_add(offset as usize) as *mut u8
+    }
+
+    pub fn get_field_size(&self, field_idx: usize) -> usize {
+        let x = self.fields_offset_[field_idx as usize];
+        let y = self.fields_offset_[field_idx + 1 as usize];
+        let diff = y - x;
+        assert_eq!(diff > 0, true);
+        assert_eq!(x >= 0, true);
+        assert_eq!(y >= 0, true);
+        diff as usize
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pub fn get_pmem_field_addr(&self, field_idx: usize) -> *mut u8 {
+        let offset = self.fields_offset_[field_idx as usize];
+        assert_eq!(offset >= 0, true);
+        (self.get_pmem_addr() as *mut u8).wrapping_add(offset as usize) as *mut u8
+    }
+
+    //Tombstones are set under the row lock, so checking once
+    //locked is enough
+    #[inline(always)]
+    pub fn lock(&self, tid: Tid) -> bool {
+        if !self.vers_.lock(tid) {
+            return false;
+        }
+
+        if self.is_tombstone() {
+            self.vers_.unlock();
+            return false;
+        }
+        true
+    }
+
+    #[inline(always)]
+    pub fn check(&self, cur_ver: u32, tid: u32) -> bool {
+        !self.is_tombstone() && self.vers_.check_version(cur_ver, tid)
+    }
+
+    //Marks the row deleted, with the row locked
+    pub fn tombstone(&self) {
+        self.tomb_.store(true, Ordering::SeqCst);
+    }
+
+    #[inline(always)]
+    pub fn is_tombstone(&self) -> bool {
+        self.tomb_.load(Ordering::SeqCst)
+    }
+
+    //FIXME: how to not Clone
+    #[inline]
+    pub fn install_val(&self, val: &Entry, tid: Tid) {
+        let old_vars = self.get_var_fields();
+        let write = || unsafe {
+            //debug!("\n[TRANSACTION:{:?}]--[INSTALL]\n\t\t[OLD]--{:?}\n\t\t[NEW]--{:?}",
+            //      tid, self.data_.get().as_ref().unwrap(), val);
+
+            //ptr::write(self.data_.get(), val.clone());
+            let data = self.data_.load(Ordering::SeqCst);
+            *data = val.clone();
+        };
+
+        #[cfg(feature = "mvcc")]
+        self.versions_
+            .install(|| self.get_data().clone(), val.clone(), write);
+
+        #[cfg(not(feature = "mvcc"))]
+        write();
+
+        varlen::replaced(&old_vars, &val.var_fields());
+        self.vers_.set_version(tid.into());
+    }
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    #[inline]
+    pub fn install_ptr(&self, ptr: *mut Entry, tid: Tid) {
+        let old_vars = self.get_var_fields();
+        let swap = || {
+            let old = self.data_.swap(ptr, Ordering::SeqCst);
+            //ptr::drop_in_place(old);
+        };
+
+        #[cfg(feature = "mvcc")]
+        self.versions_
+            .install(|| self.get_data().clone(), unsafe { (*ptr).clone() }, swap);
+
+        #[cfg(not(feature = "mvcc"))]
+        swap();
+
+        varlen::replaced(&old_vars, &self.get_var_fields());
+        self.vers_.set_version(tid.into());
+    }
+
+    //The version of the snapshot at ts, which sees the row
+    //(visible_at)
+    #[cfg(feature = "mvcc")]
+    pub fn get_data_at(&self, ts: usize) -> Arc<Entry> {
+        self.versions_
+            .read_at(ts, || self.get_data().clone())
+            .expect("Row::get_data_at: inserted after the snapshot")
+    }
+
+    //A new row, when its inserter pushes it: the snapshots
+    //before the insert do not see it
+    #[cfg(feature = "mvcc")]
+    pub fn stamp_insert(&self) {
+        self.versions_.insert(self.get_data().clone());
+    }
+
+    #[cfg(feature = "mvcc")]
+    pub fn visible_at(&self, ts: usize) -> bool {
+        self.versions_.exists_at(ts)
+    }
+
+    //Install value to a specific field
+    // pub fn install_fields(&self, vals: &[(usize, Box<Any>)], val_cnt: usize) {
+    //    for idx in 0..val_cnt {
+
+    //    }
+    // }
+
+    #[inline(always)]
+    pub fn unlock(&self) {
+        self.vers_.unlock();
+    }
+
+    #[inline(always)]
+    pub fn get_version(&self) -> u32 {
+        self.vers_.get_version()
+    }
+
+    #[inline(always)]
+    pub fn get_id(&self) -> &ObjectId {
+        &self.id_
+    }
+
+    // pub fn get_addr(&self) -> Unique<T> {
+    //     let tvalue = self.tvalue_.read().unwrap();
+    //     tvalue.get_addr()
+    // }
+
+    pub fn get_layout(&self) -> Layout {
+        Layout::new::<Entry>()
+    }
+
+    //Out of line payloads, not part of the layout
+    pub fn get_var_fields(&self) -> Vec<(Option<usize>, VarLen)> {
+        self.get_data().var_fields()
+    }
+
+    pub fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.vers_.get_access_info()
+    }
+
+    pub fn set_access_info(&self, info: Arc<TxnInfo>) {
+        self.vers_.set_access_info(info)
+    }
+}
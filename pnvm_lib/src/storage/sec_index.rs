@@ -0,0 +1,451 @@
+//************************************************
+//Secondary index
+//
+//Maps a secondary key to the primary keys (or any
+//other values) of the matching rows.
+//
+//Each bucket is guarded by a spin lock, held by the
+//SecIndexGuard returned from lock_bucket() until it is
+//dropped. Hold the guard across a row push so that the
+//row and its index entry show up together.
+//
+//Index entries are maintained by the transaction that
+//pushes or deletes the row: the TRef of the push is
+//in the write set and updates the index at install,
+//under the lock of the table bucket. Aborted pushes
+//are never installed, so they leave nothing behind.
+//
+//With the pmem feature, each bucket also appends its
+//inserts and removals to a log under PMEM_FILE_DIR,
+//as the rows of a table bucket. A record carries the
+//tid of the installing transaction (0 for the initial
+//load), and its valid flag is only set once the rest
+//of it is drained. The flag itself is not drained: the
+//commit of the transaction, or the next append, does.
+//So the valid records are a prefix of the log.
+//
+//SecIndex::recover rebuilds the buckets from the valid
+//records of the durable transactions, in log order.
+//************************************************
+
+#[cfg(feature = "pmem")]
+use flush;
+#[cfg(feature = "pmem")]
+use pnvm_sys;
+#[cfg(feature = "pmem")]
+use tcore::BenchmarkCounter;
+#[cfg(feature = "crash")]
+use pnvm_sys::emul::PmemImage;
+use txn::Tid;
+
+use std::{
+    cell::UnsafeCell,
+    collections::{HashMap, VecDeque},
+    fmt::{self, Debug},
+    hash::Hash,
+    ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(feature = "pmem")]
+use std::mem;
+#[cfg(feature = "crash")]
+use std::{collections::HashSet, ptr};
+
+#[cfg(feature = "pmem")]
+const PMEM_DIR_ROOT: Option<&str> = option_env!("PMEM_FILE_DIR");
+
+//Records of a pmem log chunk
+#[cfg(feature = "pmem")]
+const PMEM_LOG_CHUNK: usize = 1024;
+
+pub struct SecIndex<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    get_bucket_: Box<Fn(&K) -> usize>,
+    buckets_:    Vec<SecIndexBucket<K, V>>,
+}
+
+/* V is not necessary the Primary key */
+impl<K, V> SecIndex<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    pub fn new(f: Box<Fn(&K) -> usize>) -> SecIndex<K, V> {
+        SecIndex {
+            buckets_:    Vec::new(),
+            get_bucket_: f,
+        }
+    }
+
+    pub fn new_with_buckets(bucket_num: usize, f: Box<Fn(&K) -> usize>) -> SecIndex<K, V> {
+        let mut buckets = Vec::with_capacity(bucket_num);
+
+        for _ in 0..bucket_num {
+            buckets.push(SecIndexBucket::new());
+        }
+
+        SecIndex {
+            buckets_:    buckets,
+            get_bucket_: f,
+        }
+    }
+
+    //Locks the bucket of key until the guard is dropped
+    pub fn lock_bucket(&self, key: &K) -> SecIndexGuard<K, V> {
+        let bucket = &self.buckets_[(self.get_bucket_)(key)];
+        bucket.lock();
+        SecIndexGuard { bucket_: bucket }
+    }
+
+    pub fn insert_index(&self, key: K, val: V, tid: Tid) {
+        self.lock_bucket(&key).insert(key, val, tid);
+    }
+
+    //Removes the first value of key matching f
+    pub fn remove_index<F>(&self, key: &K, f: F, tid: Tid) -> Option<V>
+    where
+        F: Fn(&V) -> bool,
+    {
+        self.lock_bucket(key).remove(key, f, tid)
+    }
+
+    //Rebuild every bucket from its log in the image of a
+    //crash, keeping the records of the txns in durable and
+    //those of the initial load
+    #[cfg(feature = "crash")]
+    pub fn recover(&self, image: &PmemImage, durable: &HashSet<u32>)
+    where
+        V: PartialEq,
+    {
+        for bucket in self.buckets_.iter() {
+            bucket.lock();
+            let index = bucket.replay(image, durable);
+            unsafe { *bucket.index_.get() = index };
+            bucket.unlock();
+        }
+    }
+}
+
+impl<K, V> Debug for SecIndex<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#?}", self.buckets_)
+    }
+}
+
+//A locked bucket of a SecIndex, reads go through Deref
+pub struct SecIndexGuard<'a, K, V>
+where
+    K: 'a + Hash + Eq + Copy + Debug,
+    V: 'a + Copy + Debug,
+{
+    bucket_: &'a SecIndexBucket<K, V>,
+}
+
+impl<'a, K, V> SecIndexGuard<'a, K, V>
+where
+    K: 'a + Hash + Eq + Copy + Debug,
+    V: 'a + Copy + Debug,
+{
+    #[cfg_attr(not(feature = "pmem"), allow(unused_variables))]
+    pub fn insert(&mut self, key: K, val: V, tid: Tid) {
+        #[cfg(feature = "pmem")]
+        self.bucket_.persist(PIndexOp::Insert, key, val, tid);
+
+        self.index_mut()
+            .entry(key)
+            .or_insert_with(|| VecDeque::new())
+            .push_back(val);
+    }
+
+    #[cfg_attr(not(feature = "pmem"), allow(unused_variables))]
+    pub fn remove<F>(&mut self, key: &K, f: F, tid: Tid) -> Option<V>
+    where
+        F: Fn(&V) -> bool,
+    {
+        let removed = self
+            .index_mut()
+            .get_mut(key)
+            .and_then(|vals| vals.iter().position(|v| f(v)).and_then(|i| vals.remove(i)));
+
+        #[cfg(feature = "pmem")]
+        {
+            if let Some(val) = removed {
+                self.bucket_.persist(PIndexOp::Remove, *key, val, tid);
+            }
+        }
+
+        removed
+    }
+
+    fn index_mut(&mut self) -> &mut HashMap<K, VecDeque<V>> {
+        unsafe { self.bucket_.index_.get().as_mut().unwrap() }
+    }
+}
+
+impl<'a, K, V> Deref for SecIndexGuard<'a, K, V>
+where
+    K: 'a + Hash + Eq + Copy + Debug,
+    V: 'a + Copy + Debug,
+{
+    type Target = HashMap<K, VecDeque<V>>;
+
+    fn deref(&self) -> &HashMap<K, VecDeque<V>> {
+        unsafe { self.bucket_.index_.get().as_ref().unwrap() }
+    }
+}
+
+impl<'a, K, V> Drop for SecIndexGuard<'a, K, V>
+where
+    K: 'a + Hash + Eq + Copy + Debug,
+    V: 'a + Copy + Debug,
+{
+    fn drop(&mut self) {
+        self.bucket_.unlock();
+    }
+}
+
+#[cfg(feature = "pmem")]
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum PIndexOp {
+    Insert = 1,
+    Remove = 2,
+}
+
+//A record of the pmem log of a bucket, zeroed until written
+#[cfg(feature = "pmem")]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PIndexRecord<K: Copy, V: Copy> {
+    tid_:   u32,
+    op_:    u8, /* PIndexOp, 0 in an unwritten record */
+    key_:   K,
+    val_:   V,
+    valid_: u8, /* Set once the fields above are persisted */
+}
+
+//Chunks of the pmem log, only touched with the bucket locked
+#[cfg(feature = "pmem")]
+struct PIndexLog<K: Copy, V: Copy> {
+    chunks_: Vec<*mut PIndexRecord<K, V>>,
+    len_:    usize,
+}
+
+struct SecIndexBucket<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    index_: UnsafeCell<HashMap<K, VecDeque<V>>>,
+    lock_:  AtomicBool,
+    #[cfg(feature = "pmem")]
+    plog_:  UnsafeCell<PIndexLog<K, V>>,
+}
+
+impl<K, V> SecIndexBucket<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    pub fn new() -> SecIndexBucket<K, V> {
+        SecIndexBucket {
+            index_: UnsafeCell::new(HashMap::new()),
+            lock_:  AtomicBool::new(false),
+            #[cfg(feature = "pmem")]
+            plog_:  UnsafeCell::new(PIndexLog {
+                chunks_: Vec::new(),
+                len_:    0,
+            }),
+        }
+    }
+
+    fn lock(&self) {
+        while self.lock_.compare_and_swap(false, true, Ordering::SeqCst) {}
+    }
+
+    fn unlock(&self) {
+        self.lock_.store(false, Ordering::SeqCst);
+    }
+
+    //Appends to the log, the bucket must be locked
+    #[cfg(feature = "pmem")]
+    fn persist(&self, op: PIndexOp, key: K, val: V, tid: Tid) {
+        let plog = unsafe { self.plog_.get().as_mut().unwrap() };
+        let size = mem::size_of::<PIndexRecord<K, V>>();
+
+        /* Map a new chunk once the last one is full */
+        if plog.len_ == plog.chunks_.len() * PMEM_LOG_CHUNK {
+            let path = String::from(
+                PMEM_DIR_ROOT.expect("PMEM_FILE_DIR must be supplied at compile time"),
+            );
+            let chunk = pnvm_sys::mmap_file(path, PMEM_LOG_CHUNK * size);
+            BenchmarkCounter::mmap();
+
+            if chunk.is_null() {
+                panic!("SecIndexBucket::persist(): failed, len: {}", PMEM_LOG_CHUNK * size);
+            }
+            plog.chunks_.push(chunk as *mut PIndexRecord<K, V>);
+        }
+
+        let mut record = PIndexRecord {
+            tid_:   tid.into(),
+            op_:    op as u8,
+            key_:   key,
+            val_:   val,
+            valid_: 0,
+        };
+        let paddr = unsafe {
+            plog.chunks_[plog.len_ / PMEM_LOG_CHUNK].offset((plog.len_ % PMEM_LOG_CHUNK) as isize)
+        };
+        plog.len_ += 1;
+
+        BenchmarkCounter::flush(flush::lines_of(paddr as *const u8, size));
+        pnvm_sys::memcpy_nodrain(
+            paddr as *mut u8,
+            &mut record as *mut PIndexRecord<K, V> as *mut u8,
+            size,
+        );
+        pnvm_sys::drain();
+
+        /* A torn record is never valid */
+        unsafe {
+            let valid = &mut (*paddr).valid_ as *mut u8;
+            *valid = 1;
+            BenchmarkCounter::flush(1);
+            pnvm_sys::flush(valid, 1);
+        }
+    }
+
+    //Index of the valid records of the log in image
+    #[cfg(feature = "crash")]
+    fn replay(&self, image: &PmemImage, durable: &HashSet<u32>) -> HashMap<K, VecDeque<V>>
+    where
+        V: PartialEq,
+    {
+        let plog = unsafe { self.plog_.get().as_ref().unwrap() };
+        let size = mem::size_of::<PIndexRecord<K, V>>();
+        let mut index: HashMap<K, VecDeque<V>> = HashMap::new();
+
+        for chunk in plog.chunks_.iter() {
+            let bytes = image.read(*chunk as *const u8, PMEM_LOG_CHUNK * size);
+
+            for i in 0..PMEM_LOG_CHUNK {
+                let record = unsafe {
+                    ptr::read_unaligned(bytes[i * size..].as_ptr() as *const PIndexRecord<K, V>)
+                };
+                if record.valid_ == 0 {
+                    return index;
+                }
+                if record.tid_ != 0 && !durable.contains(&record.tid_) {
+                    continue;
+                }
+
+                if record.op_ == PIndexOp::Insert as u8 {
+                    index
+                        .entry(record.key_)
+                        .or_insert_with(|| VecDeque::new())
+                        .push_back(record.val_);
+                } else if let Some(vals) = index.get_mut(&record.key_) {
+                    if let Some(i) = vals.iter().position(|v| v == &record.val_) {
+                        vals.remove(i);
+                    }
+                }
+            }
+        }
+        index
+    }
+}
+
+impl<K, V> Debug for SecIndexBucket<K, V>
+where
+    K: Hash + Eq + Copy + Debug,
+    V: Copy + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe { write!(f, "{:?}", self.index_.get().as_ref().unwrap()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SecIndex<(i32, i32), i32> {
+        SecIndex::new_with_buckets(2, Box::new(|key: &(i32, i32)| key.0 as usize % 2))
+    }
+
+    #[test]
+    fn test_guard_unlocks() {
+        let idx = index();
+        idx.insert_index((0, 1), 10, Tid::default());
+        {
+            let mut guard = idx.lock_bucket(&(0, 2));
+            guard.insert((0, 2), 20, Tid::default());
+            assert_eq!(guard.get(&(0, 1)).unwrap()[0], 10);
+        }
+
+        /* Would spin if the guard leaked the lock */
+        idx.insert_index((0, 2), 21, Tid::default());
+        assert_eq!(idx.lock_bucket(&(0, 2)).get(&(0, 2)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_remove_index() {
+        let idx = index();
+        for v in 0..3 {
+            idx.insert_index((1, 1), v, Tid::default());
+        }
+
+        let tid = Tid::default();
+        assert_eq!(idx.remove_index(&(1, 1), |v| *v == 1, tid), Some(1));
+        assert_eq!(idx.remove_index(&(1, 1), |v| *v == 1, tid), None);
+        assert_eq!(idx.remove_index(&(1, 2), |_| true, tid), None);
+
+        let guard = idx.lock_bucket(&(1, 1));
+        assert_eq!(guard.get(&(1, 1)).unwrap().iter().collect::<Vec<_>>(), vec![&0, &2]);
+    }
+
+    //After a crash, the records of the txns without commit
+    //record are dropped, those of the initial load kept
+    #[cfg(feature = "crash")]
+    #[test]
+    fn test_recover_durable_records() {
+        use crash::{harness::RUN_LOCK, recovery};
+        use plog;
+        use pnvm_sys::emul;
+
+        let _guard = RUN_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        emul::checkpoint();
+        let idx = index();
+
+        /* The tids of the other test threads overlap small ones */
+        let durable = Tid::new(u32::max_value() - 1);
+        let pending = Tid::new(u32::max_value());
+
+        idx.insert_index((0, 1), 10, Tid::default());
+        idx.insert_index((0, 1), 11, durable);
+        idx.insert_index((1, 1), 20, durable);
+        idx.remove_index(&(0, 1), |v| *v == 10, pending);
+        idx.insert_index((1, 1), 21, pending);
+        pnvm_sys::drain();
+        plog::persist_txn(durable.into());
+
+        let image = emul::crash();
+        let durable_set = recovery::recover(&mut image.clone()).into_iter().collect();
+        idx.recover(&image, &durable_set);
+
+        let vals = |key: (i32, i32)| -> Vec<i32> {
+            idx.lock_bucket(&key).get(&key).unwrap().iter().cloned().collect()
+        };
+        assert_eq!(vals((0, 1)), vec![10, 11]);
+        assert_eq!(vals((1, 1)), vec![20]);
+    }
+}
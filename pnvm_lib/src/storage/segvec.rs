@@ -0,0 +1,216 @@
+//************************************************
+//Segmented append-only vector
+//
+//SegVec<T> is split into segments, segment k holding
+//base << k slots. Segments are allocated on demand and
+//never moved or freed before the vector, so that:
+//- an element keeps its address once pushed
+//- get() is safe while other threads push
+//- the vector grows without a bound on the segments
+//
+//push() reserves a slot with a fetch_add and publishes
+//the element once written, get() only returns published
+//elements. take() and replace() let the owner recycle a
+//slot, they must not race with a get() of the same slot.
+//
+//segment_of() is also used for the pmem extents of a
+//Bucket, which grow the same way.
+//************************************************
+
+use std::{
+    cell::UnsafeCell,
+    cmp, mem, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+//Enough segments to address any usize index
+pub const SEGMENTS: usize = mem::size_of::<usize>() * 8;
+
+//Maps idx to its (segment, offset in the segment)
+#[inline(always)]
+pub fn segment_of(base: usize, idx: usize) -> (usize, usize) {
+    let q = idx / base + 1;
+    let seg = SEGMENTS - 1 - q.leading_zeros() as usize;
+    (seg, idx - base * ((1 << seg) - 1))
+}
+
+#[inline(always)]
+pub fn segment_cap(base: usize, seg: usize) -> usize {
+    base << seg
+}
+
+struct Slot<T> {
+    ready_: AtomicBool,
+    val_:   UnsafeCell<Option<T>>,
+}
+
+pub struct SegVec<T> {
+    base_: usize,
+    segs_: Vec<AtomicPtr<Slot<T>>>,
+    len_:  AtomicUsize,
+}
+
+impl<T> SegVec<T> {
+    //The first segment holds base elements
+    pub fn with_base(base: usize) -> SegVec<T> {
+        let mut segs = Vec::with_capacity(SEGMENTS);
+        for _ in 0..SEGMENTS {
+            segs.push(AtomicPtr::new(ptr::null_mut()));
+        }
+
+        SegVec {
+            base_: cmp::max(base, 1),
+            segs_: segs,
+            len_:  AtomicUsize::new(0),
+        }
+    }
+
+    //Returns the index of val
+    pub fn push(&self, val: T) -> usize {
+        let idx = self.len_.fetch_add(1, Ordering::SeqCst);
+        let (seg, off) = segment_of(self.base_, idx);
+
+        unsafe {
+            let slot = &*self.segment(seg).offset(off as isize);
+            *slot.val_.get() = Some(val);
+            slot.ready_.store(true, Ordering::Release);
+        }
+        idx
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.slot(idx)
+            .and_then(|slot| unsafe { (*slot.val_.get()).as_ref() })
+    }
+
+    //Empties slot idx, get(idx) is None until it is replaced
+    pub unsafe fn take(&self, idx: usize) -> Option<T> {
+        (*self.slot(idx)?.val_.get()).take()
+    }
+
+    //Refills a published slot idx
+    pub unsafe fn replace(&self, idx: usize, val: T) -> Option<T> {
+        let slot = self.slot(idx).expect("SegVec::replace: slot not published");
+        (*slot.val_.get()).replace(val)
+    }
+
+    //Reserved slots, the last ones may not be published yet
+    pub fn len(&self) -> usize {
+        self.len_.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //The slot of idx, if published
+    fn slot(&self, idx: usize) -> Option<&Slot<T>> {
+        if idx >= self.len() {
+            return None;
+        }
+
+        let (seg, off) = segment_of(self.base_, idx);
+        let slots = self.segs_[seg].load(Ordering::Acquire);
+        if slots.is_null() {
+            return None;
+        }
+
+        let slot = unsafe { &*slots.offset(off as isize) };
+        if slot.ready_.load(Ordering::Acquire) {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+
+    //Allocates segment seg if no one has yet
+    fn segment(&self, seg: usize) -> *mut Slot<T> {
+        let slots = self.segs_[seg].load(Ordering::Acquire);
+        if !slots.is_null() {
+            return slots;
+        }
+
+        let cap = segment_cap(self.base_, seg);
+        let mut new_slots = Vec::with_capacity(cap);
+        for _ in 0..cap {
+            new_slots.push(Slot {
+                ready_: AtomicBool::new(false),
+                val_:   UnsafeCell::new(None),
+            });
+        }
+        let mut new_slots = new_slots.into_boxed_slice();
+        let new_ptr = new_slots.as_mut_ptr();
+
+        match self.segs_[seg].compare_exchange(
+            ptr::null_mut(),
+            new_ptr,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                mem::forget(new_slots);
+                new_ptr
+            }
+            /* Lost the race, new_slots is dropped */
+            Err(slots) => slots,
+        }
+    }
+}
+
+impl<T> Drop for SegVec<T> {
+    fn drop(&mut self) {
+        for (seg, slots) in self.segs_.iter().enumerate() {
+            let slots = slots.load(Ordering::SeqCst);
+            if !slots.is_null() {
+                let cap = segment_cap(self.base_, seg);
+                unsafe { drop(Vec::from_raw_parts(slots, cap, cap)) };
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for SegVec<T> {}
+unsafe impl<T: Send + Sync> Sync for SegVec<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_of() {
+        assert_eq!(segment_of(4, 0), (0, 0));
+        assert_eq!(segment_of(4, 3), (0, 3));
+        assert_eq!(segment_of(4, 4), (1, 0));
+        assert_eq!(segment_of(4, 11), (1, 7));
+        assert_eq!(segment_of(4, 12), (2, 0));
+        assert_eq!(segment_of(1, 0), (0, 0));
+        assert_eq!(segment_of(1, 2), (1, 1));
+    }
+
+    #[test]
+    fn test_stable_addresses() {
+        let v = SegVec::with_base(2);
+        v.push(0);
+        let first = v.get(0).unwrap() as *const i32;
+        for i in 1..100 {
+            v.push(i);
+        }
+
+        assert_eq!(v.get(0).unwrap() as *const i32, first);
+        assert_eq!(v.get(99), Some(&99));
+        assert_eq!(v.get(100), None);
+    }
+
+    #[test]
+    fn test_recycle_slot() {
+        let v = SegVec::with_base(2);
+        v.push(1);
+        v.push(2);
+
+        assert_eq!(unsafe { v.take(0) }, Some(1));
+        assert_eq!(v.get(0), None);
+        assert_eq!(unsafe { v.replace(0, 3) }, None);
+        assert_eq!(v.get(0), Some(&3));
+        assert_eq!(v.get(1), Some(&2));
+    }
+}
@@ -0,0 +1,1434 @@
+//************************************************
+//Table and its buckets
+//
+//A table is hash partitioned into buckets, see
+//Table::new_with_partition. A bucket holds its rows
+//and a primary key index, and is versioned as a whole
+//so that pushes and deletes can be validated.
+//
+//The rows are kept in a SegVec, so retrieve() is safe
+//while pushing. With pmem, the rows are copied to
+//extents that double in size, mapped on first use, in
+//slots given by the PmemLayout of the table.
+//
+//The key index is a HashMap behind a RwLock. retrieve()
+//takes it shared, one atomic on the lock word. Only
+//a push or a delete takes it exclusive, and they hold the
+//bucket lock already: writers never queue on each other
+//there, and a reader only waits out one insert or remove.
+//An index published by epoch would copy the map on every
+//push, and TPCC pushes rows into the same buckets in most
+//of its read-write txns.
+//
+//A delete tombstones its row, so that it fails any
+//later lock or check, and frees the row slot for the
+//next push. The Row is freed with its last Arc, i.e.
+//once no reader can observe it. Its pmem slot is only
+//reused once the deleting txn is persistent and the
+//Row is freed: a crash never finds the slot of a row
+//overwritten before its delete is durable. Without
+//"dir", a push is given its slot at install, and
+//"porder" tags it as an allocation of the inserter.
+//Its out of line payloads are retired with the deleting
+//txn, as the ones an install replaces (storage::varlen).
+//
+//With pmem, pushes and deletes leave a slot record to
+//the log of their txn (plog::persist_slots), so that a
+//slot deleted by a durable txn is known to be free after
+//a crash, see crash::recovery::slot_states.
+//
+//push* and delete* add a row insert/removal to a
+//transaction:
+//- push, delete                  TransactionOCC
+//- push_pc, delete_pc            TransactionParOCC
+//- push_pc_raw, delete_pc_raw    TransactionParOCCRaw
+//- push_lock, delete_lock        Transaction2PL
+//
+//T is the context handed to the TRef of the change,
+//see BucketPushRef.
+//
+//Reads through the bucket rather than a row, e.g. a
+//secondary index lookup, must Bucket::scan* first. The
+//bucket version is then validated (or the bucket read
+//locked with 2PL), so that a concurrent push or delete
+//is not missed. A snapshot (mvcc) does not scan: it skips
+//the rows pushed after it, see Row::visible_at.
+//************************************************
+
+use alloc::alloc::Layout;
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use pnvm_sys;
+#[cfg(feature = "porder")]
+use pnvm_sys::porder::{self, Role};
+
+use std::{
+    any::Any,
+    cmp,
+    collections::{hash_map::RandomState, HashMap},
+    fmt::{self, Debug},
+    hash::Hash,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+    sync::{Arc, RwLock},
+};
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use std::sync::{Mutex, Weak};
+
+use lock::lock_txn::{LockType, Transaction2PL};
+use occ::occ_txn::TransactionOCC;
+use parnvm::nvm_txn_occ::TransactionParOCC;
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use plog;
+use parnvm::nvm_txn_raw::TransactionParOCCRaw;
+use tcore::{BenchmarkCounter, ObjectId, OidFac, Operation, TRef, TVersion};
+use txn::{Tid, Transaction, TxnInfo};
+
+use super::segvec::SegVec;
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use super::segvec::{segment_cap, segment_of, SEGMENTS};
+#[cfg(any(feature = "pmem", feature = "disk"))]
+use super::SlotLayout;
+use super::{varlen, BucketDeleteRef, BucketPushRef, Key, PmemLayout, Row};
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+const PMEM_DIR_ROOT: Option<&str> = option_env!("PMEM_FILE_DIR");
+
+//Maps an entry to its bucket, taken modulo the number of buckets
+pub type Partition<Entry> = Box<Fn(&Entry) -> usize + Send + Sync>;
+
+pub struct Table<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone + Debug,
+{
+    buckets: Vec<Bucket<Entry, Index>>,
+    bucket_num: usize,
+    partition: Partition<Entry>,
+
+    //len :usize,
+    hash_builder: RandomState,
+    name: String,
+    //id_ : ObjectId,
+    //vers_ : TVersion,
+
+    //#[cfg(all(feature = "dir", feature = "pmem"))]
+    //{
+    //    pmem_cap_: AtomicUsize,
+    //    pmem_len_:AtomicUsize,
+    //    pmem_root_: NonNull<Entry>,
+    //}
+}
+
+impl<Entry, Index> Table<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone + Debug,
+{
+    pub fn new() -> Table<Entry, Index> {
+        Default::default()
+    }
+
+    //Entries are partitioned by Key::bucket_key()
+    pub fn new_with_buckets(num: usize, bkt_size: usize, name: &str) -> Table<Entry, Index> {
+        Table::new_with_layout(num, bkt_size, name, PmemLayout::default())
+    }
+
+    //Rows placed in pmem as given by layout, see PmemLayout
+    pub fn new_with_layout(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        layout: PmemLayout,
+    ) -> Table<Entry, Index> {
+        Table::build(
+            num,
+            bkt_size,
+            name,
+            Box::new(|entry: &Entry| entry.bucket_key()),
+            layout,
+        )
+    }
+
+    pub fn new_with_partition(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        partition: Partition<Entry>,
+    ) -> Table<Entry, Index> {
+        Table::build(num, bkt_size, name, partition, PmemLayout::default())
+    }
+
+    fn build(
+        num: usize,
+        bkt_size: usize,
+        name: &str,
+        partition: Partition<Entry>,
+        layout: PmemLayout,
+    ) -> Table<Entry, Index> {
+        let mut buckets = Vec::with_capacity(num);
+        for _ in 0..num {
+            buckets.push(Bucket::with_layout(bkt_size, String::from(name), layout));
+        }
+
+        Table {
+            buckets,
+            bucket_num: num,
+            partition,
+            hash_builder: Default::default(),
+            name: String::from(name),
+        }
+    }
+
+    #[inline(always)]
+    pub fn bucket_of(&self, entry: &Entry) -> usize {
+        (self.partition)(entry) % self.bucket_num
+    }
+
+    pub fn bucket_num(&self) -> usize {
+        self.bucket_num
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn push_pc_raw<T>(&self, tx: &mut TransactionParOCCRaw, entry: Entry, tables: &Arc<T>)
+    where
+        Arc<Row<Entry, Index>>: BucketPushRef<T>,
+    {
+        let bkt_idx = self.bucket_of(&entry);
+
+        //Make into row and then make into a RowRef
+        #[cfg(not(all(feature = "pmem", feature = "dir")))]
+        let row = Arc::new(Row::new_from_txn(entry, tx.txn_info().clone()));
+
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        let row = self.get_pmem_back_row(bkt_idx, entry, tx.txn_info());
+
+        let table_ref = row.into_push_table_ref(bkt_idx, tables.clone());
+
+        let tid = tx.id().clone();
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Push);
+        tag.set_write();
+        debug!(
+            "[PUSH TABLE]--[TID:{:?}]--[OID:{:?}]",
+            tid,
+            table_ref.get_id()
+        );
+    }
+
+    pub fn push_pc<T>(&self, tx: &mut TransactionParOCC, entry: Entry, tables: &Arc<T>)
+    where
+        Arc<Row<Entry, Index>>: BucketPushRef<T>,
+    {
+        let bkt_idx = self.bucket_of(&entry);
+
+        //Make into row and then make into a RowRef
+        #[cfg(not(all(feature = "pmem", feature = "dir")))]
+        let row = Arc::new(Row::new_from_txn(entry, tx.txn_info().clone()));
+
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        let row = self.get_pmem_back_row(bkt_idx, entry, tx.txn_info());
+
+        let table_ref = row.into_push_table_ref(bkt_idx, tables.clone());
+
+        let tid = tx.id().clone();
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Push);
+        tag.set_write();
+        debug!(
+            "[PUSH TABLE]--[TID:{:?}]--[OID:{:?}]",
+            tid,
+            table_ref.get_id()
+        );
+    }
+
+    // pub fn retrieve_lock(&self, tx: &mut Transaction2PL, index: &Index, bucket_idx: uisize)
+    //     -> Result<Option<Arc<Row<Entry, Index>>>, ()>
+    // {
+    //     let row = self.retrieve(index, bucket_idx);
+
+    //     let table_ref = row.into_retrieve_table_ref(bkt_idx, tables.clone());
+    //     let tid = tx.id().clone();
+
+    //     if tx.lock(&table_ref, LockType::Read) {
+    //
+    //     }
+    // }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_back_row(
+        &self,
+        bkt_idx: usize,
+        entry: Entry,
+        txn_info: &Arc<TxnInfo>,
+    ) -> Arc<Row<Entry, Index>> {
+        let bkt = self.get_bucket(bkt_idx);
+        let p = bkt.get_pmem_addr(bkt.next_pmem_offset());
+        Arc::new(Row::new_from_pmem(entry, txn_info.clone(), p))
+    }
+
+    //The bucket stays write locked until the txn ends
+    pub fn push_lock<T>(&self, tx: &mut Transaction2PL, entry: Entry, tables: &Arc<T>) -> bool
+    where
+        Arc<Row<Entry, Index>>: BucketPushRef<T>,
+    {
+        let bkt_idx = self.bucket_of(&entry);
+        let tid: u32 = tx.id().into();
+
+        //Make into row and then make into a RowRef
+
+        #[cfg(not(all(feature = "pmem", feature = "dir")))]
+        let row = Arc::new(Row::new_from_txn(entry, tx.txn_info().clone()));
+
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        let row = self.get_pmem_back_row(bkt_idx, entry, tx.txn_info());
+
+        let bucket = &self.buckets[bkt_idx];
+        let oid = *bucket.get_id();
+
+        /* Lock, scanners of the bucket hold it read locked */
+        if !tx.has_lock(&(oid, LockType::Write)) {
+            if bucket.vers_.write_lock(tid) {
+                tx.add_locks((oid, LockType::Write), bucket.vers_.clone());
+            } else {
+                return false;
+            }
+        }
+        let tref = row.into_push_table_ref(bkt_idx, tables.clone());
+
+        /* Added for persistent */
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        tx.add_ref(tref.box_clone());
+
+        /* Applied at commit, with the bucket still locked */
+        tx.add_install(tref);
+        true
+    }
+
+    pub fn push<T>(&self, tx: &mut TransactionOCC, entry: Entry, tables: &Arc<T>)
+    where
+        Arc<Row<Entry, Index>>: BucketPushRef<T>,
+    {
+        let bkt_idx = self.bucket_of(&entry);
+
+        //Make into row and then make into a RowRef
+        #[cfg(not(all(feature = "pmem", feature = "dir")))]
+        let row = Arc::new(Row::new_from_txn(entry, tx.txn_info().clone()));
+
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        let row = self.get_pmem_back_row(bkt_idx, entry, tx.txn_info());
+        //let row = {
+        //    let bkt = self.get_bucket(bkt_idx);
+        //    let p = bkt.get_pmem_addr(bkt.next_pmem_offset());
+        //    Arc::new(
+        //        Row::new_from_pmem(
+        //            entry,
+        //            tx.txn_info().clone(),
+        //            p
+        //        )
+        //    )
+        //};
+
+        let table_ref = row.into_push_table_ref(bkt_idx, tables.clone());
+
+        let tid = tx.id().clone();
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Push);
+        tag.set_write();
+        debug!(
+            "[PUSH TABLE]--[TID:{:?}]--[OID:{:?}]",
+            tid,
+            table_ref.get_id()
+        );
+    }
+
+    // fn get_next_pmem_ptr(&self) -> *mut Entry {
+    //     if self.pmem_len_.load(Ordering::SeqCst)
+    //         >= self.pmem_cap_.load(Ordering::SeqCst) {
+
+    //             println!("table : {:?} pmem size not enough has  {:?} but {:?}",
+    //                      self.name_, self.pmem_len_.load(Ordering::SeqCst),
+    //                      self.pmem_cap_.load(Ordering::SeqCst));
+    //             panic!();
+    //     }
+
+    //     let pmem_root_
+    //         .as_ptr()
+    //         .offset(self.pmem_len_.fetch_add(1, Ordering::SeqCst) as isize)
+    // }
+
+    //The bucket and the row stay write locked until the txn ends
+    pub fn delete_lock<T>(
+        &self,
+        tx: &mut Transaction2PL,
+        index: &Index,
+        tables: &Arc<T>,
+        bucket_idx: usize,
+    ) -> bool
+    where
+        Arc<Row<Entry, Index>>: BucketDeleteRef<T>,
+    {
+        let bucket_idx = bucket_idx % self.bucket_num;
+        let bucket = &self.buckets[bucket_idx];
+        let row = match bucket.retrieve(index) {
+            None => {
+                warn!("tx_delete: no element {:?}", index);
+                return false;
+            }
+            Some(row) => row,
+        };
+
+        let bk_oid = *bucket.get_id();
+        let r_oid = *row.get_id();
+        let tid: u32 = tx.id().into();
+
+        /* Lock */
+        if !tx.has_lock(&(bk_oid, LockType::Write)) {
+            if bucket.vers_.write_lock(tid) {
+                tx.add_locks((bk_oid, LockType::Write), bucket.vers_.clone());
+            } else {
+                return false;
+            }
+        }
+        if !tx.has_lock(&(r_oid, LockType::Write)) {
+            if row.vers_.write_lock(tid) {
+                tx.add_locks((r_oid, LockType::Write), row.vers_.clone());
+            } else {
+                return false;
+            }
+        }
+
+        /* Lock held */
+        let tref = row.into_delete_table_ref(bucket_idx, tables.clone(), tx.txn_info().clone());
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        tx.add_ref(tref.box_clone());
+
+        tx.add_install(tref);
+        true
+    }
+
+    pub fn delete_pc_raw<T>(
+        &self,
+        tx: &mut TransactionParOCCRaw,
+        index: &Index,
+        tables: &Arc<T>,
+        bucket_idx: usize,
+    ) -> bool
+    where
+        Arc<Row<Entry, Index>>: BucketDeleteRef<T>,
+    {
+        let bucket_idx = bucket_idx % self.bucket_num;
+        let row = match self.buckets[bucket_idx].retrieve(index) {
+            None => {
+                warn!("tx_delete: no element {:?}", index);
+                return false;
+            }
+            Some(row) => row,
+        };
+        let table_ref =
+            row.into_delete_table_ref(bucket_idx, tables.clone(), tx.txn_info().clone());
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Delete);
+        tag.set_write(); //FIXME: better way?
+        true
+    }
+
+    pub fn delete_pc<T>(
+        &self,
+        tx: &mut TransactionParOCC,
+        index: &Index,
+        tables: &Arc<T>,
+        bucket_idx: usize,
+    ) -> bool
+    where
+        Arc<Row<Entry, Index>>: BucketDeleteRef<T>,
+    {
+        let bucket_idx = bucket_idx % self.bucket_num;
+        let row = match self.buckets[bucket_idx].retrieve(index) {
+            None => {
+                warn!("tx_delete: no element {:?}", index);
+                return false;
+            }
+            Some(row) => row,
+        };
+        let table_ref =
+            row.into_delete_table_ref(bucket_idx, tables.clone(), tx.txn_info().clone());
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Delete);
+        tag.set_write(); //FIXME: better way?
+        true
+    }
+
+    pub fn delete<T>(
+        &self,
+        tx: &mut TransactionOCC,
+        index: &Index,
+        tables: &Arc<T>,
+        bucket_idx: usize,
+    ) -> bool
+    where
+        Arc<Row<Entry, Index>>: BucketDeleteRef<T>,
+    {
+        let bucket_idx = bucket_idx % self.bucket_num;
+        let row = match self.buckets[bucket_idx].retrieve(index) {
+            None => {
+                warn!("tx_delete: no element {:?}", index);
+                return false;
+            }
+            Some(row) => row,
+        };
+        let table_ref =
+            row.into_delete_table_ref(bucket_idx, tables.clone(), tx.txn_info().clone());
+        let tag = tx.retrieve_tag(table_ref.get_id(), table_ref.box_clone(), Operation::Delete);
+        tag.set_write(); //FIXME: better way?
+        true
+    }
+
+    pub fn push_raw(&self, entry: Entry) {
+        let bkt_idx = self.bucket_of(&entry);
+        self.buckets[bkt_idx].push_raw(entry);
+    }
+
+    pub fn retrieve(&self, index: &Index, bucket_idx: usize) -> Option<Arc<Row<Entry, Index>>> {
+        self.buckets[bucket_idx % self.bucket_num].retrieve(index)
+    }
+
+    // fn make_hash(&self, idx : &Index) -> usize {
+    //     let mut s = self.hash_builder.build_hasher();
+    //     idx.hash(&mut s);
+    //     s.finish() as usize
+    // }
+
+    // fn get_bucket_idx(&self, key: &Index) -> usize
+    // {
+    //     self.make_hash(key) % self.bucket_num
+    // }
+
+    pub fn get_bucket(&self, bkt_idx: usize) -> &Bucket<Entry, Index> {
+        info!("------------[TABLE] getting bucket {}-------", bkt_idx);
+        &self.buckets[bkt_idx % self.bucket_num]
+    }
+}
+
+impl<Entry, Index> Default for Table<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone + Debug,
+{
+    fn default() -> Self {
+        let mut buckets = Vec::with_capacity(16);
+
+        for _ in 0..16 {
+            buckets.push(Bucket::with_capacity(1024, String::from("default")));
+        }
+
+        Table {
+            buckets,
+            bucket_num: 16,
+            partition: Box::new(|entry: &Entry| entry.bucket_key()),
+            hash_builder: Default::default(),
+            name: String::from("default"),
+        }
+    }
+}
+
+impl<Entry, Index> Debug for Table<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[TABLE: {}]\n{:#?}", self.name, self.buckets)
+    }
+}
+
+//impl<Entry, Index> Drop for Table<Entry, Index>
+//where Entry: 'static + Key<Index> + Clone +Debug,
+//      Index: Eq+Hash  + Clone + Debug,
+//{
+//    fn drop(&mut self) {
+//        println!("Dropping table {}", self.name);
+//        //if self.name == "stock" {
+//        //    println!("{:?}", self.buckets);
+//        //}
+//
+//    }
+//}
+//
+
+//const PMEM_PAGE_ENTRY_NUM: usize = 1 << 10;
+
+/* FIXME: can we avoid the copy */
+pub struct Bucket<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    //Stable and safe to read while pushing, see SegVec
+    rows: SegVec<Arc<Row<Entry, Index>>>,
+    //Guards the take/replace of the rows as well
+    index: RwLock<RowIndex<Index>>,
+    id_: ObjectId,
+    name_: String,
+    pub vers_: Arc<TVersion>,
+    //Extent k holds pmem_per_size_ << k slots, see segment_of
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pmem_extents_: Vec<AtomicPtr<Entry>>,
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pmem_slot_: SlotLayout,
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    pmem_slots_: Mutex<PmemSlots<Entry, Index>>,
+    pmem_per_size_: usize,
+    pmem_offset_: AtomicUsize,
+}
+
+//Row slot of each key, and the slots of the deleted rows
+struct RowIndex<Index>
+where
+    Index: Eq + Hash,
+{
+    keys_: HashMap<Index, usize>,
+    free_: Vec<usize>,
+}
+
+//A pmem slot of a deleted row
+#[cfg(any(feature = "pmem", feature = "disk"))]
+struct RetiredSlot<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    slot_:    usize,
+    row_:     Weak<Row<Entry, Index>>,
+    deleter_: Arc<TxnInfo>,
+}
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+impl<Entry, Index> RetiredSlot<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    fn reclaimable(&self) -> bool {
+        self.deleter_.has_persist() && self.row_.upgrade().is_none()
+    }
+}
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+struct PmemSlots<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    retired_: Vec<RetiredSlot<Entry, Index>>,
+    free_:    Vec<usize>,
+}
+
+#[cfg(any(feature = "pmem", feature = "disk"))]
+impl<Entry, Index> PmemSlots<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    //Frees the reclaimable slots once out of free ones
+    fn reuse(&mut self) -> Option<usize> {
+        let PmemSlots {
+            ref mut retired_,
+            ref mut free_,
+        } = *self;
+
+        if free_.is_empty() {
+            retired_.retain(|retired| {
+                if retired.reclaimable() {
+                    free_.push(retired.slot_);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        free_.pop()
+    }
+}
+
+impl<Entry, Index> Bucket<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+    // pub fn new() -> Bucket<Entry, Index> {
+    //     Bucket {
+    //         rows: UnsafeCell::new(Vec::new()),
+    //         index: UnsafeCell::new(HashMap::new()),
+
+    //         id_ : OidFac::get_obj_next(),
+    //         vers_ : TVersion::default(),
+    //         pmem_root_:
+    //     }
+    // }
+
+    pub fn with_capacity(cap: usize, name: String) -> Bucket<Entry, Index> {
+        Bucket::with_layout(cap, name, PmemLayout::default())
+    }
+
+    #[cfg_attr(not(any(feature = "pmem", feature = "disk")), allow(unused_variables))]
+    pub fn with_layout(cap: usize, name: String, layout: PmemLayout) -> Bucket<Entry, Index> {
+        let bucket = Bucket {
+            rows: SegVec::with_base(cap),
+            index: RwLock::new(RowIndex {
+                keys_: HashMap::with_capacity(cap),
+                free_: Vec::new(),
+            }),
+
+            id_: OidFac::get_obj_next(),
+            vers_: Arc::new(TVersion::default()),
+            name_: name,
+
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_extents_: (0..SEGMENTS).map(|_| AtomicPtr::default()).collect(),
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_slot_: layout.slot_of::<Entry>(),
+            #[cfg(any(feature = "pmem", feature = "disk"))]
+            pmem_slots_: Mutex::new(PmemSlots {
+                retired_: Vec::new(),
+                free_:    Vec::new(),
+            }),
+            pmem_per_size_: cmp::max(cap, 1),
+            pmem_offset_: AtomicUsize::new(0),
+        };
+
+        /* Get the persistent memory */
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        bucket.pmem_extent(0);
+
+        bucket
+    }
+
+    /* Insert a row.
+     * Concurrent retrieves are safe, pushes are serialized by the bucket lock
+     * */
+    pub fn push(&self, row_arc: Arc<Row<Entry, Index>>) {
+        debug!("[PUSH ROW] : {:?}", *row_arc);
+        //assert_eq!(self.vers_.get_count() > 0 , true);
+        //assert_eq!(self.vers_.get_locker() == 0, false);
+        let idx_elem = row_arc.get_data().primary_key();
+
+        #[cfg(feature = "pmem")]
+        {
+            #[cfg(feature = "dir")]
+            {
+                //let p = self.get_pmem_addr(self.next_pmem_offset());
+                //row_arc.copy_to_ptr(p);
+            }
+
+            #[cfg(not(feature = "dir"))]
+            {
+                let paddr = self.get_pmem_addr(self.next_pmem_offset());
+                row_arc.set_pmem_addr(paddr);
+
+                /* A fresh slot, unreachable until the inserter commits */
+                #[cfg(feature = "porder")]
+                porder::tag(
+                    Role::Alloc,
+                    row_arc.get_access_info().id().into(),
+                    paddr as *const u8,
+                    ::std::mem::size_of::<Entry>(),
+                );
+            }
+        }
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        self.log_slot(&row_arc, true);
+
+        #[cfg(feature = "mvcc")]
+        row_arc.stamp_insert();
+
+        self.insert_row(idx_elem, row_arc);
+    }
+
+    /* Remove a row, with the bucket and the row locked.
+     * The deleter is the txn removing it, see RetiredSlot.
+     * */
+    pub fn delete(&self, row_arc: Arc<Row<Entry, Index>>, deleter: Arc<TxnInfo>) {
+        //assert_eq!(self.vers_.get_count() > 0 , true);
+        //assert_eq!(self.vers_.get_locker() == 0, false);
+        let idx_elem = row_arc.get_data().primary_key();
+        row_arc.tombstone();
+
+        {
+            let mut index = self.index.write().unwrap();
+            let idx = match index.keys_.get(&idx_elem) {
+                Some(idx) => *idx,
+                None => return,
+            };
+
+            /* The key may already be taken by a newer row */
+            match self.rows.get(idx) {
+                Some(row) if Arc::ptr_eq(row, &row_arc) => {}
+                _ => return,
+            }
+
+            index.keys_.remove(&idx_elem);
+            unsafe { self.rows.take(idx) };
+            index.free_.push(idx);
+        }
+
+        /* Retired with the deleter, see varlen */
+        varlen::replaced(&row_arc.get_var_fields(), &[]);
+
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        self.retire_pmem_slot(&row_arc, deleter);
+
+        #[cfg(not(any(feature = "pmem", feature = "disk")))]
+        let _ = deleter;
+    }
+
+    //Reuses the slot of a deleted row if any
+    fn insert_row(&self, idx_elem: Index, row_arc: Arc<Row<Entry, Index>>) {
+        let mut index = self.index.write().unwrap();
+        let idx = match index.free_.pop() {
+            Some(idx) => {
+                unsafe { self.rows.replace(idx, row_arc) };
+                idx
+            }
+            None => self.rows.push(row_arc),
+        };
+        index.keys_.insert(idx_elem, idx);
+    }
+
+    fn push_raw(&self, entry: Entry) {
+        let idx_elem = entry.primary_key();
+
+        #[cfg(not(all(feature = "pmem", feature = "dir")))]
+        let arc = Arc::new(Row::new(entry));
+
+        #[cfg(all(feature = "pmem", feature = "dir"))]
+        let arc = {
+            let p = self.get_pmem_addr(self.next_pmem_offset());
+            unsafe { p.write(entry) };
+            Arc::new(Row::new_from_ptr(p))
+        };
+
+        #[cfg(all(
+            any(feature = "pmem", feature = "disk"),
+            not(all(feature = "pmem", feature = "dir"))
+        ))]
+        arc.set_pmem_addr(self.get_pmem_addr(self.next_pmem_offset()));
+
+        self.insert_row(idx_elem, arc);
+    }
+
+    //Reuses the pmem slot of a deleted row if any
+    pub fn next_pmem_offset(&self) -> usize {
+        #[cfg(any(feature = "pmem", feature = "disk"))]
+        {
+            if let Some(slot) = self.pmem_slots_.lock().unwrap().reuse() {
+                return slot;
+            }
+        }
+
+        self.pmem_offset_.fetch_add(1, Ordering::SeqCst)
+    }
+
+    //The installing txn logs it with plog::persist_slots
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn log_slot(&self, row_arc: &Arc<Row<Entry, Index>>, live: bool) {
+        let paddr = row_arc.get_pmem_addr();
+        if !paddr.is_null() {
+            plog::slot_changed(paddr as *mut u8, live);
+        }
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn retire_pmem_slot(&self, row_arc: &Arc<Row<Entry, Index>>, deleter: Arc<TxnInfo>) {
+        self.log_slot(row_arc, false);
+        if let Some(slot) = self.pmem_slot_of(row_arc.get_pmem_addr()) {
+            self.pmem_slots_.lock().unwrap().retired_.push(RetiredSlot {
+                slot_:    slot,
+                row_:     Arc::downgrade(row_arc),
+                deleter_: deleter,
+            });
+        }
+    }
+
+    //Inverse of get_pmem_addr
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn pmem_slot_of(&self, addr: *mut Entry) -> Option<usize> {
+        let SlotLayout { pad_, stride_ } = self.pmem_slot_;
+        for (extent, pmem_root) in self.pmem_extents_.iter().enumerate() {
+            let pmem_root = pmem_root.load(Ordering::Acquire);
+            if pmem_root.is_null() {
+                continue;
+            }
+
+            let offset = (addr as usize).wrapping_sub(pmem_root as usize + pad_);
+            if offset < segment_cap(self.pmem_per_size_, extent) * stride_ {
+                return Some(self.pmem_per_size_ * ((1 << extent) - 1) + offset / stride_);
+            }
+        }
+        None
+    }
+
+    //The row of slot idx, pad_ bytes into the slot
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_addr(&self, idx: usize) -> *mut Entry {
+        let (extent, offset) = segment_of(self.pmem_per_size_, idx);
+        let SlotLayout { pad_, stride_ } = self.pmem_slot_;
+        let root = self.pmem_extent(extent) as *mut u8;
+        unsafe { root.add(offset * stride_ + pad_) as *mut Entry }
+    }
+
+    //Maps extent k on first use
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn pmem_extent(&self, extent: usize) -> *mut Entry {
+        let pmem_root = self.pmem_extents_[extent].load(Ordering::Acquire);
+        if !pmem_root.is_null() {
+            return pmem_root;
+        }
+
+        let path =
+            String::from(PMEM_DIR_ROOT.expect("PMEM_FILE_DIR must be supplied at compile time"));
+        let size = segment_cap(self.pmem_per_size_, extent) * self.pmem_slot_.stride_;
+        let new_root = pnvm_sys::mmap_file(path, size) as *mut Entry;
+        BenchmarkCounter::mmap();
+
+        if new_root.is_null() {
+            panic!("Bucket::pmem_extent(): failed, len: {}", size);
+        }
+
+        match self.pmem_extents_[extent].compare_exchange(
+            ptr::null_mut(),
+            new_root,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new_root,
+            Err(pmem_root) => {
+                pnvm_sys::unmap(new_root as *mut u8, size);
+                pmem_root
+            }
+        }
+    }
+
+    pub fn retrieve(&self, index_elem: &Index) -> Option<Arc<Row<Entry, Index>>> {
+        /* Clone before a delete can take the slot */
+        let index = self.index.read().unwrap();
+        let idx = match index.keys_.get(index_elem) {
+            None => return None,
+            Some(idx) => *idx,
+        };
+
+        Some(
+            self.rows
+                .get(idx)
+                .expect("row should not be empty. inconsistent with index")
+                .clone(),
+        )
+    }
+
+    //Live rows
+    pub fn len(&self) -> usize {
+        self.index.read().unwrap().keys_.len()
+    }
+
+    #[inline(always)]
+    pub fn lock(&self, tid: Tid) -> bool {
+        self.vers_.lock(tid)
+    }
+
+    #[inline(always)]
+    pub fn check(&self, cur_ver: u32, tid: u32) -> bool {
+        self.vers_.check_version(cur_ver, tid)
+    }
+
+    //FIXME: how to not Clone
+    // #[inline]
+    // pub fn install(&self, val: &Entry, tid: Tid) {
+    //     unsafe {
+    //         debug!("\n[TRANSACTION:{:?}]--[INSTALL]\n\t\t[OLD]--{:?}\n\t\t[NEW]--{:?}",
+    //                tid, self.data_.get().as_ref().unwrap(), val);
+
+    //         ptr::write(self.data_.get(), val.clone());
+    //     }
+    //     self.vers_.set_version(tid);
+    // }
+
+    #[inline(always)]
+    pub fn unlock(&self) {
+        self.vers_.unlock();
+    }
+
+    #[inline(always)]
+    pub fn get_version(&self) -> u32 {
+        self.vers_.get_version()
+    }
+
+    #[inline(always)]
+    pub fn set_version(&self, vers: u32) {
+        self.vers_.set_version(vers)
+    }
+
+    #[inline(always)]
+    pub fn get_id(&self) -> &ObjectId {
+        &self.id_
+    }
+
+    // pub fn get_addr(&self) -> Unique<T> {
+    //     let tvalue = self.tvalue_.read().unwrap();
+    //     tvalue.get_addr()
+    // }
+
+    pub fn get_layout(&self) -> Layout {
+        Layout::new::<Bucket<Entry, Index>>()
+    }
+
+    pub fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.vers_.get_access_info()
+    }
+
+    pub fn set_access_info(&self, info: Arc<TxnInfo>) {
+        self.vers_.set_access_info(info)
+    }
+
+    pub fn read_lock(&self, tid: u32) -> Result<(), ()> {
+        if self.vers_.read_lock(tid) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    pub fn write_lock(&self, tid: u32) -> Result<(), ()> {
+        if self.vers_.write_lock(tid) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    pub fn get_tvers(&self) -> Arc<TVersion> {
+        self.vers_.clone()
+    }
+
+    /* Scans, e.g. through a secondary index of the bucket rows.
+     * Call before looking into the bucket: the version read is
+     * validated at commit, so that a push or delete in the
+     * bucket in the meantime aborts the scanner. A snapshot
+     * (mvcc) does not validate it. */
+    pub fn scan(&self, tx: &mut TransactionOCC) {
+        if tx.is_snapshot() {
+            return;
+        }
+        tx.retrieve_tag(&self.id_, self.scan_ref(), Operation::RWrite)
+            .add_version(self.get_version());
+    }
+
+    pub fn scan_pc(&self, tx: &mut TransactionParOCC) {
+        if tx.is_snapshot() {
+            return;
+        }
+        tx.retrieve_tag(&self.id_, self.scan_ref(), Operation::RWrite)
+            .add_version(self.get_version());
+    }
+
+    pub fn scan_pc_raw(&self, tx: &mut TransactionParOCCRaw) {
+        tx.retrieve_tag(&self.id_, self.scan_ref(), Operation::RWrite)
+            .add_version(self.get_version());
+    }
+
+    //Read locks the bucket until the txn ends, pushes and deletes
+    //write lock it
+    pub fn scan_lock(&self, tx: &mut Transaction2PL) -> Result<(), ()> {
+        if tx.has_lock(&(self.id_, LockType::Read)) || tx.has_lock(&(self.id_, LockType::Write)) {
+            return Ok(());
+        }
+        self.read_lock(tx.id().into())?;
+        tx.add_locks((self.id_, LockType::Read), self.vers_.clone());
+        Ok(())
+    }
+
+    fn scan_ref(&self) -> Box<dyn TRef> {
+        Box::new(BucketScanRef {
+            id_:   self.id_,
+            vers_: self.vers_.clone(),
+            name_: self.name_.clone(),
+        })
+    }
+}
+
+//A bucket scanned by a transaction
+#[derive(Debug, Clone)]
+struct BucketScanRef {
+    id_:   ObjectId,
+    vers_: Arc<TVersion>,
+    name_: String,
+}
+
+impl TRef for BucketScanRef {
+    /* Nothing is logged for a scan */
+    fn get_ptr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_layout(&self) -> Layout {
+        Layout::new::<()>()
+    }
+
+    fn get_field_ptr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn get_field_size(&self, _field_idx: usize) -> usize {
+        0
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_addr(&self) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    #[cfg(any(feature = "pmem", feature = "disk"))]
+    fn get_pmem_field_addr(&self, _field_idx: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    fn box_clone(&self) -> Box<dyn TRef> {
+        Box::new(self.clone())
+    }
+
+    fn get_id(&self) -> &ObjectId {
+        &self.id_
+    }
+
+    fn get_tvers(&self) -> &Arc<TVersion> {
+        &self.vers_
+    }
+
+    fn get_version(&self) -> u32 {
+        self.vers_.get_version()
+    }
+
+    fn read(&self) -> &Any {
+        &()
+    }
+
+    fn get_access_info(&self) -> Arc<TxnInfo> {
+        self.vers_.get_access_info()
+    }
+
+    fn set_access_info(&mut self, txn_info: Arc<TxnInfo>) {
+        self.vers_.set_access_info(txn_info);
+    }
+
+    fn get_name(&self) -> String {
+        self.name_.clone()
+    }
+
+    fn install(&self, _id: Tid) {
+        panic!("BucketScanRef is never written");
+    }
+
+    #[cfg(all(feature = "pmem", feature = "wdrain"))]
+    fn write(&mut self, _ptr: *mut u8) {
+        panic!("BucketScanRef is never written");
+    }
+
+    #[cfg(not(all(feature = "pmem", feature = "wdrain")))]
+    fn write(&mut self, _val: Box<Any>) {
+        panic!("BucketScanRef is never written");
+    }
+
+    fn lock(&self, _tid: Tid) -> bool {
+        panic!("BucketScanRef is never written");
+    }
+
+    fn unlock(&self) {
+        panic!("BucketScanRef is never written");
+    }
+
+    fn check(&self, vers: u32, tid: u32) -> bool {
+        self.vers_.check_version(vers, tid)
+    }
+
+    /* For 2 Phase Locking, see Bucket::scan_lock */
+    fn write_through(&self, _val: Box<Any>, _tid: Tid) {
+        panic!("BucketScanRef is never written");
+    }
+
+    fn read_lock(&self, tid: u32) -> bool {
+        self.vers_.read_lock(tid)
+    }
+
+    fn read_unlock(&self, tid: u32) {
+        self.vers_.read_unlock(tid)
+    }
+
+    fn write_lock(&self, _tid: u32) -> bool {
+        panic!("BucketScanRef is never written");
+    }
+
+    fn write_unlock(&self, _tid: u32) {
+        panic!("BucketScanRef is never written");
+    }
+}
+
+unsafe impl<Entry, Index> Sync for Bucket<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+}
+
+unsafe impl<Entry, Index> Send for Bucket<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone,
+{
+}
+
+impl<Entry, Index> Debug for Bucket<Entry, Index>
+where
+    Entry: 'static + Key<Index> + Clone + Debug,
+    Index: Eq + Hash + Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let index = self.index.read().unwrap();
+        let rows = (0..self.rows.len())
+            .filter_map(|idx| self.rows.get(idx))
+            .collect::<Vec<_>>();
+        write!(f, "{:#?}\n{:#?}", rows, index.keys_)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::record::{Record, RecordTable};
+    use std::thread;
+    use storage::FieldOffset;
+
+    #[derive(Clone, Debug)]
+    struct Account {
+        id:      i32,
+        branch:  i32,
+        balance: u64,
+    }
+
+    impl FieldOffset for Account {
+        fn field_offset(&self) -> [isize; 32] {
+            [-1; 32]
+        }
+    }
+
+    impl Key<i32> for Account {
+        fn primary_key(&self) -> i32 {
+            self.id
+        }
+
+        fn bucket_key(&self) -> usize {
+            self.id as usize
+        }
+    }
+
+    fn account(id: i32, branch: i32) -> Account {
+        Account {
+            id,
+            branch,
+            balance: 0,
+        }
+    }
+
+    #[test]
+    fn test_default_partition() {
+        let table: Table<Account, i32> = Table::new_with_buckets(4, 8, "account");
+        for id in 0..8 {
+            table.push_raw(account(id, 0));
+        }
+
+        assert_eq!(table.bucket_of(&account(6, 0)), 2);
+        assert_eq!(table.retrieve(&6, 2).unwrap().get_data().id, 6);
+        assert_eq!(table.retrieve(&6, 1).is_none(), true);
+    }
+
+    #[test]
+    fn test_custom_partition() {
+        let table: Table<Account, i32> = Table::new_with_partition(
+            4,
+            8,
+            "account",
+            Box::new(|entry: &Account| entry.branch as usize),
+        );
+        table.push_raw(account(1, 3));
+        table.push_raw(account(2, 3));
+
+        assert_eq!(table.bucket_of(&account(1, 3)), 3);
+        assert_eq!(table.retrieve(&1, 3).unwrap().get_data().branch, 3);
+        assert_eq!(table.retrieve(&2, 3).unwrap().get_data().balance, 0);
+        assert_eq!(table.retrieve(&1, 1).is_none(), true);
+    }
+
+    #[test]
+    fn test_concurrent_push_retrieve() {
+        let table: Arc<Table<Account, i32>> = Arc::new(Table::new_with_partition(
+            1,
+            1,
+            "account",
+            Box::new(|_: &Account| 0),
+        ));
+
+        let pushers = (0..4)
+            .map(|t| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for id in (t * 500)..((t + 1) * 500) {
+                        table.push_raw(account(id, t));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        /* Rows show up whole while the rows grow */
+        let readers = (0..2)
+            .map(|_| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for id in 0..2000 {
+                        while table.retrieve(&id, 0).is_none() {}
+                        assert_eq!(table.retrieve(&id, 0).unwrap().get_data().id, id);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for h in pushers.into_iter().chain(readers) {
+            h.join().unwrap();
+        }
+        assert_eq!(table.get_bucket(0).len(), 2000);
+    }
+
+    #[test]
+    fn test_occ_scan_catches_phantom() {
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+        table.push_raw(Record::new(1, 10));
+
+        let scan = &mut TransactionOCC::new(Tid::new(1));
+        let blind = &mut TransactionOCC::new(Tid::new(2));
+        /* Both look 5 up, the scanner through the bucket */
+        table.get_bucket(0).scan(scan);
+        assert_eq!(table.retrieve(&5, 0).is_none(), true);
+
+        let push = &mut TransactionOCC::new(Tid::new(3));
+        table.push(push, Record::new(5, 50), &table);
+        assert_eq!(push.try_commit(), true);
+
+        /* Only the scanner notices the new row */
+        assert_eq!(blind.try_commit(), true);
+        assert_eq!(scan.try_commit(), false);
+    }
+
+    #[cfg(feature = "mvcc")]
+    #[test]
+    fn test_snapshot_skips_later_push() {
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+        table.push_raw(Record::new(1, 10));
+
+        let snap = &mut TransactionOCC::new_snapshot(Tid::new(1));
+        assert_eq!(snap.sees(&table.retrieve(&1, 0).unwrap()), true);
+
+        let push = &mut TransactionOCC::new(Tid::new(2));
+        table.push(push, Record::new(5, 50), &table);
+        assert_eq!(push.try_commit(), true);
+
+        /* In the index, but inserted after the snapshot */
+        assert_eq!(snap.sees(&table.retrieve(&5, 0).unwrap()), false);
+        assert_eq!(snap.try_commit(), true);
+    }
+
+    #[test]
+    fn test_2pl_scan_blocks_push() {
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+
+        let scan = &mut Transaction2PL::new(Tid::new(1));
+        assert_eq!(table.get_bucket(0).scan_lock(scan), Ok(()));
+
+        /* Younger, dies instead of waiting */
+        let push = &mut Transaction2PL::new(Tid::new(2));
+        assert_eq!(table.push_lock(push, Record::new(5, 50), &table), false);
+        push.abort();
+
+        assert_eq!(table.push_lock(scan, Record::new(6, 60), &table), true);
+        scan.commit();
+        assert_eq!(table.retrieve(&6, 0).is_some(), true);
+    }
+
+    #[test]
+    fn test_2pl_abort_drops_push() {
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+
+        let tx = &mut Transaction2PL::new(Tid::new(1));
+        assert_eq!(table.push_lock(tx, Record::new(5, 50), &table), true);
+        /* Not visible before the commit */
+        assert_eq!(table.retrieve(&5, 0).is_none(), true);
+        tx.abort();
+
+        assert_eq!(table.retrieve(&5, 0).is_none(), true);
+        assert_eq!(table.get_bucket(0).get_version(), 0);
+    }
+
+    #[test]
+    fn test_delete_reuses_slot() {
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+        table.push_raw(Record::new(1, 10));
+        table.push_raw(Record::new(2, 20));
+        let row = table.retrieve(&1, 0).unwrap();
+
+        let del = &mut TransactionOCC::new(Tid::new(1));
+        assert_eq!(table.delete(del, &1, &table, 0), true);
+        assert_eq!(del.try_commit(), true);
+
+        /* A late reader keeps the row alive, but cannot use it */
+        assert_eq!(row.lock(Tid::new(2)), false);
+        assert_eq!(row.check(row.get_version(), 2), false);
+        assert_eq!(table.get_bucket(0).len(), 1);
+
+        table.push_raw(Record::new(3, 30));
+        assert_eq!(table.get_bucket(0).rows.len(), 2);
+        assert_eq!(table.retrieve(&1, 0).is_none(), true);
+        assert_eq!(table.retrieve(&2, 0).is_some(), true);
+        assert_eq!(table.retrieve(&3, 0).is_some(), true);
+    }
+
+    //After a crash, the slot of a row deleted by a durable
+    //txn is free, the one of a pending delete still live
+    #[cfg(feature = "crash")]
+    #[test]
+    fn test_delete_logs_free_slot() {
+        use crash::{harness::RUN_LOCK, recovery};
+        use pnvm_sys::emul;
+        use txn::TidFac;
+
+        let _guard = RUN_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        emul::checkpoint();
+        let table: Arc<RecordTable<u64, u64>> = Arc::new(Table::new_with_buckets(1, 8, "kv"));
+
+        let push = &mut TransactionOCC::new(TidFac::get_thd_next());
+        table.push(push, Record::new(1, 10), &table);
+        table.push(push, Record::new(2, 20), &table);
+        assert_eq!(push.try_commit(), true);
+        let slot1 = table.retrieve(&1, 0).unwrap().get_pmem_addr() as usize;
+        let slot2 = table.retrieve(&2, 0).unwrap().get_pmem_addr() as usize;
+
+        let del = &mut TransactionOCC::new(TidFac::get_thd_next());
+        assert_eq!(table.delete(del, &1, &table, 0), true);
+        assert_eq!(del.try_commit(), true);
+
+        /* Installed, without its commit record yet. The tids of
+         * the other test threads overlap ours, not this one */
+        plog::slot_changed(slot2 as *mut u8, false);
+        plog::persist_slots(u32::max_value());
+
+        let states = recovery::slot_states(&emul::crash());
+        assert_eq!(states[&slot1], false);
+        assert_eq!(states[&slot2], true);
+    }
+}
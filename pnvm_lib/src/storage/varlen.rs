@@ -0,0 +1,440 @@
+//************************************************
+//Variable-length columns
+//
+//VarLen is a fixed-size handle to an out of line
+//payload, so that an entry stays #[repr(C)] and a row
+//is still a single Layout. Payloads are immutable:
+//setting a column allocates a new payload, from the
+//PmemFac with pmem. The handle is then valid in both
+//the DRAM and the pmem image of a row, and the before
+//image of a row still points to its old payload.
+//
+//An entry lists its VarLen columns through
+//FieldOffset::var_fields (#[varlen] with pnvm_derive),
+//which reach TRef::get_var_fields, so that FlushPlan
+//flushes the payloads written. They are not logged: the
+//undo record of the row holds the old handle, and a new
+//payload is unreachable until the row is committed.
+//
+//A payload replaced by an install, or held by a row the
+//install deleted, is retired with the txn installing it
+//(retire), and reused once:
+//- the txn is committed, and durable with pmem: until
+//  then, recovery may restore the handle to it
+//- every txn running at the retire is over: an OCC read
+//  may have copied the old handle before the install, and
+//  reads the payload until its check fails. A txn holds a
+//  ReadGuard from its start, which tells the epoch of its
+//  thread when it entered.
+//- with "mvcc", the epoch passed it: no snapshot can
+//  read a version holding it
+//Payloads are allocated by capacity, a power of two, so
+//that a retired one fits the next of its size.
+//
+//The payloads allocated while a txn runs are its own, e.g.
+//the new c_data of a Payment. An install keeps them, an
+//abort frees them at once (discard): they were never
+//installed, so no other txn read them. Transaction2PL
+//writes through, so it never discards.
+//************************************************
+
+#[cfg(feature = "mvcc")]
+use mvcc;
+#[cfg(feature = "pmem")]
+use txn::PmemFac;
+use txn::TxnInfo;
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp,
+    collections::HashMap,
+    fmt::{self, Debug},
+    marker::PhantomData,
+    mem, ptr, slice,
+    str::{self, Utf8Error},
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Arc, Mutex},
+};
+
+/* Smallest payload capacity */
+const MIN_CAPACITY: usize = 16;
+
+/* Epoch of a thread without a running txn */
+const QUIESCENT: usize = usize::max_value();
+
+/* Taken by every retire */
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VarLen {
+    ptr_: *const u8,
+    len_: usize,
+}
+
+impl VarLen {
+    //Copies bytes into a new payload
+    pub fn new(bytes: &[u8]) -> VarLen {
+        if bytes.is_empty() {
+            return VarLen::default();
+        }
+
+        let ptr = VarLen::alloc(bytes.len());
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len()) };
+        let var = VarLen {
+            ptr_: ptr,
+            len_: bytes.len(),
+        };
+
+        READER.with(|&(_, ref depth)| {
+            if depth.get() > 0 {
+                ALLOCATED.with(|allocated| allocated.borrow_mut().push(var));
+            }
+        });
+        var
+    }
+
+    pub fn from_str(s: &str) -> VarLen {
+        VarLen::new(s.as_bytes())
+    }
+
+    //A retired payload of the capacity, or a new one
+    fn alloc(len: usize) -> *mut u8 {
+        let cap = capacity(len);
+        match PAYLOADS.lock().unwrap().reuse(cap) {
+            Some(ptr) => ptr,
+            None => VarLen::alloc_new(cap),
+        }
+    }
+
+    #[cfg(feature = "pmem")]
+    fn alloc_new(cap: usize) -> *mut u8 {
+        PmemFac::alloc(cap)
+    }
+
+    #[cfg(not(feature = "pmem"))]
+    fn alloc_new(cap: usize) -> *mut u8 {
+        Box::into_raw(vec![0u8; cap].into_boxed_slice()) as *mut u8
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len_ == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr_, self.len_) }
+    }
+
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    //Start of the payload, null if empty
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr_ as *mut u8
+    }
+
+    pub fn len(&self) -> usize {
+        self.len_
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_ == 0
+    }
+}
+
+impl Default for VarLen {
+    fn default() -> VarLen {
+        VarLen {
+            ptr_: ptr::null(),
+            len_: 0,
+        }
+    }
+}
+
+impl Debug for VarLen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl PartialEq for VarLen {
+    fn eq(&self, other: &VarLen) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+/* Payloads are never written once shared */
+unsafe impl Send for VarLen {}
+unsafe impl Sync for VarLen {}
+
+fn capacity(len: usize) -> usize {
+    cmp::max(len, MIN_CAPACITY).next_power_of_two()
+}
+
+thread_local! {
+    //Replaced by the installs of the thread, not retired yet
+    static REPLACED: RefCell<Vec<VarLen>> = RefCell::new(Vec::new());
+
+    //Allocated by the running txn of the thread since it
+    //started, or since its last install
+    static ALLOCATED: RefCell<Vec<VarLen>> = RefCell::new(Vec::new());
+
+    //Epoch the running txns of the thread entered at, and
+    //their number
+    static READER: (Arc<AtomicUsize>, Cell<usize>) = {
+        let epoch = Arc::new(AtomicUsize::new(QUIESCENT));
+        READERS.lock().unwrap().push(epoch.clone());
+        (epoch, Cell::new(0))
+    };
+}
+
+lazy_static! {
+    static ref PAYLOADS: Mutex<Payloads> = Mutex::new(Payloads {
+        retired_: Vec::new(),
+        free_:    HashMap::new(),
+    });
+
+    //Epochs of the threads, see READER
+    static ref READERS: Mutex<Vec<Arc<AtomicUsize>>> = Mutex::new(Vec::new());
+}
+
+//Held by a txn while it runs, on the thread it started on
+pub struct ReadGuard {
+    marker_: PhantomData<*const u8>, /* Not Send */
+}
+
+//A txn starts on this thread
+pub fn enter() -> ReadGuard {
+    READER.with(|&(ref epoch, ref depth)| {
+        if depth.get() == 0 {
+            epoch.store(EPOCH.load(Ordering::SeqCst), Ordering::SeqCst);
+            ALLOCATED.with(|allocated| allocated.borrow_mut().clear());
+        }
+        depth.set(depth.get() + 1);
+    });
+    ReadGuard {
+        marker_: PhantomData,
+    }
+}
+
+impl Drop for ReadGuard {
+    fn drop(&mut self) {
+        READER.with(|&(ref epoch, ref depth)| {
+            depth.set(depth.get() - 1);
+            if depth.get() == 0 {
+                epoch.store(QUIESCENT, Ordering::SeqCst);
+            }
+        });
+    }
+}
+
+//Every running txn entered after the retires below it
+fn oldest_reader() -> usize {
+    let mut readers = READERS.lock().unwrap();
+    /* Threads gone */
+    readers.retain(|epoch| Arc::strong_count(epoch) > 1);
+    readers
+        .iter()
+        .map(|epoch| epoch.load(Ordering::SeqCst))
+        .min()
+        .unwrap_or(QUIESCENT)
+}
+
+struct Retired {
+    var_:      VarLen,
+    replacer_: Arc<TxnInfo>,
+    epoch_:    usize,
+    #[cfg(feature = "mvcc")]
+    ts_:       Option<usize>, /* Latest timestamp once the replacer is done */
+}
+
+impl Retired {
+    fn reclaimable(&mut self, oldest_reader: usize) -> bool {
+        if !(self.replacer_.has_commit() && self.replacer_.has_done()) {
+            return false;
+        }
+
+        if self.epoch_ >= oldest_reader {
+            return false;
+        }
+
+        #[cfg(feature = "mvcc")]
+        {
+            let ts = *self.ts_.get_or_insert_with(mvcc::latest);
+            if mvcc::epoch() < ts {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+struct Payloads {
+    retired_: Vec<Retired>,
+    free_:    HashMap<usize, Vec<usize>>, /* Capacity -> payloads */
+}
+
+impl Payloads {
+    //Frees the reclaimable payloads once out of free ones
+    fn reuse(&mut self, cap: usize) -> Option<*mut u8> {
+        if self.free_.get(&cap).map_or(true, |free| free.is_empty()) {
+            let oldest_reader = oldest_reader();
+            let retired = mem::replace(&mut self.retired_, Vec::new());
+            for mut r in retired {
+                if r.reclaimable(oldest_reader) {
+                    self.free(r.var_);
+                } else {
+                    self.retired_.push(r);
+                }
+            }
+        }
+        self.free_
+            .get_mut(&cap)
+            .and_then(|free| free.pop())
+            .map(|ptr| ptr as *mut u8)
+    }
+
+    fn free(&mut self, var: VarLen) {
+        self.free_
+            .entry(capacity(var.len()))
+            .or_insert_with(Vec::new)
+            .push(var.as_ptr() as usize);
+    }
+}
+
+//An install overwrote the columns old with new: the old
+//payloads no longer held are left to retire()
+pub fn replaced(old: &[(Option<usize>, VarLen)], new: &[(Option<usize>, VarLen)]) {
+    REPLACED.with(|replaced| {
+        let mut replaced = replaced.borrow_mut();
+        for &(_, var) in old {
+            if !var.is_empty() && new.iter().all(|&(_, v)| v.as_ptr() != var.as_ptr()) {
+                replaced.push(var);
+            }
+        }
+    });
+}
+
+//The payloads replaced on this thread since the last
+//call were replaced by the txn of replacer. Those it
+//allocated since are installed with it.
+pub fn retire(replacer: &Arc<TxnInfo>) {
+    ALLOCATED.with(|allocated| allocated.borrow_mut().clear());
+
+    let vars = REPLACED.with(|replaced| mem::replace(&mut *replaced.borrow_mut(), Vec::new()));
+    if vars.is_empty() {
+        return;
+    }
+
+    let epoch = EPOCH.fetch_add(1, Ordering::SeqCst);
+    let mut payloads = PAYLOADS.lock().unwrap();
+    for var in vars {
+        payloads.retired_.push(Retired {
+            var_:      var,
+            replacer_: replacer.clone(),
+            epoch_:    epoch,
+            #[cfg(feature = "mvcc")]
+            ts_:       None,
+        });
+    }
+}
+
+//The running txn of the thread aborted: frees what it
+//allocated since its last install
+pub fn discard() {
+    let vars = ALLOCATED.with(|allocated| mem::replace(&mut *allocated.borrow_mut(), Vec::new()));
+    if vars.is_empty() {
+        return;
+    }
+
+    let mut payloads = PAYLOADS.lock().unwrap();
+    for var in vars {
+        payloads.free(var);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+    use txn::Tid;
+
+    #[test]
+    fn test_payload_out_of_line() {
+        let bytes = vec![7u8; 300];
+        let var = VarLen::new(&bytes);
+        let copy = var;
+
+        assert_eq!(var.len(), 300);
+        assert_eq!(copy.as_ptr(), var.as_ptr());
+        assert_eq!(copy.as_bytes(), &bytes[..]);
+        assert_eq!(VarLen::from_str("c_data").as_str(), Ok("c_data"));
+        assert_eq!(VarLen::default().as_bytes().is_empty(), true);
+    }
+
+    //Reused once its replacer is done, by a payload of the
+    //same capacity
+    #[cfg(not(feature = "mvcc"))]
+    #[test]
+    fn test_replaced_payload_reused() {
+        let old = VarLen::new(&[1u8; 100]);
+        let new = VarLen::new(&[2u8; 100]);
+        replaced(
+            &[(Some(0), old), (None, VarLen::default())],
+            &[(Some(0), new)],
+        );
+
+        let info = Arc::new(TxnInfo::new(Tid::new(1)));
+        retire(&info);
+        assert_ne!(VarLen::new(&[3u8; 90]).as_ptr(), old.as_ptr());
+
+        info.commit();
+        info.persist();
+        let reused = reuse_of(old, &[4u8; 90]);
+        assert_eq!(reused.as_bytes(), &[4u8; 90][..]);
+        assert_eq!(new.as_bytes(), &[2u8; 100][..]);
+    }
+
+    //Not reused while a txn running at the retire may read it
+    #[cfg(not(feature = "mvcc"))]
+    #[test]
+    fn test_retired_payload_kept_for_readers() {
+        let reader = enter();
+        let old = VarLen::new(&[1u8; 3000]);
+        replaced(&[(Some(0), old)], &[]);
+
+        let info = Arc::new(TxnInfo::new(Tid::new(1)));
+        retire(&info);
+        info.commit();
+        info.persist();
+        assert_ne!(VarLen::new(&[2u8; 3000]).as_ptr(), old.as_ptr());
+
+        drop(reader);
+        assert_eq!(reuse_of(old, &[3u8; 3000]).as_bytes(), &[3u8; 3000][..]);
+    }
+
+    //Freed at the abort of the txn allocating it
+    #[test]
+    fn test_aborted_payload_freed() {
+        let _txn = enter();
+        let kept = VarLen::new(&[1u8; 1000]);
+        retire(&Arc::new(TxnInfo::new(Tid::new(1))));
+
+        let aborted = VarLen::new(&[2u8; 1000]);
+        discard();
+        assert_eq!(VarLen::new(&[3u8; 1000]).as_ptr(), aborted.as_ptr());
+        assert_eq!(kept.as_bytes(), &[1u8; 1000][..]);
+    }
+
+    /* The txns of the other test threads may hold it for a while */
+    fn reuse_of(old: VarLen, bytes: &[u8]) -> VarLen {
+        for _ in 0..1000 {
+            let var = VarLen::new(bytes);
+            if var.as_ptr() == old.as_ptr() {
+                return var;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("reuse_of: {:p} not reused", old.as_ptr());
+    }
+}
@@ -0,0 +1,81 @@
+//************************************************
+//Atomics of the hand-rolled locks
+//
+//TVersion and TxnInfo take their atomics, and TxnInfo the
+//mutex of its durability callbacks, from here. When
+//built with `--cfg loom`, they come from loom instead, so
+//the model checker can interleave every access. See the
+//loom tests in tcore.rs and txn.rs.
+//
+//Spin loops must go through spin_loop()/yield_now(), loom
+//only explores schedules where a spinning thread yields.
+//************************************************
+
+#[cfg(not(loom))]
+pub use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
+
+#[cfg(loom)]
+pub use loom::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
+
+#[cfg(not(loom))]
+pub use std::sync::Mutex;
+
+#[cfg(loom)]
+pub use loom::sync::Mutex;
+
+#[cfg(not(loom))]
+use std::{sync::atomic, thread};
+
+#[cfg(loom)]
+use loom::thread;
+
+#[cfg(not(loom))]
+#[inline(always)]
+pub fn spin_loop() {
+    atomic::spin_loop_hint();
+}
+
+#[cfg(loom)]
+#[inline(always)]
+pub fn spin_loop() {
+    thread::yield_now();
+}
+
+#[cfg(not(loom))]
+#[inline(always)]
+pub fn yield_now() {
+    thread::yield_now();
+}
+
+#[cfg(loom)]
+#[inline(always)]
+pub fn yield_now() {
+    thread::yield_now();
+}
+
+//Plain memory tracked by the model checker, accessing it from two
+//threads without a happens-before relation fails the model
+#[cfg(all(test, loom))]
+pub struct TrackedCell(loom::cell::UnsafeCell<u32>);
+
+#[cfg(all(test, loom))]
+unsafe impl Sync for TrackedCell {}
+
+#[cfg(all(test, loom))]
+impl TrackedCell {
+    pub fn new(val: u32) -> TrackedCell {
+        TrackedCell(loom::cell::UnsafeCell::new(val))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.with(|ptr| unsafe { *ptr })
+    }
+
+    pub fn incr(&self) {
+        self.0.with_mut(|ptr| unsafe { *ptr += 1 })
+    }
+
+    pub fn set(&self, val: u32) {
+        self.0.with_mut(|ptr| unsafe { *ptr = val })
+    }
+}
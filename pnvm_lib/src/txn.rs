@@ -2,11 +2,12 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
-    ptr,
+    fmt, mem, ptr,
     rc::Rc,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::Ordering,
     sync::{Arc, RwLock},
 };
+use sync::{self, AtomicBool, AtomicUsize, Mutex};
 use tcore::{FieldArray, ObjectId, Operation, TRef, TTag};
 
 use occ::occ_txn::TransactionOCC;
@@ -89,6 +90,10 @@ pub struct PmemFac {
 #[cfg(all(feature = "pmem"))]
 const PMEM_DIR_ROOT: Option<&str> = option_env!("PMEM_FILE_DIR");
 
+/* Bytes of the first extent of a thread, 0 for the default */
+#[cfg(all(feature = "pmem"))]
+static PMEM_FAC_CAP: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+
 #[cfg(all(feature = "pmem"))]
 impl PmemFac {
     pub fn new() -> PmemFac {
@@ -97,10 +102,24 @@ impl PmemFac {
             pmem_offset_:   0,
             pmem_len_:      0,
             pmem_root_idx_: 0,
-            pmem_cap_:      1 << 30,
+            pmem_cap_:      PmemFac::capacity(),
         }
     }
 
+    //Size of the first extent of the threads created after,
+    //each next extent of a thread doubles
+    pub fn set_capacity(bytes: usize) {
+        PMEM_FAC_CAP.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn capacity() -> usize {
+        match PMEM_FAC_CAP.load(Ordering::Relaxed) {
+            0 => pnvm_sys::PMEM_FAC_DEFAULT_SIZE,
+            cap => cap,
+        }
+    }
+
+    //Maps the first extent of the thread, else its first alloc does
     pub fn init() {
         PMEM_FAC.with(|fac| fac.borrow_mut().init_inner())
     }
@@ -120,17 +139,24 @@ impl PmemFac {
     }
 
     fn alloc_inner(&mut self, size: usize) -> *mut u8 {
+        /* Threads that never called init, e.g. the unit tests */
+        if self.pmem_root_[0].is_null() {
+            self.init_inner();
+        }
+
         if self.pmem_len_ + size >= self.pmem_cap_ {
             //Exponentially increase the allocated page size
             let path = String::from(
                 PMEM_DIR_ROOT.expect("PMEM_FILE_DIR must be supplied at compile time"),
             );
 
-            let size = self.pmem_cap_;
+            self.pmem_cap_ = 2 * self.pmem_cap_;
+            while self.pmem_cap_ <= size {
+                self.pmem_cap_ = 2 * self.pmem_cap_;
+            }
 
-            let pmem_root = pnvm_sys::mmap_file(path, size);
+            let pmem_root = pnvm_sys::mmap_file(path, self.pmem_cap_);
 
-            self.pmem_cap_ = 2 * self.pmem_cap_;
             self.pmem_root_idx_ += 1;
             self.pmem_root_[self.pmem_root_idx_] = pmem_root;
 
@@ -176,6 +202,16 @@ impl TidFac {
         TID_FAC.with(|fac| fac.borrow_mut().get_next())
     }
 
+    //Next id of the thread, None once its factory is gone
+    pub fn try_thd_next_id() -> Option<u32> {
+        TID_FAC.try_with(|fac| fac.borrow().next_id_).ok()
+    }
+
+    //Go on with the ids of a former thread of the same mask
+    pub fn set_thd_next_id(next_id: u32) {
+        TID_FAC.with(|fac| fac.borrow_mut().next_id_ = next_id)
+    }
+
     pub fn new() -> TidFac {
         TidFac {
             mask_:    0,
@@ -225,6 +261,25 @@ pub struct TxnInfo {
     rank_:      AtomicUsize,
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     persist_: AtomicBool,
+    persist_rank_: AtomicUsize, /* The pieces of a lower rank are durable */
+    durable_:      DurableCallbacks,
+}
+
+/* Run once, when the txn is persisted */
+type DurableCallback = Box<FnMut() + Send>;
+
+struct DurableCallbacks(Mutex<Vec<DurableCallback>>);
+
+impl DurableCallbacks {
+    fn new() -> DurableCallbacks {
+        DurableCallbacks(Mutex::new(Vec::new()))
+    }
+}
+
+impl fmt::Debug for DurableCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DurableCallbacks")
+    }
 }
 
 impl Default for TxnInfo {
@@ -234,9 +289,11 @@ impl Default for TxnInfo {
             locked_:    AtomicBool::new(false),
             committed_: AtomicBool::new(true),
             //status_ : AtomicUsize::new(TxnStatus::Active as usize),
-            rank_: AtomicUsize::default(),
+            rank_: AtomicUsize::new(0),
             //#[cfg(any(feature = "pmem", feature = "disk"))]
             persist_: AtomicBool::new(true),
+            persist_rank_: AtomicUsize::new(0),
+            durable_:      DurableCallbacks::new(),
         }
     }
 }
@@ -262,6 +319,8 @@ impl TxnInfo {
 
             //#[cfg(any(feature = "pmem", feature = "disk"))]
             persist_: AtomicBool::new(false),
+            persist_rank_: AtomicUsize::new(0),
+            durable_:      DurableCallbacks::new(),
         }
     }
 
@@ -270,10 +329,28 @@ impl TxnInfo {
         self.persist_.load(Ordering::Acquire)
     }
 
+    //The pieces of the ranks below rank are durable, their
+    //undo records and their data (ppipe), or the whole txn
+    pub fn has_persist_below(&self, rank: usize) -> bool {
+        self.has_persist() || self.persist_rank_.load(Ordering::Acquire) >= rank
+    }
+
     pub fn has_commit(&self) -> bool {
         self.committed_.load(Ordering::Acquire)
     }
 
+    //Nothing left to wait for: committed, and persisted too
+    //with pmem. A txn committed but not persisted yet stays a
+    //dependency, for its commit record to reach the log before
+    //the ones of the txns depending on it.
+    pub fn has_done(&self) -> bool {
+        if cfg!(any(feature = "pmem", feature = "disk")) {
+            self.has_persist()
+        } else {
+            self.has_commit()
+        }
+    }
+
     //if deps just started rank 3
     //  txn ready to start rank 3 must wait for it to complete
     //  txn ready to start rank 2 can safely go
@@ -302,9 +379,49 @@ impl TxnInfo {
         self.committed_.store(true, Ordering::Release);
     }
 
+    //A txn handed over to the persister before it commits
+    //(commit_async) is persisted once visible, never before
+    pub fn wait_commit(&self) {
+        while !self.has_commit() {
+            sync::yield_now();
+        }
+    }
+
     //#[cfg(any(feature = "pmem", feature = "disk"))]
     pub fn persist(&self) {
         self.persist_.store(true, Ordering::Release);
+
+        /* Callbacks added before the lock saw persist_ unset */
+        let cbs = mem::replace(&mut *self.durable_.0.lock().unwrap(), Vec::new());
+        for mut cb in cbs {
+            cb();
+        }
+    }
+
+    //f runs once the txn is persisted: on the thread setting
+    //persist(), or on this one if it already is
+    pub fn on_persist<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        {
+            let mut cbs = self.durable_.0.lock().unwrap();
+            if !self.has_persist() {
+                let mut f = Some(f);
+                cbs.push(Box::new(move || {
+                    if let Some(f) = f.take() {
+                        f()
+                    }
+                }));
+                return;
+            }
+        }
+        f()
+    }
+
+    //Raised by the txn itself, as its pieces run in rank order
+    pub fn persist_below(&self, rank: usize) {
+        self.persist_rank_.store(rank, Ordering::Release);
     }
 
     pub fn start(&self, rank: usize) {
@@ -319,3 +436,210 @@ impl TxnInfo {
         self.rank_.load(Ordering::Acquire)
     }
 }
+
+//Handed back by an asynchronous commit, once the txn is
+//visible (TxState::COMMITTED). Without pmem, the txn is
+//as durable as it gets once it commits.
+#[derive(Clone, Debug)]
+pub struct DurabilityHandle {
+    txn_info_: Arc<TxnInfo>,
+}
+
+impl DurabilityHandle {
+    pub fn new(txn_info: Arc<TxnInfo>) -> DurabilityHandle {
+        DurabilityHandle {
+            txn_info_: txn_info,
+        }
+    }
+
+    pub fn id(&self) -> &Tid {
+        self.txn_info_.id()
+    }
+
+    pub fn is_durable(&self) -> bool {
+        self.txn_info_.has_done()
+    }
+
+    pub fn wait(&self) {
+        while !self.is_durable() {
+            sync::yield_now();
+        }
+    }
+
+    //f runs once the txn is durable, on the persister thread
+    //or at once if it already is
+    pub fn on_durable<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if cfg!(any(feature = "pmem", feature = "disk")) {
+            self.txn_info_.on_persist(f);
+        } else {
+            f();
+        }
+    }
+}
+
+//Model checked with loom, run with:
+//RUSTFLAGS="--cfg loom" cargo +nightly test --release --features unstable loom_tests
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{self, thread};
+    use sync::TrackedCell;
+
+    /* Same condition as TransactionParOCC::wait_deps_start */
+    fn wait_start(dep: &TxnInfo, rank: usize) {
+        while !dep.has_commit() && !dep.has_started(rank) {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn loom_rank_signalling() {
+        loom::model(|| {
+            let info = Arc::new(TxnInfo::new(Tid::new(1)));
+            let outputs = Arc::new([TrackedCell::new(0), TrackedCell::new(0)]);
+
+            /* The dependency runs its pieces of rank 1 and 2 */
+            let dep = {
+                let info = info.clone();
+                let outputs = outputs.clone();
+                thread::spawn(move || {
+                    info.start(1);
+                    outputs[0].set(1);
+                    info.start(2);
+                    outputs[1].set(2);
+                    info.commit();
+                })
+            };
+
+            /* Pieces of rank 1 wait for the dependency to go past rank 1 */
+            wait_start(&info, 1);
+            assert_eq!(outputs[0].get(), 1);
+            wait_start(&info, 2);
+            assert_eq!(outputs[1].get(), 2);
+            assert_eq!(info.has_finished(1) || info.has_commit(), true);
+
+            dep.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_commit_persist_visibility() {
+        loom::model(|| {
+            let info = Arc::new(TxnInfo::new(Tid::new(1)));
+            let output = Arc::new(TrackedCell::new(0));
+
+            let dep = {
+                let info = info.clone();
+                let output = output.clone();
+                thread::spawn(move || {
+                    output.set(1);
+                    info.commit();
+                    info.persist();
+                })
+            };
+
+            if info.has_persist() {
+                assert_eq!(info.has_commit(), true);
+                assert_eq!(output.get(), 1);
+            } else if info.has_commit() {
+                assert_eq!(output.get(), 1);
+            }
+
+            dep.join().unwrap();
+            assert_eq!(info.has_persist(), true);
+        });
+    }
+
+    #[test]
+    fn loom_async_commit_handoff() {
+        loom::model(|| {
+            let info = Arc::new(TxnInfo::new(Tid::new(1)));
+            let output = Arc::new(TrackedCell::new(0));
+
+            /* The persister takes the txn as soon as it is handed over */
+            let persister = {
+                let info = info.clone();
+                thread::spawn(move || {
+                    info.wait_commit();
+                    info.persist();
+                })
+            };
+
+            /* commit_async: handed over, then published and committed */
+            let committer = {
+                let info = info.clone();
+                let output = output.clone();
+                thread::spawn(move || {
+                    output.set(1);
+                    info.commit();
+                })
+            };
+
+            /* What DurabilityHandle::is_durable sees with pmem */
+            if info.has_persist() {
+                assert_eq!(info.has_commit(), true);
+                assert_eq!(output.get(), 1);
+            }
+
+            committer.join().unwrap();
+            persister.join().unwrap();
+            assert_eq!(info.has_persist(), true);
+        });
+    }
+
+    #[test]
+    fn loom_persist_watermark() {
+        loom::model(|| {
+            let info = Arc::new(TxnInfo::new(Tid::new(1)));
+            let outputs = Arc::new([TrackedCell::new(0), TrackedCell::new(0)]);
+
+            /* Pieces of rank 1 and 2, each durable once the next one is logged */
+            let dep = {
+                let info = info.clone();
+                let outputs = outputs.clone();
+                thread::spawn(move || {
+                    outputs[0].set(1);
+                    info.persist_below(2);
+                    outputs[1].set(2);
+                    info.commit();
+                    info.persist();
+                })
+            };
+
+            if info.has_persist_below(3) {
+                assert_eq!(outputs[1].get(), 2);
+            }
+            if info.has_persist_below(2) {
+                assert_eq!(outputs[0].get(), 1);
+            }
+
+            dep.join().unwrap();
+            assert_eq!(info.has_persist_below(3), true);
+        });
+    }
+
+    #[test]
+    fn loom_durable_callback() {
+        loom::model(|| {
+            let info = Arc::new(TxnInfo::new(Tid::new(1)));
+            let fired = Arc::new(AtomicUsize::new(0));
+
+            let persister = {
+                let info = info.clone();
+                thread::spawn(move || info.persist())
+            };
+
+            /* Runs exactly once, whoever gets there first */
+            let cb_fired = fired.clone();
+            info.on_persist(move || {
+                cb_fired.fetch_add(1, Ordering::Relaxed);
+            });
+
+            persister.join().unwrap();
+            assert_eq!(fired.load(Ordering::Relaxed), 1);
+        });
+    }
+}
@@ -0,0 +1,360 @@
+//************************************************
+//Copy strategies
+//
+//How data is written back to pmem: PMDK's memcpy_nodrain
+//and pmem_flush, or native stores and flushes:
+//- Mov: plain stores, the lines are then flushed
+//- Movnt: non-temporal stores (_mm_stream_si128, or
+//  vmovntdq of a zmm register with AVX-512) for the whole
+//  lines, plain stores for the partial lines at the ends
+//  only, which are flushed
+//
+//The flush is clflush, clflushopt, clwb or none (empty,
+//e.g. to measure the stores alone). clflushopt and clwb
+//are detected with CPUID at runtime, see is_supported().
+//Neither has an intrinsic on our toolchain, they are
+//issued with asm!, as is the AVX-512 stream: the toolchain
+//has no _mm512 intrinsics. It is used if the CPU has
+//avx512f (is_x86_feature_detected!), whatever the target
+//features of the build.
+//
+//A strategy is named as the copy modes of pnvm_bench,
+//e.g. "movnt-clwb" or "mov-clflushopt", or "pmdk".
+//
+//pnvm_sys::flush and memcpy_nodrain use the strategy set
+//with set_copy_strategy, PMDK by default.
+//
+//A strategy does what it is named after, whatever the
+//persistence domain: with eADR, callers skip the flushes
+//themselves (see FlushPlan in pnvm_lib).
+//
+//Under "emul", every strategy goes through the emulated
+//device, so that the crash model is unchanged.
+//************************************************
+
+use std::{
+    arch::x86_64::*,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "emul")]
+use super::emul;
+#[cfg(feature = "porder")]
+use super::porder;
+#[cfg(not(feature = "emul"))]
+use super::{pmem_drain, pmem_flush, pmem_memcpy_nodrain};
+#[cfg(not(feature = "emul"))]
+use libc::c_void;
+
+use super::CACHE_LINE_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreMode {
+    Mov,
+    Movnt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlushMode {
+    Clflush,
+    Clflushopt,
+    Clwb,
+    Empty,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CopyStrategy {
+    Pmdk,
+    Native(StoreMode, FlushMode),
+}
+
+/* Encoded with CopyStrategy::bits, Pmdk is 0 */
+static COPY_STRATEGY: AtomicUsize = AtomicUsize::new(0);
+
+//Used by the persist paths of the transactions
+pub fn set_copy_strategy(strategy: CopyStrategy) {
+    assert!(
+        strategy.is_supported(),
+        "copy strategy {} not supported by this CPU",
+        strategy.name()
+    );
+    info!("copy strategy: {}", strategy.name());
+    COPY_STRATEGY.store(strategy.bits(), Ordering::SeqCst);
+}
+
+#[inline(always)]
+pub fn copy_strategy() -> CopyStrategy {
+    CopyStrategy::from_bits(COPY_STRATEGY.load(Ordering::Relaxed))
+}
+
+impl CopyStrategy {
+    //"auto" is the best native strategy of this CPU
+    pub fn from_name(name: &str) -> Option<CopyStrategy> {
+        let name = name.to_lowercase();
+        match name.as_ref() {
+            "pmdk" => return Some(CopyStrategy::Pmdk),
+            "auto" => return Some(CopyStrategy::detect()),
+            _ => {}
+        }
+
+        let mut parts = name.splitn(2, '-');
+        let store = match parts.next() {
+            Some("mov") => StoreMode::Mov,
+            Some("movnt") => StoreMode::Movnt,
+            _ => return None,
+        };
+        let flush = match parts.next() {
+            Some("clflush") => FlushMode::Clflush,
+            Some("clflushopt") => FlushMode::Clflushopt,
+            Some("clwb") => FlushMode::Clwb,
+            Some("empty") => FlushMode::Empty,
+            _ => return None,
+        };
+        Some(CopyStrategy::Native(store, flush))
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            CopyStrategy::Pmdk => String::from("pmdk"),
+            CopyStrategy::Native(store, flush) => {
+                let store = match store {
+                    StoreMode::Mov => "mov",
+                    StoreMode::Movnt => "movnt",
+                };
+                let flush = match flush {
+                    FlushMode::Clflush => "clflush",
+                    FlushMode::Clflushopt => "clflushopt",
+                    FlushMode::Clwb => "clwb",
+                    FlushMode::Empty => "empty",
+                };
+                format!("{}-{}", store, flush)
+            }
+        }
+    }
+
+    //Non-temporal stores, and the best flush available
+    pub fn detect() -> CopyStrategy {
+        let flush = [FlushMode::Clwb, FlushMode::Clflushopt]
+            .iter()
+            .cloned()
+            .find(|flush| flush_supported(*flush))
+            .unwrap_or(FlushMode::Clflush);
+        CopyStrategy::Native(StoreMode::Movnt, flush)
+    }
+
+    pub fn is_supported(&self) -> bool {
+        match *self {
+            CopyStrategy::Pmdk => true,
+            CopyStrategy::Native(_, flush) => flush_supported(flush),
+        }
+    }
+
+    //Whether copy_nodrain leaves the whole lines out of the
+    //caches, so that they need no flush
+    pub fn streams(&self) -> bool {
+        match *self {
+            CopyStrategy::Native(StoreMode::Movnt, _) => true,
+            _ => false,
+        }
+    }
+
+    //Copies and flushes, without draining
+    pub fn copy_nodrain(&self, dst: *mut u8, src: *mut u8, len: usize) {
+        #[cfg(feature = "porder")]
+        porder::flushed(dst, len);
+
+        #[cfg(feature = "emul")]
+        emul::memcpy_nodrain(dst, src, len);
+
+        #[cfg(not(feature = "emul"))]
+        match *self {
+            CopyStrategy::Pmdk => unsafe {
+                pmem_memcpy_nodrain(dst as *mut c_void, src as *mut c_void, len);
+            },
+            CopyStrategy::Native(StoreMode::Mov, flush) => {
+                unsafe { ptr::copy_nonoverlapping(src, dst, len) };
+                flush_lines(flush, dst, len);
+            }
+            CopyStrategy::Native(StoreMode::Movnt, flush) => copy_movnt(flush, dst, src, len),
+        }
+    }
+
+    pub fn flush(&self, addr: *mut u8, len: usize) {
+        #[cfg(feature = "porder")]
+        porder::flushed(addr, len);
+
+        #[cfg(feature = "emul")]
+        emul::flush(addr, len);
+
+        #[cfg(not(feature = "emul"))]
+        match *self {
+            CopyStrategy::Pmdk => unsafe { pmem_flush(addr as *const c_void, len) },
+            CopyStrategy::Native(_, flush) => flush_lines(flush, addr, len),
+        }
+    }
+
+    pub fn drain(&self) {
+        #[cfg(feature = "porder")]
+        porder::fenced();
+
+        #[cfg(feature = "emul")]
+        emul::drain();
+
+        #[cfg(not(feature = "emul"))]
+        match *self {
+            CopyStrategy::Pmdk => unsafe { pmem_drain() },
+            CopyStrategy::Native(..) => unsafe { _mm_sfence() },
+        }
+    }
+
+    fn bits(&self) -> usize {
+        match *self {
+            CopyStrategy::Pmdk => 0,
+            CopyStrategy::Native(store, flush) => 1 | (store as usize) << 1 | (flush as usize) << 2,
+        }
+    }
+
+    fn from_bits(bits: usize) -> CopyStrategy {
+        if bits == 0 {
+            return CopyStrategy::Pmdk;
+        }
+        let store = match (bits >> 1) & 1 {
+            0 => StoreMode::Mov,
+            _ => StoreMode::Movnt,
+        };
+        let flush = match bits >> 2 {
+            0 => FlushMode::Clflush,
+            1 => FlushMode::Clflushopt,
+            2 => FlushMode::Clwb,
+            _ => FlushMode::Empty,
+        };
+        CopyStrategy::Native(store, flush)
+    }
+}
+
+impl Default for CopyStrategy {
+    fn default() -> CopyStrategy {
+        CopyStrategy::Pmdk
+    }
+}
+
+/* CPUID leaf 7, EBX */
+const CPUID_CLFLUSHOPT: u32 = 1 << 23;
+const CPUID_CLWB: u32 = 1 << 24;
+
+fn flush_supported(flush: FlushMode) -> bool {
+    let bit = match flush {
+        FlushMode::Clflush | FlushMode::Empty => return true,
+        FlushMode::Clflushopt => CPUID_CLFLUSHOPT,
+        FlushMode::Clwb => CPUID_CLWB,
+    };
+
+    if __get_cpuid_max(0).0 < 7 {
+        return false;
+    }
+    __cpuid_count(7, 0).ebx & bit != 0
+}
+
+fn flush_lines(flush: FlushMode, addr: *mut u8, len: usize) {
+    if len == 0 || flush == FlushMode::Empty {
+        return;
+    }
+
+    let start = addr as usize / CACHE_LINE_SIZE * CACHE_LINE_SIZE;
+    let end = addr as usize + len;
+    for line in (start..end).step_by(CACHE_LINE_SIZE) {
+        let line = line as *const u8;
+        unsafe {
+            match flush {
+                FlushMode::Clflush => _mm_clflush(line),
+                FlushMode::Clflushopt => {
+                    asm!("clflushopt ($0)" :: "r"(line) : "memory" : "volatile")
+                }
+                FlushMode::Clwb => asm!("clwb ($0)" :: "r"(line) : "memory" : "volatile"),
+                FlushMode::Empty => {}
+            }
+        }
+    }
+}
+
+//The whole lines of dst are streamed, the partial lines at
+//either end are copied then flushed
+fn copy_movnt(flush: FlushMode, dst: *mut u8, src: *mut u8, len: usize) {
+    let head = (CACHE_LINE_SIZE - dst as usize % CACHE_LINE_SIZE) % CACHE_LINE_SIZE;
+    if len <= head {
+        unsafe { ptr::copy_nonoverlapping(src, dst, len) };
+        return flush_lines(flush, dst, len);
+    }
+
+    let body = (len - head) / CACHE_LINE_SIZE * CACHE_LINE_SIZE;
+    let tail = len - head - body;
+    unsafe {
+        ptr::copy_nonoverlapping(src, dst, head);
+        stream_lines(dst.add(head), src.add(head), body);
+        ptr::copy_nonoverlapping(src.add(head + body), dst.add(head + body), tail);
+    }
+    flush_lines(flush, dst, head);
+    flush_lines(flush, unsafe { dst.add(head + body) }, tail);
+}
+
+//dst is line aligned, len a multiple of the line size
+unsafe fn stream_lines(dst: *mut u8, src: *const u8, len: usize) {
+    if is_x86_feature_detected!("avx512f") {
+        return stream_lines_avx512(dst, src, len);
+    }
+
+    for off in (0..len).step_by(16) {
+        let v = _mm_loadu_si128(src.add(off) as *const __m128i);
+        _mm_stream_si128(dst.add(off) as *mut __m128i, v);
+    }
+}
+
+//One line per store
+unsafe fn stream_lines_avx512(dst: *mut u8, src: *const u8, len: usize) {
+    for off in (0..len).step_by(CACHE_LINE_SIZE) {
+        asm!("vmovdqu64 ($1), %zmm0
+              vmovntdq %zmm0, ($0)"
+             :: "r"(dst.add(off)), "r"(src.add(off)) : "zmm0", "memory" : "volatile");
+    }
+    /* No AVX-SSE transition penalty for the caller */
+    asm!("vzeroupper" :::: "volatile");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let names = ["pmdk", "mov-clflush", "movnt-clwb", "mov-empty"];
+        for name in &names {
+            let strategy = CopyStrategy::from_name(name).unwrap();
+            assert_eq!(strategy.name(), *name);
+            assert_eq!(CopyStrategy::from_bits(strategy.bits()), strategy);
+        }
+        assert_eq!(CopyStrategy::from_name("movnt"), None);
+        assert_eq!(CopyStrategy::from_name("mov-clzero"), None);
+        assert!(CopyStrategy::detect().streams());
+        assert!(CopyStrategy::detect().is_supported());
+    }
+
+    //Native copies of unaligned ranges, to DRAM
+    #[cfg(not(feature = "emul"))]
+    #[test]
+    fn test_native_copies() {
+        let src: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+        for name in &["mov-clflush", "movnt-clflush", "movnt-empty", "auto"] {
+            let strategy = CopyStrategy::from_name(name).unwrap();
+            for &(off, len) in &[(0, 1024), (3, 60), (5, 700), (64, 128), (61, 6)] {
+                let mut dst = vec![0u8; 1024 + 64];
+                let d = dst.as_mut_ptr().wrapping_add(off);
+                strategy.copy_nodrain(d, src.as_ptr() as *mut u8, len);
+                strategy.drain();
+
+                assert_eq!(&dst[off..off + len], &src[..len], "{} at {}", name, off);
+                assert!(dst[off + len..].iter().all(|b| *b == 0));
+            }
+        }
+    }
+}
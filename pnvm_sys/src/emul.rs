@@ -0,0 +1,320 @@
+//************************************************
+//Emulated persistent memory
+//
+//Stands in for libpmem/libpmemlog when the "emul" feature
+//is on, so that crash consistency can be tested on a
+//machine without pmem.
+//
+//Model:
+//- Mapped regions are plain memory, stores are volatile
+//- memcpy_nodrain/flush make the written cache lines pending
+//  on the calling thread
+//- drain persists the pending lines of the calling thread
+//- Log appends are atomic and durable on return, as with
+//  pmemlog_appendv
+//- A crash keeps the persisted lines and any subset of the
+//  pending ones, so rows can be torn at line granularity
+//- Flushes of unmapped (volatile) memory are no-ops
+//
+//Every flush, drain, log append and ack is an event. When
+//armed, the device snapshots itself at random events.
+//************************************************
+
+use libc::iovec;
+use rand;
+use std::{
+    collections::HashMap,
+    mem, ptr, slice,
+    sync::Mutex,
+    thread::{self, ThreadId},
+};
+
+use super::CACHE_LINE_SIZE;
+
+lazy_static! {
+    static ref DEVICE: Mutex<Device> = Mutex::new(Device::new());
+}
+
+struct Region {
+    base_:      usize,
+    len_:       usize,
+    buf_:       Vec<u8>, /* Backing memory, base_ is aligned into it */
+    persisted_: Vec<u8>,
+}
+
+/* A flushed part of a single cache line */
+struct Chunk {
+    addr_: usize,
+    data_: Vec<u8>,
+}
+
+//Content of the device after a crash
+#[derive(Clone, Debug)]
+pub struct PmemImage {
+    regions_: Vec<(usize, Vec<u8>)>,
+    log_:     Vec<u8>,
+    acks_:    Vec<u32>,
+    event_:   usize,
+}
+
+impl PmemImage {
+    pub fn read(&self, addr: *const u8, len: usize) -> &[u8] {
+        let (base, data) = self.find(addr as usize, len);
+        let off = addr as usize - base;
+        &data[off..off + len]
+    }
+
+    pub fn write(&mut self, addr: *const u8, src: &[u8]) {
+        let addr = addr as usize;
+        let len = src.len();
+        let idx = self
+            .regions_
+            .iter()
+            .position(|(base, data)| *base <= addr && addr + len <= *base + data.len())
+            .expect("PmemImage::write: address not mapped");
+
+        let (base, ref mut data) = self.regions_[idx];
+        let off = addr - base;
+        data[off..off + len].copy_from_slice(src);
+    }
+
+    //The durable log, in append order
+    pub fn log(&self) -> &[u8] {
+        &self.log_
+    }
+
+    //Transactions acknowledged as persisted before the crash
+    pub fn acks(&self) -> &[u32] {
+        &self.acks_
+    }
+
+    //Number of events before the crash
+    pub fn event(&self) -> usize {
+        self.event_
+    }
+
+    fn find(&self, addr: usize, len: usize) -> (usize, &Vec<u8>) {
+        self.regions_
+            .iter()
+            .find(|(base, data)| *base <= addr && addr + len <= *base + data.len())
+            .map(|(base, data)| (*base, data))
+            .expect("PmemImage::read: address not mapped")
+    }
+}
+
+struct Device {
+    regions_: Vec<Region>,
+    pending_: HashMap<ThreadId, Vec<Chunk>>,
+    log_:     Vec<u8>,
+    acks_:    Vec<u32>,
+    events_:  usize,
+    rate_:    usize, /* Snapshot once every rate_ events on average, 0 when disarmed */
+    images_:  Vec<PmemImage>,
+}
+
+impl Device {
+    fn new() -> Device {
+        Device {
+            regions_: Vec::new(),
+            pending_: HashMap::new(),
+            log_:     Vec::new(),
+            acks_:    Vec::new(),
+            events_:  0,
+            rate_:    0,
+            images_:  Vec::new(),
+        }
+    }
+
+    fn region_mut(&mut self, addr: usize, len: usize) -> &mut Region {
+        self.regions_
+            .iter_mut()
+            .find(|r| r.base_ <= addr && addr + len <= r.base_ + r.len_)
+            .expect("emul: address not mapped")
+    }
+
+    /* Persist a chunk into the shadow copy */
+    fn apply(&mut self, chunk: &Chunk) {
+        let region = self.region_mut(chunk.addr_, chunk.data_.len());
+        let off = chunk.addr_ - region.base_;
+        region.persisted_[off..off + chunk.data_.len()].copy_from_slice(&chunk.data_);
+    }
+
+    fn is_mapped(&self, addr: usize, len: usize) -> bool {
+        self.regions_
+            .iter()
+            .any(|r| r.base_ <= addr && addr + len <= r.base_ + r.len_)
+    }
+
+    fn flush(&mut self, addr: usize, len: usize) {
+        /* Volatile memory, as for objects not allocated from pmem */
+        if !self.is_mapped(addr, len) {
+            self.event();
+            return;
+        }
+
+        let mut chunks = vec![];
+        let end = addr + len;
+        let mut cur = addr;
+        while cur < end {
+            let line_end = (cur / CACHE_LINE_SIZE + 1) * CACHE_LINE_SIZE;
+            let next = if line_end < end { line_end } else { end };
+            let data = unsafe { slice::from_raw_parts(cur as *const u8, next - cur) }.to_vec();
+            chunks.push(Chunk {
+                addr_: cur,
+                data_: data,
+            });
+            cur = next;
+        }
+
+        self.pending_
+            .entry(thread::current().id())
+            .or_insert_with(Vec::new)
+            .extend(chunks);
+        self.event();
+    }
+
+    fn drain(&mut self) {
+        let chunks = self
+            .pending_
+            .remove(&thread::current().id())
+            .unwrap_or_else(Vec::new);
+        for chunk in chunks.iter() {
+            self.apply(chunk);
+        }
+        self.event();
+    }
+
+    fn event(&mut self) {
+        self.events_ += 1;
+        if self.rate_ != 0 && rand::random::<usize>() % self.rate_ == 0 {
+            let image = self.snapshot();
+            self.images_.push(image);
+        }
+    }
+
+    /* Persisted lines plus a random subset of the in-flight ones */
+    fn snapshot(&self) -> PmemImage {
+        let mut regions: Vec<(usize, Vec<u8>)> = self
+            .regions_
+            .iter()
+            .map(|r| (r.base_, r.persisted_.clone()))
+            .collect();
+
+        for chunk in self.pending_.values().flat_map(|chunks| chunks.iter()) {
+            if !rand::random::<bool>() {
+                continue;
+            }
+            let (base, ref mut data) = *regions
+                .iter_mut()
+                .find(|(base, data)| *base <= chunk.addr_ && chunk.addr_ < *base + data.len())
+                .expect("emul: pending chunk not mapped");
+            let off = chunk.addr_ - base;
+            data[off..off + chunk.data_.len()].copy_from_slice(&chunk.data_);
+        }
+
+        PmemImage {
+            regions_: regions,
+            log_:     self.log_.clone(),
+            acks_:    self.acks_.clone(),
+            event_:   self.events_,
+        }
+    }
+}
+
+/* Exposed APIS */
+pub fn map(len: usize) -> *mut u8 {
+    let mut buf = vec![0u8; len + CACHE_LINE_SIZE];
+    let start = buf.as_mut_ptr() as usize;
+    let base = (start + CACHE_LINE_SIZE - 1) / CACHE_LINE_SIZE * CACHE_LINE_SIZE;
+
+    DEVICE.lock().unwrap().regions_.push(Region {
+        base_:      base,
+        len_:       len,
+        buf_:       buf,
+        persisted_: vec![0u8; len],
+    });
+    debug!("emul::map: {:#x}, {}", base, len);
+    base as *mut u8
+}
+
+pub fn unmap(addr: *mut u8, _len: usize) {
+    DEVICE
+        .lock()
+        .unwrap()
+        .regions_
+        .retain(|r| r.base_ != addr as usize);
+}
+
+pub fn flush(addr: *mut u8, len: usize) {
+    DEVICE.lock().unwrap().flush(addr as usize, len);
+}
+
+pub fn drain() {
+    DEVICE.lock().unwrap().drain();
+}
+
+pub fn memcpy_nodrain(dest: *mut u8, src: *mut u8, len: usize) {
+    let mut device = DEVICE.lock().unwrap();
+    unsafe { ptr::copy(src, dest, len) };
+    device.flush(dest as usize, len);
+}
+
+pub fn memcpy_persist(dest: *mut u8, src: *mut u8, len: usize) {
+    let mut device = DEVICE.lock().unwrap();
+    unsafe { ptr::copy(src, dest, len) };
+    device.flush(dest as usize, len);
+    device.drain();
+}
+
+pub fn memset_persist(dest: *mut u8, c: i32, len: usize) {
+    let mut device = DEVICE.lock().unwrap();
+    unsafe { ptr::write_bytes(dest, c as u8, len) };
+    device.flush(dest as usize, len);
+    device.drain();
+}
+
+pub fn append_log(iovecs: &[iovec]) {
+    let mut device = DEVICE.lock().unwrap();
+    for iov in iovecs.iter() {
+        let data = unsafe { slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len) };
+        device.log_.extend_from_slice(data);
+    }
+    device.event();
+}
+
+//Acknowledge that the transaction is persisted
+pub fn ack(tid: u32) {
+    let mut device = DEVICE.lock().unwrap();
+    device.acks_.push(tid);
+    device.event();
+}
+
+//Take a snapshot once every `rate` events on average, 0 disarms
+pub fn arm(rate: usize) {
+    DEVICE.lock().unwrap().rate_ = rate;
+}
+
+//Snapshot the device as if it crashed now
+pub fn crash() -> PmemImage {
+    DEVICE.lock().unwrap().snapshot()
+}
+
+pub fn take_images() -> Vec<PmemImage> {
+    mem::replace(&mut DEVICE.lock().unwrap().images_, Vec::new())
+}
+
+//Persist the current content of every region, and start
+//over with an empty log
+pub fn checkpoint() {
+    let mut device = DEVICE.lock().unwrap();
+    for region in device.regions_.iter_mut() {
+        let data = unsafe { slice::from_raw_parts(region.base_ as *const u8, region.len_) };
+        region.persisted_.copy_from_slice(data);
+    }
+    device.pending_.clear();
+    device.log_.clear();
+    device.acks_.clear();
+    device.images_.clear();
+    device.events_ = 0;
+    device.rate_ = 0;
+}
@@ -2,6 +2,7 @@
 #![feature(alloc, allocator_api)]
 #![feature(ptr_internals)]
 #![feature(box_into_raw_non_null)]
+#![feature(asm)]
 extern crate libc;
 
 #[cfg(not(any(feature = "profile", feature = "unstable")))]
@@ -12,6 +13,10 @@ extern crate core;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "emul")]
+#[macro_use]
+extern crate lazy_static;
+
 extern crate errno;
 use errno::errno;
 
@@ -25,7 +30,28 @@ pub use alloc::allocator::{Alloc, AllocErr, Layout};
 
 extern crate rand;
 
-use std::{cell::RefCell, ffi::CString, ptr::NonNull, rc::Rc, str, string::String, thread};
+use std::{
+    cell::RefCell,
+    ffi::CString,
+    ptr::NonNull,
+    rc::Rc,
+    str,
+    string::String,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+#[cfg(feature = "emul")]
+pub mod emul;
+
+pub mod copy;
+pub use copy::{copy_strategy, set_copy_strategy, CopyStrategy};
+
+#[cfg(feature = "porder")]
+pub mod porder;
 
 const LPREFIX: &'static str = "pnvm_sys::";
 const PMEM_FILE_CREATE: c_int = 1 << 0;
@@ -47,13 +73,83 @@ const PMEM_FILE_TMPFILE: c_int = 1 << 3;
 //    PMEM_ALLOCATOR.with(|pmem_cell| pmem_cell.borrow_mut().dealloc(ptr, layout))
 //}
 
+/* Persistence domain
+ *
+ * With ADR only the memory controller is persistent: a store
+ * must be flushed from the CPU caches, then drained. With
+ * eADR the caches are persistent too, so flush() does nothing
+ * and the copies are plain stores. drain() is then only a
+ * store fence, as pmem_drain() is.
+ *
+ * The domain is detected once at startup, or forced, see
+ * detect_persist_domain(). The emul device always models
+ * ADR: flushes are kept there whatever the domain.
+ * */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PersistDomain {
+    Adr,
+    Eadr,
+}
+
+impl PersistDomain {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PersistDomain::Adr => "adr",
+            PersistDomain::Eadr => "eadr",
+        }
+    }
+}
+
+static CACHES_PERSISTENT: AtomicBool = AtomicBool::new(false);
+
+//Asks libpmem unless forced, e.g. to test eADR on a machine without it
+pub fn detect_persist_domain(forced: Option<PersistDomain>) -> PersistDomain {
+    #[cfg(not(feature = "emul"))]
+    let detected = if has_auto_flush() == 1 {
+        PersistDomain::Eadr
+    } else {
+        PersistDomain::Adr
+    };
+
+    #[cfg(feature = "emul")]
+    let detected = PersistDomain::Adr;
+
+    let domain = forced.unwrap_or(detected);
+    info!("persistence domain: {:?} (detected {:?})", domain, detected);
+    CACHES_PERSISTENT.store(domain == PersistDomain::Eadr, Ordering::SeqCst);
+    domain
+}
+
+pub fn persist_domain() -> PersistDomain {
+    if caches_persistent() {
+        PersistDomain::Eadr
+    } else {
+        PersistDomain::Adr
+    }
+}
+
+#[inline(always)]
+pub fn caches_persistent() -> bool {
+    CACHES_PERSISTENT.load(Ordering::Relaxed)
+}
+
+//Flushes are left out: eADR, but on the emul device
+#[inline(always)]
+pub fn skips_flushes() -> bool {
+    !cfg!(feature = "emul") && caches_persistent()
+}
+
 pub fn flush(ptr: *mut u8, size: usize) {
     trace!("flush {:p} , {}", ptr, size);
-    unsafe { pmem_flush(ptr as *const c_void, size) };
+
+    if skips_flushes() {
+        return;
+    }
+    copy_strategy().flush(ptr, size);
 }
 
 pub fn drain() {
-    unsafe { pmem_drain() };
+    copy_strategy().drain();
 }
 
 /* Disk Operations*/
@@ -74,7 +170,53 @@ pub fn disk_persist_log(iovecs: &Vec<iovec>) {
 }
 
 pub fn persist_log(iovecs: &Vec<iovec>) {
-    PMEM_LOGGER.with(|pmem_log| pmem_log.borrow_mut().append_many(iovecs, iovecs.len()));
+    persist_log_to(&log_handle(), iovecs);
+}
+
+//The log of the calling thread, to append to from another
+//thread (see persist_log_to)
+#[derive(Clone)]
+pub struct LogHandle {
+    #[cfg(not(feature = "emul"))]
+    log_: Arc<PLog>,
+}
+
+impl LogHandle {
+    pub fn same_log(&self, other: &LogHandle) -> bool {
+        #[cfg(not(feature = "emul"))]
+        let same = Arc::ptr_eq(&self.log_, &other.log_);
+
+        /* One log on the device */
+        #[cfg(feature = "emul")]
+        let same = {
+            let _ = other;
+            true
+        };
+
+        same
+    }
+}
+
+pub fn log_handle() -> LogHandle {
+    LogHandle {
+        #[cfg(not(feature = "emul"))]
+        log_: PMEM_LOGGER.with(|pmem_log| pmem_log.clone()),
+    }
+}
+
+pub fn persist_log_to(log: &LogHandle, iovecs: &Vec<iovec>) {
+    #[cfg(feature = "emul")]
+    {
+        let _ = log;
+        emul::append_log(iovecs);
+    }
+
+    #[cfg(not(feature = "emul"))]
+    log.log_.append_many(iovecs, iovecs.len());
+
+    /* The append ends with a drain */
+    #[cfg(feature = "porder")]
+    porder::fenced();
 }
 
 pub fn walk(
@@ -82,14 +224,21 @@ pub fn walk(
     callback: extern "C" fn(buf: *const c_void, len: size_t, arg: *mut c_void) -> c_int,
 ) {
     trace!("walk : chunksize = {}", chunksize);
-    PMEM_LOGGER.with(|pmem_log| pmem_log.borrow_mut().walk(chunksize, callback));
+    PMEM_LOGGER.with(|pmem_log| pmem_log.walk(chunksize, callback));
 }
 
 pub fn init() {
     //    PMEM_ALLOCATOR.with(|pmem_cell| pmem_cell.borrow_mut().check());
-    PMEM_LOGGER.with(|pmem_log| pmem_log.borrow_mut().check());
+    #[cfg(not(feature = "emul"))]
+    PMEM_LOGGER.with(|pmem_log| pmem_log.check());
+}
+
+#[cfg(feature = "emul")]
+pub fn mmap_file(_path: String, len: usize) -> *mut u8 {
+    emul::map(len)
 }
 
+#[cfg(not(feature = "emul"))]
 pub fn mmap_file(path: String, len: usize) -> *mut u8 {
     let path = CString::new(path).unwrap();
     let pathp = path.as_ptr();
@@ -134,19 +283,55 @@ pub fn mmap_file(path: String, len: usize) -> *mut u8 {
 }
 
 pub fn memcpy_persist(pmemaddr: *mut u8, src: *mut u8, len: usize) {
-    unsafe { pmem_memcpy_persist(pmemaddr as *mut c_void, src as *mut c_void, len) };
+    #[cfg(feature = "emul")]
+    emul::memcpy_persist(pmemaddr, src, len);
+
+    #[cfg(not(feature = "emul"))]
+    {
+        if caches_persistent() {
+            unsafe { std::ptr::copy_nonoverlapping(src, pmemaddr, len) };
+            return drain();
+        }
+        unsafe { pmem_memcpy_persist(pmemaddr as *mut c_void, src as *mut c_void, len) };
+    }
 }
 
 pub fn memcpy_nodrain(pmemaddr: *mut u8, src: *mut u8, len: usize) {
-    unsafe { pmem_memcpy_nodrain(pmemaddr as *mut c_void, src as *mut c_void, len) };
+    #[cfg(feature = "emul")]
+    copy_strategy().copy_nodrain(pmemaddr, src, len);
+
+    #[cfg(not(feature = "emul"))]
+    {
+        if caches_persistent() {
+            unsafe { std::ptr::copy_nonoverlapping(src, pmemaddr, len) };
+            return;
+        }
+        copy_strategy().copy_nodrain(pmemaddr, src, len);
+    }
 }
 
 pub fn memset_persist(pmemaddr: *mut u8, c: i32, len: usize) {
-    unsafe { pmem_memset_persist(pmemaddr as *mut c_void, c as c_int, len) };
+    #[cfg(feature = "emul")]
+    emul::memset_persist(pmemaddr, c, len);
+
+    #[cfg(not(feature = "emul"))]
+    {
+        if caches_persistent() {
+            unsafe { std::ptr::write_bytes(pmemaddr, c as u8, len) };
+            return drain();
+        }
+        unsafe { pmem_memset_persist(pmemaddr as *mut c_void, c as c_int, len) };
+    }
 }
 
 pub fn unmap(pmemaddr: *mut u8, len: usize) {
-    unsafe { pmem_unmap(pmemaddr as *mut c_void, len) };
+    #[cfg(feature = "emul")]
+    emul::unmap(pmemaddr, len);
+
+    #[cfg(not(feature = "emul"))]
+    unsafe {
+        pmem_unmap(pmemaddr as *mut c_void, len)
+    };
 }
 
 pub fn has_hw_drain() -> c_int {
@@ -217,6 +402,14 @@ extern "C" {
 
 pub const PMEM_MIN_SIZE: usize = 1024 * 1024 * 16;
 pub const PMEM_DEFAULT_SIZE: usize = 48 * PMEM_MIN_SIZE;
+pub const CACHE_LINE_SIZE: usize = 64;
+//First extent of a thread in pnvm_lib's PmemFac, unless
+//configured. The emulated device backs a mapping with two
+//DRAM buffers, and snapshots them.
+#[cfg(feature = "emul")]
+pub const PMEM_FAC_DEFAULT_SIZE: usize = PMEM_MIN_SIZE;
+#[cfg(not(feature = "emul"))]
+pub const PMEM_FAC_DEFAULT_SIZE: usize = 1 << 30;
 const PMEM_ERROR_OK: c_int = 0;
 //pub const PMEM_FILE_DIR: &'static str = "/home/v-xuc/ParNVM/data";
 pub const PMEM_FILE_DIR: Option<&'static str> = option_env!("PMEM_FILE_DIR");
@@ -291,7 +484,7 @@ pub struct ShutdownState {
 
 thread_local!{
 
-    pub static PMEM_LOGGER : Rc<RefCell<PLog>> = Rc::new(RefCell::new(PLog::new(String::from(PLOG_FILE_PATH.expect("plog_file_path should be set at compile time")), PLOG_DEFAULT_SIZE, !std::env::var("DEBUG").unwrap_or("false".to_string()).parse::<bool>().unwrap())));
+    pub static PMEM_LOGGER : Arc<PLog> = Arc::new(PLog::new(String::from(PLOG_FILE_PATH.expect("plog_file_path should be set at compile time")), PLOG_DEFAULT_SIZE, !std::env::var("DEBUG").unwrap_or("false".to_string()).parse::<bool>().unwrap()));
 
     pub static DISK_LOGGER: Rc<RefCell<DLogger>>= Rc::new(RefCell::new(DLogger::new(String::from(DISK_LOG_FILE))));
 
@@ -455,6 +648,11 @@ impl Drop for DLogger {
     }
 }
 
+//libpmemlog is thread-safe: a PLog is appended to by its
+//thread, and by the persister with a LogHandle
+unsafe impl Send for PLog {}
+unsafe impl Sync for PLog {}
+
 impl PLog {
     fn new(path: String, size: usize, thread_local: bool) -> PLog {
         trace!("{:}Plog::new(path: {:}, size:{:})", LPREFIX, path, size);
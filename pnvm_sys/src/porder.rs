@@ -0,0 +1,376 @@
+//************************************************
+//Persistence ordering checks, "porder" feature
+//
+//The undo protocol needs, for every transaction:
+//1. its undo records durable before its data is stored
+//   in place in pmem
+//2. its data flushed and fenced before its commit marker
+//
+//Memory freshly allocated to the txn (Alloc: a VarLen
+//payload, the pmem slot of a row inserted) is not
+//reachable from a committed row until the commit: it
+//needs no undo record, only 2. Data stored over it is
+//covered as if logged.
+//
+//The persist paths tag the pmem ranges they store with
+//their role and transaction. The tracker follows each
+//range through the flushes and fences of the thread:
+//- Stored: written, maybe still in the caches
+//- Flushed(e): written back since fence epoch e
+//- Fenced(e): durable, at the fence ending epoch e
+//
+//Undo records are appended by libpmemlog, which flushes
+//them: they are tagged Flushed, and fenced by the drain
+//ending persist_log.
+//
+//The first store breaking 1 or 2 panics, with the range
+//and its state. The tracker is per thread, as a fence
+//only orders the flushes of its own thread.
+//A thread handing the commit marker over to another one
+//checks 2 at the handoff.
+//************************************************
+
+use std::{cell::RefCell, collections::VecDeque, fmt};
+
+use super::CACHE_LINE_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Log,
+    Data,
+    Alloc,
+    Commit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Stored,
+    Flushed(u64),
+    Fenced(u64),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Range {
+    start_: usize,
+    end_:   usize,
+    role_:  Role,
+    tid_:   u32,
+    state_: State,
+}
+
+/* Commits remembered, to tell data stored after its commit */
+const RECENT_COMMITS: usize = 1024;
+
+struct Tracker {
+    epoch_:   u64,
+    ranges_:  Vec<Range>,
+    commits_: VecDeque<u32>,
+}
+
+thread_local! {
+    static TRACKER: RefCell<Tracker> = RefCell::new(Tracker::new());
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            State::Stored => write!(f, "stored, never flushed"),
+            State::Flushed(e) => write!(f, "flushed in epoch {}, not fenced since", e),
+            State::Fenced(e) => write!(f, "fenced at epoch {}", e),
+        }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} of txn {:#x} at [{:#x}, {:#x})",
+            self.role_, self.tid_, self.start_, self.end_
+        )
+    }
+}
+
+impl Tracker {
+    fn new() -> Tracker {
+        Tracker {
+            epoch_:   0,
+            ranges_:  Vec::new(),
+            commits_: VecDeque::with_capacity(RECENT_COMMITS),
+        }
+    }
+
+    fn tag(&mut self, role: Role, tid: u32, start: usize, end: usize, stored: State) {
+        let range = Range {
+            start_: start,
+            end_:   end,
+            role_:  role,
+            tid_:   tid,
+            state_: stored,
+        };
+
+        match role {
+            Role::Log | Role::Alloc => self.ranges_.push(range),
+            Role::Data => {
+                self.check_logged(&range);
+                self.ranges_.push(range);
+            }
+            Role::Commit => self.commit(&range),
+        }
+    }
+
+    fn check_logged(&self, data: &Range) {
+        if self.commits_.contains(&data.tid_) {
+            panic!("porder::{} stored after the commit marker of its txn", data);
+        }
+
+        /* Walk the undo records and allocations of the txn over the data */
+        let mut logs: Vec<&Range> = self
+            .ranges_
+            .iter()
+            .filter(|r| (r.role_ == Role::Log || r.role_ == Role::Alloc) && r.tid_ == data.tid_)
+            .filter(|r| r.start_ < data.end_ && data.start_ < r.end_)
+            .collect();
+        logs.sort_by_key(|r| r.start_);
+
+        let mut at = data.start_;
+        for log in logs {
+            if log.start_ > at {
+                break;
+            }
+            match (log.role_, log.state_) {
+                (Role::Log, State::Fenced(_)) | (Role::Alloc, _) => {}
+                _ => panic!(
+                    "porder::{} stored before its undo record was fenced: {} is {}",
+                    data, log, log.state_
+                ),
+            }
+            at = ::std::cmp::max(at, log.end_);
+        }
+
+        if at < data.end_ {
+            panic!(
+                "porder::{} stored before its undo record: [{:#x}, {:#x}) is not logged",
+                data, at, data.end_
+            );
+        }
+    }
+
+    fn commit(&mut self, marker: &Range) {
+        let tid = marker.tid_;
+        for data in self.ranges_.iter().filter(|r| r.tid_ == tid) {
+            match data.state_ {
+                State::Fenced(_) => {}
+                state => panic!("porder::{} precedes its data: {} is {}", marker, data, state),
+            }
+        }
+
+        self.ranges_.retain(|r| r.tid_ != tid);
+        if self.commits_.len() == RECENT_COMMITS {
+            self.commits_.pop_front();
+        }
+        self.commits_.push_back(tid);
+    }
+
+    //The lines of [start, end) are written back: split the
+    //ranges partly in them
+    fn flush(&mut self, start: usize, end: usize) {
+        let start = start / CACHE_LINE_SIZE * CACHE_LINE_SIZE;
+        let end = (end + CACHE_LINE_SIZE - 1) / CACHE_LINE_SIZE * CACHE_LINE_SIZE;
+        let flushed = State::Flushed(self.epoch_);
+
+        let mut split = Vec::new();
+        for r in self.ranges_.iter_mut() {
+            if r.end_ <= start || end <= r.start_ {
+                continue;
+            }
+            if let State::Fenced(_) = r.state_ {
+                continue;
+            }
+
+            if r.start_ < start {
+                split.push(Range { end_: start, ..*r });
+                r.start_ = start;
+            }
+            if end < r.end_ {
+                split.push(Range { start_: end, ..*r });
+                r.end_ = end;
+            }
+            r.state_ = flushed;
+        }
+        self.ranges_.extend(split);
+    }
+
+    fn fence(&mut self) {
+        let fenced = State::Fenced(self.epoch_);
+        for r in self.ranges_.iter_mut() {
+            if let State::Flushed(_) = r.state_ {
+                r.state_ = fenced;
+            }
+        }
+        self.epoch_ += 1;
+    }
+}
+
+//len bytes at addr of txn tid, about to be stored or
+//appended (Log). Panics if their order is broken.
+pub fn tag(role: Role, tid: u32, addr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let start = addr as usize;
+    TRACKER.with(|t| {
+        let mut t = t.borrow_mut();
+        let stored = match role {
+            Role::Log => State::Flushed(t.epoch_),
+            _ if super::skips_flushes() => State::Flushed(t.epoch_),
+            _ => State::Stored,
+        };
+        t.tag(role, tid, start, start + len, stored)
+    });
+}
+
+//The commit marker of tid is appended by another thread
+//(asynchronous commits): its ranges stored by this one
+//must be fenced already
+pub fn handoff(tid: u32) {
+    let marker = Range {
+        start_: 0,
+        end_:   0,
+        role_:  Role::Commit,
+        tid_:   tid,
+        state_: State::Stored,
+    };
+    TRACKER.with(|t| t.borrow_mut().commit(&marker));
+}
+
+pub fn flushed(addr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let start = addr as usize;
+    TRACKER.with(|t| t.borrow_mut().flush(start, start + len));
+}
+
+pub fn fenced() {
+    TRACKER.with(|t| t.borrow_mut().fence());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TID: u32 = 7;
+
+    fn at(off: usize) -> *const u8 {
+        (4096 + off) as *const u8
+    }
+
+    //Undo record, data, flush, fence, then commit
+    fn txn(tid: u32, data: usize, len: usize) {
+        tag(Role::Log, tid, at(data), len);
+        fenced();
+
+        tag(Role::Data, tid, at(data), len);
+        flushed(at(data), len);
+        fenced();
+
+        tag(Role::Commit, tid, at(0), 4);
+    }
+
+    #[test]
+    fn test_ordered() {
+        txn(TID, 8, 16);
+        txn(TID + 1, 60, 200);
+
+        /* Two undo records, flushed in two parts */
+        tag(Role::Log, TID + 2, at(0), 64);
+        tag(Role::Log, TID + 2, at(64), 64);
+        fenced();
+        tag(Role::Data, TID + 2, at(32), 64);
+        flushed(at(0), 64);
+        flushed(at(64), 8);
+        fenced();
+        tag(Role::Commit, TID + 2, at(0), 4);
+    }
+
+    #[test]
+    fn test_alloc_not_logged() {
+        tag(Role::Log, TID, at(0), 8);
+        fenced();
+        tag(Role::Data, TID, at(0), 8);
+        tag(Role::Alloc, TID, at(256), 300);
+        flushed(at(0), 8);
+        flushed(at(256), 300);
+        fenced();
+        tag(Role::Commit, TID, at(0), 4);
+    }
+
+    #[test]
+    fn test_data_over_alloc() {
+        tag(Role::Alloc, TID, at(0), 72);
+        tag(Role::Data, TID, at(0), 72);
+        flushed(at(0), 72);
+        fenced();
+        tag(Role::Commit, TID, at(0), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not logged")]
+    fn test_data_over_alloc_of_other_txn() {
+        tag(Role::Alloc, TID, at(0), 72);
+        tag(Role::Data, TID + 1, at(0), 72);
+    }
+
+    #[test]
+    #[should_panic(expected = "precedes its data")]
+    fn test_commit_before_alloc_fence() {
+        tag(Role::Alloc, TID, at(256), 300);
+        flushed(at(256), 300);
+        tag(Role::Commit, TID, at(0), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "before its undo record was fenced")]
+    fn test_data_before_log_fence() {
+        tag(Role::Log, TID, at(0), 64);
+        tag(Role::Data, TID, at(0), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not logged")]
+    fn test_data_not_logged() {
+        tag(Role::Log, TID, at(0), 8);
+        fenced();
+        tag(Role::Data, TID, at(0), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "precedes its data")]
+    fn test_commit_before_fence() {
+        tag(Role::Log, TID, at(0), 128);
+        fenced();
+        tag(Role::Data, TID, at(0), 128);
+        flushed(at(0), 64);
+        fenced();
+        flushed(at(64), 64);
+        tag(Role::Commit, TID, at(0), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "precedes its data")]
+    fn test_handoff_before_fence() {
+        tag(Role::Log, TID, at(0), 64);
+        fenced();
+        tag(Role::Data, TID, at(0), 64);
+        flushed(at(0), 64);
+        handoff(TID);
+    }
+
+    #[test]
+    #[should_panic(expected = "after the commit marker")]
+    fn test_data_after_commit() {
+        txn(TID, 0, 8);
+        tag(Role::Data, TID, at(0), 8);
+    }
+}